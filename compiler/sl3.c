@@ -0,0 +1,38 @@
+// Generated by U v1.0 — DO NOT EDIT
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+static int u_check_index(int64_t index, int64_t len, int line, int column) {
+    if (index < 0 || index >= len) {
+        fflush(stdout);
+        fprintf(stderr, "index out of bounds: the length is %lld but the index is %lld at %d:%d\n",
+                (long long)len, (long long)index, line, column);
+        abort();
+    }
+    return (int)index;
+}
+typedef struct { int* ptr; int len; } Slice_i32;
+static inline int* Slice_i32_at(Slice_i32 s, int64_t i, int line, int column) { return &s.ptr[u_check_index(i, s.len, line, column)]; }
+typedef struct { int data[2]; } Array2_i32;
+
+Slice_i32 first(Slice_i32 xs);
+Slice_i32 ok(Slice_i32 xs);
+int main(void);
+
+Slice_i32 first(Slice_i32 xs) {
+    return xs;
+}
+
+Slice_i32 ok(Slice_i32 xs) {
+    return first(xs);
+}
+
+int main(void) {
+    Array2_i32 a = ((Array2_i32){ { 1, 2 } });
+    Slice_i32 s = first(((Slice_i32){ a.data, 2 }));
+    printf("%d\n", s.len);
+    printf("%d\n", ok(((Slice_i32){ a.data, 2 })).len);
+}
+
@@ -0,0 +1,30 @@
+// Generated by U v1.0 — DO NOT EDIT
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+static int u_check_index(int64_t index, int64_t len, int line, int column) {
+    if (index < 0 || index >= len) {
+        fflush(stdout);
+        fprintf(stderr, "index out of bounds: the length is %lld but the index is %lld at %d:%d\n",
+                (long long)len, (long long)index, line, column);
+        abort();
+    }
+    return (int)index;
+}
+typedef struct { int* ptr; int len; } Slice_i32;
+static inline int* Slice_i32_at(Slice_i32 s, int64_t i, int line, int column) { return &s.ptr[u_check_index(i, s.len, line, column)]; }
+typedef struct { int data[2]; } Array2_i32;
+
+Slice_i32 bad(void);
+int main(void);
+
+Slice_i32 bad(void) {
+    return ((Slice_i32){ ((Array2_i32){ { 1, 2 } }).data, 2 });
+}
+
+int main(void) {
+    printf("%d\n", bad().len);
+}
+
@@ -0,0 +1,295 @@
+// captures.rs — U v0.8 Closure Capture Analysis
+// MIT License — Copyright (c) 2025 Webcien and U contributors
+//
+// Which variables of enclosing scopes a closure body uses, and how
+// Features:
+// - Free names of a body, minus its parameters and the variables, loop
+//   variables and pattern bindings it declares itself
+// - Whether a capture is consumed (moved out by the body, the way the
+//   ownership checker counts moves) or assigned to
+// - Nested closures pass their own captures on to the enclosing one
+//
+// The ownership checker decides from this which captures are moved into a
+// closure and which are borrowed; the C generator lays out the closure's
+// environment the same way.
+
+use crate::diagnostics::Span;
+use crate::parser::{ArmBody, Expression, ExpressionKind, Pattern, PatternKind, Statement, StatementKind, Type};
+use std::collections::HashSet;
+
+/// A name a closure body uses without declaring it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Capture {
+    pub name: String,
+    /// The body moves the value: it is passed to a function or method,
+    /// bound to a variable, assigned, put into a literal, matched by a
+    /// pattern that binds part of it or moved into a nested `move` closure
+    pub consumed: bool,
+    /// The body assigns to it or to one of its fields or elements
+    pub assigned: bool,
+    /// Where the body first uses it
+    pub span: Span,
+}
+
+/// Free names of a closure, in order of first use. Function names and
+/// `None` are included too; callers keep the ones that name a variable.
+pub fn captures(params: &[(String, Type)], body: &[Statement]) -> Vec<Capture> {
+    let mut collector = Collector {
+        scopes: vec![params.iter().map(|(name, _)| name.clone()).collect()],
+        captures: Vec::new(),
+    };
+    collector.block(body);
+    collector.captures
+}
+
+struct Collector {
+    /// Names declared inside the closure, innermost scope last
+    scopes: Vec<HashSet<String>>,
+    captures: Vec<Capture>,
+}
+
+impl Collector {
+    fn declare(&mut self, name: &str) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string());
+        }
+    }
+
+    fn use_name(&mut self, name: &str, span: Span, consumed: bool, assigned: bool) {
+        if self.scopes.iter().any(|scope| scope.contains(name)) {
+            return;
+        }
+        match self.captures.iter_mut().find(|c| c.name == name) {
+            Some(capture) => {
+                capture.consumed |= consumed;
+                capture.assigned |= assigned;
+            }
+            None => self.captures.push(Capture {
+                name: name.to_string(),
+                consumed,
+                assigned,
+                span,
+            }),
+        }
+    }
+
+    fn block(&mut self, body: &[Statement]) {
+        self.scopes.push(HashSet::new());
+        for stmt in body {
+            self.statement(stmt);
+        }
+        self.scopes.pop();
+    }
+
+    fn statement(&mut self, stmt: &Statement) {
+        match &stmt.kind {
+            StatementKind::Let { name, value, .. } => {
+                if let Some(value) = value {
+                    self.moved(value);
+                }
+                self.declare(name);
+            }
+            StatementKind::Expr(expr) | StatementKind::Return(expr) => self.expression(expr),
+            StatementKind::If { condition, then_branch, else_branch } => {
+                self.expression(condition);
+                self.block(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.block(else_branch);
+                }
+            }
+            StatementKind::While { condition, body } => {
+                self.expression(condition);
+                self.block(body);
+            }
+            StatementKind::For { variable, iterable, body } => {
+                self.moved(iterable);
+                self.scopes.push(HashSet::from([variable.clone()]));
+                self.block(body);
+                self.scopes.pop();
+            }
+            StatementKind::Unsafe { body } => self.block(body),
+            StatementKind::Break | StatementKind::Continue | StatementKind::Error => {}
+        }
+    }
+
+    /// An expression whose value is moved where it goes
+    fn moved(&mut self, expr: &Expression) {
+        match &expr.kind {
+            ExpressionKind::Identifier(name) => self.use_name(name, expr.span, true, false),
+            _ => self.expression(expr),
+        }
+    }
+
+    fn expression(&mut self, expr: &Expression) {
+        match &expr.kind {
+            ExpressionKind::Literal(_) => {}
+            ExpressionKind::Identifier(name) => self.use_name(name, expr.span, false, false),
+            ExpressionKind::FunctionCall { name, arguments } => {
+                // The callee may itself be a captured closure
+                self.use_name(name, expr.span, false, false);
+                for arg in arguments {
+                    self.moved(arg);
+                }
+            }
+            ExpressionKind::MethodCall { receiver, arguments, .. } => {
+                // A method is given a pointer to its receiver
+                self.expression(receiver);
+                for arg in arguments {
+                    self.moved(arg);
+                }
+            }
+            ExpressionKind::Binary { left, right, .. } => {
+                self.expression(left);
+                self.expression(right);
+            }
+            ExpressionKind::Unary { operand, .. } => self.expression(operand),
+            ExpressionKind::Assignment { target, value } => {
+                self.expression(target);
+                if let Some(root) = target.root_variable() {
+                    self.use_name(root, target.span, false, true);
+                }
+                self.moved(value);
+            }
+            ExpressionKind::StructLiteral { fields, .. } => {
+                for (_, value) in fields {
+                    self.moved(value);
+                }
+            }
+            ExpressionKind::EnumVariant { payload, .. } => {
+                for (_, value) in payload.fields() {
+                    self.moved(value);
+                }
+            }
+            ExpressionKind::ArrayLiteral(elements) => {
+                for element in elements {
+                    self.moved(element);
+                }
+            }
+            ExpressionKind::FieldAccess { object, .. } => self.expression(object),
+            ExpressionKind::Index { object, index } => {
+                self.expression(object);
+                self.expression(index);
+            }
+            ExpressionKind::Range { start, end, step, .. } => {
+                self.expression(start);
+                self.expression(end);
+                if let Some(step) = step {
+                    self.expression(step);
+                }
+            }
+            ExpressionKind::Match { scrutinee, arms } => {
+                if arms.iter().any(|arm| binds(&arm.pattern)) {
+                    self.moved(scrutinee);
+                } else {
+                    self.expression(scrutinee);
+                }
+                for arm in arms {
+                    self.scopes.push(HashSet::new());
+                    self.bind(&arm.pattern);
+                    if let Some(guard) = &arm.guard {
+                        self.expression(guard);
+                    }
+                    match &arm.body {
+                        ArmBody::Expression(value) => self.expression(value),
+                        ArmBody::Block(body) => self.block(body),
+                    }
+                    self.scopes.pop();
+                }
+            }
+            ExpressionKind::Tuple(items) => {
+                for item in items {
+                    self.expression(item);
+                }
+            }
+            ExpressionKind::Closure { params, body, by_move, .. } => {
+                for capture in captures(params, body) {
+                    self.use_name(&capture.name, capture.span, capture.consumed || *by_move, capture.assigned);
+                }
+            }
+        }
+    }
+
+    /// Declare the variables a pattern binds
+    fn bind(&mut self, pattern: &Pattern) {
+        match &pattern.kind {
+            PatternKind::Binding(name) => self.declare(name),
+            PatternKind::Wildcard | PatternKind::Literal(_) => {}
+            PatternKind::Tuple(items) => {
+                for item in items {
+                    self.bind(item);
+                }
+            }
+            PatternKind::Struct { fields, .. } => {
+                for (_, item) in fields {
+                    self.bind(item);
+                }
+            }
+            PatternKind::Variant { payload, .. } => {
+                for (_, item) in payload.fields() {
+                    self.bind(item);
+                }
+            }
+        }
+    }
+}
+
+/// Whether a pattern binds a variable
+fn binds(pattern: &Pattern) -> bool {
+    match &pattern.kind {
+        PatternKind::Binding(_) => true,
+        PatternKind::Wildcard | PatternKind::Literal(_) => false,
+        PatternKind::Tuple(items) => items.iter().any(binds),
+        PatternKind::Struct { fields, .. } => fields.iter().any(|(_, item)| binds(item)),
+        PatternKind::Variant { payload, .. } => payload.fields().into_iter().any(|(_, item)| binds(item)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::{Declaration, Parser};
+
+    /// Captures of the first closure in `main`'s first statement
+    fn closure_captures(source: &str) -> Vec<(String, bool, bool)> {
+        let mut parser = Parser::new(Lexer::new(source.to_string()).tokenize());
+        let decls = parser.parse().unwrap();
+        let Some(Declaration::Function(main)) = decls.into_iter().next() else {
+            panic!("expected a function");
+        };
+        let StatementKind::Let { value: Some(value), .. } = &main.body[0].kind else {
+            panic!("expected a let");
+        };
+        let ExpressionKind::Closure { params, body, .. } = &value.kind else {
+            panic!("expected a closure");
+        };
+        captures(params, body)
+            .into_iter()
+            .map(|c| (c.name, c.consumed, c.assigned))
+            .collect()
+    }
+
+    #[test]
+    fn test_closure_captures() {
+        let found = closure_captures(
+            "fn main() { let f = |x: i32| { let y = x + a; consume(b); c.n = y; match d { Some(e) => e, None => y } }; }",
+        );
+        assert_eq!(
+            found,
+            vec![
+                ("a".to_string(), false, false),
+                ("consume".to_string(), false, false),
+                ("b".to_string(), true, false),
+                ("c".to_string(), false, true),
+                ("d".to_string(), true, false),
+            ]
+        );
+        // A match moves its scrutinee only if a pattern binds part of it
+        let found = closure_captures("fn main() { let f = || { match d { Some(_) => 1, None => 0 } }; }");
+        assert_eq!(found, vec![("d".to_string(), false, false)]);
+
+        // A nested `move` closure consumes what it captures
+        let found = closure_captures("fn main() { let f = || { let g = move || s; g() }; }");
+        assert_eq!(found, vec![("s".to_string(), true, false)]);
+    }
+}
@@ -1,14 +1,93 @@
 // c.rs — U v1.0 C Code Generator
 // MIT License — Copyright (c) 2025 Webcien and U contributors
 
-use crate::parser::{BinaryOp, Declaration, Expression, Function, Literal, Statement, Type, UnaryOp, Actor, Trait, TypeDef, TraitImpl};
-use std::collections::HashSet;
+use crate::parser::{ArmBody, BinaryOp, Declaration, EnumDef, Expression, ExpressionKind, Function, Literal, MatchArm, Pattern, PatternKind, Statement, StatementKind, Type, UnaryOp, Actor, Trait, TypeDef, TraitImpl, VariantPayload};
+use crate::captures::captures;
+use crate::traits::{self, MethodSignature, TraitRegistry};
+use crate::diagnostics::Span;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
 use std::fmt::Write;
+use std::rc::Rc;
 
 pub struct CGenerator {
     output: String,
     indent_level: usize,
     current_params: HashSet<String>,
+    enums: HashMap<String, EnumDef>,
+    /// C name of the tag of every enum variant, by enum and variant name
+    tags: HashMap<(String, String), String>,
+    structs: HashMap<String, TypeDef>,
+    /// Parameter and return types of every function, for lowering
+    /// `Some`/`None`/`Ok`/`Err` where the expected type is known
+    functions: HashMap<String, (Vec<Type>, Option<Type>)>,
+    /// Types of the locals in the current function, where known
+    locals: HashMap<String, Type>,
+    return_type: Option<Type>,
+    /// Item type of every type with an `impl Iterator`
+    iterators: HashMap<String, Type>,
+    /// Traits and their impls, through which method calls are resolved
+    traits: Rc<TraitRegistry>,
+    /// Whether an impl method is being generated, where `self` is a pointer
+    in_method: bool,
+    match_count: usize,
+    loop_count: usize,
+    /// Nesting depth of `unsafe` blocks, where indexing is unchecked
+    unsafe_depth: usize,
+    /// Typedefs for enums, options, results and tuples, emitted in
+    /// dependency order as `type_to_c` first meets each type
+    type_defs: Rc<RefCell<String>>,
+    defined: Rc<RefCell<HashSet<String>>>,
+    /// Environments, functions and constructors of the closures lowered so
+    /// far, and the vtables of the `dyn` values made, emitted after the
+    /// prototypes
+    closures: Rc<RefCell<String>>,
+    closure_count: Rc<Cell<usize>>,
+    /// In a closure body, each capture and whether its environment holds a
+    /// copy of it (rather than a pointer)
+    captured: HashMap<String, bool>,
+    /// Whether the closure being generated returns nothing, so that a
+    /// `return value` only evaluates its value
+    returns_void: bool,
+    /// Locals of each scope around the statement being generated whose
+    /// values are dropped when it ends, innermost last
+    drops: Vec<Vec<Owned>>,
+    /// Number of scopes in `drops` outside the body of each loop around
+    /// the statement being generated, innermost last
+    loop_drops: Vec<usize>,
+    /// Declarations of the temporaries of the expressions lowered for the
+    /// next line, which are emitted before it
+    temporaries: RefCell<Vec<String>>,
+    temporary_count: Cell<usize>,
+}
+
+/// A local whose value needs dropping when its scope ends
+#[derive(Clone)]
+struct Owned {
+    name: String,
+    ty: Type,
+    /// C variable saying whether the local still holds its value, for one
+    /// that may be moved out first
+    flag: Option<String>,
+}
+
+/// How a method call reaches the impl method
+enum Dispatch {
+    /// A direct call of the C function of the receiver type's impl
+    Static(String),
+    /// A call through the vtable of a `dyn Trait`
+    Dynamic,
+}
+
+/// Where the value of a lowered `match` arm goes
+enum ArmAction {
+    /// Statement position: the value is evaluated and dropped
+    Discard,
+    /// `let` or assignment: the value is stored in the C lvalue, whose type
+    /// is given where known
+    Assign(String, Option<Type>),
+    /// `return match ...`
+    Return,
 }
 
 impl CGenerator {
@@ -17,6 +96,60 @@ impl CGenerator {
             output: String::new(),
             indent_level: 0,
             current_params: HashSet::new(),
+            enums: HashMap::new(),
+            tags: HashMap::new(),
+            structs: HashMap::new(),
+            functions: HashMap::new(),
+            locals: HashMap::new(),
+            return_type: None,
+            iterators: HashMap::new(),
+            traits: Rc::new(traits::create_builtin_traits()),
+            in_method: false,
+            match_count: 0,
+            loop_count: 0,
+            unsafe_depth: 0,
+            type_defs: Rc::new(RefCell::new(String::new())),
+            defined: Rc::new(RefCell::new(HashSet::new())),
+            closures: Rc::new(RefCell::new(String::new())),
+            closure_count: Rc::new(Cell::new(0)),
+            captured: HashMap::new(),
+            returns_void: false,
+            drops: Vec::new(),
+            loop_drops: Vec::new(),
+            temporaries: RefCell::new(Vec::new()),
+            temporary_count: Cell::new(0),
+        }
+    }
+
+    /// Generator for the body of a closure, sharing this one's type
+    /// definitions and closure numbering
+    fn closure_generator(&self) -> CGenerator {
+        CGenerator {
+            output: String::new(),
+            indent_level: 1,
+            current_params: HashSet::new(),
+            enums: self.enums.clone(),
+            tags: self.tags.clone(),
+            structs: self.structs.clone(),
+            functions: self.functions.clone(),
+            locals: HashMap::new(),
+            return_type: None,
+            iterators: self.iterators.clone(),
+            traits: Rc::clone(&self.traits),
+            in_method: false,
+            match_count: 0,
+            loop_count: 0,
+            unsafe_depth: self.unsafe_depth,
+            type_defs: Rc::clone(&self.type_defs),
+            defined: Rc::clone(&self.defined),
+            closures: Rc::clone(&self.closures),
+            closure_count: Rc::clone(&self.closure_count),
+            captured: HashMap::new(),
+            returns_void: false,
+            drops: Vec::new(),
+            loop_drops: Vec::new(),
+            temporaries: RefCell::new(Vec::new()),
+            temporary_count: Cell::new(0),
         }
     }
 
@@ -26,6 +159,9 @@ impl CGenerator {
 
     fn emit(&mut self, line: &str) {
         let indent_str = self.indent();
+        for temporary in self.temporaries.take() {
+            writeln!(&mut self.output, "{}{}", indent_str, temporary).unwrap();
+        }
         writeln!(&mut self.output, "{}{}", indent_str, line).unwrap();
     }
 
@@ -36,63 +172,148 @@ impl CGenerator {
     pub fn generate_program(&mut self, declarations: Vec<Declaration>) -> String {
         // Minimal header
         self.emit("// Generated by U v1.0 — DO NOT EDIT");
+        self.emit("#include <stdint.h>");
         self.emit("#include <stdio.h>");
         self.emit("#include <stdlib.h>");
         self.emit("#include <string.h>");
         self.emitln();
+        let types_at = self.output.len();
+
+        // The type checker has already reported traits and impls that do
+        // not register
+        let mut registry = traits::create_builtin_traits();
+        for decl in &declarations {
+            if let Declaration::Trait(t) = decl {
+                let _ = registry.register_trait(traits::Trait::from(t));
+            }
+        }
+        for decl in &declarations {
+            if let Declaration::TraitImpl(i) = decl {
+                let _ = registry.register_impl(traits::TraitImpl::from(i));
+            }
+        }
+        self.traits = Rc::new(registry);
+
+        for decl in &declarations {
+            match decl {
+                Declaration::Function(f) => {
+                    let params = f.params.iter().map(|(_, ty)| ty.clone()).collect();
+                    self.functions.insert(f.name.clone(), (params, f.return_type.clone()));
+                }
+                Declaration::ExternBlock(block) => {
+                    for f in &block.functions {
+                        let params = f.params.iter().map(|(_, ty)| ty.clone()).collect();
+                        self.functions.insert(f.name.clone(), (params, f.return_type.clone()));
+                    }
+                }
+                Declaration::Enum(e) => {
+                    self.enums.insert(e.name.clone(), e.clone());
+                }
+                Declaration::TypeDef(t) => {
+                    self.structs.insert(t.name.clone(), t.clone());
+                }
+                Declaration::TraitImpl(i) => {
+                    if let Some(item) = i.iterator_item() {
+                        self.iterators.insert(i.type_name.clone(), item);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        self.name_tags(&declarations);
+
+        // Every enum and struct is defined even if unused, in declaration
+        // order as far as their fields allow
+        for decl in &declarations {
+            match decl {
+                Declaration::Enum(e) => self.type_to_c(&Type::Custom(e.name.clone())),
+                Declaration::TypeDef(t) => self.type_to_c(&Type::Custom(t.name.clone())),
+                _ => continue,
+            };
+        }
 
         // Forward declarations
         for decl in &declarations {
-            if let Declaration::Function(f) = decl {
-                self.generate_function_prototype(f);
+            match decl {
+                Declaration::Function(f) => self.generate_function_prototype(&f.name, f, None),
+                Declaration::TraitImpl(i) => {
+                    for method in &i.methods {
+                        let name = method_name(&i.type_name, &i.trait_name, &method.name);
+                        self.generate_function_prototype(&name, method, Some(&i.type_name));
+                    }
+                }
+                _ => {}
             }
         }
         self.emitln();
+        let closures_at = self.output.len();
 
         // Declarations
         for decl in declarations {
             self.generate_declaration(decl);
         }
 
+        // Closures are lowered as the bodies using them are generated
+        let closures = self.closures.borrow();
+        if !closures.is_empty() {
+            self.output.insert_str(closures_at, &closures);
+        }
+        // Bodies can still introduce types, so the typedefs go in last
+        let type_defs = self.type_defs.borrow();
+        if !type_defs.is_empty() {
+            self.output.insert_str(types_at, &format!("{}\n", type_defs));
+        }
         self.output.clone()
     }
 
-    fn generate_function_prototype(&mut self, f: &Function) {
-        let return_type = if f.name == "main" {
-            "int"
-        } else if f.return_type.is_some() {
-            "int"
-        } else {
-            "void"
-        };
+    /// `main` always returns int; other functions follow their signature
+    fn return_type_to_c(&self, f: &Function) -> String {
+        match &f.return_type {
+            _ if f.name == "main" => "int".to_string(),
+            Some(ty) => self.type_to_c(ty),
+            None => "void".to_string(),
+        }
+    }
 
-        let params = if f.params.is_empty() {
+    /// C parameter list of `f`, led by a `self` pointer for a method of
+    /// `receiver`
+    fn params_to_c(&self, f: &Function, receiver: Option<&str>) -> String {
+        let receiver = receiver.map(|name| format!("{}* self", self.type_to_c(&Type::Custom(name.to_string()))));
+        let params: Vec<_> = receiver
+            .into_iter()
+            .chain(f.params.iter().map(|(name, ty)| format!("{} {}", self.type_to_c(ty), name)))
+            .collect();
+        if params.is_empty() {
             "void".to_string()
         } else {
-            f.params
-                .iter()
-                .map(|(name, ty)| format!("{} {}", self.type_to_c(ty), name))
-                .collect::<Vec<_>>()
-                .join(", ")
-        };
+            params.join(", ")
+        }
+    }
 
-        self.emit(&format!("{} {}({});", return_type, f.name, params));
+    fn generate_function_prototype(&mut self, name: &str, f: &Function, receiver: Option<&str>) {
+        let return_type = self.return_type_to_c(f);
+        let params = self.params_to_c(f, receiver);
+        self.emit(&format!("{} {}({});", return_type, name, params));
     }
 
     fn generate_declaration(&mut self, decl: Declaration) {
         match decl {
-            Declaration::Function(f) => self.generate_function(f),
+            Declaration::Function(f) => self.generate_function(f.name.clone(), f, None),
             Declaration::Actor(_) => {
                 // Actors: struct generation deferred to v0.9
             }
             Declaration::Trait(_) => {
-                // Traits: vtable generation deferred to v0.9
+                // Vtables are generated for the `dyn` types that are used
             }
-            Declaration::TypeDef(_) => {
-                // Type definitions: struct generation deferred to v0.9
+            Declaration::TypeDef(_) | Declaration::Enum(_) => {
+                // Emitted with the other type definitions, ahead of the prototypes
             }
-            Declaration::TraitImpl(_) => {
-                // Trait implementations: method generation deferred to v0.9
+            Declaration::TraitImpl(i) => {
+                for method in i.methods {
+                    let name = method_name(&i.type_name, &i.trait_name, &method.name);
+                    self.generate_function(name, method, Some(&i.type_name));
+                }
             }
             Declaration::ExternBlock(block) => {
                 self.generate_extern_block(block);
@@ -100,202 +321,1622 @@ impl CGenerator {
             Declaration::UiBlock(ui) => {
                 self.generate_ui_block(ui);
             }
+            Declaration::Import(_) => {
+                // The module loader has already merged imported items
+            }
+            Declaration::Error(_) => {
+                // Syntax errors stop the build before codegen
+            }
         }
     }
 
-    fn generate_function(&mut self, f: Function) {
+    fn generate_function(&mut self, name: String, f: Function, receiver: Option<&str>) {
         // Clear previous parameters
         self.current_params.clear();
-        for (name, _) in &f.params {
+        self.locals.clear();
+        if let Some(receiver) = receiver {
+            self.locals.insert("self".to_string(), Type::Custom(receiver.to_string()));
+        }
+        for (name, ty) in &f.params {
             self.current_params.insert(name.clone());
+            self.locals.insert(name.clone(), ty.clone());
         }
+        self.return_type = f.return_type.clone();
+        self.in_method = receiver.is_some();
+        self.returns_void = false;
 
-        // Return type (main always returns int, others based on signature)
-        let return_type = if f.name == "main" {
-            "int"
-        } else if f.return_type.is_some() {
-            "int"
-        } else {
-            "void"
-        };
-
-        let params = if f.params.is_empty() {
-            "void".to_string()
-        } else {
-            f.params
-                .iter()
-                .map(|(name, ty)| format!("{} {}", self.type_to_c(ty), name))
-                .collect::<Vec<_>>()
-                .join(", ")
-        };
+        let return_type = self.return_type_to_c(&f);
+        let params = self.params_to_c(&f, receiver);
 
         // Signature
-        self.emit(&format!("{} {}({}) {{", return_type, f.name, params));
+        self.emit(&format!("{} {}({}) {{", return_type, name, params));
         self.indent_level += 1;
 
-        // Body
-        for stmt in f.body {
-            self.generate_statement(stmt);
-        }
+        // Body, which owns the parameters
+        let params = f.params.into_iter().map(|(name, ty)| (moved_in(&name, &f.body), name, ty)).collect();
+        self.generate_block_with(params, f.body);
 
         self.indent_level -= 1;
         self.emit("}");
         self.emitln();
     }
 
+    /// Name the tag of every enum variant `Enum_Variant`, unless that is
+    /// the C name of an item or another tag, as with `enum A_B { C }` and
+    /// `enum A { B_C }`; then a number is added to make it unique
+    fn name_tags(&mut self, declarations: &[Declaration]) {
+        let mut taken: HashSet<String> = HashSet::new();
+        for decl in declarations {
+            match decl {
+                Declaration::Function(f) => {
+                    taken.insert(f.name.clone());
+                }
+                Declaration::ExternBlock(block) => taken.extend(block.functions.iter().map(|f| f.name.clone())),
+                Declaration::Enum(e) => {
+                    taken.insert(e.name.clone());
+                    taken.insert(format!("{}_Tag", e.name));
+                }
+                Declaration::TypeDef(t) => {
+                    taken.insert(t.name.clone());
+                }
+                Declaration::Trait(t) => {
+                    taken.insert(t.name.clone());
+                }
+                Declaration::Actor(a) => {
+                    taken.insert(a.name.clone());
+                }
+                Declaration::TraitImpl(i) => {
+                    taken.extend(i.methods.iter().map(|m| method_name(&i.type_name, &i.trait_name, &m.name)));
+                }
+                _ => {}
+            }
+        }
+        for decl in declarations {
+            let Declaration::Enum(e) = decl else {
+                continue;
+            };
+            for variant in &e.variants {
+                let base = format!("{}_{}", e.name, variant.name);
+                let mut tag = base.clone();
+                let mut n = 2;
+                while !taken.insert(tag.clone()) {
+                    tag = format!("{}_{}", base, n);
+                    n += 1;
+                }
+                self.tags.insert((e.name.clone(), variant.name.clone()), tag);
+            }
+        }
+    }
+
+    /// C name of the tag of `variant` of the enum `enum_name`
+    fn tag(&self, enum_name: &str, variant: &str) -> String {
+        self.tags
+            .get(&(enum_name.to_string(), variant.to_string()))
+            .cloned()
+            .unwrap_or_else(|| format!("{}_{}", enum_name, variant))
+    }
+
+    /// Lower an enum to a tagged union:
+    ///
+    /// ```c
+    /// typedef enum { Shape_Circle, Shape_Rect } Shape_Tag;
+    /// typedef struct Shape {
+    ///     Shape_Tag tag;
+    ///     union {
+    ///         struct { int _0; } Circle;
+    ///         struct { int w; int h; } Rect;
+    ///     } as;
+    /// } Shape;
+    /// ```
+    fn enum_to_c(&self, e: &EnumDef) -> String {
+        let tags = e
+            .variants
+            .iter()
+            .map(|v| self.tag(&e.name, &v.name))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut out = String::new();
+        writeln!(out, "typedef enum {{ {} }} {}_Tag;", tags, e.name).unwrap();
+        writeln!(out, "typedef struct {} {{", e.name).unwrap();
+        writeln!(out, "    {}_Tag tag;", e.name).unwrap();
+
+        // Unit variants need no storage, and C forbids an empty union
+        let payloads: Vec<_> = e
+            .variants
+            .iter()
+            .filter(|v| !v.payload.fields().is_empty())
+            .collect();
+        if !payloads.is_empty() {
+            out.push_str("    union {\n");
+            for variant in payloads {
+                let fields = variant
+                    .payload
+                    .fields()
+                    .into_iter()
+                    .map(|(name, ty)| format!("{} {};", self.type_to_c(ty), c_field_name(&name)))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                writeln!(out, "        struct {{ {} }} {};", fields, variant.name).unwrap();
+            }
+            out.push_str("    } as;\n");
+        }
+
+        writeln!(out, "}} {};", e.name).unwrap();
+        out
+    }
+
+    /// Lower a struct to a C struct of the same name. Generic structs are
+    /// replaced by their instances before codegen; one given here directly
+    /// keeps its type parameters, which only lowers to valid C if its
+    /// fields do not use them.
+    fn struct_to_c(&self, t: &TypeDef) -> String {
+        let mut out = format!("typedef struct {} {{\n", t.name);
+        for (field, ty) in &t.fields {
+            writeln!(out, "    {} {};", self.type_to_c(ty), field).unwrap();
+        }
+        if t.fields.is_empty() {
+            // C has no empty structs
+            out.push_str("    char _empty;\n");
+        }
+        writeln!(out, "}} {};", t.name).unwrap();
+        out
+    }
+
+    /// Define the runtime check behind every checked index
+    fn define_index_check(&self) {
+        self.define_type("u_check_index", || {
+            [
+                "static int u_check_index(int64_t index, int64_t len, int line, int column) {",
+                "    if (index < 0 || index >= len) {",
+                "        fflush(stdout);",
+                "        fprintf(stderr, \"index out of bounds: the length is %lld but the index is %lld at %d:%d\\n\",",
+                "                (long long)len, (long long)index, line, column);",
+                "        abort();",
+                "    }",
+                "    return (int)index;",
+                "}",
+                "",
+            ]
+            .join("\n")
+        });
+    }
+
+    /// Append a typedef to the type definitions unless `name` already has
+    /// one. `body` runs after `name` is marked, so the field types it
+    /// lowers are defined first and a type never waits on itself.
+    fn define_type(&self, name: &str, body: impl FnOnce() -> String) {
+        if !self.defined.borrow_mut().insert(name.to_string()) {
+            return;
+        }
+        let text = body();
+        self.type_defs.borrow_mut().push_str(&text);
+    }
+
     fn type_to_c(&self, ty: &Type) -> String {
         match ty {
+            // A Unicode scalar value, not a C byte
+            Type::Char => "uint32_t".to_string(),
             Type::I32 => "int".to_string(),
+            Type::I64 => "int64_t".to_string(),
+            Type::U8 => "uint8_t".to_string(),
+            Type::U64 => "uint64_t".to_string(),
+            Type::F32 => "float".to_string(),
+            Type::F64 => "double".to_string(),
             Type::Bool => "int".to_string(),
             Type::Str => "const char*".to_string(),
             Type::Ptr => "void*".to_string(),
+            // `const T*`, or `T const*` where `T` is already const
+            Type::Ref(inner) => match self.type_to_c(inner) {
+                inner if inner.starts_with("const ") => format!("{} const*", inner),
+                inner => format!("const {}*", inner),
+            },
             Type::Option(inner) => {
-                format!("struct {{ {} value; int is_some; }}", self.type_to_c(inner))
+                let name = mangle(ty);
+                self.define_type(&name, || {
+                    format!("typedef struct {{ {} value; int is_some; }} {};\n", self.type_to_c(inner), name)
+                });
+                name
+            }
+            Type::Result(ok, err) => {
+                let name = mangle(ty);
+                self.define_type(&name, || {
+                    format!(
+                        "typedef struct {{ {} value; {} error; int is_ok; }} {};\n",
+                        self.type_to_c(ok),
+                        self.type_to_c(err),
+                        name
+                    )
+                });
+                name
+            }
+            Type::Tuple(items) => {
+                let name = mangle(ty);
+                self.define_type(&name, || {
+                    let fields = items
+                        .iter()
+                        .enumerate()
+                        .map(|(i, item)| format!("{} _{};", self.type_to_c(item), i))
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    format!("typedef struct {{ {} }} {};\n", fields, name)
+                });
+                name
             }
-            Type::Result(ok, _) => {
-                format!("struct {{ {} value; int is_ok; }}", self.type_to_c(ok))
+            Type::Array(element, len) => {
+                let name = mangle(ty);
+                self.define_type(&name, || {
+                    // C has no empty arrays
+                    format!("typedef struct {{ {} data[{}]; }} {};\n", self.type_to_c(element), (*len).max(1), name)
+                });
+                name
             }
-            Type::Custom(name) => format!("struct {}", name),
-            Type::Generic { name, type_args: _ } => format!("struct {}", name),
+            Type::Slice(element) => {
+                let name = mangle(ty);
+                self.define_type(&name, || {
+                    let element = self.type_to_c(element);
+                    self.define_index_check();
+                    let mut out = format!("typedef struct {{ {}* ptr; int len; }} {};\n", element, name);
+                    writeln!(
+                        out,
+                        "static inline {}* {}_at({} s, int64_t i, int line, int column) {{ return &s.ptr[u_check_index(i, s.len, line, column)]; }}",
+                        element, name, name
+                    )
+                    .unwrap();
+                    out
+                });
+                name
+            }
+            Type::Range(item) => {
+                let name = mangle(ty);
+                self.define_type(&name, || range_to_c(&name, &self.type_to_c(item), unsigned_c_type(item)));
+                name
+            }
+            Type::Custom(name) | Type::Generic { name, .. } => {
+                if let Some(e) = self.enums.get(name) {
+                    self.define_type(name, || self.enum_to_c(e));
+                } else if let Some(t) = self.structs.get(name) {
+                    self.define_type(name, || self.struct_to_c(t));
+                }
+                format!("struct {}", name)
+            }
+            // A closure: its function, which takes the environment first,
+            // the environment, and the function that frees it, if any
             Type::FunctionPointer { params, return_type } => {
-                let ret = if let Some(r) = return_type {
-                    self.type_to_c(r)
-                } else {
-                    "void".to_string()
+                let name = mangle(ty);
+                self.define_type(&name, || {
+                    let ret = return_type.as_ref().map_or_else(|| "void".to_string(), |r| self.type_to_c(r));
+                    let params: String = params.iter().map(|t| format!(", {}", self.type_to_c(t))).collect();
+                    format!(
+                        "typedef struct {{ {} (*call)(void*{}); void* env; void (*drop)(void*); }} {};\n",
+                        ret, params, name
+                    )
+                });
+                name
+            }
+            // A pointer to the value and the vtable of its type
+            Type::Dyn(trait_name) => {
+                let name = mangle(ty);
+                self.define_type(&name, || {
+                    let entries: String = self
+                        .dyn_methods(trait_name)
+                        .iter()
+                        .map(|m| format!(" {} (*{})(void*{});", self.return_to_c(m), m.name, self.params_to_c_types(m)))
+                        .collect();
+                    // C has no empty structs
+                    let entries = if entries.is_empty() { " char _empty;".to_string() } else { entries };
+                    format!(
+                        "typedef struct {{{} }} {}_vtable;\ntypedef struct {{ void* self; const {}_vtable* vtable; }} {};\n",
+                        entries, trait_name, trait_name, name
+                    )
+                });
+                name
+            }
+        }
+    }
+
+    /// The methods of a trait that can be called through `dyn`, in the
+    /// order of its vtable entries
+    fn dyn_methods(&self, trait_name: &str) -> Vec<MethodSignature> {
+        let Some(trait_def) = self.traits.get_trait(trait_name) else {
+            return Vec::new();
+        };
+        trait_def
+            .methods
+            .iter()
+            .filter(|m| matches!(self.traits.dyn_method(trait_name, &m.name), Ok(Some(_))))
+            .cloned()
+            .collect()
+    }
+
+    fn return_to_c(&self, method: &MethodSignature) -> String {
+        method.return_type.as_ref().map_or_else(|| "void".to_string(), |ty| self.type_to_c(ty))
+    }
+
+    /// `, T1, T2`: the parameter types of a vtable entry after `self`
+    fn params_to_c_types(&self, method: &MethodSignature) -> String {
+        method.params.iter().map(|(_, ty)| format!(", {}", self.type_to_c(ty))).collect()
+    }
+
+    /// Define the vtable through which a `dyn Trait` reaches the methods of
+    /// `type_name`. Its entries take `self` as `void*`, so each calls the
+    /// impl method through a function converting the pointer back:
+    ///
+    /// ```c
+    /// static int Circle_Shape_area_dyn(void* self) { return Circle_Shape_area((struct Circle*)self); }
+    /// static const Shape_vtable Circle_Shape_vtable = { Circle_Shape_area_dyn };
+    /// ```
+    fn define_vtable(&self, type_name: &str, trait_name: &str) -> String {
+        let name = format!("{}_{}_vtable", type_name, trait_name);
+        if !self.defined.borrow_mut().insert(name.clone()) {
+            return name;
+        }
+        self.type_to_c(&Type::Dyn(trait_name.to_string()));
+        let methods = self.dyn_methods(trait_name);
+        let mut out = String::new();
+        for m in &methods {
+            let target = method_name(type_name, trait_name, &m.name);
+            let params: String = m
+                .params
+                .iter()
+                .map(|(param, ty)| format!(", {} {}", self.type_to_c(ty), param))
+                .collect();
+            let args: String = m.params.iter().map(|(param, _)| format!(", {}", param)).collect();
+            let ret = self.return_to_c(m);
+            let call = format!("{}((struct {}*)self{})", target, type_name, args);
+            let body = if m.return_type.is_some() { format!("return {};", call) } else { format!("{};", call) };
+            writeln!(out, "static {} {}_dyn(void* self{}) {{ {} }}", ret, target, params, body).unwrap();
+        }
+        let entries: Vec<String> = methods
+            .iter()
+            .map(|m| format!("{}_dyn", method_name(type_name, trait_name, &m.name)))
+            .collect();
+        let entries = if entries.is_empty() { "0".to_string() } else { entries.join(", ") };
+        writeln!(out, "static const {}_vtable {} = {{ {} }};\n", trait_name, name, entries).unwrap();
+        self.closures.borrow_mut().push_str(&out);
+        name
+    }
+
+    /// How a call of `method` on a value of type `receiver` (or one a
+    /// reference of that type points to) is made, and the method's
+    /// signature with `Self` bound to the receiver's type
+    fn dispatch(&self, receiver: &Type, method: &str) -> Option<(Dispatch, MethodSignature)> {
+        match referent(receiver) {
+            Type::Custom(type_name) | Type::Generic { name: type_name, .. } => {
+                let (impl_def, signature) = self.traits.resolve_method(type_name, method).ok()??;
+                let self_type = Type::Custom(type_name.clone());
+                let bindings = HashMap::from([("Self", &self_type)]);
+                let signature = MethodSignature {
+                    name: signature.name.clone(),
+                    params: signature.params.iter().map(|(name, ty)| (name.clone(), ty.substitute(&bindings))).collect(),
+                    return_type: signature.return_type.as_ref().map(|ty| ty.substitute(&bindings)),
                 };
-                let param_str = params.iter()
-                    .map(|t| self.type_to_c(t))
-                    .collect::<Vec<_>>()
-                    .join(", ");
-                format!("{}(*)({})", ret, param_str)
+                let function = method_name(type_name, &impl_def.trait_name, method);
+                Some((Dispatch::Static(function), signature))
+            }
+            Type::Dyn(trait_name) => {
+                let signature = self.traits.dyn_method(trait_name, method).ok()??;
+                Some((Dispatch::Dynamic, signature.clone()))
             }
+            _ => None,
         }
     }
 
     fn generate_statement(&mut self, stmt: Statement) {
-        match stmt {
-            Statement::Let { name, mutable: _, value } => {
-                let c_type = self.expr_type_to_c(&value);
-                let c_value = self.expr_to_c(value);
-                self.emit(&format!("{} {} = {};", c_type, name, c_value));
+        match stmt.kind {
+            StatementKind::Let { name, mutable: _, ty, value: None } => {
+                // The parser only leaves out the value after a type, and the
+                // type checker ensures it is assigned before use
+                let ty = ty.unwrap_or(Type::I32);
+                self.emit(&format!("{} {};", self.type_to_c(&ty), name));
+                self.locals.insert(name, ty);
             }
-            Statement::Expr(expr) => {
-                let c_expr = self.expr_to_c(expr);
-                self.emit(&format!("{};", c_expr));
+            StatementKind::Let { name, mutable: _, ty: declared, value: Some(value) } => {
+                // A declared type decides the C type; otherwise it follows
+                // the value
+                let c_type = match &declared {
+                    Some(ty) => self.type_to_c(ty),
+                    None => self.expr_type_to_c(&value),
+                };
+                let ty = declared.or_else(|| self.expr_type(&value));
+                if let ExpressionKind::Match { scrutinee, arms } = value.kind {
+                    self.emit(&format!("{} {};", c_type, name));
+                    if let Some(ty) = &ty {
+                        self.locals.insert(name.clone(), ty.clone());
+                    }
+                    self.generate_match(*scrutinee, arms, &ArmAction::Assign(name, ty));
+                } else {
+                    let c_value = self.value_to_c(value, ty.as_ref());
+                    self.emit(&format!("{} {} = {};", c_type, name, c_value));
+                    if let Some(ty) = ty {
+                        self.locals.insert(name, ty);
+                    }
+                }
             }
-            Statement::Return(expr) => {
-                let c_expr = self.expr_to_c(expr);
-                self.emit(&format!("return {};", c_expr));
+            // Assigning to a local that owns its value drops the old one,
+            // once the new one is worked out
+            StatementKind::Expr(Expression { kind: ExpressionKind::Assignment { target, value }, .. })
+                if matches!(&target.kind, ExpressionKind::Identifier(name) if self.owned(name).is_some()) =>
+            {
+                let name = target.root_variable().unwrap_or_default().to_string();
+                let Some(owned) = self.owned(&name) else {
+                    return;
+                };
+                let c_value = self.value_to_c(*value, Some(&owned.ty));
+                self.emit("{");
+                self.indent_level += 1;
+                self.emit(&format!("{} _d = {};", self.type_to_c(&owned.ty), c_value));
+                self.emit(&self.drop_statement(&owned));
+                self.emit(&format!("{} = _d;", name));
+                if let Some(flag) = &owned.flag {
+                    self.emit(&format!("{} = 1;", flag));
+                }
+                self.indent_level -= 1;
+                self.emit("}");
+            }
+            // as does assigning to a field or element of one
+            StatementKind::Expr(Expression { kind: ExpressionKind::Assignment { target, value }, .. })
+                if matches!(target.kind, ExpressionKind::FieldAccess { .. } | ExpressionKind::Index { .. })
+                    && !matches!(value.kind, ExpressionKind::Match { .. })
+                    && self.expr_type(&target).is_some_and(|ty| self.needs_drop(&ty)) =>
+            {
+                let Some(ty) = self.expr_type(&target) else {
+                    return;
+                };
+                let c_type = self.type_to_c(&ty);
+                let c_value = self.value_to_c(*value, Some(&ty));
+                let c_target = self.expr_to_c(*target);
+                self.emit("{");
+                self.indent_level += 1;
+                self.emit(&format!("{} _d = {};", c_type, c_value));
+                self.emit(&format!("{}* _p = &{};", c_type, c_target));
+                self.emit(&format!("{}(_p);", self.drop_function(&ty)));
+                self.emit("*_p = _d;");
+                self.indent_level -= 1;
+                self.emit("}");
+            }
+            StatementKind::Expr(expr) => match expr.kind {
+                ExpressionKind::Match { scrutinee, arms } => {
+                    self.generate_match(*scrutinee, arms, &ArmAction::Discard);
+                }
+                ExpressionKind::Assignment { target, value } if matches!(value.kind, ExpressionKind::Match { .. }) => {
+                    if let ExpressionKind::Match { scrutinee, arms } = value.kind {
+                        let ty = self.expr_type(&target);
+                        let target = self.expr_to_c(*target);
+                        self.generate_match(*scrutinee, arms, &ArmAction::Assign(target, ty));
+                    }
+                }
+                kind => {
+                    let expr = Expression::new(kind, expr.span);
+                    let ty = self.expr_type(&expr).filter(|ty| self.needs_drop(ty));
+                    let c_expr = self.expr_to_c(expr);
+                    match ty {
+                        // A value nothing takes is dropped at once
+                        Some(ty) => {
+                            let drop = self.drop_function(&ty);
+                            self.emit(&format!("{}(({}[]){{ {} }});", drop, self.type_to_c(&ty), c_expr));
+                        }
+                        None => self.emit(&format!("{};", c_expr)),
+                    }
+                }
+            },
+            StatementKind::Return(expr) => {
+                if let ExpressionKind::Match { scrutinee, arms } = expr.kind {
+                    self.generate_match(*scrutinee, arms, &ArmAction::Return);
+                } else {
+                    self.generate_return(expr);
+                }
             }
-            Statement::If { condition, then_branch, else_branch } => {
+            StatementKind::If { condition, then_branch, else_branch } => {
                 let cond = self.expr_to_c(condition);
                 self.emit(&format!("if ({}) {{", cond));
                 self.indent_level += 1;
-                for stmt in then_branch {
-                    self.generate_statement(stmt);
-                }
+                self.generate_block(then_branch);
                 self.indent_level -= 1;
                 if let Some(else_stmts) = else_branch {
                     self.emit("} else {");
                     self.indent_level += 1;
-                    for stmt in else_stmts {
-                        self.generate_statement(stmt);
-                    }
+                    self.generate_block(else_stmts);
                     self.indent_level -= 1;
                     self.emit("}");
                 } else {
                     self.emit("}");
                 }
             }
-            Statement::While { condition, body } => {
+            StatementKind::While { condition, body } => {
                 let cond = self.expr_to_c(condition);
                 self.emit(&format!("while ({}) {{", cond));
                 self.indent_level += 1;
-                for stmt in body {
-                    self.generate_statement(stmt);
-                }
+                self.loop_drops.push(self.drops.len());
+                self.generate_block(body);
+                self.loop_drops.pop();
                 self.indent_level -= 1;
                 self.emit("}");
             }
-            Statement::For { variable, iterable, body } => {
-                let iter = self.expr_to_c(iterable);
-                self.emit(&format!("for (int {} = 0; {} < {}; {}++) {{", variable, variable, iter, variable));
+            StatementKind::For { variable, iterable, body } => {
+                self.generate_for(variable, iterable, body);
+            }
+            // Leaving a loop body drops what it owns
+            StatementKind::Break => {
+                self.emit_drops(self.drops.len() - self.loop_drops.last().copied().unwrap_or_default(), None);
+                self.emit("break;");
+            }
+            StatementKind::Continue => {
+                self.emit_drops(self.drops.len() - self.loop_drops.last().copied().unwrap_or_default(), None);
+                self.emit("continue;");
+            }
+            StatementKind::Unsafe { body } => {
+                // Unsafe blocks: generate C code directly without additional wrapping
+                self.unsafe_depth += 1;
+                self.generate_statements(body);
+                self.unsafe_depth -= 1;
+            }
+            StatementKind::Error => {
+                // Syntax errors stop the build before codegen
+            }
+        }
+    }
+
+    /// Generate a block, which owns the values of the locals it declares
+    /// and drops them at its end
+    fn generate_block(&mut self, body: Vec<Statement>) {
+        self.generate_block_with(Vec::new(), body);
+    }
+
+    /// Generate a block that also owns `locals` (parameters or a loop
+    /// variable), each with whether the block may move it out
+    fn generate_block_with(&mut self, locals: Vec<(bool, String, Type)>, body: Vec<Statement>) {
+        self.drops.push(Vec::new());
+        for (moved, name, ty) in locals {
+            self.own(&name, &ty, moved, true);
+        }
+        // A block that ends in a jump has dropped its values there
+        let falls_through = !matches!(
+            body.last().map(|stmt| &stmt.kind),
+            Some(StatementKind::Return(_) | StatementKind::Break | StatementKind::Continue)
+        );
+        self.generate_statements(body);
+        if falls_through {
+            self.emit_drops(1, None);
+        }
+        self.drops.pop();
+    }
+
+    /// Generate statements, making the innermost scope own the value of
+    /// each local they declare
+    fn generate_statements(&mut self, body: Vec<Statement>) {
+        let mut body = body.into_iter();
+        while let Some(stmt) = body.next() {
+            let declared = match &stmt.kind {
+                StatementKind::Let { name, value, .. } => Some((name.clone(), value.is_some())),
+                _ => None,
+            };
+            self.generate_statement(stmt);
+            let Some((name, initialized)) = declared else {
+                continue;
+            };
+            let Some(ty) = self.locals.get(&name).cloned() else {
+                continue;
+            };
+            // An unsafe block shares the enclosing C block, whose later
+            // statements may move the value too
+            let moved = self.unsafe_depth > 0 || moved_in(&name, body.as_slice());
+            self.own(&name, &ty, moved, initialized);
+        }
+    }
+
+    /// Make the innermost scope own the local `name`, if values of `ty`
+    /// need dropping. One that may be moved out, or is not `initialized`
+    /// yet, gets a flag saying whether it holds its value.
+    fn own(&mut self, name: &str, ty: &Type, moved: bool, initialized: bool) {
+        if !self.needs_drop(ty) || self.drops.is_empty() {
+            return;
+        }
+        let flag = (moved || !initialized).then(|| format!("_live_{}", name));
+        if let Some(flag) = &flag {
+            self.emit(&format!("int {} = {};", flag, initialized as i32));
+        }
+        if let Some(scope) = self.drops.last_mut() {
+            scope.push(Owned { name: name.to_string(), ty: ty.clone(), flag });
+        }
+    }
+
+    /// The owned local `name` in scope, unless a local that needs no
+    /// dropping shadows it
+    fn owned(&self, name: &str) -> Option<Owned> {
+        if !self.locals.get(name).is_some_and(|ty| self.needs_drop(ty)) {
+            return None;
+        }
+        self.drops.iter().rev().flat_map(|scope| scope.iter().rev()).find(|owned| owned.name == name).cloned()
+    }
+
+    /// Drop the values owned by the innermost `scopes` scopes, latest
+    /// first, except that of `kept`, which is being returned
+    fn emit_drops(&mut self, scopes: usize, kept: Option<&str>) {
+        let statements: Vec<String> = self
+            .drops
+            .iter()
+            .rev()
+            .take(scopes)
+            .flat_map(|scope| scope.iter().rev())
+            .filter(|owned| Some(owned.name.as_str()) != kept)
+            .map(|owned| self.drop_statement(owned))
+            .collect();
+        for statement in statements {
+            self.emit(&statement);
+        }
+    }
+
+    /// C statement dropping the value of `owned`, if it still holds it
+    fn drop_statement(&self, owned: &Owned) -> String {
+        let call = format!("{}(&{});", self.drop_function(&owned.ty), owned.name);
+        match &owned.flag {
+            Some(flag) => format!("if ({}) {}", flag, call),
+            None => call,
+        }
+    }
+
+    /// Return the value of `expr` after dropping every value the function
+    /// owns, other than a local returned as it is
+    fn generate_return(&mut self, expr: Expression) {
+        let kept = match &expr.kind {
+            ExpressionKind::Identifier(name) => Some(name.clone()),
+            _ => None,
+        };
+        let owns = self.drops.iter().any(|scope| !scope.is_empty());
+        if self.returns_void {
+            let c_expr = self.expr_to_c(expr);
+            self.emit(&format!("{};", c_expr));
+            self.emit_drops(self.drops.len(), kept.as_deref());
+            self.emit("return;");
+        } else if let (true, Some(ty)) = (owns, self.return_type.clone()) {
+            // The value may use what is dropped, so it is worked out first
+            let c_expr = self.value_to_c(expr, Some(&ty));
+            self.emit("{");
+            self.indent_level += 1;
+            self.emit(&format!("{} _r = {};", self.type_to_c(&ty), c_expr));
+            self.emit_drops(self.drops.len(), kept.as_deref());
+            self.emit("return _r;");
+            self.indent_level -= 1;
+            self.emit("}");
+        } else {
+            // A function declared without a result (or `main`) returns a
+            // plain status value
+            self.emit_drops(self.drops.len(), kept.as_deref());
+            let c_expr = self.value_to_c(expr, self.return_type.as_ref());
+            self.emit(&format!("return {};", c_expr));
+        }
+    }
+
+    /// Whether values of `ty` need dropping: those of types with an impl
+    /// of `Drop`, and those holding such values. Slices and references
+    /// only view values, and closures and `dyn` values do not own theirs.
+    fn needs_drop(&self, ty: &Type) -> bool {
+        match ty {
+            Type::Custom(name) | Type::Generic { name, .. } => {
+                if self.traits.implements(name, "Drop") {
+                    return true;
+                }
+                if let Some(def) = self.structs.get(name) {
+                    return self.fields_of(def, ty).iter().any(|(_, field)| self.needs_drop(field));
+                }
+                self.enums.get(name).is_some_and(|e| {
+                    e.variants.iter().any(|v| v.payload.fields().into_iter().any(|(_, field)| self.needs_drop(field)))
+                })
+            }
+            Type::Option(inner) | Type::Array(inner, _) => self.needs_drop(inner),
+            Type::Result(ok, err) => self.needs_drop(ok) || self.needs_drop(err),
+            Type::Tuple(items) => items.iter().any(|item| self.needs_drop(item)),
+            // A closure may own its environment
+            Type::FunctionPointer { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Fields of the struct `def`, with its type parameters replaced by
+    /// the arguments in `ty`
+    fn fields_of(&self, def: &TypeDef, ty: &Type) -> Vec<(String, Type)> {
+        let type_args = match ty {
+            Type::Generic { type_args, .. } => type_args.as_slice(),
+            _ => &[],
+        };
+        let bindings = def.type_params.iter().map(String::as_str).zip(type_args).collect();
+        def.fields.iter().map(|(name, field)| (name.clone(), field.substitute(&bindings))).collect()
+    }
+
+    /// Name of the C function that drops a value of type `ty` through a
+    /// pointer, defined on first use: it calls the type's `Drop::drop`,
+    /// then drops the values the value holds
+    fn drop_function(&self, ty: &Type) -> String {
+        let name = format!("u_drop_{}", mangle(ty));
+        if !self.defined.borrow_mut().insert(name.clone()) {
+            return name;
+        }
+        let mut body = Vec::new();
+        match ty {
+            Type::Custom(type_name) | Type::Generic { name: type_name, .. } => {
+                if self.traits.implements(type_name, "Drop") {
+                    body.push(format!("{}(value);", method_name(type_name, "Drop", "drop")));
+                }
+                if let Some(def) = self.structs.get(type_name) {
+                    for (field, field_ty) in self.fields_of(def, ty) {
+                        if self.needs_drop(&field_ty) {
+                            body.push(format!("{}(&value->{});", self.drop_function(&field_ty), field));
+                        }
+                    }
+                }
+                if let Some(e) = self.enums.get(type_name) {
+                    let mut cases = Vec::new();
+                    for variant in &e.variants {
+                        let drops: Vec<String> = variant
+                            .payload
+                            .fields()
+                            .into_iter()
+                            .filter(|(_, field_ty)| self.needs_drop(field_ty))
+                            .map(|(field, field_ty)| {
+                                let path = format!("value->as.{}.{}", variant.name, c_field_name(&field));
+                                format!("{}(&{});", self.drop_function(field_ty), path)
+                            })
+                            .collect();
+                        if !drops.is_empty() {
+                            cases.push(format!("case {}: {} break;", self.tag(type_name, &variant.name), drops.join(" ")));
+                        }
+                    }
+                    if !cases.is_empty() {
+                        body.push(format!("switch (value->tag) {{ {} default: break; }}", cases.join(" ")));
+                    }
+                }
+            }
+            Type::Option(inner) => body.push(format!("if (value->is_some) {}(&value->value);", self.drop_function(inner))),
+            Type::Result(ok, err) => {
+                if self.needs_drop(ok) {
+                    body.push(format!("if (value->is_ok) {}(&value->value);", self.drop_function(ok)));
+                }
+                if self.needs_drop(err) {
+                    body.push(format!("if (!value->is_ok) {}(&value->error);", self.drop_function(err)));
+                }
+            }
+            Type::Array(element, len) => body.push(format!(
+                "for (int i = 0; i < {}; i++) {}(&value->data[i]);",
+                len,
+                self.drop_function(element)
+            )),
+            Type::Tuple(items) => {
+                for (i, item) in items.iter().enumerate().filter(|(_, item)| self.needs_drop(item)) {
+                    body.push(format!("{}(&value->_{});", self.drop_function(item), i));
+                }
+            }
+            Type::FunctionPointer { .. } => body.push("if (value->drop) value->drop(value->env);".to_string()),
+            _ => {}
+        }
+        let mut out = format!("static void {}({}* value) {{\n", name, self.type_to_c(ty));
+        for line in body {
+            writeln!(out, "    {}", line).unwrap();
+        }
+        out.push_str("}\n\n");
+        self.closures.borrow_mut().push_str(&out);
+        name
+    }
+
+    /// Lower a `for` loop. The iterable is evaluated once into a temporary,
+    /// which is then indexed (arrays and slices), stepped through with its
+    /// `_next` helper (ranges) or advanced with `Iterator::next`.
+    fn generate_for(&mut self, variable: String, iterable: Expression, body: Vec<Statement>) {
+        let temp = format!("_f{}", self.loop_count);
+        self.loop_count += 1;
+        let ty = self.expr_type(&iterable).unwrap_or(Type::Range(Box::new(Type::I32)));
+        let item = match &ty {
+            Type::Range(item) | Type::Array(item, _) | Type::Slice(item) => (**item).clone(),
+            Type::Custom(name) => self.iterators.get(name).cloned().unwrap_or(Type::I32),
+            _ => Type::I32,
+        };
+        let item_c = self.type_to_c(&item);
+        let value = self.value_to_c(iterable, None);
+
+        self.emit("{");
+        self.indent_level += 1;
+        self.emit(&format!("{} {} = {};", self.type_to_c(&ty), temp, value));
+        // An iterator is dropped after the loop; the items of an array
+        // are moved out one by one
+        self.drops.push(Vec::new());
+        if matches!(ty, Type::Custom(_)) {
+            self.own(&temp, &ty, false, true);
+        }
+        match &ty {
+            Type::Array(..) | Type::Slice(_) => {
+                let (data, len) = match &ty {
+                    Type::Array(_, len) => (format!("{}.data", temp), len.to_string()),
+                    _ => (format!("{}.ptr", temp), format!("{}.len", temp)),
+                };
+                self.emit(&format!("for (int {t}_i = 0; {t}_i < {}; {t}_i++) {{", len, t = temp));
+                self.emit(&format!("    {} {} = {}[{}_i];", item_c, variable, data, temp));
+            }
+            Type::Custom(name) => {
+                let option = self.type_to_c(&Type::Option(Box::new(item.clone())));
+                let next = method_name(name, "Iterator", "next");
+                self.emit(&format!("{} {}_item;", option, temp));
+                self.emit(&format!("while (({t}_item = {}(&{t})).is_some) {{", next, t = temp));
+                self.emit(&format!("    {} {} = {}_item.value;", item_c, variable, temp));
+            }
+            _ => {
+                self.emit(&format!("{} {};", item_c, variable));
+                self.emit(&format!("while ({}_next(&{}, &{})) {{", self.type_to_c(&ty), temp, variable));
+            }
+        }
+        self.locals.insert(variable.clone(), item.clone());
+        self.indent_level += 1;
+        self.loop_drops.push(self.drops.len());
+        let moved = moved_in(&variable, &body);
+        self.generate_block_with(vec![(moved, variable, item)], body);
+        self.loop_drops.pop();
+        self.indent_level -= 1;
+        self.emit("}");
+        self.emit_drops(1, None);
+        self.drops.pop();
+        self.indent_level -= 1;
+        self.emit("}");
+    }
+
+    /// Lower a `match` in one of the statement positions the type checker
+    /// allows. The scrutinee is evaluated once into a temporary; arms over
+    /// an enum without guards or nested tests become a `switch` on its tag,
+    /// anything else an `if` chain. An arm that binds a value needing
+    /// drop takes the scrutinee's value from the local holding it: its
+    /// variables own what they bind, and the rest is dropped.
+    fn generate_match(&mut self, scrutinee: Expression, arms: Vec<MatchArm>, action: &ArmAction) {
+        let temp = format!("_m{}", self.match_count);
+        self.match_count += 1;
+        let ty = self.expr_type(&scrutinee);
+        let owner_flag = match &scrutinee.kind {
+            ExpressionKind::Identifier(name) => self.owned(name).and_then(|owned| owned.flag),
+            _ => None,
+        };
+        let c_type = self.expr_type_to_c(&scrutinee);
+        let c_value = self.expr_to_c(scrutinee);
+
+        self.emit("{");
+        self.indent_level += 1;
+        self.emit(&format!("{} {} = {};", c_type, temp, c_value));
+
+        let lowered: Vec<_> = arms
+            .iter()
+            .map(|arm| {
+                let mut tests = Vec::new();
+                let mut binds = Vec::new();
+                self.pattern_to_c(&arm.pattern, &temp, ty.as_ref(), &mut tests, &mut binds);
+                (tests, binds)
+            })
+            .collect();
+
+        let enum_name = match &ty {
+            Some(Type::Custom(name)) if self.enums.contains_key(name) => Some(name.clone()),
+            _ => None,
+        };
+        let use_switch = enum_name.is_some()
+            && arms.iter().all(|arm| arm.guard.is_none() && !arm_breaks(arm))
+            && lowered.iter().all(|(tests, _)| tests.len() <= 1);
+        let guarded = arms.iter().any(|arm| arm.guard.is_some());
+        // When every arm returns, reaching the last one means no guarded
+        // arm matched, so it needs no flag
+        let all_return = matches!(action, ArmAction::Return)
+            && arms.iter().all(|arm| matches!(arm.body, ArmBody::Expression(_)));
+        let done = format!("{}_done", temp);
+        if guarded {
+            self.emit(&format!("int {} = 0;", done));
+        }
+
+        if use_switch {
+            self.emit(&format!("switch ({}.tag) {{", temp));
+        }
+        let last = arms.len().saturating_sub(1);
+        for (i, (arm, (tests, binds))) in arms.into_iter().zip(lowered).enumerate() {
+            // The type checker proved the match exhaustive, so the last arm
+            // needs no test of its own
+            let tests = if i == last && arm.guard.is_none() { Vec::new() } else { tests };
+            let condition = tests.join(" && ");
+            if use_switch {
+                let label = match &arm.pattern.kind {
+                    PatternKind::Variant { variant, .. } if i != last => {
+                        format!("case {}:", self.tag(enum_name.as_deref().unwrap_or_default(), variant))
+                    }
+                    _ => "default:".to_string(),
+                };
+                self.emit(&format!("{} {{", label));
+            } else if guarded && i == last && all_return {
+                self.emit("{");
+            } else if guarded {
+                let condition = if condition.is_empty() {
+                    format!("!{}", done)
+                } else {
+                    format!("!{} && {}", done, condition)
+                };
+                self.emit(&format!("if ({}) {{", condition));
+            } else if i == 0 {
+                self.emit(&format!("if ({}) {{", if condition.is_empty() { "1" } else { &condition }));
+            } else if condition.is_empty() {
+                self.emit("} else {");
+            } else {
+                self.emit(&format!("}} else if ({}) {{", condition));
+            }
+            self.indent_level += 1;
+
+            let taken: Vec<(String, Type)> = match owner_flag {
+                Some(_) => binds.iter().filter(|(_, ty, _)| self.needs_drop(ty)).map(|(name, ty, _)| (name.clone(), ty.clone())).collect(),
+                None => Vec::new(),
+            };
+            let mut unbound = Vec::new();
+            if !taken.is_empty() {
+                self.unbound_drops(&arm.pattern, &temp, ty.as_ref(), &mut unbound);
+            }
+            for (name, ty, path) in binds {
+                self.emit(&format!("{} {} = {};", self.type_to_c(&ty), name, path));
+                self.locals.insert(name, ty);
+            }
+            let has_guard = arm.guard.is_some();
+            if let Some(guard) = arm.guard {
+                let guard = self.expr_to_c(guard);
+                self.emit(&format!("if ({}) {{", guard));
                 self.indent_level += 1;
-                for stmt in body {
-                    self.generate_statement(stmt);
+                self.emit(&format!("{} = 1;", done));
+            } else if guarded {
+                self.emit(&format!("{} = 1;", done));
+            }
+            let returns = matches!(action, ArmAction::Return) && matches!(arm.body, ArmBody::Expression(_));
+            let falls_through = match &arm.body {
+                ArmBody::Expression(_) => !returns,
+                ArmBody::Block(body) => !matches!(
+                    body.last().map(|stmt| &stmt.kind),
+                    Some(StatementKind::Return(_) | StatementKind::Break | StatementKind::Continue)
+                ),
+            };
+            if let (Some(flag), false) = (&owner_flag, taken.is_empty()) {
+                self.emit(&format!("{} = 0;", flag));
+                for statement in unbound {
+                    self.emit(&statement);
+                }
+                self.drops.push(Vec::new());
+                for (name, ty) in &taken {
+                    // An arm's value may be one of them, moved where it
+                    // goes; one returned as it is is just not dropped
+                    let moved = match &arm.body {
+                        ArmBody::Expression(expr) => {
+                            !returns || !matches!(&expr.kind, ExpressionKind::Identifier(value) if value == name)
+                        }
+                        ArmBody::Block(body) => moved_in(name, body),
+                    };
+                    self.own(name, ty, moved, true);
                 }
+            }
+            match arm.body {
+                ArmBody::Expression(expr) => self.generate_arm_value(expr, action),
+                ArmBody::Block(body) => self.generate_block(body),
+            }
+            if !taken.is_empty() {
+                if falls_through {
+                    self.emit_drops(1, None);
+                }
+                self.drops.pop();
+            }
+            if has_guard {
                 self.indent_level -= 1;
                 self.emit("}");
             }
-            Statement::Break => {
+            if use_switch && !returns {
                 self.emit("break;");
             }
-            Statement::Continue => {
-                self.emit("continue;");
+            self.indent_level -= 1;
+            // Cases and guarded arms close themselves; a plain chain is
+            // closed by the next `else` or after the loop
+            if use_switch || guarded {
+                self.emit("}");
             }
-            Statement::Unsafe { body } => {
-                // Unsafe blocks: generate C code directly without additional wrapping
-                for stmt in body {
-                    self.generate_statement(stmt);
+        }
+        if use_switch || !guarded {
+            self.emit("}");
+        }
+
+        self.indent_level -= 1;
+        self.emit("}");
+    }
+
+    /// Emit what an arm does with its value
+    fn generate_arm_value(&mut self, expr: Expression, action: &ArmAction) {
+        if let ExpressionKind::Match { scrutinee, arms } = expr.kind {
+            self.generate_match(*scrutinee, arms, action);
+            return;
+        }
+        match action {
+            ArmAction::Discard => {
+                let c_expr = self.expr_to_c(expr);
+                self.emit(&format!("{};", c_expr));
+            }
+            ArmAction::Assign(target, ty) => {
+                let c_expr = self.value_to_c(expr, ty.as_ref());
+                self.emit(&format!("{} = {};", target, c_expr));
+            }
+            ArmAction::Return => self.generate_return(expr),
+        }
+    }
+
+    /// Collect the C conditions under which `pattern` matches the value at
+    /// `path`, and the `(name, type, path)` of each variable it binds
+    fn pattern_to_c(
+        &self,
+        pattern: &Pattern,
+        path: &str,
+        ty: Option<&Type>,
+        tests: &mut Vec<String>,
+        binds: &mut Vec<(String, Type, String)>,
+    ) {
+        match &pattern.kind {
+            PatternKind::Wildcard => {}
+            PatternKind::Binding(name) => {
+                binds.push((name.clone(), ty.cloned().unwrap_or(Type::I32), path.to_string()));
+            }
+            PatternKind::Literal(Literal::String(s)) => {
+                tests.push(format!("strcmp({}, {}) == 0", path, c_string_literal(s)));
+            }
+            PatternKind::Literal(Literal::Boolean(true)) => tests.push(path.to_string()),
+            PatternKind::Literal(Literal::Boolean(false)) => tests.push(format!("!{}", path)),
+            PatternKind::Literal(lit) => {
+                let value = self.expr_to_c(Expression::new(ExpressionKind::Literal(lit.clone()), pattern.span));
+                tests.push(format!("{} == {}", path, value));
+            }
+            PatternKind::Tuple(items) => {
+                let types = match ty {
+                    Some(Type::Tuple(types)) => types.as_slice(),
+                    _ => &[],
+                };
+                for (i, item) in items.iter().enumerate() {
+                    self.pattern_to_c(item, &format!("{}._{}", path, i), types.get(i), tests, binds);
+                }
+            }
+            PatternKind::Struct { name, fields, .. } => {
+                let declared = self.structs.get(name).map(|s| s.fields.as_slice()).unwrap_or_default();
+                for (field, item) in fields {
+                    let field_ty = declared.iter().find(|(n, _)| n == field).map(|(_, t)| t);
+                    self.pattern_to_c(item, &format!("{}.{}", path, field), field_ty, tests, binds);
+                }
+            }
+            PatternKind::Variant { enum_name: Some(enum_name), variant, payload, .. } => {
+                tests.push(format!("{}.tag == {}", path, self.tag(enum_name, variant)));
+                let declared = self
+                    .enums
+                    .get(enum_name)
+                    .and_then(|e| e.variants.iter().find(|v| &v.name == variant))
+                    .map(|v| v.payload.fields())
+                    .unwrap_or_default();
+                for (field, item) in payload.fields() {
+                    let field_ty = declared.iter().find(|(n, _)| *n == field).map(|(_, t)| *t);
+                    let field_path = format!("{}.as.{}.{}", path, variant, c_field_name(&field));
+                    self.pattern_to_c(item, &field_path, field_ty, tests, binds);
+                }
+            }
+            PatternKind::Variant { enum_name: None, variant, payload, .. } => {
+                let (test, field, field_ty) = match (variant.as_str(), ty) {
+                    ("Some", Some(Type::Option(inner))) => (format!("{}.is_some", path), "value", Some(&**inner)),
+                    ("Some", _) => (format!("{}.is_some", path), "value", None),
+                    ("None", _) => (format!("!{}.is_some", path), "value", None),
+                    ("Ok", Some(Type::Result(ok, _))) => (format!("{}.is_ok", path), "value", Some(&**ok)),
+                    ("Ok", _) => (format!("{}.is_ok", path), "value", None),
+                    ("Err", Some(Type::Result(_, err))) => (format!("!{}.is_ok", path), "error", Some(&**err)),
+                    _ => (format!("!{}.is_ok", path), "error", None),
+                };
+                tests.push(test);
+                for (_, item) in payload.fields() {
+                    self.pattern_to_c(item, &format!("{}.{}", path, field), field_ty, tests, binds);
+                }
+            }
+        }
+    }
+
+    /// Collect statements dropping the parts of the value at `path` that
+    /// `pattern` leaves unbound, once the variables it binds have taken
+    /// the rest out of it
+    fn unbound_drops(&self, pattern: &Pattern, path: &str, ty: Option<&Type>, drops: &mut Vec<String>) {
+        // A field the pattern leaves out is matched as by `_`
+        let wildcard = Pattern { kind: PatternKind::Wildcard, span: pattern.span };
+        match &pattern.kind {
+            PatternKind::Binding(_) => {}
+            PatternKind::Wildcard | PatternKind::Literal(_) => {
+                if let Some(ty) = ty.filter(|ty| self.needs_drop(ty)) {
+                    drops.push(format!("{}(&{});", self.drop_function(ty), path));
+                }
+            }
+            PatternKind::Tuple(items) => {
+                let types = match ty {
+                    Some(Type::Tuple(types)) => types.as_slice(),
+                    _ => &[],
+                };
+                for (i, item) in items.iter().enumerate() {
+                    self.unbound_drops(item, &format!("{}._{}", path, i), types.get(i), drops);
+                }
+            }
+            PatternKind::Struct { name, fields, .. } => {
+                let declared = self.structs.get(name).map(|s| s.fields.as_slice()).unwrap_or_default();
+                for (field, field_ty) in declared {
+                    let item = fields.iter().find(|(name, _)| name == field).map_or(&wildcard, |(_, item)| item);
+                    self.unbound_drops(item, &format!("{}.{}", path, field), Some(field_ty), drops);
+                }
+            }
+            PatternKind::Variant { enum_name: Some(enum_name), variant, payload, .. } => {
+                let declared = self
+                    .enums
+                    .get(enum_name)
+                    .and_then(|e| e.variants.iter().find(|v| &v.name == variant))
+                    .map(|v| v.payload.fields())
+                    .unwrap_or_default();
+                let items = payload.fields();
+                for (field, field_ty) in declared {
+                    let item = items.iter().find(|(name, _)| *name == field).map_or(&wildcard, |(_, item)| *item);
+                    let field_path = format!("{}.as.{}.{}", path, variant, c_field_name(&field));
+                    self.unbound_drops(item, &field_path, Some(field_ty), drops);
+                }
+            }
+            PatternKind::Variant { enum_name: None, variant, payload, .. } => {
+                let (field, field_ty) = match (variant.as_str(), ty) {
+                    ("Some", Some(Type::Option(inner))) | ("Ok", Some(Type::Result(inner, _))) => ("value", Some(&**inner)),
+                    ("Err", Some(Type::Result(_, err))) => ("error", Some(&**err)),
+                    _ => ("value", None),
+                };
+                for (_, item) in payload.fields() {
+                    self.unbound_drops(item, &format!("{}.{}", path, field), field_ty, drops);
                 }
             }
         }
     }
 
+    /// The type of `expr` where it can be worked out from literals, locals
+    /// and signatures
+    fn expr_type(&self, expr: &Expression) -> Option<Type> {
+        match &expr.kind {
+            ExpressionKind::Literal(lit) => Some(lit.ty()),
+            ExpressionKind::Identifier(name) => self.locals.get(name).cloned(),
+            ExpressionKind::FunctionCall { name, .. } if self.locals.contains_key(name) => match &self.locals[name] {
+                Type::FunctionPointer { return_type, .. } => return_type.as_deref().cloned(),
+                _ => None,
+            },
+            ExpressionKind::FunctionCall { name, arguments } => match self.functions.get(name) {
+                Some((_, ret)) => ret.clone(),
+                None if name == "Some" && arguments.len() == 1 => {
+                    let inner = self.expr_type(&arguments[0]).unwrap_or(Type::I32);
+                    Some(Type::Option(Box::new(inner)))
+                }
+                None => None,
+            },
+            ExpressionKind::Binary { left, operator, .. } => match operator {
+                BinaryOp::Add | BinaryOp::Subtract | BinaryOp::Multiply | BinaryOp::Divide | BinaryOp::Modulo => {
+                    self.expr_type(left)
+                }
+                _ => Some(Type::Bool),
+            },
+            ExpressionKind::Unary { operator: UnaryOp::Not, .. } => Some(Type::Bool),
+            ExpressionKind::Unary { operator: UnaryOp::Borrow, operand } => Some(Type::Ref(Box::new(self.expr_type(operand)?))),
+            ExpressionKind::Unary { operator: UnaryOp::Deref, operand } => match self.expr_type(operand)? {
+                Type::Ref(inner) => Some(*inner),
+                _ => None,
+            },
+            ExpressionKind::Unary { operand, .. } => self.expr_type(operand),
+            ExpressionKind::EnumVariant { enum_name, .. } => Some(Type::Custom(enum_name.clone())),
+            ExpressionKind::Tuple(items) => Some(Type::Tuple(
+                items.iter().map(|item| self.expr_type(item).unwrap_or(Type::I32)).collect(),
+            )),
+            // Arms usually agree; the first one whose type is known decides
+            ExpressionKind::Match { arms, .. } => arms.iter().find_map(|arm| match &arm.body {
+                ArmBody::Expression(expr) => self.expr_type(expr),
+                ArmBody::Block(_) => None,
+            }),
+            ExpressionKind::StructLiteral { name, type_args, .. } if type_args.is_empty() => Some(Type::Custom(name.clone())),
+            ExpressionKind::StructLiteral { name, type_args, .. } => Some(Type::Generic {
+                name: name.clone(),
+                type_args: type_args.clone(),
+            }),
+            ExpressionKind::FieldAccess { object, field } => {
+                let (name, type_args) = match referent(&self.expr_type(object)?).clone() {
+                    Type::Custom(name) => (name, Vec::new()),
+                    Type::Generic { name, type_args } => (name, type_args),
+                    _ => return None,
+                };
+                let def = self.structs.get(&name)?;
+                let (_, ty) = def.fields.iter().find(|(f, _)| f == field)?;
+                let bindings = def.type_params.iter().map(String::as_str).zip(&type_args).collect();
+                Some(ty.substitute(&bindings))
+            }
+            ExpressionKind::ArrayLiteral(elements) => {
+                let element = elements.first().and_then(|e| self.expr_type(e)).unwrap_or(Type::I32);
+                Some(Type::Array(Box::new(element), elements.len()))
+            }
+            ExpressionKind::Index { object, .. } => match referent(&self.expr_type(object)?).clone() {
+                Type::Array(element, _) | Type::Slice(element) => Some(*element),
+                _ => None,
+            },
+            ExpressionKind::Range { start, end, .. } => Some(Type::Range(Box::new(self.range_item(start, end)))),
+            ExpressionKind::Closure { params, return_type, body, .. } => Some(Type::FunctionPointer {
+                params: params.iter().map(|(_, ty)| ty.clone()).collect(),
+                return_type: self.closure_return(params, return_type, body).map(Box::new),
+            }),
+            ExpressionKind::MethodCall { receiver, method, .. } => {
+                let receiver = self.expr_type(receiver)?;
+                match self.dispatch(&receiver, method) {
+                    Some((_, signature)) => signature.return_type,
+                    None if method == "clone" => Some(referent(&receiver).clone()),
+                    None if method == "len" => Some(Type::I32),
+                    None => None,
+                }
+            }
+            ExpressionKind::Assignment { .. } => None,
+        }
+    }
+
+    /// Return type of a closure: the declared one, or else that of its
+    /// final value, if it has one
+    fn closure_return(&self, params: &[(String, Type)], return_type: &Option<Type>, body: &[Statement]) -> Option<Type> {
+        if return_type.is_some() {
+            return return_type.clone();
+        }
+        let Some(StatementKind::Return(value)) = body.last().map(|stmt| &stmt.kind) else {
+            return None;
+        };
+        // The value may use the closure's own locals
+        let mut scope = self.closure_generator();
+        scope.locals = self.locals.clone();
+        scope.locals.extend(params.iter().cloned());
+        for stmt in body {
+            if let StatementKind::Let { name, ty, value, .. } = &stmt.kind {
+                if let Some(ty) = ty.clone().or_else(|| value.as_ref().and_then(|value| scope.expr_type(value))) {
+                    scope.locals.insert(name.clone(), ty);
+                }
+            }
+        }
+        if scope.returns_nothing(value) {
+            None
+        } else {
+            Some(scope.expr_type(value).unwrap_or(Type::I32))
+        }
+    }
+
+    /// Whether `expr` is a call or an assignment that produces no value,
+    /// which a closure ending in it does not return
+    fn returns_nothing(&self, expr: &Expression) -> bool {
+        match &expr.kind {
+            ExpressionKind::Assignment { .. } => true,
+            ExpressionKind::FunctionCall { name, .. } => match self.locals.get(name) {
+                Some(ty) => matches!(ty, Type::FunctionPointer { return_type: None, .. }),
+                None => match self.functions.get(name) {
+                    Some((_, ret)) => ret.is_none(),
+                    None => name == "print",
+                },
+            },
+            _ => false,
+        }
+    }
+
+    /// Lower a closure to a function that takes its environment first, and
+    /// evaluate to the `{ call, env }` pair for it. The environment holds a
+    /// pointer to each borrowed capture and a copy of each moved one:
+    ///
+    /// ```c
+    /// typedef struct { int* total; } _closure0_env;
+    /// int _closure0(void* _env, int x) {
+    ///     _closure0_env* env = _env;
+    ///     return (x + (*env->total));
+    /// }
+    /// Fn1_i32_i32 _closure0_new(int* total) { ... }
+    /// ```
+    fn closure_to_c(
+        &self,
+        params: Vec<(String, Type)>,
+        return_type: Option<Type>,
+        body: Vec<Statement>,
+        by_move: bool,
+    ) -> String {
+        let id = self.closure_count.get();
+        self.closure_count.set(id + 1);
+        let name = format!("_closure{}", id);
+        let ret = self.closure_return(&params, &return_type, &body);
+        let fn_type = self.type_to_c(&Type::FunctionPointer {
+            params: params.iter().map(|(_, ty)| ty.clone()).collect(),
+            return_type: ret.clone().map(Box::new),
+        });
+
+        let mut generator = self.closure_generator();
+        generator.locals.extend(params.iter().cloned());
+        generator.return_type = ret.clone();
+        generator.returns_void = ret.is_none();
+        let mut names = Vec::new();
+        let mut fields = Vec::new();
+        let mut values = Vec::new();
+        let mut env_params = Vec::new();
+        let mut env_drops = Vec::new();
+        for capture in captures(&params, &body) {
+            let Some(ty) = self.locals.get(&capture.name) else {
+                continue;
+            };
+            let by_value = by_move || capture.consumed;
+            let c_type = if by_value {
+                self.type_to_c(ty)
+            } else {
+                format!("{}*", self.type_to_c(ty))
+            };
+            let value = Expression::new(ExpressionKind::Identifier(capture.name.clone()), capture.span);
+            values.push(if by_value { self.value_to_c(value, None) } else { format!("&{}", self.expr_to_c(value)) });
+            // The environment owns what is moved into it, which the body
+            // cannot move out again
+            if by_value && self.needs_drop(ty) {
+                env_drops.push(format!("{}(&env->{});", self.drop_function(ty), capture.name));
+            }
+            fields.push(format!("{} {};", c_type, capture.name));
+            env_params.push(format!("{} {}", c_type, capture.name));
+            names.push(capture.name.clone());
+            generator.locals.insert(capture.name.clone(), ty.clone());
+            generator.captured.insert(capture.name, by_value);
+        }
+        if !generator.captured.is_empty() {
+            generator.emit(&format!("{}_env* env = _env;", name));
+        }
+        generator.generate_block(body);
+
+        let ret_c = ret.as_ref().map_or_else(|| "void".to_string(), |ty| self.type_to_c(ty));
+        let params_c: String = params
+            .iter()
+            .map(|(param, ty)| format!(", {} {}", self.type_to_c(ty), param))
+            .collect();
+        let mut out = String::new();
+        if !fields.is_empty() {
+            writeln!(out, "typedef struct {{ {} }} {}_env;", fields.join(" "), name).unwrap();
+        }
+        writeln!(out, "{} {}(void* _env{}) {{", ret_c, name, params_c).unwrap();
+        out.push_str(&generator.output);
+        out.push_str("}\n\n");
+        if fields.is_empty() {
+            self.closures.borrow_mut().push_str(&out);
+            return format!("(({}){{ {}, NULL, NULL }})", fn_type, name);
+        }
+        writeln!(out, "static void {}_drop(void* _env) {{", name).unwrap();
+        writeln!(out, "    {}_env* env = _env;", name).unwrap();
+        for line in &env_drops {
+            writeln!(out, "    {}", line).unwrap();
+        }
+        out.push_str("    free(env);\n}\n\n");
+        writeln!(out, "{} {}_new({}) {{", fn_type, name, env_params.join(", ")).unwrap();
+        writeln!(out, "    {}_env* env = malloc(sizeof *env);", name).unwrap();
+        for field in &names {
+            writeln!(out, "    env->{f} = {f};", f = field).unwrap();
+        }
+        writeln!(out, "    return ({}){{ {}, env, {}_drop }};", fn_type, name, name).unwrap();
+        out.push_str("}\n\n");
+        self.closures.borrow_mut().push_str(&out);
+        format!("{}_new({})", name, values.join(", "))
+    }
+
+    /// Item type of `start..end`, where an unsuffixed literal bound follows
+    /// the other one
+    fn range_item(&self, start: &Expression, end: &Expression) -> Type {
+        let bound = match start.kind {
+            ExpressionKind::Literal(Literal::Integer(_, None)) => end,
+            _ => start,
+        };
+        self.expr_type(bound).unwrap_or(Type::I32)
+    }
+
     fn expr_type_to_c(&self, expr: &Expression) -> String {
-        match expr {
-            Expression::Literal(Literal::Integer(_)) => "int".to_string(),
-            Expression::Literal(Literal::String(_)) => "const char*".to_string(),
-            Expression::Literal(Literal::Boolean(_)) => "int".to_string(),
-            Expression::FunctionCall { name, .. } => {
-                // For function calls, try to infer based on known patterns
-                // Memory allocation functions
-                if name == "malloc" || name == "calloc" || name == "realloc" {
+        if let ExpressionKind::FunctionCall { name, .. } = &expr.kind {
+            // For function calls, try to infer based on known patterns
+            // Memory allocation functions
+            if name == "malloc" || name == "calloc" || name == "realloc" {
+                return "void*".to_string();
+            }
+            
+            // Skia functions that return pointers
+            if name.starts_with("u_skia_") {
+                if name.contains("create") || name.contains("get_") || name.contains("version") {
                     return "void*".to_string();
                 }
-                
-                // Skia functions that return pointers
-                if name.starts_with("u_skia_") {
-                    if name.contains("create") || name.contains("get_") || name.contains("version") {
-                        return "void*".to_string();
+            }
+        }
+        // Default to int
+        self.expr_type(expr)
+            .map(|ty| self.type_to_c(&ty))
+            .unwrap_or_else(|| "int".to_string())
+    }
+
+    /// Lower `expr` where a value of type `expected` is wanted. This is what
+    /// gives `Some`, `None`, `Ok` and `Err` their C type.
+    fn value_to_c(&self, expr: Expression, expected: Option<&Type>) -> String {
+        // Moving an owned local out clears its flag, so it is not dropped;
+        // a slice or `dyn` value only points to it
+        if let ExpressionKind::Identifier(name) = &expr.kind {
+            let flag = self.owned(name).and_then(|owned| owned.flag);
+            if let (Some(flag), false) = (flag, matches!(expected, Some(Type::Slice(_) | Type::Dyn(_)))) {
+                return format!("({} = 0, {})", flag, self.expr_to_c(expr));
+            }
+        }
+        // An array passed where a slice is wanted becomes a view of it
+        if let (Some(slice @ Type::Slice(_)), Some(Type::Array(_, len))) = (expected, self.expr_type(&expr)) {
+            return format!("(({}){{ {}.data, {} }})", self.type_to_c(slice), self.expr_to_c(expr), len);
+        }
+        // A value passed where a `dyn Trait` is wanted is reached through a
+        // pointer; a temporary lives until the end of the enclosing block
+        if let (Some(dyn_type @ Type::Dyn(trait_name)), Some(Type::Custom(type_name))) = (expected, self.expr_type(&expr)) {
+            let place = matches!(
+                expr.kind,
+                ExpressionKind::Identifier(_) | ExpressionKind::FieldAccess { .. } | ExpressionKind::Index { .. }
+            );
+            let value = self.expr_to_c(expr);
+            let pointer = if place { format!("&{}", value) } else { format!("(struct {}[]){{ {} }}", type_name, value) };
+            let vtable = self.define_vtable(&type_name, trait_name);
+            return format!("(({}){{ {}, &{} }})", self.type_to_c(dyn_type), pointer, vtable);
+        }
+        let ty = match expected {
+            Some(ty @ (Type::Option(_) | Type::Result(..))) => ty,
+            _ => return self.expr_to_c(expr),
+        };
+        match (expr.kind, ty) {
+            (ExpressionKind::Identifier(name), Type::Option(_)) if name == "None" && !self.locals.contains_key(&name) => {
+                format!("(({}){{ .is_some = 0 }})", self.type_to_c(ty))
+            }
+            (ExpressionKind::FunctionCall { name, mut arguments }, _) if arguments.len() == 1 => {
+                let field = match (name.as_str(), ty) {
+                    ("Some", Type::Option(inner)) => Some(("value", "is_some = 1", &**inner)),
+                    ("Ok", Type::Result(ok, _)) => Some(("value", "is_ok = 1", &**ok)),
+                    ("Err", Type::Result(_, err)) => Some(("error", "is_ok = 0", &**err)),
+                    _ => None,
+                };
+                match field {
+                    Some((field, flag, inner)) => {
+                        let value = self.value_to_c(arguments.remove(0), Some(inner));
+                        format!("(({}){{ .{} = {}, .{} }})", self.type_to_c(ty), field, value, flag)
                     }
+                    None => self.expr_to_c(Expression::new(ExpressionKind::FunctionCall { name, arguments }, expr.span)),
                 }
-                
-                // Default to int
-                "int".to_string()
             }
-            _ => "int".to_string(),
+            (kind, _) => self.expr_to_c(Expression::new(kind, expr.span)),
         }
     }
 
     fn expr_to_c(&self, expr: Expression) -> String {
-        match expr {
-            Expression::Literal(lit) => match lit {
-                Literal::Integer(n) => n.to_string(),
-                Literal::String(s) => format!("\"{}\"", s.escape_default()),
+        match expr.kind {
+            ExpressionKind::Literal(lit) => match lit {
+                Literal::Integer(n, ty) => match ty {
+                    Some(Type::U64) => format!("{}ULL", n),
+                    Some(Type::I64) => format!("{}LL", n),
+                    None if n > i32::MAX as u64 => format!("{}LL", n),
+                    _ => n.to_string(),
+                },
+                // `{:?}` always keeps a `.` or exponent, so C reads a double
+                Literal::Float(x, Some(Type::F32)) => format!("{:?}f", x),
+                Literal::Float(x, _) => format!("{:?}", x),
+                Literal::String(s) => c_string_literal(&s),
+                Literal::Char(c) => c_char_literal(c),
                 Literal::Boolean(b) => if b { "1" } else { "0" }.to_string(),
             },
-            Expression::Identifier(name) => name,
-            Expression::FunctionCall { name, arguments } => {
-                let args = arguments
+            // Methods take the value they are called on by pointer
+            ExpressionKind::Identifier(name) if self.captured.contains_key(&name) => {
+                if self.captured[&name] {
+                    format!("env->{}", name)
+                } else {
+                    format!("(*env->{})", name)
+                }
+            }
+            ExpressionKind::Identifier(name) if name == "self" && self.in_method => "(*self)".to_string(),
+            ExpressionKind::Identifier(name) => name,
+            ExpressionKind::FunctionCall { name, arguments } if name == "Some" => {
+                let call = Expression::new(ExpressionKind::FunctionCall { name, arguments }, expr.span);
+                let ty = self.expr_type(&call);
+                self.value_to_c(call, ty.as_ref())
+            }
+            ExpressionKind::FunctionCall { name, arguments } if self.locals.contains_key(&name) => {
+                self.closure_call_to_c(name, arguments, expr.span)
+            }
+            ExpressionKind::FunctionCall { name, arguments } => {
+                let params = self.functions.get(&name).map(|(params, _)| params.as_slice()).unwrap_or_default();
+                // `print` only reads its argument, formatted by its type
+                let print = name == "print";
+                let (format, cast) = match arguments.first().and_then(|arg| self.expr_type(arg)) {
+                    Some(Type::I64) => ("%lld", "(long long)"),
+                    Some(Type::U64) => ("%llu", "(unsigned long long)"),
+                    Some(Type::F32 | Type::F64) => ("%g", ""),
+                    Some(Type::Char) => ("%c", ""),
+                    Some(Type::Str) => ("%s", ""),
+                    _ => ("%d", ""),
+                };
+                if print {
+                    let args = arguments.into_iter().map(|arg| self.expr_to_c(arg)).collect::<Vec<_>>().join(", ");
+                    return format!("printf(\"{}\\n\", {}{})", format, cast, args);
+                }
+                let arguments = arguments.into_iter().enumerate().map(|(i, arg)| (arg, params.get(i).cloned())).collect();
+                let (sequence, args) = self.arguments_to_c(arguments);
+                sequenced(&sequence, format!("{}({})", name, args.join(", ")))
+            }
+            ExpressionKind::MethodCall { receiver, method, arguments } if method == "len" && arguments.is_empty() => {
+                let ty = self.expr_type(&receiver);
+                match ty {
+                    Some(Type::Array(_, len)) => len.to_string(),
+                    Some(Type::Slice(_)) => format!("{}.len", self.expr_to_c(*receiver)),
+                    Some(Type::Ref(inner)) => match *inner {
+                        Type::Array(_, len) => len.to_string(),
+                        _ => format!("{}->len", self.expr_to_c(*receiver)),
+                    },
+                    _ => "0 /* method call stub */".to_string(),
+                }
+            }
+            ExpressionKind::MethodCall { receiver, method, arguments } => {
+                let Some(receiver_ty) = self.expr_type(&receiver) else {
+                    return "0 /* method call stub */".to_string();
+                };
+                let place = receiver.root_variable().is_some();
+                let value = self.expr_to_c(*receiver);
+                // A method is called through a reference on the value it
+                // points to, whose C pointer is the reference itself. A
+                // value that is not a place is given one to point to.
+                let (receiver_c, pointer) = match &receiver_ty {
+                    Type::Ref(inner) => (format!("(*{})", value), format!("({}*){}", self.type_to_c(inner), value)),
+                    _ if place => (value.clone(), format!("&{}", value)),
+                    ty => (value.clone(), format!("({}[]){{ {} }}", self.type_to_c(ty), value)),
+                };
+                let Some((dispatch, signature)) = self.dispatch(&receiver_ty, &method) else {
+                    // Without an impl of `Clone`, a value is `Copy` or a
+                    // `str`, whose clone is the value itself
+                    if method == "clone" {
+                        return receiver_c;
+                    }
+                    return "0 /* method call stub */".to_string();
+                };
+                let receiver = receiver_c;
+                let arguments = arguments.into_iter().zip(&signature.params).map(|(arg, (_, ty))| (arg, Some(ty.clone()))).collect();
+                let (sequence, args) = self.arguments_to_c(arguments);
+                let args: String = args.into_iter().map(|arg| format!(", {}", arg)).collect();
+                let call = match dispatch {
+                    Dispatch::Static(function) => format!("{}({}{})", function, pointer, args),
+                    Dispatch::Dynamic => format!("{}.vtable->{}({}.self{})", receiver, method, receiver, args),
+                };
+                sequenced(&sequence, call)
+            }
+            ExpressionKind::ArrayLiteral(elements) => {
+                let ty = self.expr_type(&Expression::new(ExpressionKind::ArrayLiteral(elements.clone()), expr.span));
+                let element = match &ty {
+                    Some(Type::Array(element, _)) => Some(&**element),
+                    _ => None,
+                };
+                let values = elements
                     .into_iter()
-                    .map(|arg| self.expr_to_c(arg))
+                    .map(|e| self.value_to_c(e, element))
                     .collect::<Vec<_>>()
                     .join(", ");
-                if name == "print" {
-                    format!("printf(\"%d\\n\", {})", args)
-                } else {
-                    format!("{}({})", name, args)
-                }
+                format!("(({}){{ {{ {} }} }})", self.type_to_c(&ty.unwrap_or(Type::Array(Box::new(Type::I32), 0))), values)
             }
-            Expression::MethodCall {
-                receiver: _,
-                method: _,
-                arguments: _,
-            } => {
-                "0 /* method call stub */".to_string()
+            ExpressionKind::Index { object, index } => {
+                let object_ty = self.expr_type(&object);
+                let position = index.span.start;
+                // The type checker has already proved constant indexes into arrays in range
+                let constant = matches!(index.kind, ExpressionKind::Literal(Literal::Integer(..)));
+                let base = self.expr_to_c(*object);
+                // Elements are reached through a reference as on the value
+                let (object_ty, base) = match object_ty {
+                    Some(Type::Ref(inner)) => (Some(*inner), format!("(*{})", base)),
+                    object_ty => (object_ty, base),
+                };
+                let index = self.expr_to_c(*index);
+                match object_ty {
+                    Some(Type::Slice(_)) if self.unsafe_depth > 0 => format!("{}.ptr[{}]", base, index),
+                    Some(slice @ Type::Slice(_)) => format!(
+                        "(*{}_at({}, {}, {}, {}))",
+                        self.type_to_c(&slice),
+                        base,
+                        index,
+                        position.line,
+                        position.column
+                    ),
+                    Some(Type::Array(_, len)) if self.unsafe_depth == 0 && !constant => {
+                        self.define_index_check();
+                        format!(
+                            "{}.data[u_check_index({}, {}, {}, {})]",
+                            base, index, len, position.line, position.column
+                        )
+                    }
+                    _ => format!("{}.data[{}]", base, index),
+                }
             }
-            Expression::Binary { left, operator, right } => {
+            ExpressionKind::Binary { left, operator, right } => {
                 let left_c = self.expr_to_c(*left);
                 let right_c = self.expr_to_c(*right);
                 let op_str = match operator {
@@ -315,20 +1956,135 @@ impl CGenerator {
                 };
                 format!("({} {} {})", left_c, op_str, right_c)
             }
-            Expression::Unary { operator, operand } => {
+            ExpressionKind::Unary { operator, operand } => {
                 let op_c = self.expr_to_c(*operand);
                 match operator {
                     UnaryOp::Not => format!("(!{})", op_c),
                     UnaryOp::Negate => format!("(-{})", op_c),
+                    UnaryOp::Borrow => format!("(&{})", op_c),
+                    UnaryOp::Deref => format!("(*{})", op_c),
                 }
             }
-            Expression::Assignment { target, value } => {
-                let val_c = self.expr_to_c(*value);
-                format!("({} = {})", target, val_c)
+            ExpressionKind::Assignment { target, value } => {
+                let ty = self.expr_type(&target);
+                let val_c = self.value_to_c(*value, ty.as_ref());
+                format!("({} = {})", self.expr_to_c(*target), val_c)
+            }
+            ExpressionKind::StructLiteral { name, type_args: _, fields } => {
+                let declared = self.structs.get(&name).map(|s| s.fields.clone()).unwrap_or_default();
+                let values = fields
+                    .into_iter()
+                    .map(|(field, value)| {
+                        let ty = declared.iter().find(|(f, _)| *f == field).map(|(_, ty)| ty);
+                        format!(".{} = {}", field, self.value_to_c(value, ty))
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("((struct {}){{ {} }})", name, values)
+            }
+            ExpressionKind::FieldAccess { object, field } => match self.expr_type(&object) {
+                Some(Type::Ref(_)) => format!("{}->{}", self.expr_to_c(*object), field),
+                _ => format!("{}.{}", self.expr_to_c(*object), field),
+            },
+            ExpressionKind::Range { start, end, inclusive, step } => {
+                let range = self.type_to_c(&Type::Range(Box::new(self.range_item(&start, &end))));
+                // A bad step is reported where it is written
+                let position = step.as_ref().map_or(expr.span.start, |step| step.span.start);
+                let step = step.map_or_else(|| "1".to_string(), |step| self.expr_to_c(*step));
+                format!(
+                    "{}_new({}, {}, {}, {}, {}, {})",
+                    range,
+                    self.expr_to_c(*start),
+                    self.expr_to_c(*end),
+                    step,
+                    inclusive as i32,
+                    position.line,
+                    position.column
+                )
+            }
+            ExpressionKind::Tuple(items) => {
+                let ty = self.expr_type(&Expression::new(ExpressionKind::Tuple(items.clone()), expr.span));
+                let values = items
+                    .into_iter()
+                    .map(|item| self.expr_to_c(item))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("(({}){{ {} }})", self.type_to_c(&ty.unwrap_or(Type::Tuple(Vec::new()))), values)
+            }
+            ExpressionKind::Match { .. } => {
+                // The type checker only allows match where generate_statement lowers it
+                "0 /* match outside a statement */".to_string()
+            }
+            ExpressionKind::Closure { params, return_type, body, by_move } => {
+                self.closure_to_c(params, return_type, body, by_move)
+            }
+            ExpressionKind::EnumVariant { enum_name, variant, payload } => {
+                let tag = format!(".tag = {}", self.tag(&enum_name, &variant));
+                let fields = match payload {
+                    VariantPayload::Unit => Vec::new(),
+                    VariantPayload::Tuple(values) => values
+                        .into_iter()
+                        .enumerate()
+                        .map(|(i, value)| (i.to_string(), value))
+                        .collect(),
+                    VariantPayload::Struct(fields) => fields,
+                };
+                if fields.is_empty() {
+                    format!("((struct {}){{ {} }})", enum_name, tag)
+                } else {
+                    let values = fields
+                        .into_iter()
+                        .map(|(name, value)| format!(".{} = {}", c_field_name(&name), self.value_to_c(value, None)))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    format!("((struct {}){{ {}, .as.{} = {{ {} }} }})", enum_name, tag, variant, values)
+                }
             }
         }
     }
 
+    /// Call the closure held in the variable `name` with its environment
+    fn closure_call_to_c(&self, name: String, arguments: Vec<Expression>, span: Span) -> String {
+        let params = match self.locals.get(&name) {
+            Some(Type::FunctionPointer { params, .. }) => params.clone(),
+            _ => Vec::new(),
+        };
+        let closure = self.expr_to_c(Expression::new(ExpressionKind::Identifier(name), span));
+        let arguments = arguments.into_iter().enumerate().map(|(i, arg)| (arg, params.get(i).cloned())).collect();
+        let (sequence, args) = self.arguments_to_c(arguments);
+        let args: String = args.into_iter().map(|arg| format!(", {}", arg)).collect();
+        sequenced(&sequence, format!("{c}.call({c}.env{})", args, c = closure))
+    }
+
+    /// Lower the arguments of a call, each wanted as a value of the type
+    /// beside it where known. C evaluates arguments in no set order, so
+    /// where one may drop a value that another uses, as in
+    /// `add(look(&b), eat(b))`, they are first evaluated in order into
+    /// temporaries, which are passed instead: the assignments are returned
+    /// too, each followed by a comma.
+    fn arguments_to_c(&self, arguments: Vec<(Expression, Option<Type>)>) -> (String, Vec<String>) {
+        let drops = |arg: &Expression| {
+            let stmt = Statement::new(StatementKind::Expr(arg.clone()), arg.span);
+            captures(&[], &[stmt]).iter().any(|c| c.consumed && self.owned(&c.name).is_some())
+        };
+        let ordered = arguments.len() > 1 && arguments.iter().any(|(arg, _)| drops(arg));
+        let types: Vec<Option<Type>> = arguments.iter().map(|(arg, ty)| ty.clone().or_else(|| self.expr_type(arg))).collect();
+        let values: Vec<String> = arguments.into_iter().map(|(arg, ty)| self.value_to_c(arg, ty.as_ref())).collect();
+        if !ordered || types.iter().any(Option::is_none) {
+            return (String::new(), values);
+        }
+        let mut sequence = String::new();
+        let mut temporaries = Vec::new();
+        for (value, ty) in values.into_iter().zip(types.into_iter().flatten()) {
+            let temporary = format!("_arg{}", self.temporary_count.get());
+            self.temporary_count.set(self.temporary_count.get() + 1);
+            self.temporaries.borrow_mut().push(format!("{} {};", self.type_to_c(&ty), temporary));
+            write!(sequence, "{} = {}, ", temporary, value).unwrap();
+            temporaries.push(temporary);
+        }
+        (sequence, temporaries)
+    }
+
     fn generate_extern_block(&mut self, block: crate::parser::ExternBlock) {
         self.emit(&format!("// extern \"{}\" block", block.abi));
         for func in block.functions {
@@ -487,7 +2243,7 @@ impl CGenerator {
         
         self.emit(&format!("void* paint = skia_create_paint();"));
         self.emit(&format!("skia_paint_set_color(paint, {}, {}, {}, 255);", color.0, color.1, color.2));
-        self.emit(&format!("skia_draw_text({}, \"{}\", 10, 30, {}, paint);", canvas_var, content, font_size));
+        self.emit(&format!("skia_draw_text({}, {}, 10, 30, {}, paint);", canvas_var, c_string_literal(&content), font_size));
         self.emit(&format!("skia_destroy_paint(paint);"));
     }
 
@@ -516,11 +2272,11 @@ impl CGenerator {
                         height = *h;
                     }
                 }
-                "onClick" => {
-                    if let WidgetValue::Identifier(func) = value {
-                        on_click = Some(func.clone());
-                    }
-                }
+                "onClick" => match value {
+                    WidgetValue::Identifier(func) => on_click = Some(format!("{}()", func)),
+                    WidgetValue::Closure(closure) => on_click = Some(self.expr_to_c((**closure).clone())),
+                    _ => {}
+                },
                 _ => {}
             }
         }
@@ -532,12 +2288,12 @@ impl CGenerator {
         
         // Draw button text
         self.emit(&format!("skia_paint_set_color(paint, 255, 255, 255, 255);"));
-        self.emit(&format!("skia_draw_text({}, \"{}\", 20, 35, 14, paint);", canvas_var, text));
+        self.emit(&format!("skia_draw_text({}, {}, 20, 35, 14, paint);", canvas_var, c_string_literal(&text)));
         self.emit(&format!("skia_destroy_paint(paint);"));
         
         // Event handler (requires event loop integration)
         if let Some(handler) = on_click {
-            self.emit(&format!("// onClick handler: {}() - requires event system", handler));
+            self.emit(&format!("// onClick handler: {} - requires event system", handler));
         }
     }
 
@@ -622,7 +2378,7 @@ impl CGenerator {
         
         // Draw placeholder text
         self.emit(&format!("skia_paint_set_color(paint, 150, 150, 150, 255);"));
-        self.emit(&format!("skia_draw_text({}, \"{}\", 15, 30, 14, paint);", canvas_var, placeholder));
+        self.emit(&format!("skia_draw_text({}, {}, 15, 30, 14, paint);", canvas_var, c_string_literal(&placeholder)));
         self.emit(&format!("skia_destroy_paint(paint);"));
     }
 
@@ -686,6 +2442,175 @@ impl CGenerator {
     }
 }
 
+/// Whether `body` may move the value of the local `name` out
+fn moved_in(name: &str, body: &[Statement]) -> bool {
+    captures(&[], body).iter().any(|capture| capture.name == name && capture.consumed)
+}
+
+/// `call` after the argument assignments `sequence` made for it, if any
+fn sequenced(sequence: &str, call: String) -> String {
+    if sequence.is_empty() {
+        call
+    } else {
+        format!("({}{})", sequence, call)
+    }
+}
+
+/// Whether an arm body has a `break` aimed at an enclosing loop, which
+/// would only leave a `switch`
+fn arm_breaks(arm: &MatchArm) -> bool {
+    match &arm.body {
+        ArmBody::Block(body) => breaks_out(body),
+        ArmBody::Expression(expr) => matches!(&expr.kind, ExpressionKind::Match { arms, .. } if arms.iter().any(arm_breaks)),
+    }
+}
+
+fn breaks_out(body: &[Statement]) -> bool {
+    body.iter().any(|stmt| match &stmt.kind {
+        StatementKind::Break => true,
+        StatementKind::If { then_branch, else_branch, .. } => {
+            breaks_out(then_branch) || else_branch.as_deref().is_some_and(breaks_out)
+        }
+        StatementKind::Unsafe { body } => breaks_out(body),
+        StatementKind::Expr(expr) | StatementKind::Let { value: Some(expr), .. } | StatementKind::Return(expr) => {
+            matches!(&expr.kind, ExpressionKind::Match { arms, .. } if arms.iter().any(arm_breaks))
+        }
+        _ => false,
+    })
+}
+
+/// C name for the typedef of an option, result, tuple, array, slice, range,
+/// closure or `dyn` type
+fn mangle(ty: &Type) -> String {
+    match ty {
+        Type::I32 => "i32".to_string(),
+        Type::I64 => "i64".to_string(),
+        Type::U8 => "u8".to_string(),
+        Type::U64 => "u64".to_string(),
+        Type::F32 => "f32".to_string(),
+        Type::F64 => "f64".to_string(),
+        Type::Bool => "bool".to_string(),
+        Type::Char => "char".to_string(),
+        Type::Str => "str".to_string(),
+        Type::Ptr => "ptr".to_string(),
+        Type::Option(inner) => format!("Option_{}", mangle(inner)),
+        Type::Result(ok, err) => format!("Result_{}_{}", mangle(ok), mangle(err)),
+        Type::Tuple(items) => {
+            let items: Vec<_> = items.iter().map(mangle).collect();
+            format!("Tuple{}_{}", items.len(), items.join("_"))
+        }
+        Type::Array(element, len) => format!("Array{}_{}", len, mangle(element)),
+        Type::Slice(element) => format!("Slice_{}", mangle(element)),
+        Type::Range(item) => format!("Range_{}", mangle(item)),
+        Type::Ref(inner) => format!("Ref_{}", mangle(inner)),
+        Type::Custom(name) | Type::Generic { name, .. } => name.clone(),
+        Type::FunctionPointer { params, return_type } => {
+            let ret = return_type.as_deref().map_or_else(|| "void".to_string(), mangle);
+            let parts: Vec<_> = params.iter().map(mangle).chain([ret]).collect();
+            format!("Fn{}_{}", params.len(), parts.join("_"))
+        }
+        Type::Dyn(trait_name) => format!("Dyn_{}", trait_name),
+    }
+}
+
+/// The type a reference points to, or `ty` itself if it is not one
+fn referent(ty: &Type) -> &Type {
+    match ty {
+        Type::Ref(inner) => inner,
+        _ => ty,
+    }
+}
+
+/// C name of a method in `impl Trait for Type`
+fn method_name(type_name: &str, trait_name: &str, method: &str) -> String {
+    format!("{}_{}_{}", type_name, trait_name, method)
+}
+
+/// Unsigned counterpart of an integer type, in which the distance between
+/// two of its values never overflows
+fn unsigned_c_type(ty: &Type) -> &'static str {
+    match ty {
+        Type::I64 | Type::U64 => "uint64_t",
+        Type::U8 => "uint8_t",
+        _ => "unsigned int",
+    }
+}
+
+/// Typedef and helpers for a range over `item`. The range keeps its next
+/// value and its last one (so `a..b` and `a..=b` look alike) and finishes
+/// without ever stepping past the last value, which could overflow.
+fn range_to_c(name: &str, item: &str, unsigned: &str) -> String {
+    [
+        format!("typedef struct {{ {item} next; {item} last; {item} step; int done; }} {name};"),
+        format!("static inline {name} {name}_new({item} start, {item} end, {item} step, int inclusive, int line, int column) {{"),
+        "    if (step < 1) {".to_string(),
+        "        fflush(stdout);".to_string(),
+        "        fprintf(stderr, \"range step must be positive at %d:%d\\n\", line, column);".to_string(),
+        "        abort();".to_string(),
+        "    }".to_string(),
+        format!("    {name} r = {{ start, end, step, inclusive ? start > end : start >= end }};"),
+        "    if (!inclusive && !r.done) {".to_string(),
+        "        r.last = end - 1;".to_string(),
+        "    }".to_string(),
+        "    return r;".to_string(),
+        "}".to_string(),
+        format!("static inline int {name}_next({name}* r, {item}* item) {{"),
+        "    if (r->done) {".to_string(),
+        "        return 0;".to_string(),
+        "    }".to_string(),
+        "    *item = r->next;".to_string(),
+        format!("    if (({unsigned})r->last - ({unsigned})r->next < ({unsigned})r->step) {{"),
+        "        r->done = 1;".to_string(),
+        "    } else {".to_string(),
+        "        r->next += r->step;".to_string(),
+        "    }".to_string(),
+        "    return 1;".to_string(),
+        "}".to_string(),
+        String::new(),
+    ]
+    .join("\n")
+}
+
+/// C member name of a payload field; tuple fields `0`, `1`, ... become
+/// `_0`, `_1`, ...
+fn c_field_name(name: &str) -> String {
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{}", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// Quote a string as a C literal. Bytes outside printable ASCII (including
+/// UTF-8 sequences) are written as three-digit octal escapes, which cannot
+/// run into the character that follows the way `\x` escapes can.
+fn c_string_literal(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for byte in s.bytes() {
+        match byte {
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            b'\n' => out.push_str("\\n"),
+            b'\t' => out.push_str("\\t"),
+            b'\r' => out.push_str("\\r"),
+            0x20..=0x7e => out.push(byte as char),
+            _ => write!(out, "\\{:03o}", byte).unwrap(),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// A `char` lowers to its code point; printable ASCII stays readable
+fn c_char_literal(c: char) -> String {
+    if (c.is_ascii_graphic() || c == ' ') && c != '\'' && c != '\\' {
+        format!("'{}'", c)
+    } else {
+        format!("0x{:X}", c as u32)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -720,4 +2645,283 @@ mod tests {
         assert!(c_code.contains("while"));
         assert!(c_code.contains("<"));
     }
+
+    #[test]
+    fn test_string_and_char_lowering() {
+        let source = r#"fn main() { let s = "a\"b\\\n\t\0é"; let c = 'x'; let q = '\''; let u = '\u{1F600}'; }"#;
+        let mut parser = Parser::new(Lexer::new(source.to_string()).tokenize());
+        let c_code = CGenerator::new().generate_program(parser.parse().unwrap());
+
+        assert!(c_code.contains(r#"const char* s = "a\"b\\\n\t\000\303\251";"#));
+        assert!(c_code.contains("uint32_t c = 'x';"));
+        assert!(c_code.contains("uint32_t q = 0x27;"));
+        assert!(c_code.contains("uint32_t u = 0x1F600;"));
+
+        // `print` formats its argument by type
+        let source = "fn main() { print(\"a\"); print('b'); print(1.5); print(2i64); print(3u64); print(4); }";
+        let mut parser = Parser::new(Lexer::new(source.to_string()).tokenize());
+        let c_code = CGenerator::new().generate_program(parser.parse().unwrap());
+        assert!(c_code.contains("printf(\"%s\\n\", \"a\");\n    printf(\"%c\\n\", 'b');\n    printf(\"%g\\n\", 1.5);"));
+        assert!(c_code.contains("printf(\"%lld\\n\", (long long)2LL);\n    printf(\"%llu\\n\", (unsigned long long)3ULL);"));
+        assert!(c_code.contains("printf(\"%d\\n\", 4);"));
+    }
+
+    #[test]
+    fn test_enum_lowering() {
+        let source = "enum Shape { Circle(i32), Rect { w: i32, h: i32 }, Empty }\nfn main() { let c = Shape::Circle(5); let r = Shape::Rect { w: 2, h: 3 }; let e = Shape::Empty; }";
+        let mut parser = Parser::new(Lexer::new(source.to_string()).tokenize());
+        let c_code = CGenerator::new().generate_program(parser.parse().unwrap());
+
+        assert!(c_code.contains("typedef enum { Shape_Circle, Shape_Rect, Shape_Empty } Shape_Tag;"));
+        assert!(c_code.contains("struct { int _0; } Circle;"));
+        assert!(c_code.contains("struct { int w; int h; } Rect;"));
+        assert!(c_code.contains("struct Shape c = ((struct Shape){ .tag = Shape_Circle, .as.Circle = { ._0 = 5 } });"));
+        assert!(c_code.contains(".as.Rect = { .w = 2, .h = 3 }"));
+        assert!(c_code.contains("struct Shape e = ((struct Shape){ .tag = Shape_Empty });"));
+        // Types come before the prototypes that use them
+        assert!(c_code.find("} Shape;").unwrap() < c_code.find("int main(void);").unwrap());
+    }
+
+    #[test]
+    fn test_enum_tags_do_not_clash() {
+        let source = "enum A_B { C }\nenum A { B_C, D }\nfn main() { let x = A_B::C; let y = A::B_C; match y { A::B_C => print(1), A::D => print(2), } }";
+        let mut parser = Parser::new(Lexer::new(source.to_string()).tokenize());
+        let c_code = CGenerator::new().generate_program(parser.parse().unwrap());
+
+        assert!(c_code.contains("typedef enum { A_B_C } A_B_Tag;"));
+        assert!(c_code.contains("typedef enum { A_B_C_2, A_D } A_Tag;"));
+        assert!(c_code.contains("struct A y = ((struct A){ .tag = A_B_C_2 });"));
+        assert!(c_code.contains("case A_B_C_2: {"));
+    }
+
+    #[test]
+    fn test_match_lowering() {
+        let source = "enum Shape { Circle(i32), Empty }\n\
+            fn area(s: Shape) -> i32 { return match s { Shape::Circle(r) => r * r, Shape::Empty => 0, }; }\n\
+            fn half(n: i32) -> Option<i32> { if n > 0 { return Some(n / 2); } return None; }\n\
+            fn main() { let h = match half(4) { Some(x) if x > 1 => x, Some(_) => 1, None => 0, }; \
+            match (h, true) { (0, _) => print(0), (_, b) => print(1), } }";
+        let mut parser = Parser::new(Lexer::new(source.to_string()).tokenize());
+        let c_code = CGenerator::new().generate_program(parser.parse().unwrap());
+
+        // Enum arms switch on the tag; the last arm is the default
+        assert!(c_code.contains("switch (_m0.tag) {"));
+        assert!(c_code.contains("case Shape_Circle: {"));
+        assert!(c_code.contains("int r = _m0.as.Circle._0;"));
+        assert!(c_code.contains("default: {"));
+        // Option is a named struct so signatures and values agree
+        assert!(c_code.contains("typedef struct { int value; int is_some; } Option_i32;"));
+        assert!(c_code.contains("Option_i32 half(int n);"));
+        assert!(c_code.contains("return ((Option_i32){ .value = (n / 2), .is_some = 1 });"));
+        assert!(c_code.contains("return ((Option_i32){ .is_some = 0 });"));
+        // Guards run after the bindings they use
+        assert!(c_code.contains("int h;"));
+        assert!(c_code.contains("if (!_m1_done && _m1.is_some) {\n            int x = _m1.value;\n            if ((x > 1)) {"));
+        // Tuples are evaluated once into a struct
+        assert!(c_code.contains("Tuple2_i32_bool _m2 = ((Tuple2_i32_bool){ h, 1 });"));
+        assert!(c_code.contains("if (_m2._0 == 0) {"));
+    }
+
+    #[test]
+    fn test_struct_lowering() {
+        let source = "type Line { from: Point, to: Point }\ntype Point { x: i32, y: i32 }\n\
+            fn main() { var l = Line { from: Point { x: 0, y: 0 }, to: Point { x: 1, y: 2 } }; l.to.x = 3; let y = l.to.y; }";
+        let mut parser = Parser::new(Lexer::new(source.to_string()).tokenize());
+        let c_code = CGenerator::new().generate_program(parser.parse().unwrap());
+
+        assert!(c_code.contains("typedef struct Point {\n    int x;\n    int y;\n} Point;"));
+        // Field types are defined before the structs that embed them
+        assert!(c_code.find("} Point;").unwrap() < c_code.find("} Line;").unwrap());
+        assert!(c_code.contains("struct Line l = ((struct Line){ .from = ((struct Point){ .x = 0, .y = 0 }), .to = ((struct Point){ .x = 1, .y = 2 }) });"));
+        assert!(c_code.contains("(l.to.x = 3);"));
+        assert!(c_code.contains("int y = l.to.y;"));
+    }
+
+    #[test]
+    fn test_array_lowering() {
+        let source = "fn sum(xs: [i32]) -> i32 { return xs[0] + xs.len(); }\n\
+            fn main() { var a = [1, 2, 3]; var i = 2; a[i] = a[0]; print(sum(a)); unsafe { print(a[i]); } }";
+        let mut parser = Parser::new(Lexer::new(source.to_string()).tokenize());
+        let c_code = CGenerator::new().generate_program(parser.parse().unwrap());
+
+        assert!(c_code.contains("typedef struct { int data[3]; } Array3_i32;"));
+        assert!(c_code.contains("typedef struct { int* ptr; int len; } Slice_i32;"));
+        assert!(c_code.contains("Array3_i32 a = ((Array3_i32){ { 1, 2, 3 } });"));
+        // Slices check through a helper; constant array indexes need no check
+        assert!(c_code.contains("return ((*Slice_i32_at(xs, 0, 1, 38)) + xs.len);"));
+        assert!(c_code.contains("(a.data[u_check_index(i, 3, 2, 45)] = a.data[0]);"));
+        assert!(c_code.contains("sum(((Slice_i32){ a.data, 3 }))"));
+        // Unsafe blocks index directly
+        assert!(c_code.contains("printf(\"%d\\n\", a.data[i]);"));
+    }
+
+    #[test]
+    fn test_for_lowering() {
+        let source = "type Counter { n: i32 }\n\
+            impl Iterator for Counter { fn next() -> Option<i32> { self.n = self.n + 1; return Some(self.n); } }\n\
+            fn main() { for i in (0..=9).step_by(3) { print(i); } for x in [1, 2] { print(x); } for c in (Counter { n: 0 }) { print(c); } }";
+        let mut parser = Parser::new(Lexer::new(source.to_string()).tokenize());
+        let c_code = CGenerator::new().generate_program(parser.parse().unwrap());
+
+        // Impl methods take `self` by pointer
+        assert!(c_code.contains("Option_i32 Counter_Iterator_next(struct Counter* self) {"));
+        assert!(c_code.contains("((*self).n = ((*self).n + 1));"));
+        assert!(c_code.contains("typedef struct { int next; int last; int step; int done; } Range_i32;"));
+        assert!(c_code.contains("Range_i32 _f0 = Range_i32_new(0, 9, 3, 1, 3, 38);"));
+        assert!(c_code.contains("while (Range_i32_next(&_f0, &i)) {"));
+        assert!(c_code.contains("for (int _f1_i = 0; _f1_i < 2; _f1_i++) {"));
+        assert!(c_code.contains("int x = _f1.data[_f1_i];"));
+        assert!(c_code.contains("while ((_f2_item = Counter_Iterator_next(&_f2)).is_some) {"));
+        assert!(c_code.contains("int c = _f2_item.value;"));
+    }
+
+    #[test]
+    fn test_closure_lowering() {
+        let source = "fn apply(f: fn(i32) -> i32, x: i32) -> i32 { return f(x); }\n\
+            fn main() { let n = 2; let add = |x: i32| x + n; let one = move || 1; print(apply(add, one())); }";
+        let mut parser = Parser::new(Lexer::new(source.to_string()).tokenize());
+        let c_code = CGenerator::new().generate_program(parser.parse().unwrap());
+
+        assert!(c_code.contains("typedef struct { int (*call)(void*, int); void* env; void (*drop)(void*); } Fn1_i32_i32;"));
+        assert!(c_code.contains("int apply(Fn1_i32_i32 f, int x);"));
+        assert!(c_code.contains("int _r = f.call(f.env, x);\n        u_drop_Fn1_i32_i32(&f);"));
+        // Borrowed captures are reached through a pointer
+        assert!(c_code.contains("typedef struct { int* n; } _closure0_env;"));
+        assert!(c_code.contains("int _closure0(void* _env, int x) {"));
+        assert!(c_code.contains("return (x + (*env->n));"));
+        assert!(c_code.contains("Fn1_i32_i32 add = _closure0_new(&n);"));
+        // Dropping the closure frees its environment
+        assert!(c_code.contains("return (Fn1_i32_i32){ _closure0, env, _closure0_drop };"));
+        assert!(c_code.contains("static void _closure0_drop(void* _env) {\n    _closure0_env* env = _env;\n    free(env);\n}"));
+        assert!(c_code.contains("if (value->drop) value->drop(value->env);"));
+        // Without captures there is no environment
+        assert!(c_code.contains("Fn0_i32 one = ((Fn0_i32){ _closure1, NULL, NULL });"));
+        // Passing a closure moves it, so only the callee drops it
+        assert!(c_code.contains("printf(\"%d\\n\", apply((_live_add = 0, add), one.call(one.env)));"));
+
+        // The environment drops every capture moved into it, including one
+        // the body passes on by reference
+        let source = "type Handle { fd: i32 }\nimpl Drop for Handle { fn drop() { print(self.fd); } }\n\
+            fn look(h: &Handle) -> i32 { return h.fd; }\nfn main() { let h = Handle { fd: 1 }; let f = move || look(&h); f(); f(); }";
+        let mut parser = Parser::new(Lexer::new(source.to_string()).tokenize());
+        let c_code = CGenerator::new().generate_program(parser.parse().unwrap());
+        assert!(c_code.contains("static void _closure0_drop(void* _env) {\n    _closure0_env* env = _env;\n    u_drop_Handle(&env->h);\n    free(env);\n}"));
+    }
+
+    #[test]
+    fn test_trait_dispatch_lowering() {
+        let source = "trait Shape { fn area() -> i32; fn scale(k: i32) -> i32; }\ntype Sq { side: i32 }\n\
+            impl Shape for Sq { fn area() -> i32 { return self.side; } fn scale(k: i32) -> i32 { return self.area() * k; } }\n\
+            fn total(s: dyn Shape) -> i32 { return s.scale(2); }\n\
+            fn main() { let s = Sq { side: 2 }; let a = s.area(); let d: dyn Shape = s; print(total(d)); print(total(Sq { side: 1 })); }";
+        let mut parser = Parser::new(Lexer::new(source.to_string()).tokenize());
+        let c_code = CGenerator::new().generate_program(parser.parse().unwrap());
+
+        // Calls on a known type go straight to the impl method
+        assert!(c_code.contains("int a = Sq_Shape_area(&s);"));
+        assert!(c_code.contains("return (Sq_Shape_area(&(*self)) * k);"));
+        // A `dyn` value is a pointer to the value and its type's vtable
+        assert!(c_code.contains("typedef struct { int (*area)(void*); int (*scale)(void*, int); } Shape_vtable;"));
+        assert!(c_code.contains("typedef struct { void* self; const Shape_vtable* vtable; } Dyn_Shape;"));
+        assert!(c_code.contains("static int Sq_Shape_scale_dyn(void* self, int k) { return Sq_Shape_scale((struct Sq*)self, k); }"));
+        assert!(c_code.contains("static const Shape_vtable Sq_Shape_vtable = { Sq_Shape_area_dyn, Sq_Shape_scale_dyn };"));
+        assert!(c_code.contains("return s.vtable->scale(s.self, 2);"));
+        assert!(c_code.contains("Dyn_Shape d = ((Dyn_Shape){ &s, &Sq_Shape_vtable });"));
+        assert!(c_code.contains("total(((Dyn_Shape){ (struct Sq[]){ ((struct Sq){ .side = 1 }) }, &Sq_Shape_vtable }))"));
+
+        // A field is called through its address, a call result through a
+        // compound literal holding it
+        let source = "trait Shape { fn area() -> i32; }\ntype Sq { side: i32 }\ntype Holder { sq: Sq }\n\
+            impl Shape for Sq { fn area() -> i32 { return self.side; } }\nfn make() -> Sq { return Sq { side: 3 }; }\n\
+            fn main() { let h = Holder { sq: Sq { side: 2 } }; print(h.sq.area() + make().area()); }";
+        let mut parser = Parser::new(Lexer::new(source.to_string()).tokenize());
+        let c_code = CGenerator::new().generate_program(parser.parse().unwrap());
+        assert!(c_code.contains("(Sq_Shape_area(&h.sq) + Sq_Shape_area((struct Sq[]){ make() }))"), "{}", c_code);
+    }
+
+    #[test]
+    fn test_reference_lowering() {
+        let source = "trait Area { fn area() -> i32; }\ntype Pt { x: i32, name: str }\n\
+            impl Area for Pt { fn area() -> i32 { return self.x; } }\n\
+            fn get(p: &Pt) -> i32 { return p.x + p.area(); }\nfn name(p: &Pt) -> &str { return &p.name; }\n\
+            fn main() { let p = Pt { x: 1, name: \"a\" }; let r = &p; let n = get(r); let xs = [1, 2]; let e = &xs; let f = e[1]; }";
+        let mut parser = Parser::new(Lexer::new(source.to_string()).tokenize());
+        let c_code = CGenerator::new().generate_program(parser.parse().unwrap());
+
+        assert!(c_code.contains("int get(const struct Pt* p) {"));
+        assert!(c_code.contains("const char* const* name(const struct Pt* p) {"));
+        assert!(c_code.contains("return (&p->name);"));
+        // Methods are called on the value a reference points to
+        assert!(c_code.contains("return (p->x + Pt_Area_area((struct Pt*)p));"));
+        assert!(c_code.contains("const struct Pt* r = (&p);"));
+        assert!(c_code.contains("int f = (*e).data[1];"));
+    }
+
+    #[test]
+    fn test_drop_lowering() {
+        let source = "type Handle { fd: i32 }\ntype Pair { a: Handle, b: Handle }\n\
+            impl Drop for Handle { fn drop() { print(self.fd); } }\nfn take(h: Handle) -> i32 { return 0; }\n\
+            fn main() { let p = Pair { a: Handle { fd: 1 }, b: Handle { fd: 2 } }; let h = Handle { fd: 3 }; \
+            if p.a.fd > 0 { take(h); } var i = 0; while i < 2 { let l = Handle { fd: i }; i = i + 1; if i > 0 { break; } } }";
+        let mut parser = Parser::new(Lexer::new(source.to_string()).tokenize());
+        let c_code = CGenerator::new().generate_program(parser.parse().unwrap());
+
+        assert!(c_code.contains("static void u_drop_Handle(struct Handle* value) {\n    Handle_Drop_drop(value);\n}"));
+        assert!(c_code.contains("static void u_drop_Pair(struct Pair* value) {\n    u_drop_Handle(&value->a);\n    u_drop_Handle(&value->b);\n}"));
+        // A parameter is dropped unless moved out, which it is not here
+        assert!(c_code.contains("int _r = 0;\n        u_drop_Handle(&h);\n        return _r;"));
+        // A value moved on one path only has a flag
+        assert!(c_code.contains("int _live_h = 1;"));
+        assert!(c_code.contains("take((_live_h = 0, h));"));
+        // Leaving a loop drops its body's values, latest first
+        assert!(c_code.contains("u_drop_Handle(&l);\n            break;"));
+        assert!(c_code.contains("if (_live_h) u_drop_Handle(&h);\n    u_drop_Pair(&p);"));
+
+        // A field is cloned out, so only the value holding it drops it
+        let source = "type Buf { n: i32 }\ntype Holder { b: Buf }\nimpl Drop for Buf { fn drop() { print(self.n); } }\n\
+            impl Clone for Buf { fn clone() -> Buf { return Buf { n: self.n }; } }\nfn take(b: Buf) -> i32 { return 0; }\n\
+            fn main() { let h = Holder { b: Buf { n: 1 } }; take(h.b.clone()); take(h.b.clone()); }";
+        let mut parser = Parser::new(Lexer::new(source.to_string()).tokenize());
+        let c_code = CGenerator::new().generate_program(parser.parse().unwrap());
+        assert!(c_code.contains("take(Buf_Clone_clone(&h.b));\n    take(Buf_Clone_clone(&h.b));\n    u_drop_Holder(&h);\n}"));
+        assert_eq!(c_code.matches("u_drop_Holder(&h);").count(), 1);
+
+        // An arm binding a payload takes it out of the scrutinee, and
+        // drops what it leaves behind
+        let source = "type Handle { fd: i32 }
+impl Drop for Handle { fn drop() { print(self.fd); } }
+            enum W { Two(Handle, Handle), Zero }
+fn take(h: Handle) -> i32 { return 0; }
+            fn main() { let w = W::Two(Handle { fd: 1 }, Handle { fd: 2 }); match w { W::Two(a, _) => take(a), W::Zero => 0, } }";
+        let mut parser = Parser::new(Lexer::new(source.to_string()).tokenize());
+        let c_code = CGenerator::new().generate_program(parser.parse().unwrap());
+        assert!(c_code.contains("int _live_w = 1;"));
+        assert!(c_code.contains(
+            "struct Handle a = _m0.as.Two._0;\n            _live_w = 0;\n            u_drop_Handle(&_m0.as.Two._1);\n            int _live_a = 1;\n            take((_live_a = 0, a));\n            if (_live_a) u_drop_Handle(&a);"
+        ));
+        assert!(c_code.contains("if (_live_w) u_drop_W(&w);"));
+
+        // Arguments that may drop a value another one uses are evaluated
+        // in order
+        let source = "type Handle { fd: i32 }\nimpl Drop for Handle { fn drop() { print(self.fd); } }\n\
+            fn look(h: &Handle) -> i32 { return h.fd; }\nfn eat(h: Handle) -> i32 { return 0; }\nfn add(a: i32, b: i32) -> i32 { return a + b; }\n\
+            fn main() { let h = Handle { fd: 1 }; let g = Handle { fd: 2 }; let n = add(look(&g), look(&g)); let m = add(look(&h), eat(h)); }";
+        let mut parser = Parser::new(Lexer::new(source.to_string()).tokenize());
+        let c_code = CGenerator::new().generate_program(parser.parse().unwrap());
+        assert!(c_code.contains("int n = add(look((&g)), look((&g)));"));
+        assert!(c_code.contains(
+            "int _arg0;\n    int _arg1;\n    int m = (_arg0 = look((&h)), _arg1 = eat((_live_h = 0, h)), add(_arg0, _arg1));"
+        ));
+
+        // Assigning to a field or element drops the value it held
+        let source = "type Handle { fd: i32 }\nimpl Drop for Handle { fn drop() { print(self.fd); } }\ntype P { h: Handle, n: i32 }\n\
+            fn main() { var p = P { h: Handle { fd: 1 }, n: 0 }; p.h = Handle { fd: 2 }; p.n = 3; var a = [Handle { fd: 4 }]; a[0] = Handle { fd: 5 }; }";
+        let mut parser = Parser::new(Lexer::new(source.to_string()).tokenize());
+        let c_code = CGenerator::new().generate_program(parser.parse().unwrap());
+        assert!(c_code.contains(
+            "struct Handle _d = ((struct Handle){ .fd = 2 });\n        struct Handle* _p = &p.h;\n        u_drop_Handle(_p);\n        *_p = _d;"
+        ));
+        assert!(c_code.contains("(p.n = 3);"));
+        assert!(c_code.contains("struct Handle* _p = &a.data[0];\n        u_drop_Handle(_p);"));
+    }
 }
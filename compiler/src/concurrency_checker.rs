@@ -13,7 +13,7 @@
 // 4. Actor state is private and inaccessible from outside
 // 5. No global mutable state
 
-use crate::parser::{Actor, Declaration, Expression, Function, Statement};
+use crate::parser::{Actor, Declaration, Expression, ExpressionKind, Function, Statement, StatementKind};
 use std::collections::{HashMap, HashSet};
 use std::fmt;
 
@@ -186,10 +186,12 @@ impl ConcurrencyChecker {
     }
 
     fn check_statement(&mut self, actor_name: &str, stmt: &Statement) -> Result<()> {
-        match stmt {
-            Statement::Let { name, value, .. } => {
+        match &stmt.kind {
+            StatementKind::Let { name, value, .. } => {
                 // Check if we're accessing shared memory
-                self.check_expression(actor_name, value)?;
+                if let Some(value) = value {
+                    self.check_expression(actor_name, value)?;
+                }
                 
                 // Track local variables (actor state)
                 if let Some(actor_info) = self.actors.get_mut(actor_name) {
@@ -197,11 +199,11 @@ impl ConcurrencyChecker {
                 }
                 Ok(())
             }
-            Statement::Expr(expr) => {
+            StatementKind::Expr(expr) => {
                 self.check_expression(actor_name, expr)?;
                 Ok(())
             }
-            Statement::If { condition, then_branch, else_branch } => {
+            StatementKind::If { condition, then_branch, else_branch } => {
                 self.check_expression(actor_name, condition)?;
                 
                 for stmt in then_branch {
@@ -215,25 +217,25 @@ impl ConcurrencyChecker {
                 }
                 Ok(())
             }
-            Statement::While { condition, body } => {
+            StatementKind::While { condition, body } => {
                 self.check_expression(actor_name, condition)?;
                 for stmt in body {
                     self.check_statement(actor_name, stmt)?;
                 }
                 Ok(())
             }
-            Statement::For { iterable, body, .. } => {
+            StatementKind::For { iterable, body, .. } => {
                 self.check_expression(actor_name, iterable)?;
                 for stmt in body {
                     self.check_statement(actor_name, stmt)?;
                 }
                 Ok(())
             }
-            Statement::Return(expr) => {
+            StatementKind::Return(expr) => {
                 self.check_expression(actor_name, expr)?;
                 Ok(())
             }
-            Statement::Unsafe { body } => {
+            StatementKind::Unsafe { body } => {
                 // Unsafe blocks are allowed but should be minimized
                 // We still check for concurrency violations
                 for stmt in body {
@@ -246,8 +248,8 @@ impl ConcurrencyChecker {
     }
 
     fn check_expression(&mut self, actor_name: &str, expr: &Expression) -> Result<()> {
-        match expr {
-            Expression::Identifier(name) => {
+        match &expr.kind {
+            ExpressionKind::Identifier(name) => {
                 // Check if this is accessing another actor's state
                 if self.is_foreign_actor_state(actor_name, name) {
                     return Err(ConcurrencyError::SharedMemoryAccess {
@@ -258,7 +260,7 @@ impl ConcurrencyChecker {
                 }
                 Ok(())
             }
-            Expression::FunctionCall { name, arguments } => {
+            ExpressionKind::FunctionCall { name, arguments } => {
                 // Check if this is a message send operation
                 if self.is_message_send(name) {
                     self.check_message_send(actor_name, arguments)?;
@@ -269,39 +271,43 @@ impl ConcurrencyChecker {
                 }
                 Ok(())
             }
-            Expression::MethodCall { receiver, method, arguments } => {
+            ExpressionKind::MethodCall { receiver, method, arguments } => {
                 // Check if receiver is another actor
-                if self.is_actor(receiver) && receiver != actor_name {
-                    // This is inter-actor communication
-                    if method != "send" && method != "spawn" {
-                        return Err(ConcurrencyError::ActorStateLeakage {
-                            actor: receiver.clone(),
-                            field: method.clone(),
-                            location: "method call".to_string(),
-                        });
+                if let ExpressionKind::Identifier(receiver) = &receiver.kind {
+                    if self.is_actor(receiver) && receiver != actor_name {
+                        // This is inter-actor communication
+                        if method != "send" && method != "spawn" {
+                            return Err(ConcurrencyError::ActorStateLeakage {
+                                actor: receiver.clone(),
+                                field: method.clone(),
+                                location: "method call".to_string(),
+                            });
+                        }
                     }
                 }
+                self.check_expression(actor_name, receiver)?;
                 
                 for arg in arguments {
                     self.check_expression(actor_name, arg)?;
                 }
                 Ok(())
             }
-            Expression::Binary { left, right, .. } => {
+            ExpressionKind::Binary { left, right, .. } => {
                 self.check_expression(actor_name, left)?;
                 self.check_expression(actor_name, right)?;
                 Ok(())
             }
-            Expression::Unary { operand, .. } => {
+            ExpressionKind::Unary { operand, .. } => {
                 self.check_expression(actor_name, operand)?;
                 Ok(())
             }
-            Expression::Assignment { target, value } => {
+            ExpressionKind::Assignment { target, value } => {
                 // Check if we're assigning to foreign actor state
-                if self.is_foreign_actor_state(actor_name, target) {
+                let root = target.root_variable().unwrap_or_default();
+                if self.is_foreign_actor_state(actor_name, root) {
                     return Err(ConcurrencyError::SharedMemoryAccess {
                         actor: actor_name.to_string(),
-                        variable: target.clone(),
+                        variable: root.to_string(),
                         location: "assignment".to_string(),
                     });
                 }
@@ -309,6 +315,44 @@ impl ConcurrencyChecker {
                 self.check_expression(actor_name, value)?;
                 Ok(())
             }
+            ExpressionKind::EnumVariant { payload, .. } => {
+                for (_, value) in payload.fields() {
+                    self.check_expression(actor_name, value)?;
+                }
+                Ok(())
+            }
+            ExpressionKind::StructLiteral { fields, .. } => {
+                for (_, value) in fields {
+                    self.check_expression(actor_name, value)?;
+                }
+                Ok(())
+            }
+            ExpressionKind::ArrayLiteral(elements) => {
+                for element in elements {
+                    self.check_expression(actor_name, element)?;
+                }
+                Ok(())
+            }
+            ExpressionKind::FieldAccess { object, .. } => self.check_expression(actor_name, object),
+            ExpressionKind::Index { object, index } => {
+                self.check_expression(actor_name, object)?;
+                self.check_expression(actor_name, index)
+            }
+            ExpressionKind::Range { start, end, step, .. } => {
+                self.check_expression(actor_name, start)?;
+                self.check_expression(actor_name, end)?;
+                match step {
+                    Some(step) => self.check_expression(actor_name, step),
+                    None => Ok(()),
+                }
+            }
+            // A closure body runs in the actor that creates it
+            ExpressionKind::Closure { body, .. } => {
+                for stmt in body {
+                    self.check_statement(actor_name, stmt)?;
+                }
+                Ok(())
+            }
             _ => Ok(()),
         }
     }
@@ -316,7 +360,7 @@ impl ConcurrencyChecker {
     fn check_message_send(&self, actor_name: &str, arguments: &[Expression]) -> Result<()> {
         // Rule: Messages must be owned values, not borrowed
         for arg in arguments {
-            if let Expression::Identifier(var_name) = arg {
+            if let ExpressionKind::Identifier(var_name) = &arg.kind {
                 // Check if this is a reference (simplified check)
                 if var_name.starts_with('&') {
                     return Err(ConcurrencyError::BorrowedMessage {
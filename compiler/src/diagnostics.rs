@@ -31,16 +31,39 @@ impl fmt::Display for Severity {
     }
 }
 
+/// Identifier of a source file within a compilation session
+pub type FileId = usize;
+
 /// Location in source code
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
 pub struct Location {
     pub line: usize,
+    /// Column in characters, starting at 1
     pub column: usize,
+    /// Column in UTF-16 code units, starting at 1, as LSP clients count it
+    pub utf16_column: usize,
+    /// Byte offset from the start of the file
+    pub offset: usize,
 }
 
 impl Location {
     pub fn new(line: usize, column: usize) -> Self {
-        Self { line, column }
+        Self::with_offset(line, column, 0)
+    }
+
+    /// Location in ASCII text, where characters and UTF-16 units coincide
+    pub fn with_offset(line: usize, column: usize, offset: usize) -> Self {
+        Self {
+            line,
+            column,
+            utf16_column: column,
+            offset,
+        }
+    }
+
+    /// Zero-based `(line, character)` pair of an LSP `Position`
+    pub fn lsp_position(&self) -> (usize, usize) {
+        (self.line.saturating_sub(1), self.utf16_column.saturating_sub(1))
     }
 }
 
@@ -51,23 +74,51 @@ impl fmt::Display for Location {
 }
 
 /// Span in source code (start and end locations)
-#[derive(Debug, Clone, Copy)]
+///
+/// `end` is exclusive: it points just past the last character of the span.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
 pub struct Span {
+    pub file_id: FileId,
     pub start: Location,
     pub end: Location,
 }
 
 impl Span {
     pub fn new(start: Location, end: Location) -> Self {
-        Self { start, end }
+        Self {
+            file_id: 0,
+            start,
+            end,
+        }
     }
 
     pub fn single(loc: Location) -> Self {
         Self {
+            file_id: 0,
             start: loc,
             end: loc,
         }
     }
+
+    pub fn with_file(mut self, file_id: FileId) -> Self {
+        self.file_id = file_id;
+        self
+    }
+
+    /// Span covering both `self` and `other` (assumes `other` ends after `self` starts)
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            file_id: self.file_id,
+            start: self.start,
+            end: other.end,
+        }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.start)
+    }
 }
 
 /// Diagnostic message with context
@@ -147,6 +198,7 @@ impl fmt::Display for Diagnostic {
 }
 
 /// Diagnostic collector for gathering multiple diagnostics
+#[derive(Debug, Clone)]
 pub struct DiagnosticCollector {
     diagnostics: Vec<Diagnostic>,
 }
@@ -294,6 +346,16 @@ mod tests {
         assert!(diag.span.is_some());
     }
 
+    #[test]
+    fn test_span_join() {
+        let a = Span::new(Location::with_offset(1, 1, 0), Location::with_offset(1, 3, 2));
+        let b = Span::new(Location::with_offset(1, 5, 4), Location::with_offset(1, 9, 8));
+        let joined = a.to(b);
+        assert_eq!(joined.start, a.start);
+        assert_eq!(joined.end, b.end);
+        assert_eq!(joined.end.offset - joined.start.offset, 8);
+    }
+
     #[test]
     fn test_diagnostic_collector() {
         let mut collector = DiagnosticCollector::new();
@@ -0,0 +1,410 @@
+// exhaustiveness.rs — U v0.8 Match Checking
+// MIT License — Copyright (c) 2025 Webcien and U contributors
+//
+// Exhaustiveness and reachability of `match` arms
+// Features:
+// - Usefulness over constructor patterns (Maranget, "Warnings for pattern
+//   matching", 2007)
+// - Complete constructor sets for enums, Option, Result, bool, tuples and
+//   structs; every other type has too many values to list
+// - An example of an unmatched value for the error message
+// - Guarded arms may be unreachable but never make a match exhaustive
+//
+// Patterns are assumed to be well-typed: the type checker validates them
+// before asking for a report.
+
+use crate::parser::{EnumDef, Literal, Pattern, PatternKind, Type, TypeDef, VariantPayload};
+use std::collections::HashMap;
+
+/// Outcome of checking the arms of one `match`
+#[derive(Debug)]
+pub struct MatchReport {
+    /// Indices of arms that no value reaches
+    pub unreachable: Vec<usize>,
+    /// A value no arm matches, written as a pattern
+    pub missing: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Constructor {
+    /// Enum variant, `Some`/`None`/`Ok`/`Err` or `true`/`false`
+    Variant(String),
+    /// The only constructor of a tuple or struct
+    Single,
+    /// Literal of a type whose values cannot be listed
+    Literal(String),
+}
+
+/// Named fields of a constructor (tuple fields are `0`, `1`, ...)
+type Fields = Vec<(String, Type)>;
+
+#[derive(Debug, Clone)]
+enum Pat {
+    Wild,
+    Ctor(Constructor, Vec<Pat>),
+}
+
+pub struct MatchChecker<'a> {
+    enums: &'a HashMap<String, EnumDef>,
+    structs: &'a HashMap<String, TypeDef>,
+}
+
+impl<'a> MatchChecker<'a> {
+    pub fn new(enums: &'a HashMap<String, EnumDef>, structs: &'a HashMap<String, TypeDef>) -> Self {
+        Self { enums, structs }
+    }
+
+    /// Check the arms of a match on a value of type `ty`; each arm is its
+    /// pattern and whether it has a guard
+    pub fn check(&self, ty: &Type, arms: &[(&Pattern, bool)]) -> MatchReport {
+        let tys = [ty.clone()];
+        let mut rows: Vec<Vec<Pat>> = Vec::new();
+        let mut unreachable = Vec::new();
+        for (index, (pattern, guarded)) in arms.iter().enumerate() {
+            let row = vec![self.lower(pattern, ty)];
+            if !self.is_useful(&rows, &row, &tys) {
+                unreachable.push(index);
+            }
+            if !guarded {
+                rows.push(row);
+            }
+        }
+        let missing = self
+            .witness(&rows, &tys)
+            .map(|witness| self.render(&witness[0], ty));
+        MatchReport {
+            unreachable,
+            missing,
+        }
+    }
+
+    /// Every constructor of `ty` with its named fields (tuple fields are
+    /// `0`, `1`, ...), or None if the type's values cannot be listed
+    fn constructors(&self, ty: &Type) -> Option<Vec<(Constructor, Fields)>> {
+        let variant = |name: &str| Constructor::Variant(name.to_string());
+        let field = |ty: &Type| vec![("0".to_string(), ty.clone())];
+        match ty {
+            Type::Bool => Some(vec![(variant("true"), vec![]), (variant("false"), vec![])]),
+            Type::Option(inner) => Some(vec![(variant("Some"), field(inner)), (variant("None"), vec![])]),
+            Type::Result(ok, err) => Some(vec![(variant("Ok"), field(ok)), (variant("Err"), field(err))]),
+            Type::Tuple(items) => Some(vec![(
+                Constructor::Single,
+                items.iter().enumerate().map(|(i, ty)| (i.to_string(), ty.clone())).collect(),
+            )]),
+            Type::Custom(name) => {
+                if let Some(def) = self.enums.get(name) {
+                    Some(
+                        def.variants
+                            .iter()
+                            .map(|v| {
+                                let fields = v.payload.fields();
+                                let fields = fields.into_iter().map(|(n, ty)| (n, ty.clone())).collect();
+                                (variant(&v.name), fields)
+                            })
+                            .collect(),
+                    )
+                } else {
+                    self.structs
+                        .get(name)
+                        .map(|def| vec![(Constructor::Single, def.fields.clone())])
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Field types of constructor `ctor` of `ty`
+    fn field_types(&self, ty: &Type, ctor: &Constructor) -> Vec<Type> {
+        self.fields(ty, ctor).into_iter().map(|(_, ty)| ty).collect()
+    }
+
+    fn fields(&self, ty: &Type, ctor: &Constructor) -> Fields {
+        self.constructors(ty)
+            .and_then(|ctors| ctors.into_iter().find(|(c, _)| c == ctor))
+            .map(|(_, fields)| fields)
+            .unwrap_or_default()
+    }
+
+    fn lower(&self, pattern: &Pattern, ty: &Type) -> Pat {
+        match &pattern.kind {
+            PatternKind::Wildcard | PatternKind::Binding(_) => Pat::Wild,
+            PatternKind::Literal(Literal::Boolean(b)) => {
+                Pat::Ctor(Constructor::Variant(b.to_string()), Vec::new())
+            }
+            PatternKind::Literal(lit) => Pat::Ctor(Constructor::Literal(literal_key(lit)), Vec::new()),
+            PatternKind::Tuple(items) => {
+                let tys = self.field_types(ty, &Constructor::Single);
+                let args = items.iter().zip(&tys).map(|(p, ty)| self.lower(p, ty)).collect();
+                Pat::Ctor(Constructor::Single, args)
+            }
+            PatternKind::Struct { fields, .. } => {
+                let ctor = Constructor::Single;
+                let args = self.lower_fields(ty, &ctor, fields.iter().map(|(n, p)| (n.clone(), p)).collect());
+                Pat::Ctor(ctor, args)
+            }
+            PatternKind::Variant { variant, payload, .. } => {
+                let ctor = Constructor::Variant(variant.clone());
+                let given = match payload {
+                    VariantPayload::Unit => Vec::new(),
+                    _ => payload.fields(),
+                };
+                let args = self.lower_fields(ty, &ctor, given);
+                Pat::Ctor(ctor, args)
+            }
+        }
+    }
+
+    /// Sub-patterns in declaration order; fields left out match anything
+    fn lower_fields(&self, ty: &Type, ctor: &Constructor, given: Vec<(String, &Pattern)>) -> Vec<Pat> {
+        self.fields(ty, ctor)
+            .iter()
+            .map(|(name, field_ty)| match given.iter().find(|(n, _)| n == name) {
+                Some((_, pattern)) => self.lower(pattern, field_ty),
+                None => Pat::Wild,
+            })
+            .collect()
+    }
+
+    /// If every constructor of `ty` heads some row, all of them
+    fn complete_signature(&self, rows: &[Vec<Pat>], ty: &Type) -> Option<Vec<(Constructor, Vec<Type>)>> {
+        let all = self.constructors(ty)?;
+        let used = head_constructors(rows);
+        if all.iter().all(|(c, _)| used.contains(&c)) {
+            Some(
+                all.into_iter()
+                    .map(|(c, fields)| (c, fields.into_iter().map(|(_, ty)| ty).collect()))
+                    .collect(),
+            )
+        } else {
+            None
+        }
+    }
+
+    /// Whether some value matches `row` but none of `rows`
+    fn is_useful(&self, rows: &[Vec<Pat>], row: &[Pat], tys: &[Type]) -> bool {
+        let Some((head, rest)) = row.split_first() else {
+            return rows.is_empty();
+        };
+        match head {
+            Pat::Ctor(ctor, args) => {
+                let tys = [self.field_types(&tys[0], ctor), tys[1..].to_vec()].concat();
+                let row = [args.clone(), rest.to_vec()].concat();
+                self.is_useful(&specialize(rows, ctor, args.len()), &row, &tys)
+            }
+            Pat::Wild => match self.complete_signature(rows, &tys[0]) {
+                Some(ctors) => ctors.into_iter().any(|(ctor, fields)| {
+                    let row = [vec![Pat::Wild; fields.len()], rest.to_vec()].concat();
+                    let tys = [fields.clone(), tys[1..].to_vec()].concat();
+                    self.is_useful(&specialize(rows, &ctor, fields.len()), &row, &tys)
+                }),
+                None => self.is_useful(&default_rows(rows), rest, &tys[1..]),
+            },
+        }
+    }
+
+    /// Values, one per column, that no row matches
+    fn witness(&self, rows: &[Vec<Pat>], tys: &[Type]) -> Option<Vec<Pat>> {
+        let Some((ty, rest)) = tys.split_first() else {
+            return if rows.is_empty() { Some(Vec::new()) } else { None };
+        };
+        if let Some(ctors) = self.complete_signature(rows, ty) {
+            for (ctor, fields) in ctors {
+                let arity = fields.len();
+                let tys = [fields, rest.to_vec()].concat();
+                if let Some(mut args) = self.witness(&specialize(rows, &ctor, arity), &tys) {
+                    let tail = args.split_off(arity);
+                    return Some([vec![Pat::Ctor(ctor, args)], tail].concat());
+                }
+            }
+            return None;
+        }
+
+        let mut witness = self.witness(&default_rows(rows), rest)?;
+        let used = head_constructors(rows);
+        let head = self
+            .constructors(ty)
+            .and_then(|ctors| ctors.into_iter().find(|(c, _)| !used.contains(&c)))
+            .map(|(ctor, fields)| Pat::Ctor(ctor, vec![Pat::Wild; fields.len()]))
+            .unwrap_or(Pat::Wild);
+        witness.insert(0, head);
+        Some(witness)
+    }
+
+    /// Write a value of type `ty` in pattern syntax
+    fn render(&self, pat: &Pat, ty: &Type) -> String {
+        let (ctor, args) = match pat {
+            Pat::Wild => return "_".to_string(),
+            Pat::Ctor(ctor, args) => (ctor, args),
+        };
+        let fields = self.fields(ty, ctor);
+        let rendered: Vec<String> = args
+            .iter()
+            .zip(&fields)
+            .map(|(arg, (_, ty))| self.render(arg, ty))
+            .collect();
+        let name = match ctor {
+            Constructor::Variant(name) | Constructor::Literal(name) => name.clone(),
+            Constructor::Single => String::new(),
+        };
+
+        match ty {
+            Type::Tuple(_) => format!("({})", rendered.join(", ")),
+            Type::Custom(type_name) => {
+                let prefix = match ctor {
+                    Constructor::Single => type_name.clone(),
+                    _ => format!("{}::{}", type_name, name),
+                };
+                let is_tuple = self
+                    .enums
+                    .get(type_name)
+                    .and_then(|def| def.variants.iter().find(|v| v.name == name))
+                    .is_some_and(|v| matches!(v.payload, VariantPayload::Tuple(_)));
+                if rendered.is_empty() {
+                    prefix
+                } else if is_tuple {
+                    format!("{}({})", prefix, rendered.join(", "))
+                } else if args.iter().all(|arg| matches!(arg, Pat::Wild)) {
+                    format!("{} {{ .. }}", prefix)
+                } else {
+                    let fields: Vec<String> = fields
+                        .iter()
+                        .zip(&rendered)
+                        .map(|((field, _), value)| format!("{}: {}", field, value))
+                        .collect();
+                    format!("{} {{ {} }}", prefix, fields.join(", "))
+                }
+            }
+            _ if rendered.is_empty() => name,
+            _ => format!("{}({})", name, rendered.join(", ")),
+        }
+    }
+}
+
+fn head_constructors(rows: &[Vec<Pat>]) -> Vec<&Constructor> {
+    rows.iter()
+        .filter_map(|row| match row.first() {
+            Some(Pat::Ctor(ctor, _)) => Some(ctor),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Rows that can match a value built with `ctor`, with its `arity` fields
+/// in place of the first column
+fn specialize(rows: &[Vec<Pat>], ctor: &Constructor, arity: usize) -> Vec<Vec<Pat>> {
+    rows.iter()
+        .filter_map(|row| {
+            let (head, rest) = row.split_first()?;
+            let args = match head {
+                Pat::Ctor(c, args) if c == ctor => args.clone(),
+                Pat::Ctor(..) => return None,
+                Pat::Wild => vec![Pat::Wild; arity],
+            };
+            Some([args, rest.to_vec()].concat())
+        })
+        .collect()
+}
+
+/// Rows that match whatever the first column holds, without that column
+fn default_rows(rows: &[Vec<Pat>]) -> Vec<Vec<Pat>> {
+    rows.iter()
+        .filter(|row| matches!(row.first(), Some(Pat::Wild)))
+        .map(|row| row[1..].to_vec())
+        .collect()
+}
+
+fn literal_key(lit: &Literal) -> String {
+    match lit {
+        Literal::Integer(n, _) => n.to_string(),
+        Literal::Float(x, _) => format!("{:?}", x),
+        Literal::String(s) => format!("{:?}", s),
+        Literal::Char(c) => format!("{:?}", c),
+        Literal::Boolean(b) => b.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::{Declaration, ExpressionKind, Parser, StatementKind};
+
+    /// Report for the only `match` in `main`, whose scrutinee has type `ty`
+    fn check_arms(source: &str, ty: Type) -> MatchReport {
+        let mut parser = Parser::new(Lexer::new(source.to_string()).tokenize());
+        let mut enums = HashMap::new();
+        let mut structs = HashMap::new();
+        let mut arms = None;
+        for decl in parser.parse().unwrap() {
+            match decl {
+                Declaration::Enum(e) => {
+                    enums.insert(e.name.clone(), e);
+                }
+                Declaration::TypeDef(t) => {
+                    structs.insert(t.name.clone(), t);
+                }
+                Declaration::Function(f) => {
+                    if let StatementKind::Expr(expr) = &f.body[0].kind {
+                        if let ExpressionKind::Match { arms: a, .. } = &expr.kind {
+                            arms = Some(a.clone());
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        let arms = arms.expect("a match in main");
+        let arms: Vec<(&Pattern, bool)> = arms.iter().map(|a| (&a.pattern, a.guard.is_some())).collect();
+        MatchChecker::new(&enums, &structs).check(&ty, &arms)
+    }
+
+    const SHAPE: &str = "enum Shape { Circle(i32), Rect { w: i32, h: i32 }, Empty }\n";
+
+    #[test]
+    fn test_exhaustive_enum() {
+        let source = format!(
+            "{}fn main() {{ match s {{ Shape::Circle(r) => 1, Shape::Rect {{ w, .. }} => 2, Shape::Empty => 3 }} }}",
+            SHAPE
+        );
+        let report = check_arms(&source, Type::Custom("Shape".to_string()));
+        assert!(report.unreachable.is_empty());
+        assert_eq!(report.missing, None);
+    }
+
+    #[test]
+    fn test_missing_variants() {
+        let source = format!("{}fn main() {{ match s {{ Shape::Circle(0) => 1, Shape::Rect {{ .. }} => 2, Shape::Empty => 3 }} }}", SHAPE);
+        let report = check_arms(&source, Type::Custom("Shape".to_string()));
+        assert_eq!(report.missing.as_deref(), Some("Shape::Circle(_)"));
+
+        let source = format!("{}fn main() {{ match s {{ Shape::Circle(_) => 1, Shape::Rect {{ w: 1, h }} => 2, Shape::Empty => 3 }} }}", SHAPE);
+        let report = check_arms(&source, Type::Custom("Shape".to_string()));
+        assert_eq!(report.missing.as_deref(), Some("Shape::Rect { .. }"));
+    }
+
+    #[test]
+    fn test_guards_and_unreachable_arms() {
+        let source = "fn main() { match o { Some(x) if x > 0 => 1, None => 2 } }";
+        let report = check_arms(source, Type::Option(Box::new(Type::I32)));
+        assert_eq!(report.missing.as_deref(), Some("Some(_)"));
+
+        let source = "fn main() { match o { Some(x) if x > 0 => 1, _ => 2, None => 3 } }";
+        let report = check_arms(source, Type::Option(Box::new(Type::I32)));
+        assert_eq!(report.unreachable, vec![2]);
+        assert_eq!(report.missing, None);
+    }
+
+    #[test]
+    fn test_tuples_bools_and_literals() {
+        let ty = Type::Tuple(vec![Type::Bool, Type::Bool]);
+        let source = "fn main() { match (a, b) { (true, _) => 1, (false, true) => 2 } }";
+        assert_eq!(check_arms(source, ty.clone()).missing.as_deref(), Some("(false, false)"));
+        let source = "fn main() { match (a, b) { (true, _) => 1, (_, true) => 2, (false, false) => 3 } }";
+        assert_eq!(check_arms(source, ty).missing, None);
+
+        let source = "fn main() { match n { 1 => 1, 2 => 2, 1 => 3 } }";
+        let report = check_arms(source, Type::I32);
+        assert_eq!(report.unreachable, vec![2]);
+        assert_eq!(report.missing.as_deref(), Some("_"));
+    }
+}
@@ -5,11 +5,14 @@
 // Features:
 // - Consistent indentation (4 spaces)
 // - Proper spacing around operators
-// - Line length management
-// - Comment preservation
+// - Comment and blank line preservation
 // - Configurable formatting rules
+//
+// Works on the lossless syntax tree, so the output is printed from tokens
+// and their trivia rather than patched character by character.
 
-use crate::lexer::{Lexer, TokenType};
+use crate::lexer::{LosslessToken, TokenType, TriviaKind};
+use crate::syntax::{starts_declaration, SyntaxElement, SyntaxKind, SyntaxNode, SyntaxTree};
 
 /// Formatting configuration
 #[derive(Debug, Clone)]
@@ -49,140 +52,381 @@ impl Formatter {
 
     /// Format source code
     pub fn format(&self, source: &str) -> String {
-        let mut output = String::new();
-        let mut indent_level = 0;
-        let mut in_string = false;
-        let mut in_comment = false;
-        let mut prev_char = ' ';
-
-        for ch in source.chars() {
-            // Handle strings
-            if ch == '"' && prev_char != '\\' {
-                in_string = !in_string;
-                output.push(ch);
-                prev_char = ch;
-                continue;
-            }
+        let tree = SyntaxTree::parse(source);
+        let mut printer = Printer::new(&self.config);
+        printer.node(tree.root(), 0);
+        printer.finish()
+    }
 
-            if in_string {
-                output.push(ch);
-                prev_char = ch;
-                continue;
-            }
+    /// Check if code is already formatted
+    pub fn is_formatted(&self, source: &str) -> bool {
+        self.format(source) == source
+    }
+}
 
-            // Handle comments
-            if ch == '/' && prev_char == '/' {
-                in_comment = true;
-                output.push(ch);
-                prev_char = ch;
-                continue;
-            }
+/// Role of a token within its enclosing block
+#[derive(Clone, Copy)]
+enum Brace {
+    None,
+    /// Opening `{`; `true` if the block stays on one line
+    Open(bool),
+    /// Closing `}`; `true` if the block stays on one line
+    Close(bool),
+}
 
-            if in_comment && ch == '\n' {
-                in_comment = false;
-                output.push(ch);
-                prev_char = ch;
-                continue;
-            }
+struct Printer<'a> {
+    config: &'a FormatterConfig,
+    output: String,
+    prev: Option<TokenType>,
+    prev2: Option<TokenType>,
+    /// No space may follow `prev` (prefix operator, generic `<`, the `=`
+    /// of `..=` or the `|` opening closure parameters)
+    prev_tight: bool,
+    /// Between the `|`s around closure parameters
+    closure_params: bool,
+    /// Inside a `use` declaration, whose item list is `{a, b}`
+    in_use: bool,
+    prev_generic_close: bool,
+    /// The next token starts a new line (after `;`, `{` or `}`)
+    break_line: bool,
+    /// A comment ending its line was just written, so a new line is mandatory
+    after_comment: bool,
+    /// An inline `/* */` comment was just written and needs a space after it
+    after_block_comment: bool,
+    generic_depth: usize,
+    /// Open `(` and `[`, indenting continuation lines
+    nesting: usize,
+}
 
-            if in_comment {
-                output.push(ch);
-                prev_char = ch;
-                continue;
-            }
+impl<'a> Printer<'a> {
+    fn new(config: &'a FormatterConfig) -> Self {
+        Self {
+            config,
+            output: String::new(),
+            prev: None,
+            prev2: None,
+            prev_tight: false,
+            closure_params: false,
+            in_use: false,
+            prev_generic_close: false,
+            break_line: false,
+            after_comment: false,
+            after_block_comment: false,
+            generic_depth: 0,
+            nesting: 0,
+        }
+    }
 
-            // Handle indentation and braces
-            match ch {
-                '{' => {
-                    if self.config.spaces_around_operators && prev_char != ' ' && prev_char != '\n'
-                    {
-                        output.push(' ');
-                    }
-                    output.push('{');
-                    output.push('\n');
-                    indent_level += 1;
-                    self.add_indent(&mut output, indent_level);
-                }
-                '}' => {
-                    if indent_level > 0 {
-                        indent_level -= 1;
-                    }
-                    // Remove trailing whitespace on current line
-                    while output.ends_with(' ') || output.ends_with('\t') {
-                        output.pop();
-                    }
-                    if !output.ends_with('\n') {
-                        output.push('\n');
-                    }
-                    self.add_indent(&mut output, indent_level);
-                    output.push('}');
-                    output.push('\n');
-                }
-                ';' => {
-                    output.push(';');
-                    output.push('\n');
-                    self.add_indent(&mut output, indent_level);
-                }
-                ',' => {
-                    output.push(',');
-                    if self.config.spaces_after_comma {
-                        output.push(' ');
-                    }
-                }
-                ':' => {
-                    output.push(':');
-                    if self.config.spaces_after_colon {
-                        output.push(' ');
-                    }
-                }
-                '+' | '-' | '*' | '/' | '%' | '=' | '<' | '>' | '!' | '&' | '|' => {
-                    if self.config.spaces_around_operators && prev_char != ' ' && prev_char != '\n'
-                    {
-                        output.push(' ');
-                    }
-                    output.push(ch);
-                    if self.config.spaces_around_operators {
-                        output.push(' ');
-                    }
+    fn finish(mut self) -> String {
+        let trimmed = self.output.trim_end().len();
+        self.output.truncate(trimmed);
+        if !self.output.is_empty() {
+            self.output.push('\n');
+        }
+        self.output
+    }
+
+    fn node(&mut self, node: &SyntaxNode, depth: usize) {
+        if node.kind == SyntaxKind::Block {
+            self.block(node, depth);
+            return;
+        }
+        for child in &node.children {
+            self.element(child, depth);
+        }
+    }
+
+    fn element(&mut self, element: &SyntaxElement, depth: usize) {
+        match element {
+            SyntaxElement::Node(node) => self.node(node, depth),
+            SyntaxElement::Token(token) => self.token(token, depth, Brace::None),
+        }
+    }
+
+    fn block(&mut self, block: &SyntaxNode, depth: usize) {
+        let inline = is_inline(block);
+        let last = block.children.len() - 1;
+        for (i, child) in block.children.iter().enumerate() {
+            match child {
+                SyntaxElement::Token(token) if i == 0 => {
+                    self.token(token, depth, Brace::Open(inline))
                 }
-                ' ' | '\t' => {
-                    // Collapse multiple spaces
-                    if !output.ends_with(' ') && !output.ends_with('\n') {
-                        output.push(' ');
-                    }
+                SyntaxElement::Token(token)
+                    if i == last && token.token.kind == TokenType::RightBrace =>
+                {
+                    self.token(token, depth, Brace::Close(inline))
                 }
-                '\n' => {
-                    // Remove trailing whitespace
-                    while output.ends_with(' ') || output.ends_with('\t') {
-                        output.pop();
-                    }
-                    if !output.ends_with('\n') {
-                        output.push('\n');
+                _ => self.element(child, depth + 1),
+            }
+        }
+    }
+
+    fn token(&mut self, token: &LosslessToken, depth: usize, brace: Brace) {
+        // Comments inside a block that ends here belong to the block body
+        let comment_depth = match brace {
+            Brace::Close(false) => depth + 1,
+            _ => depth,
+        };
+        let mut newlines = 0;
+        for (i, trivia) in token.leading_trivia.iter().enumerate() {
+            match trivia.kind {
+                TriviaKind::Whitespace => {}
+                TriviaKind::Newline => newlines += 1,
+                TriviaKind::LineComment | TriviaKind::BlockComment => {
+                    if newlines == 0 && !self.output.is_empty() {
+                        // Comment on the previous token's line
+                        if !self.output.ends_with(['(', '[']) {
+                            self.output.push(' ');
+                        }
+                    } else {
+                        self.new_line(newlines, comment_depth + self.nesting);
                     }
-                }
-                _ => {
-                    output.push(ch);
+                    self.output.push_str(trivia.text.trim_end());
+                    // A block comment followed by code on the same line stays inline
+                    let ends_line = trivia.kind == TriviaKind::LineComment
+                        || token.leading_trivia[i + 1..]
+                            .iter()
+                            .find(|t| t.kind != TriviaKind::Whitespace)
+                            .is_some_and(|t| t.kind == TriviaKind::Newline);
+                    self.after_comment = ends_line;
+                    self.after_block_comment = !ends_line;
+                    newlines = 0;
                 }
             }
+        }
+
+        let kind = &token.token.kind;
+        if *kind == TokenType::Eof {
+            return;
+        }
 
-            prev_char = ch;
+        let unary = *kind == TokenType::Not
+            || (*kind == TokenType::Minus && !self.prev_ends_operand());
+        let generic_open = *kind == TokenType::LessThan && self.opens_generic();
+        let generic_close = *kind == TokenType::GreaterThan && self.generic_depth > 0;
+        let inclusive_range = *kind == TokenType::Equal
+            && self.prev == Some(TokenType::Dot)
+            && self.prev2 == Some(TokenType::Dot);
+        let closure_open = *kind == TokenType::Pipe && !self.closure_params;
+
+        if self.starts_line(kind, depth, brace, newlines) {
+            self.new_line(newlines, depth + self.nesting);
+        } else if self.after_block_comment
+            || self.needs_space(kind, unary, generic_open || generic_close)
+        {
+            self.output.push(' ');
+        }
+        if is_doc_comment(kind) {
+            self.output.push_str(token.text.trim_end());
+        } else {
+            self.output.push_str(&token.text);
         }
 
-        output
+        match kind {
+            TokenType::LeftParen | TokenType::LeftBracket => self.nesting += 1,
+            TokenType::RightParen | TokenType::RightBracket => {
+                self.nesting = self.nesting.saturating_sub(1)
+            }
+            _ => {}
+        }
+        if generic_open {
+            self.generic_depth += 1;
+        }
+        if generic_close {
+            self.generic_depth -= 1;
+        }
+        if *kind == TokenType::Pipe {
+            self.closure_params = closure_open;
+        }
+        match kind {
+            TokenType::Use => self.in_use = true,
+            TokenType::Semicolon => self.in_use = false,
+            _ => {}
+        }
+        self.prev_tight = unary || generic_open || inclusive_range || closure_open;
+        self.prev_generic_close = generic_close;
+        self.break_line = match brace {
+            Brace::Open(inline) | Brace::Close(inline) => !inline,
+            Brace::None => {
+                (*kind == TokenType::Semicolon && self.nesting == 0) || is_doc_comment(kind)
+            }
+        };
+        self.after_comment = false;
+        self.after_block_comment = false;
+        self.prev2 = self.prev.replace(kind.clone());
     }
 
-    fn add_indent(&self, output: &mut String, level: usize) {
-        for _ in 0..level * self.config.indent_size {
-            output.push(' ');
+    fn starts_line(&self, kind: &TokenType, depth: usize, brace: Brace, newlines: usize) -> bool {
+        let prev = match &self.prev {
+            Some(prev) => prev,
+            None => return self.after_comment,
+        };
+        if self.after_comment || is_doc_comment(kind) {
+            return true;
+        }
+        // `} else`, `})`, `};` and friends stay on the closing brace's line
+        let attached = *prev == TokenType::RightBrace
+            && matches!(
+                kind,
+                TokenType::Else
+                    | TokenType::RightParen
+                    | TokenType::RightBracket
+                    | TokenType::Comma
+                    | TokenType::Semicolon
+                    | TokenType::Dot
+            );
+        match brace {
+            Brace::Close(false) => *prev != TokenType::LeftBrace,
+            _ if self.break_line && !attached => true,
+            _ if depth == 0 && self.nesting == 0 && starts_declaration(kind) => *prev != TokenType::Pub,
+            // Keep the author's line breaks in lists and between items
+            _ => {
+                newlines > 0
+                    && (*prev == TokenType::Comma || (*prev == TokenType::RightBrace && !attached))
+            }
         }
     }
 
-    /// Check if code is already formatted
-    pub fn is_formatted(&self, source: &str) -> bool {
-        self.format(source) == source
+    fn needs_space(&self, kind: &TokenType, unary: bool, generic: bool) -> bool {
+        let prev = match &self.prev {
+            Some(prev) => prev,
+            None => return false,
+        };
+        if self.prev_tight {
+            return false;
+        }
+        match (prev, kind) {
+            // Rest patterns: `Point { x, .. }`
+            (TokenType::LeftBrace | TokenType::Comma, TokenType::Dot) => true,
+            (TokenType::Dot, TokenType::RightBrace) => true,
+            // Closure parameters: `|x: i32|`
+            (_, TokenType::Pipe) if self.closure_params => false,
+            // Imported items: `use shapes::{area, Shape};`
+            (TokenType::LeftBrace, _) | (_, TokenType::RightBrace) if self.in_use => false,
+            (TokenType::LeftParen | TokenType::LeftBracket | TokenType::Dot, _) => false,
+            // Paths: `Shape::Circle`
+            (TokenType::ColonColon, _) | (_, TokenType::ColonColon) => false,
+            (TokenType::LeftBrace, TokenType::RightBrace) => false,
+            (
+                _,
+                TokenType::RightParen
+                | TokenType::RightBracket
+                | TokenType::Comma
+                | TokenType::Semicolon
+                | TokenType::Dot
+                | TokenType::Colon,
+            ) => false,
+            _ if generic => false,
+            // Calls, indexing and parameter lists
+            (
+                TokenType::Identifier(_) | TokenType::RightParen | TokenType::RightBracket,
+                TokenType::LeftParen | TokenType::LeftBracket,
+            ) => false,
+            (_, TokenType::LeftParen) if self.prev_generic_close => false,
+            _ if self.prev_generic_close => true,
+            (TokenType::Comma, _) => self.config.spaces_after_comma,
+            (TokenType::Colon, _) => self.config.spaces_after_colon,
+            _ if is_binary_operator(prev) || (is_binary_operator(kind) && !unary) => {
+                self.config.spaces_around_operators
+            }
+            _ => true,
+        }
+    }
+
+    fn prev_ends_operand(&self) -> bool {
+        matches!(
+            self.prev,
+            Some(
+                TokenType::Identifier(_)
+                    | TokenType::StringLiteral(_)
+                    | TokenType::CharLiteral(_)
+                    | TokenType::IntegerLiteral(..)
+                    | TokenType::FloatLiteral(..)
+                    | TokenType::RightParen
+                    | TokenType::RightBracket
+            )
+        )
+    }
+
+    /// Whether a `<` opens generic arguments (`Option<`, `Vec<`, `fn id<`)
+    /// rather than comparing
+    fn opens_generic(&self) -> bool {
+        match &self.prev {
+            Some(TokenType::Option | TokenType::Result) => true,
+            Some(TokenType::Identifier(name)) => {
+                name == "impl"
+                    || name.starts_with(|c: char| c.is_uppercase())
+                    || match &self.prev2 {
+                        Some(TokenType::Fn) => true,
+                        Some(TokenType::Identifier(s)) => s == "type" || s == "trait",
+                        _ => false,
+                    }
+            }
+            _ => false,
+        }
+    }
+
+    fn new_line(&mut self, newlines: usize, level: usize) {
+        let trimmed = self.output.trim_end_matches([' ', '\t']).len();
+        self.output.truncate(trimmed);
+        if !self.output.is_empty() {
+            self.output.push('\n');
+            if newlines >= 2 {
+                self.output.push('\n');
+            }
+        }
+        for _ in 0..level * self.config.indent_size {
+            self.output.push(' ');
+        }
     }
 }
 
+fn is_doc_comment(kind: &TokenType) -> bool {
+    matches!(kind, TokenType::DocComment(_) | TokenType::InnerDocComment(_))
+}
+
+fn is_binary_operator(kind: &TokenType) -> bool {
+    matches!(
+        kind,
+        TokenType::Equal
+            | TokenType::DoubleEqual
+            | TokenType::NotEqual
+            | TokenType::LessThan
+            | TokenType::LessEqual
+            | TokenType::GreaterThan
+            | TokenType::GreaterEqual
+            | TokenType::And
+            | TokenType::Or
+            | TokenType::Plus
+            | TokenType::Minus
+            | TokenType::Star
+            | TokenType::Slash
+            | TokenType::Percent
+            | TokenType::Arrow
+            | TokenType::FatArrow
+    )
+}
+
+/// A block stays on one line if it was written on one line and holds no
+/// statements (`Point { x: 1, y: 2 }`)
+fn is_inline(block: &SyntaxNode) -> bool {
+    let closed = block.children.len() > 1
+        && matches!(
+            block.children.last(),
+            Some(SyntaxElement::Token(t)) if t.token.kind == TokenType::RightBrace
+        );
+    closed
+        && block.children.iter().skip(1).all(|child| match child {
+            SyntaxElement::Node(_) => false,
+            SyntaxElement::Token(t) => {
+                t.token.kind != TokenType::Semicolon
+                    && t.leading_trivia
+                        .iter()
+                        .all(|trivia| trivia.kind == TriviaKind::Whitespace)
+            }
+        })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -213,4 +457,68 @@ mod tests {
         // The formatter adds indentation after { and before statements
         assert!(output.contains("print"));
     }
+
+    #[test]
+    fn test_formatter_layout() {
+        let formatter = Formatter::default();
+        let input = "// math\n\n\nfn add(a:i32,b:i32)->i32{return a+b;}  \nfn main(){\nlet p=Point{x:1,y:-2};\nif !done {x=add(1,2);}else{x=0;} // done?\n}";
+        let expected = "// math\n\nfn add(a: i32, b: i32) -> i32 {\n    return a + b;\n}\nfn main() {\n    let p = Point { x: 1, y: -2 };\n    if !done {\n        x = add(1, 2);\n    } else {\n        x = 0;\n    } // done?\n}\n";
+        assert_eq!(formatter.format(input), expected);
+        assert!(formatter.is_formatted(expected));
+    }
+
+    #[test]
+    fn test_formatter_preserves_comments() {
+        let formatter = Formatter::default();
+        let input = "fn main() {\n    // first\n    print(1); // trailing\n\n    // last\n}\n// end\n";
+        assert_eq!(formatter.format(input), input);
+    }
+
+    #[test]
+    fn test_formatter_doc_and_block_comments() {
+        let formatter = Formatter::default();
+        let input = "//! Module\n/// Docs\nfn main() { /* a */ print(/* b */ 1);\n/* own\n   line */\nreturn 0; }";
+        let expected = "//! Module\n/// Docs\nfn main() { /* a */\n    print(/* b */ 1);\n    /* own\n   line */\n    return 0;\n}\n";
+        assert_eq!(formatter.format(input), expected);
+        assert!(formatter.is_formatted(expected));
+    }
+
+    #[test]
+    fn test_formatter_ranges() {
+        let formatter = Formatter::default();
+        let input = "fn main() { for i in 0..=n { print(i); } for j in (a .. b).step_by(2) { print(j); } }";
+        let output = formatter.format(input);
+        assert!(output.contains("for i in 0..=n {"), "{}", output);
+        assert!(output.contains("for j in (a..b).step_by(2) {"), "{}", output);
+    }
+
+    #[test]
+    fn test_formatter_closures() {
+        let formatter = Formatter::default();
+        let input = "fn main() { let f = | x:i32 , y:i32 |x+y; let g = move||->i32{ 1 }; apply(|s: str| s); }";
+        let output = formatter.format(input);
+        assert!(output.contains("let f = |x: i32, y: i32| x + y;"), "{}", output);
+        assert!(output.contains("let g = move || -> i32 {"), "{}", output);
+        assert!(output.contains("apply(|s: str| s);"), "{}", output);
+    }
+
+    #[test]
+    fn test_formatter_generics() {
+        let formatter = Formatter::default();
+        let input = "fn get<T>(x:Option<Option<i32>>)->Result<i32,str>{ if a<b { return x; } }";
+        let output = formatter.format(input);
+        assert!(output.starts_with("fn get<T>(x: Option<Option<i32>>) -> Result<i32, str> {\n"));
+        assert!(output.contains("if a < b {"));
+    }
+
+    #[test]
+    fn test_formatter_modules() {
+        let formatter = Formatter::default();
+        let input = "mod shapes;\nuse shapes::{ area,Shape };\n/// Area\npub fn f(s:shapes::Shape)->i32{ return shapes::area(s); }";
+        let output = formatter.format(input);
+        assert!(output.contains("use shapes::{area, Shape};\n"), "{}", output);
+        assert!(output.contains("/// Area\npub fn f(s: shapes::Shape) -> i32 {\n"), "{}", output);
+        assert!(output.contains("return shapes::area(s);"), "{}", output);
+    }
+
 }
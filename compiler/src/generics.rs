@@ -1,8 +1,12 @@
 // generics.rs — Generics implementation for U Language
 // MIT License — Copyright (c) 2025 Webcien and U contributors
 
-use crate::parser::*;
-use std::collections::HashMap;
+use crate::diagnostics::Span;
+use crate::parser::{
+    ArmBody, Declaration, Expression, ExpressionKind, Function, Literal, Statement, StatementKind, Type, TypeDef,
+    VariantPayload, Widget, WidgetValue,
+};
+use std::collections::{HashMap, HashSet};
 
 /// Represents a monomorphized instance of a generic function or type
 #[derive(Debug, Clone)]
@@ -15,8 +19,10 @@ pub struct MonomorphizedInstance {
 
 /// Generics engine for monomorphization
 pub struct GenericsEngine {
-    /// Map from (original_name, concrete_types) to mangled_name
-    instances: HashMap<(String, Vec<String>), String>,
+    /// Map from (original_name, concrete_types) to the instance
+    instances: HashMap<(String, Vec<String>), MonomorphizedInstance>,
+    /// Names of declared items and of the instances made so far
+    taken: HashSet<String>,
     /// Counter for generating unique names
     counter: usize,
 }
@@ -25,10 +31,17 @@ impl GenericsEngine {
     pub fn new() -> Self {
         GenericsEngine {
             instances: HashMap::new(),
+            taken: HashSet::new(),
             counter: 0,
         }
     }
 
+    /// Keep `name`, the name of a declared item, from being given to an
+    /// instance
+    pub fn reserve(&mut self, name: &str) {
+        self.taken.insert(name.to_string());
+    }
+
     /// Monomorphize a generic function
     pub fn monomorphize_function(
         &mut self,
@@ -36,21 +49,7 @@ impl GenericsEngine {
         type_params: &[String],
         concrete_types: &[String],
     ) -> String {
-        let key = (func_name.to_string(), concrete_types.to_vec());
-        
-        if let Some(mangled) = self.instances.get(&key) {
-            return mangled.clone();
-        }
-
-        // Generate mangled name: func_name_Type1_Type2_...
-        let mangled = if concrete_types.is_empty() {
-            func_name.to_string()
-        } else {
-            format!("{}_{}", func_name, concrete_types.join("_"))
-        };
-
-        self.instances.insert(key, mangled.clone());
-        mangled
+        self.instance(func_name, type_params, concrete_types)
     }
 
     /// Monomorphize a generic type
@@ -60,99 +59,467 @@ impl GenericsEngine {
         type_params: &[String],
         concrete_types: &[String],
     ) -> String {
-        let key = (type_name.to_string(), concrete_types.to_vec());
-        
-        if let Some(mangled) = self.instances.get(&key) {
-            return mangled.clone();
+        self.instance(type_name, type_params, concrete_types)
+    }
+
+    /// Name of the instance of `name` for `concrete_types`, written as in
+    /// U source: `Vec_push_i32`, `Pair_str_Option_i32`. A name that is
+    /// already taken gets a numbered suffix, so each instance has its own.
+    fn instance(&mut self, name: &str, type_params: &[String], concrete_types: &[String]) -> String {
+        let key = (name.to_string(), concrete_types.to_vec());
+        if let Some(instance) = self.instances.get(&key) {
+            return instance.mangled_name.clone();
         }
 
-        // Generate mangled name: TypeName_Type1_Type2_...
-        let mangled = if concrete_types.is_empty() {
-            type_name.to_string()
+        // Generate mangled name: name_Type1_Type2_...
+        let base = if concrete_types.is_empty() {
+            name.to_string()
         } else {
-            format!("{}_{}", type_name, concrete_types.join("_"))
+            let types: Vec<String> = concrete_types.iter().map(|ty| c_identifier(ty)).collect();
+            format!("{}_{}", name, types.join("_"))
         };
+        let mut mangled = base.clone();
+        while self.taken.contains(&mangled) {
+            self.counter += 1;
+            mangled = format!("{}_{}", base, self.counter);
+        }
+        self.taken.insert(mangled.clone());
 
-        self.instances.insert(key, mangled.clone());
+        let instance = MonomorphizedInstance {
+            original_name: name.to_string(),
+            mangled_name: mangled.clone(),
+            type_params: type_params.to_vec(),
+            concrete_types: concrete_types.to_vec(),
+        };
+        self.instances.insert(key, instance);
         mangled
     }
 
     /// Get all monomorphized instances
     pub fn get_instances(&self) -> Vec<MonomorphizedInstance> {
-        self.instances
-            .iter()
-            .map(|((name, types), mangled)| MonomorphizedInstance {
-                original_name: name.clone(),
-                mangled_name: mangled.clone(),
-                type_params: vec![],
-                concrete_types: types.clone(),
-            })
-            .collect()
+        self.instances.values().cloned().collect()
     }
 }
 
-/// Trait bounds checker
-pub struct TraitBoundsChecker {
-    /// Map from type to implemented traits
-    type_traits: HashMap<String, Vec<String>>,
+/// `Option<i32>` as `Option_i32`: the identifiers and numbers of a type,
+/// joined by `_`
+fn c_identifier(ty: &str) -> String {
+    ty.split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("_")
 }
 
-impl TraitBoundsChecker {
-    pub fn new() -> Self {
-        let mut checker = TraitBoundsChecker {
-            type_traits: HashMap::new(),
-        };
+/// Nesting of instances within instances past which type arguments are
+/// taken to grow without end, as in `fn f<T>(x: T) { f(Some(x)); }`
+const MAX_INSTANCE_DEPTH: usize = 64;
 
-        // Register built-in trait implementations
-        checker.register_trait("i32", "Clone");
-        checker.register_trait("i64", "Clone");
-        checker.register_trait("f32", "Clone");
-        checker.register_trait("f64", "Clone");
-        checker.register_trait("bool", "Clone");
-        checker.register_trait("str", "Clone");
+/// Replace generic functions and structs by instances for the type
+/// arguments they are used with, so that code generation only meets
+/// concrete types:
+///
+/// - a call `Vec_push(v, 1)` calls `Vec_push_i32`, a copy of
+///   `Vec_push<T>` with `T = i32`
+/// - `Vec<i32>` becomes `Vec_i32`, a copy of `type Vec<T>` with `T = i32`
+///
+/// `instantiations` holds the type arguments the type checker inferred for
+/// each generic call and struct literal, by span. Each instance takes the
+/// place of its generic item; generic items that are never used are left
+/// out. Unsuffixed integer literals in `literal_types` are given the type
+/// it holds for them, so they are not lowered as `int`.
+pub fn monomorphize(
+    declarations: Vec<Declaration>,
+    instantiations: &HashMap<Span, Vec<Type>>,
+    literal_types: &HashMap<Span, Type>,
+) -> Result<Vec<Declaration>, String> {
+    let mut mono = Monomorphizer {
+        engine: GenericsEngine::new(),
+        instantiations,
+        literal_types,
+        functions: HashMap::new(),
+        structs: HashMap::new(),
+        instances: HashMap::new(),
+        pending: Vec::new(),
+        depth: 0,
+    };
+    for decl in &declarations {
+        match decl {
+            Declaration::Function(f) if !f.type_params.is_empty() => {
+                mono.functions.insert(f.name.clone(), f.clone());
+            }
+            Declaration::TypeDef(t) if !t.type_params.is_empty() => {
+                mono.structs.insert(t.name.clone(), t.clone());
+            }
+            _ => {}
+        }
+        for name in declared_names(decl) {
+            mono.engine.reserve(name);
+        }
+    }
 
-        checker
+    let mut program = Vec::new();
+    for mut decl in declarations {
+        match &decl {
+            Declaration::Function(f) if !f.type_params.is_empty() => program.push(Err(f.name.clone())),
+            Declaration::TypeDef(t) if !t.type_params.is_empty() => program.push(Err(t.name.clone())),
+            _ => {
+                mono.declaration(&mut decl, &HashMap::new());
+                program.push(Ok(decl));
+            }
+        }
     }
 
-    pub fn register_trait(&mut self, type_name: &str, trait_name: &str) {
-        self.type_traits
-            .entry(type_name.to_string())
-            .or_insert_with(Vec::new)
-            .push(trait_name.to_string());
+    // Instances can use further instances, which are made in turn
+    let mut next = 0;
+    while next < mono.pending.len() {
+        let (generic, mut instance, bindings, depth) = mono.pending[next].clone();
+        next += 1;
+        if depth > MAX_INSTANCE_DEPTH {
+            return Err(format!(
+                "{} is instantiated with ever larger type arguments, reaching {}",
+                generic, instance.name
+            ));
+        }
+        mono.depth = depth;
+        mono.function(&mut instance, &bindings);
+        mono.instances.entry(generic).or_default().push(Declaration::Function(instance));
     }
 
-    pub fn check_trait_bound(&self, type_name: &str, trait_name: &str) -> bool {
-        if let Some(traits) = self.type_traits.get(type_name) {
-            traits.contains(&trait_name.to_string())
-        } else {
-            false
+    Ok(program
+        .into_iter()
+        .flat_map(|item| match item {
+            Ok(decl) => vec![decl],
+            Err(generic) => mono.instances.remove(&generic).unwrap_or_default(),
+        })
+        .collect())
+}
+
+/// Names a declaration gives to C functions and types
+fn declared_names(decl: &Declaration) -> Vec<&str> {
+    match decl {
+        Declaration::Function(f) => vec![&f.name],
+        Declaration::Actor(a) => vec![&a.name],
+        Declaration::Trait(t) => vec![&t.name],
+        Declaration::TypeDef(t) => vec![&t.name],
+        Declaration::Enum(e) => vec![&e.name],
+        Declaration::ExternBlock(block) => block.functions.iter().map(|f| f.name.as_str()).collect(),
+        Declaration::TraitImpl(_) | Declaration::UiBlock(_) | Declaration::Import(_) | Declaration::Error(_) => {
+            Vec::new()
         }
     }
+}
 
-    pub fn check_all_bounds(
-        &self,
-        concrete_types: &[String],
-        trait_bounds: &[(String, String)],
-    ) -> Result<(), String> {
-        for (type_param, trait_name) in trait_bounds {
-            // Find the concrete type for this type parameter
-            // For simplicity, we assume the first concrete type corresponds to the first type parameter
-            if let Some(concrete_type) = concrete_types.first() {
-                if !self.check_trait_bound(concrete_type, trait_name) {
-                    return Err(format!(
-                        "Type '{}' does not implement trait '{}'",
-                        concrete_type, trait_name
-                    ));
+/// Type parameters of the function being instantiated and their types
+type Bindings = HashMap<String, Type>;
+
+struct Monomorphizer<'a> {
+    engine: GenericsEngine,
+    instantiations: &'a HashMap<Span, Vec<Type>>,
+    literal_types: &'a HashMap<Span, Type>,
+    /// Generic functions and structs, by name
+    functions: HashMap<String, Function>,
+    structs: HashMap<String, TypeDef>,
+    /// Instances made of each generic item, in the order they were made
+    instances: HashMap<String, Vec<Declaration>>,
+    /// Function instances in the order they were made, each with its
+    /// generic function, bindings and nesting depth; the ones not yet
+    /// rewritten come last
+    pending: Vec<(String, Function, Bindings, usize)>,
+    /// Nesting depth of the instance being rewritten
+    depth: usize,
+}
+
+impl Monomorphizer<'_> {
+    /// `ty` with the bound type parameters replaced and generic structs
+    /// replaced by their instances
+    fn ty(&mut self, ty: &Type, bindings: &Bindings) -> Type {
+        let bound: HashMap<&str, &Type> = bindings.iter().map(|(name, ty)| (name.as_str(), ty)).collect();
+        self.instantiate(ty.substitute(&bound))
+    }
+
+    fn instantiate(&mut self, ty: Type) -> Type {
+        match ty {
+            Type::Generic { name, type_args } => {
+                let type_args: Vec<Type> = type_args.into_iter().map(|arg| self.instantiate(arg)).collect();
+                if self.structs.contains_key(&name) {
+                    Type::Custom(self.struct_instance(&name, &type_args))
+                } else {
+                    Type::Generic { name, type_args }
+                }
+            }
+            Type::Option(inner) => Type::Option(Box::new(self.instantiate(*inner))),
+            Type::Result(ok, err) => Type::Result(Box::new(self.instantiate(*ok)), Box::new(self.instantiate(*err))),
+            Type::Tuple(items) => Type::Tuple(items.into_iter().map(|item| self.instantiate(item)).collect()),
+            Type::Array(element, len) => Type::Array(Box::new(self.instantiate(*element)), len),
+            Type::Slice(element) => Type::Slice(Box::new(self.instantiate(*element))),
+            Type::Ref(target) => Type::Ref(Box::new(self.instantiate(*target))),
+            Type::Range(item) => Type::Range(Box::new(self.instantiate(*item))),
+            Type::FunctionPointer { params, return_type } => Type::FunctionPointer {
+                params: params.into_iter().map(|param| self.instantiate(param)).collect(),
+                return_type: return_type.map(|ret| Box::new(self.instantiate(*ret))),
+            },
+            ty => ty,
+        }
+    }
+
+    /// Name of the instance of the generic struct `name` for `type_args`,
+    /// which are concrete, making it if it is new
+    fn struct_instance(&mut self, name: &str, type_args: &[Type]) -> String {
+        let def = self.structs[name].clone();
+        let concrete: Vec<String> = type_args.iter().map(Type::to_string).collect();
+        let known = self.engine.instances.contains_key(&(name.to_string(), concrete.clone()));
+        let mangled = self.engine.monomorphize_type(name, &def.type_params, &concrete);
+        if !known {
+            let bindings: Bindings = def.type_params.iter().cloned().zip(type_args.iter().cloned()).collect();
+            let fields = def.fields.iter().map(|(field, ty)| (field.clone(), self.ty(ty, &bindings))).collect();
+            let instance = TypeDef {
+                name: mangled.clone(),
+                fields,
+                type_params: Vec::new(),
+                bounds: Vec::new(),
+                ..def
+            };
+            self.instances.entry(name.to_string()).or_default().push(Declaration::TypeDef(instance));
+        }
+        mangled
+    }
+
+    /// Name of the instance of the generic function `name` for
+    /// `type_args`, queueing it to be made if it is new
+    fn function_instance(&mut self, name: &str, type_args: &[Type]) -> String {
+        let generic = self.functions[name].clone();
+        let concrete: Vec<String> = type_args.iter().map(Type::to_string).collect();
+        let known = self.engine.instances.contains_key(&(name.to_string(), concrete.clone()));
+        let mangled = self.engine.monomorphize_function(name, &generic.type_params, &concrete);
+        if !known {
+            let bindings: Bindings = generic.type_params.iter().cloned().zip(type_args.iter().cloned()).collect();
+            let instance = Function {
+                name: mangled.clone(),
+                type_params: Vec::new(),
+                bounds: Vec::new(),
+                ..generic
+            };
+            self.pending.push((name.to_string(), instance, bindings, self.depth + 1));
+        }
+        mangled
+    }
+
+    fn declaration(&mut self, decl: &mut Declaration, bindings: &Bindings) {
+        match decl {
+            Declaration::Function(f) => self.function(f, bindings),
+            Declaration::Actor(actor) => {
+                for method in &mut actor.methods {
+                    self.function(method, bindings);
+                }
+            }
+            Declaration::Trait(t) => {
+                for method in &mut t.methods {
+                    self.params(&mut method.params, bindings);
+                    self.return_type(&mut method.return_type, bindings);
+                }
+            }
+            Declaration::TypeDef(t) => self.params(&mut t.fields, bindings),
+            Declaration::Enum(e) => {
+                for variant in &mut e.variants {
+                    match &mut variant.payload {
+                        VariantPayload::Unit => {}
+                        VariantPayload::Tuple(types) => {
+                            for ty in types {
+                                *ty = self.ty(ty, bindings);
+                            }
+                        }
+                        VariantPayload::Struct(fields) => self.params(fields, bindings),
+                    }
+                }
+            }
+            Declaration::TraitImpl(i) => {
+                for arg in &mut i.type_args {
+                    *arg = self.ty(arg, bindings);
+                }
+                for method in &mut i.methods {
+                    self.function(method, bindings);
+                }
+            }
+            Declaration::ExternBlock(block) => {
+                for f in &mut block.functions {
+                    self.params(&mut f.params, bindings);
+                    self.return_type(&mut f.return_type, bindings);
+                }
+            }
+            Declaration::UiBlock(ui) => self.widget(&mut ui.root, bindings),
+            Declaration::Import(_) | Declaration::Error(_) => {}
+        }
+    }
+
+    fn function(&mut self, f: &mut Function, bindings: &Bindings) {
+        self.params(&mut f.params, bindings);
+        self.return_type(&mut f.return_type, bindings);
+        self.block(&mut f.body, bindings);
+    }
+
+    fn params(&mut self, params: &mut [(String, Type)], bindings: &Bindings) {
+        for (_, ty) in params {
+            *ty = self.ty(ty, bindings);
+        }
+    }
+
+    fn return_type(&mut self, ty: &mut Option<Type>, bindings: &Bindings) {
+        if let Some(ty) = ty {
+            *ty = self.ty(ty, bindings);
+        }
+    }
+
+    fn widget(&mut self, widget: &mut Widget, bindings: &Bindings) {
+        for (_, value) in &mut widget.properties {
+            match value {
+                WidgetValue::Widget(child) => self.widget(child, bindings),
+                WidgetValue::WidgetList(children) => {
+                    for child in children {
+                        self.widget(child, bindings);
+                    }
                 }
+                WidgetValue::Closure(closure) => self.expression(closure, bindings),
+                _ => {}
+            }
+        }
+    }
+
+    fn block(&mut self, body: &mut [Statement], bindings: &Bindings) {
+        for stmt in body {
+            self.statement(stmt, bindings);
+        }
+    }
+
+    fn statement(&mut self, stmt: &mut Statement, bindings: &Bindings) {
+        match &mut stmt.kind {
+            StatementKind::Let { ty, value, .. } => {
+                self.return_type(ty, bindings);
+                if let Some(value) = value {
+                    self.expression(value, bindings);
+                }
+            }
+            StatementKind::Expr(expr) | StatementKind::Return(expr) => self.expression(expr, bindings),
+            StatementKind::If { condition, then_branch, else_branch } => {
+                self.expression(condition, bindings);
+                self.block(then_branch, bindings);
+                if let Some(else_branch) = else_branch {
+                    self.block(else_branch, bindings);
+                }
+            }
+            StatementKind::While { condition, body } => {
+                self.expression(condition, bindings);
+                self.block(body, bindings);
+            }
+            StatementKind::For { iterable, body, .. } => {
+                self.expression(iterable, bindings);
+                self.block(body, bindings);
+            }
+            StatementKind::Unsafe { body } => self.block(body, bindings),
+            StatementKind::Break | StatementKind::Continue | StatementKind::Error => {}
+        }
+    }
+
+    fn expressions(&mut self, exprs: &mut [Expression], bindings: &Bindings) {
+        for expr in exprs {
+            self.expression(expr, bindings);
+        }
+    }
+
+    fn expression(&mut self, expr: &mut Expression, bindings: &Bindings) {
+        let span = expr.span;
+        match &mut expr.kind {
+            ExpressionKind::Literal(Literal::Integer(_, suffix @ None)) => *suffix = self.literal_types.get(&span).cloned(),
+            ExpressionKind::Literal(_) | ExpressionKind::Identifier(_) => {}
+            ExpressionKind::FunctionCall { name, arguments } => {
+                self.expressions(arguments, bindings);
+                // Calls of closures and built-ins have no type arguments
+                if let (true, Some(type_args)) = (self.functions.contains_key(name), self.instantiations.get(&span)) {
+                    let type_args: Vec<Type> = type_args.iter().map(|arg| self.ty(arg, bindings)).collect();
+                    *name = self.function_instance(name, &type_args);
+                }
+            }
+            ExpressionKind::MethodCall { receiver, arguments, .. } => {
+                self.expression(receiver, bindings);
+                self.expressions(arguments, bindings);
+            }
+            ExpressionKind::Binary { left, right, .. } => {
+                self.expression(left, bindings);
+                self.expression(right, bindings);
+            }
+            ExpressionKind::Unary { operand, .. } => self.expression(operand, bindings),
+            ExpressionKind::Assignment { target, value } => {
+                self.expression(target, bindings);
+                self.expression(value, bindings);
+            }
+            ExpressionKind::StructLiteral { name, type_args, fields } => {
+                for (_, value) in fields.iter_mut() {
+                    self.expression(value, bindings);
+                }
+                if self.structs.contains_key(name) {
+                    let given = if type_args.is_empty() {
+                        self.instantiations.get(&span).cloned().unwrap_or_default()
+                    } else {
+                        std::mem::take(type_args)
+                    };
+                    let given: Vec<Type> = given.iter().map(|arg| self.ty(arg, bindings)).collect();
+                    *name = self.struct_instance(name, &given);
+                } else {
+                    for arg in type_args {
+                        *arg = self.ty(arg, bindings);
+                    }
+                }
+            }
+            ExpressionKind::FieldAccess { object, .. } => self.expression(object, bindings),
+            ExpressionKind::ArrayLiteral(elements) | ExpressionKind::Tuple(elements) => {
+                self.expressions(elements, bindings)
+            }
+            ExpressionKind::Index { object, index } => {
+                self.expression(object, bindings);
+                self.expression(index, bindings);
+            }
+            ExpressionKind::Range { start, end, step, .. } => {
+                self.expression(start, bindings);
+                self.expression(end, bindings);
+                if let Some(step) = step {
+                    self.expression(step, bindings);
+                }
+            }
+            ExpressionKind::EnumVariant { payload, .. } => match payload {
+                VariantPayload::Unit => {}
+                VariantPayload::Tuple(items) => self.expressions(items, bindings),
+                VariantPayload::Struct(fields) => {
+                    for (_, value) in fields {
+                        self.expression(value, bindings);
+                    }
+                }
+            },
+            ExpressionKind::Match { scrutinee, arms } => {
+                self.expression(scrutinee, bindings);
+                for arm in arms {
+                    if let Some(guard) = &mut arm.guard {
+                        self.expression(guard, bindings);
+                    }
+                    match &mut arm.body {
+                        ArmBody::Expression(value) => self.expression(value, bindings),
+                        ArmBody::Block(body) => self.block(body, bindings),
+                    }
+                }
+            }
+            ExpressionKind::Closure { params, return_type, body, .. } => {
+                self.params(params, bindings);
+                self.return_type(return_type, bindings);
+                self.block(body, bindings);
             }
         }
-        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::type_checker::TypeChecker;
 
     #[test]
     fn test_monomorphize_function() {
@@ -166,10 +533,89 @@ mod tests {
     }
 
     #[test]
-    fn test_trait_bounds() {
-        let checker = TraitBoundsChecker::new();
-        
-        assert!(checker.check_trait_bound("i32", "Clone"));
-        assert!(!checker.check_trait_bound("i32", "Display"));
+    fn test_mangled_names_are_unique() {
+        let mut engine = GenericsEngine::new();
+        engine.reserve("Pair_i32_str");
+        let t = ["T".to_string()];
+        let pair = engine.monomorphize_type("Pair", &t, &["i32".to_string(), "str".to_string()]);
+        assert_eq!(pair, "Pair_i32_str_1");
+        assert_eq!(engine.monomorphize_type("Pair", &t, &["i32".to_string(), "str".to_string()]), pair);
+        assert_eq!(engine.monomorphize_type("Pair", &t, &["(i32, str)".to_string()]), "Pair_i32_str_2");
+        assert_eq!(engine.monomorphize_function("wrap", &t, &["Option<[u8; 4]>".to_string()]), "wrap_Option_u8_4");
+    }
+
+    #[test]
+    fn test_monomorphize_program() {
+        let source = "type Box<T> { item: T }\n\
+            fn wrap<T>(item: T) -> Box<T> { return Box { item: item }; }\n\
+            fn twice<T>(item: T) -> Box<Box<T>> { return wrap(wrap(item)); }\n\
+            fn unused<T>(item: T) { }\n\
+            fn peek<T>(b: &Box<T>) -> i32 { return 0; }\n\
+            fn main() { let a = twice(1); let b: Box<str> = wrap(\"b\"); let c = peek(&b); }";
+        let declarations = Parser::new(Lexer::new(source.to_string()).tokenize()).parse().unwrap();
+        let mut checker = TypeChecker::new();
+        checker.check_program(declarations.clone()).unwrap();
+        let program = monomorphize(declarations, checker.instantiations(), checker.literal_types()).unwrap();
+
+        let names: Vec<&str> = program
+            .iter()
+            .filter_map(|decl| match decl {
+                Declaration::Function(f) => Some(f.name.as_str()),
+                Declaration::TypeDef(t) => Some(t.name.as_str()),
+                _ => None,
+            })
+            .collect();
+        // Instances take the place of their generic item, in the order they are met
+        assert_eq!(
+            names,
+            ["Box_str", "Box_i32", "Box_Box_i32", "wrap_str", "wrap_i32", "wrap_Box_i32", "twice_i32", "peek_str", "main"]
+        );
+        let Some(Declaration::TypeDef(nested)) = program.get(2) else {
+            panic!("expected struct");
+        };
+        assert_eq!(nested.fields[0].1, Type::Custom("Box_i32".to_string()));
+        let Some(Declaration::Function(wrap)) = program.iter().find(|d| matches!(d, Declaration::Function(f) if f.name == "wrap_i32")) else {
+            panic!("expected wrap_i32");
+        };
+        assert_eq!(wrap.params[0].1, Type::I32);
+        assert_eq!(wrap.return_type, Some(Type::Custom("Box_i32".to_string())));
+        assert!(matches!(&wrap.body[0].kind, StatementKind::Return(Expression { kind: ExpressionKind::StructLiteral { name, .. }, .. }) if name == "Box_i32"));
+        let Some(Declaration::Function(peek)) = program.iter().find(|d| matches!(d, Declaration::Function(f) if f.name == "peek_str")) else {
+            panic!("expected peek_str");
+        };
+        assert_eq!(peek.params[0].1, Type::Ref(Box::new(Type::Custom("Box_str".to_string()))));
+
+        // Unsuffixed literals take the integer type the checker gave them
+        let source = "fn main() { let a: i64 = 2000000000 + 2000000000; let b = 1; }";
+        let declarations = Parser::new(Lexer::new(source.to_string()).tokenize()).parse().unwrap();
+        let mut checker = TypeChecker::new();
+        checker.check_program(declarations.clone()).unwrap();
+        let program = monomorphize(declarations, checker.instantiations(), checker.literal_types()).unwrap();
+        let Some(Declaration::Function(main)) = program.first() else {
+            panic!("expected main");
+        };
+        let literal = |value: &Expression| match &value.kind {
+            ExpressionKind::Binary { left, .. } => left.kind.clone(),
+            kind => kind.clone(),
+        };
+        let lets: Vec<ExpressionKind> = main
+            .body
+            .iter()
+            .map(|stmt| match &stmt.kind {
+                StatementKind::Let { value: Some(value), .. } => literal(value),
+                _ => panic!("expected let"),
+            })
+            .collect();
+        assert!(matches!(&lets[0], ExpressionKind::Literal(Literal::Integer(2000000000, Some(Type::I64)))));
+        assert!(matches!(&lets[1], ExpressionKind::Literal(Literal::Integer(1, None))));
+
+        // Type arguments that grow with every instance never settle
+        let source = "type Box<T> { item: T }\n\
+            fn deep<T>(item: T) { deep(Box { item: item }); }\n\
+            fn main() { deep(1); }";
+        let declarations = Parser::new(Lexer::new(source.to_string()).tokenize()).parse().unwrap();
+        let mut checker = TypeChecker::new();
+        checker.check_program(declarations.clone()).unwrap();
+        assert!(monomorphize(declarations, checker.instantiations(), checker.literal_types()).unwrap_err().contains("ever larger"));
     }
 }
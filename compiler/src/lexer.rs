@@ -1,6 +1,8 @@
 // lexer.rs — U v0.6
 // MIT License — Copyright (c) 2025 Webcien and U contributors
 
+use crate::diagnostics::{Diagnostic, DiagnosticCollector, FileId, Location, Span};
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum TokenType {
     // Keywords
@@ -17,8 +19,14 @@ pub enum TokenType {
     Continue,
     If,
     Else,
+    Match,
     Extern,
     Unsafe,
+    Move,
+    Pub,
+    Mod,
+    Import,
+    Use,
     Ui,
     Child,
     Children,
@@ -26,7 +34,13 @@ pub enum TokenType {
     // Literals
     Identifier(String),
     StringLiteral(String),
-    IntegerLiteral(u32),
+    CharLiteral(char),
+    IntegerLiteral(u64, Option<NumberSuffix>),
+    FloatLiteral(f64, Option<NumberSuffix>),
+    /// `/// text`: documents the item that follows
+    DocComment(String),
+    /// `//! text`: documents the enclosing item (or the file)
+    InnerDocComment(String),
     // Symbols
     LeftParen,      // (
     RightParen,     // )
@@ -38,7 +52,9 @@ pub enum TokenType {
     Semicolon,      // ;
     Dot,            // .
     Arrow,          // ->
+    FatArrow,       // =>
     Colon,          // :
+    ColonColon,     // ::
     Equal,          // =
     Plus,           // +
     Minus,          // -
@@ -51,10 +67,18 @@ pub enum TokenType {
     GreaterEqual,   // >=
     And,            // &&
     Or,             // ||
+    Pipe,           // |  (closure parameters)
+    Ampersand,      // &  (references)
     Not,            // !
 
     // Types
+    Char,
     I32,
+    I64,
+    U8,
+    U64,
+    F32,
+    F64,
     Str,
     Bool,
     Ptr,
@@ -71,70 +95,208 @@ pub enum TokenType {
 #[derive(Debug, Clone)]
 pub struct Token {
     pub kind: TokenType,
-    pub line: usize,
-    pub column: usize,
+    pub span: Span,
+}
+
+/// Type suffix of a numeric literal (`10u8`, `2.0f32`)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NumberSuffix {
+    I32,
+    I64,
+    U8,
+    U64,
+    F32,
+    F64,
+}
+
+impl NumberSuffix {
+    fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "i32" => Some(NumberSuffix::I32),
+            "i64" => Some(NumberSuffix::I64),
+            "u8" => Some(NumberSuffix::U8),
+            "u64" => Some(NumberSuffix::U64),
+            "f32" => Some(NumberSuffix::F32),
+            "f64" => Some(NumberSuffix::F64),
+            _ => None,
+        }
+    }
+
+    pub fn is_float(self) -> bool {
+        matches!(self, NumberSuffix::F32 | NumberSuffix::F64)
+    }
+
+    /// Largest value an integer literal of this type may have
+    fn max_integer(self) -> u64 {
+        match self {
+            NumberSuffix::I32 => i32::MAX as u64,
+            NumberSuffix::I64 => i64::MAX as u64,
+            NumberSuffix::U8 => u8::MAX as u64,
+            NumberSuffix::U64 | NumberSuffix::F32 | NumberSuffix::F64 => u64::MAX,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            NumberSuffix::I32 => "i32",
+            NumberSuffix::I64 => "i64",
+            NumberSuffix::U8 => "u8",
+            NumberSuffix::U64 => "u64",
+            NumberSuffix::F32 => "f32",
+            NumberSuffix::F64 => "f64",
+        }
+    }
+}
+
+/// Source text between tokens that carries no meaning for the parser
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TriviaKind {
+    /// A run of spaces, tabs and carriage returns
+    Whitespace,
+    Newline,
+    /// `// ...` up to (not including) the end of the line
+    LineComment,
+    /// `/* ... */`, possibly nested and spanning lines
+    BlockComment,
+}
+
+#[derive(Debug, Clone)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub text: String,
+    pub span: Span,
+}
+
+/// A token together with its exact source text and the trivia preceding it.
+///
+/// Concatenating `leading_trivia` and `text` of every token, Eof included,
+/// reproduces the source byte-for-byte.
+#[derive(Debug, Clone)]
+pub struct LosslessToken {
+    pub leading_trivia: Vec<Trivia>,
+    pub token: Token,
+    pub text: String,
 }
 
 pub struct Lexer {
     source: String,
-    current: usize,
     line: usize,
     column: usize,
+    utf16_column: usize,
+    /// Byte offset of the next character; always on a char boundary
+    offset: usize,
+    /// Where the token being scanned starts
+    token_start: Location,
+    file_id: FileId,
+    diagnostics: DiagnosticCollector,
 }
 
 impl Lexer {
     pub fn new(source: String) -> Self {
+        Self::with_file_id(source, 0)
+    }
+
+    pub fn with_file_id(source: String, file_id: FileId) -> Self {
         Self {
             source,
-            current: 0,
             line: 1,
             column: 1,
+            utf16_column: 1,
+            offset: 0,
+            token_start: Location::default(),
+            file_id,
+            diagnostics: DiagnosticCollector::new(),
         }
     }
 
+    /// Malformed literals found while tokenizing
+    pub fn diagnostics(&self) -> &DiagnosticCollector {
+        &self.diagnostics
+    }
+
+    /// Report an error covering the token scanned so far
+    fn error(&mut self, message: String) {
+        self.error_from(self.token_start, message);
+    }
+
+    /// Report an error covering the text from `start` to the current position
+    fn error_from(&mut self, start: Location, message: String) {
+        let span = Span::new(start, self.location()).with_file(self.file_id);
+        self.diagnostics.add(Diagnostic::error(message).with_span(span));
+    }
+
     pub fn tokenize(&mut self) -> Vec<Token> {
+        self.tokenize_lossless()
+            .into_iter()
+            .map(|t| t.token)
+            .collect()
+    }
+
+    /// Tokenize keeping whitespace and comments. The last token is always
+    /// Eof, whose leading trivia holds everything after the final token.
+    pub fn tokenize_lossless(&mut self) -> Vec<LosslessToken> {
         let mut tokens = Vec::new();
-        while !self.is_at_end() {
-            tokens.push(self.scan_token());
+        loop {
+            let leading_trivia = self.scan_trivia();
+            let token = self.scan_token();
+            let text = self.source[token.span.start.offset..token.span.end.offset].to_string();
+            let at_eof = token.kind == TokenType::Eof;
+            tokens.push(LosslessToken {
+                leading_trivia,
+                token,
+                text,
+            });
+            if at_eof {
+                return tokens;
+            }
         }
-        tokens.push(Token {
-            kind: TokenType::Eof,
+    }
+
+    fn location(&self) -> Location {
+        Location {
             line: self.line,
             column: self.column,
-        });
-        tokens
+            utf16_column: self.utf16_column,
+            offset: self.offset,
+        }
     }
 
     fn is_at_end(&self) -> bool {
-        self.current >= self.source.len()
+        self.offset >= self.source.len()
+    }
+
+    /// The source text not yet scanned
+    fn rest(&self) -> &str {
+        &self.source[self.offset..]
     }
 
     fn advance(&mut self) -> char {
-        let c = self.source.chars().nth(self.current).unwrap_or('\0');
-        self.current += 1;
+        let Some(c) = self.rest().chars().next() else {
+            return '\0';
+        };
+        self.offset += c.len_utf8();
         if c == '\n' {
             self.line += 1;
             self.column = 1;
+            self.utf16_column = 1;
         } else {
             self.column += 1;
+            self.utf16_column += c.len_utf16();
         }
         c
     }
 
     fn peek(&self) -> char {
-        if self.is_at_end() {
-            '\0'
-        } else {
-            self.source.chars().nth(self.current).unwrap_or('\0')
-        }
+        self.rest().chars().next().unwrap_or('\0')
     }
 
     fn peek_next(&self) -> char {
-        if self.current + 1 >= self.source.len() {
-            '\0'
-        } else {
-            self.source.chars().nth(self.current + 1).unwrap_or('\0')
-        }
+        self.peek_at(1)
+    }
+
+    /// Character `distance` chars ahead; only used for short lookahead
+    fn peek_at(&self, distance: usize) -> char {
+        self.rest().chars().nth(distance).unwrap_or('\0')
     }
 
     fn match_char(&mut self, expected: char) -> bool {
@@ -146,25 +308,94 @@ impl Lexer {
         }
     }
 
-    fn skip_whitespace(&mut self) {
+    fn scan_trivia(&mut self) -> Vec<Trivia> {
+        let mut trivia = Vec::new();
         while !self.is_at_end() {
-            match self.peek() {
-                ' ' | '\r' | '\t' | '\n' => {
+            let start = self.location();
+            let kind = match self.peek() {
+                '\n' => {
                     self.advance();
+                    TriviaKind::Newline
+                }
+                ' ' | '\r' | '\t' => {
+                    while matches!(self.peek(), ' ' | '\r' | '\t') {
+                        self.advance();
+                    }
+                    TriviaKind::Whitespace
                 }
-                '/' if self.peek_next() == '/' => {
-                    // Line comment: skip until end of line
+                '/' if self.peek_next() == '/' && !self.at_doc_comment() => {
+                    // Line comment: runs until end of line
                     while !self.is_at_end() && self.peek() != '\n' {
                         self.advance();
                     }
+                    TriviaKind::LineComment
+                }
+                '/' if self.peek_next() == '*' => {
+                    self.block_comment();
+                    TriviaKind::BlockComment
                 }
                 _ => break,
+            };
+            trivia.push(Trivia {
+                kind,
+                text: self.source[start.offset..self.offset].to_string(),
+                span: Span::new(start, self.location()).with_file(self.file_id),
+            });
+        }
+        trivia
+    }
+
+    /// `///` and `//!` start doc comments; `////` is an ordinary comment
+    fn at_doc_comment(&self) -> bool {
+        self.peek() == '/'
+            && self.peek_next() == '/'
+            && match self.peek_at(2) {
+                '!' => true,
+                '/' => self.peek_at(3) != '/',
+                _ => false,
             }
+    }
+
+    fn block_comment(&mut self) {
+        let start = self.location();
+        self.advance();
+        self.advance();
+        let mut depth = 1;
+        while !self.is_at_end() && depth > 0 {
+            if self.peek() == '/' && self.peek_next() == '*' {
+                self.advance();
+                self.advance();
+                depth += 1;
+            } else if self.peek() == '*' && self.peek_next() == '/' {
+                self.advance();
+                self.advance();
+                depth -= 1;
+            } else {
+                self.advance();
+            }
+        }
+        if depth > 0 {
+            self.error_from(start, "unterminated block comment".to_string());
+        }
+    }
+
+    /// Called with the first `/` consumed
+    fn doc_comment(&mut self) -> TokenType {
+        self.advance();
+        let inner = self.advance() == '!';
+        let mut text = String::new();
+        while !self.is_at_end() && self.peek() != '\n' {
+            text.push(self.advance());
+        }
+        let text = text.trim_end_matches('\r').to_string();
+        if inner {
+            TokenType::InnerDocComment(text)
+        } else {
+            TokenType::DocComment(text)
         }
     }
 
     fn identifier(&mut self) -> String {
-        let start = self.current - 1;
         while !self.is_at_end() {
             let c = self.peek();
             if c.is_alphanumeric() || c == '_' {
@@ -173,230 +404,370 @@ impl Lexer {
                 break;
             }
         }
-        self.source[start..self.current].to_string()
+        self.source[self.token_start.offset..self.offset].to_string()
+    }
+
+    /// Scan a string literal after its opening quote. Strings may span
+    /// lines; a `\` at the end of a line joins it with the next one.
+    fn string(&mut self) -> TokenType {
+        let mut value = String::new();
+        loop {
+            if self.is_at_end() {
+                self.error("unterminated string literal".to_string());
+                break;
+            }
+            let start = self.location();
+            match self.advance() {
+                '"' => break,
+                '\\' => value.extend(self.escape(start)),
+                c => value.push(c),
+            }
+        }
+        TokenType::StringLiteral(value)
+    }
+
+    /// Whether an `r` just consumed starts a raw string (`r"..."`, `r#"..."#`)
+    fn at_raw_string(&self) -> bool {
+        self.rest().trim_start_matches('#').starts_with('"')
     }
 
-    fn string(&mut self) -> String {
+    /// Scan a raw string after its `r`: no escapes, closed by a quote
+    /// followed by as many `#` as opened it
+    fn raw_string(&mut self) -> TokenType {
+        let mut hashes = 0;
+        while self.match_char('#') {
+            hashes += 1;
+        }
+        self.advance();
         let mut value = String::new();
-        while !self.is_at_end() && self.peek() != '"' {
+        loop {
+            if self.is_at_end() {
+                self.error("unterminated raw string literal".to_string());
+                break;
+            }
             let c = self.advance();
-            if c == '\\' {
-                // Basic escape support (optional in MVP)
-                let next = self.advance();
-                match next {
-                    '"' | '\\' => value.push(next),
-                    'n' => value.push('\n'),
-                    _ => value.push(next), // or report error
+            if c == '"' && (0..hashes).all(|i| self.peek_at(i) == '#') {
+                for _ in 0..hashes {
+                    self.advance();
                 }
-            } else {
-                value.push(c);
+                break;
             }
+            value.push(c);
         }
-        if self.is_at_end() {
-            // Error: unclosed string (will be handled in parser or checker)
+        TokenType::StringLiteral(value)
+    }
+
+    /// Scan a character literal after its opening quote
+    fn char_literal(&mut self) -> TokenType {
+        let start = self.location();
+        let value = match self.peek() {
+            '\'' => {
+                self.advance();
+                self.error("empty character literal".to_string());
+                return TokenType::CharLiteral('\0');
+            }
+            '\n' => None,
+            _ if self.is_at_end() => None,
+            '\\' => {
+                self.advance();
+                Some(self.escape(start))
+            }
+            _ => Some(Some(self.advance())),
+        };
+        if value.is_some() && self.match_char('\'') {
+            return TokenType::CharLiteral(value.flatten().unwrap_or('\0'));
+        }
+
+        // Several characters, or no closing quote on this line
+        while !self.is_at_end() && !matches!(self.peek(), '\'' | '\n') {
+            self.advance();
+        }
+        if self.match_char('\'') {
+            self.error("character literal may only contain one character".to_string());
         } else {
-            self.advance(); // consume the '"'
+            self.error("unterminated character literal".to_string());
         }
-        value
+        TokenType::CharLiteral('\0')
     }
 
-    fn number(&mut self) -> u32 {
-        let start = self.current - 1;
-        while !self.is_at_end() && self.peek().is_ascii_digit() {
-            self.advance();
+    /// Decode an escape sequence whose `\` (at `start`) was just consumed.
+    /// Returns `None` for a line continuation or a malformed escape, which
+    /// is reported.
+    fn escape(&mut self, start: Location) -> Option<char> {
+        if self.is_at_end() {
+            // The caller reports the unterminated literal
+            return None;
+        }
+        match self.advance() {
+            'n' => Some('\n'),
+            't' => Some('\t'),
+            'r' => Some('\r'),
+            '0' => Some('\0'),
+            '\\' => Some('\\'),
+            '"' => Some('"'),
+            '\'' => Some('\''),
+            '\n' => {
+                // Line continuation: skip the line break and the indentation after it
+                while matches!(self.peek(), ' ' | '\t' | '\r' | '\n') {
+                    self.advance();
+                }
+                None
+            }
+            'x' => {
+                let mut value = 0;
+                for _ in 0..2 {
+                    if !self.peek().is_ascii_hexdigit() {
+                        self.error_from(start, "invalid '\\x' escape: expected two hex digits".to_string());
+                        return None;
+                    }
+                    value = value * 16 + self.advance().to_digit(16).unwrap();
+                }
+                if value > 0x7F {
+                    self.error_from(start, "'\\x' escape out of range: must be at most \\x7F".to_string());
+                    return None;
+                }
+                char::from_u32(value)
+            }
+            'u' => {
+                if !self.match_char('{') {
+                    self.error_from(start, "invalid '\\u' escape: expected '{'".to_string());
+                    return None;
+                }
+                let mut digits = String::new();
+                while self.peek().is_ascii_hexdigit() {
+                    digits.push(self.advance());
+                }
+                if !self.match_char('}') {
+                    self.error_from(start, "unterminated '\\u' escape: expected '}'".to_string());
+                    return None;
+                }
+                if digits.is_empty() || digits.len() > 6 {
+                    self.error_from(start, "invalid '\\u' escape: expected 1 to 6 hex digits".to_string());
+                    return None;
+                }
+                let value = u32::from_str_radix(&digits, 16).ok().and_then(char::from_u32);
+                if value.is_none() {
+                    self.error_from(start, format!("invalid unicode character escape '\\u{{{}}}'", digits));
+                }
+                value
+            }
+            other => {
+                self.error_from(start, format!("unknown escape sequence '\\{}'", other));
+                None
+            }
         }
-        let num_str = &self.source[start..self.current];
-        num_str.parse().unwrap_or(0)
     }
 
-    fn scan_token(&mut self) -> Token {
-        self.skip_whitespace();
+    /// Consume digits (and `_` separators) valid in the given radix
+    fn digits(&mut self, radix: u32) -> String {
+        let mut digits = String::new();
+        while self.peek().is_digit(radix) || self.peek() == '_' {
+            let c = self.advance();
+            if c != '_' {
+                digits.push(c);
+            }
+        }
+        digits
+    }
 
-        let line = self.line;
-        let column = self.column;
+    /// Scan a numeric literal whose first digit has been consumed.
+    ///
+    /// Unsuffixed integers are `i32` when they fit and `i64` otherwise;
+    /// unsuffixed floats are `f64`.
+    fn number(&mut self, first: char) -> TokenType {
+        let radix = match (first, self.peek()) {
+            ('0', 'x') => 16,
+            ('0', 'b') => 2,
+            ('0', 'o') => 8,
+            _ => 10,
+        };
+        if radix != 10 {
+            self.advance();
+            // Take every decimal digit so `0b102` is reported, not split
+            let digits = self.digits(radix.max(10));
+            let suffix = self.number_suffix();
+            if digits.is_empty() {
+                self.error("missing digits after the integer base prefix".to_string());
+                return TokenType::IntegerLiteral(0, suffix);
+            }
+            if let Some(bad) = digits.chars().find(|c| !c.is_digit(radix)) {
+                self.error(format!("invalid digit '{}' in base {} literal", bad, radix));
+                return TokenType::IntegerLiteral(0, suffix);
+            }
+            if let Some(suffix) = suffix.filter(|s| s.is_float()) {
+                self.error(format!("base {} literal cannot have float suffix '{}'", radix, suffix.name()));
+                return TokenType::IntegerLiteral(0, None);
+            }
+            let value = u64::from_str_radix(&digits, radix).ok();
+            return self.integer_literal(value, suffix);
+        }
 
-        if self.is_at_end() {
-            return Token {
-                kind: TokenType::Eof,
-                line,
-                column,
+        let mut text = first.to_string();
+        text.push_str(&self.digits(10));
+        let mut is_float = false;
+        if self.peek() == '.' && self.peek_next().is_ascii_digit() {
+            // `1..2` and `x.0.field` stay integers: a digit must follow the dot
+            text.push(self.advance());
+            text.push_str(&self.digits(10));
+            is_float = true;
+        }
+        if matches!(self.peek(), 'e' | 'E') {
+            let signed = matches!(self.peek_next(), '+' | '-');
+            let exponent_digit = if signed { self.peek_at(2) } else { self.peek_next() };
+            if exponent_digit.is_ascii_digit() {
+                text.push(self.advance());
+                if signed {
+                    text.push(self.advance());
+                }
+                text.push_str(&self.digits(10));
+                is_float = true;
+            }
+        }
+        let suffix = self.number_suffix();
+
+        if is_float || suffix.is_some_and(|s| s.is_float()) {
+            if let Some(suffix) = suffix.filter(|s| !s.is_float()) {
+                self.error(format!("float literal cannot have integer suffix '{}'", suffix.name()));
+                return TokenType::FloatLiteral(0.0, None);
+            }
+            let value: f64 = text.parse().unwrap_or(f64::INFINITY);
+            let ty = suffix.unwrap_or(NumberSuffix::F64);
+            let out_of_range = match ty {
+                NumberSuffix::F32 => (value as f32).is_infinite(),
+                _ => value.is_infinite(),
             };
+            if out_of_range {
+                self.error(format!("float literal out of range for {}", ty.name()));
+                return TokenType::FloatLiteral(0.0, suffix);
+            }
+            return TokenType::FloatLiteral(value, suffix);
         }
+        let value = text.parse().ok();
+        self.integer_literal(value, suffix)
+    }
 
+    /// Range-check an integer literal; `None` means it overflowed `u64`
+    fn integer_literal(&mut self, value: Option<u64>, suffix: Option<NumberSuffix>) -> TokenType {
+        let max = suffix.unwrap_or(NumberSuffix::I64).max_integer();
+        match value {
+            Some(value) if value <= max => TokenType::IntegerLiteral(value, suffix),
+            _ => {
+                let ty = suffix.unwrap_or(NumberSuffix::I64).name();
+                let message = format!("integer literal out of range for {} (max {})", ty, max);
+                let span = Span::new(self.token_start, self.location()).with_file(self.file_id);
+                let mut diag = Diagnostic::error(message).with_span(span);
+                if suffix.is_none() && value.is_some() {
+                    diag = diag.with_suggestion("add a 'u64' suffix".to_string());
+                }
+                self.diagnostics.add(diag);
+                TokenType::IntegerLiteral(0, suffix)
+            }
+        }
+    }
+
+    fn number_suffix(&mut self) -> Option<NumberSuffix> {
+        if !self.peek().is_alphabetic() {
+            return None;
+        }
+        let mut text = String::new();
+        while self.peek().is_alphanumeric() || self.peek() == '_' {
+            text.push(self.advance());
+        }
+        let suffix = NumberSuffix::from_str(&text);
+        if suffix.is_none() {
+            self.error(format!("invalid suffix '{}' for number literal", text));
+        }
+        suffix
+    }
+
+    fn scan_token(&mut self) -> Token {
+        let start = self.location();
+        self.token_start = start;
+        let kind = if self.is_at_end() {
+            TokenType::Eof
+        } else {
+            self.scan_kind()
+        };
+
+        Token {
+            kind,
+            span: Span::new(start, self.location()).with_file(self.file_id),
+        }
+    }
+
+    fn scan_kind(&mut self) -> TokenType {
         let c = self.advance();
 
         match c {
-            '(' => Token {
-                kind: TokenType::LeftParen,
-                line,
-                column,
-            },
-            ')' => Token {
-                kind: TokenType::RightParen,
-                line,
-                column,
-            },
-            '{' => Token {
-                kind: TokenType::LeftBrace,
-                line,
-                column,
-            },
-            '}' => Token {
-                kind: TokenType::RightBrace,
-                line,
-                column,
-            },
-            '[' => Token {
-                kind: TokenType::LeftBracket,
-                line,
-                column,
-            },
-            ']' => Token {
-                kind: TokenType::RightBracket,
-                line,
-                column,
-            },
-            ',' => Token {
-                kind: TokenType::Comma,
-                line,
-                column,
-            },
-            '.' => Token {
-                kind: TokenType::Dot,
-                line,
-                column,
-            },
-            ';' => Token {
-                kind: TokenType::Semicolon,
-                line,
-                column,
-            },
-            ':' => Token {
-                kind: TokenType::Colon,
-                line,
-                column,
-            },
+            '(' => TokenType::LeftParen,
+            ')' => TokenType::RightParen,
+            '{' => TokenType::LeftBrace,
+            '}' => TokenType::RightBrace,
+            '[' => TokenType::LeftBracket,
+            ']' => TokenType::RightBracket,
+            ',' => TokenType::Comma,
+            '.' => TokenType::Dot,
+            ';' => TokenType::Semicolon,
+            ':' => {
+                if self.match_char(':') {
+                    TokenType::ColonColon
+                } else {
+                    TokenType::Colon
+                }
+            }
             '=' => {
                 if self.match_char('=') {
-                    Token {
-                        kind: TokenType::DoubleEqual,
-                        line,
-                        column,
-                    }
+                    TokenType::DoubleEqual
+                } else if self.match_char('>') {
+                    TokenType::FatArrow
                 } else {
-                    Token {
-                        kind: TokenType::Equal,
-                        line,
-                        column,
-                    }
+                    TokenType::Equal
                 }
             }
-            '+' => Token {
-                kind: TokenType::Plus,
-                line,
-                column,
-            },
+            '+' => TokenType::Plus,
             '-' if self.peek() == '>' => {
                 self.advance();
-                Token {
-                    kind: TokenType::Arrow,
-                    line,
-                    column,
-                }
+                TokenType::Arrow
             }
-            '-' => Token {
-                kind: TokenType::Minus,
-                line,
-                column,
-            },
-            '*' => Token {
-                kind: TokenType::Star,
-                line,
-                column,
-            },
-            '/' => Token {
-                kind: TokenType::Slash,
-                line,
-                column,
-            },
-            '%' => Token {
-                kind: TokenType::Percent,
-                line,
-                column,
-            },
+            '-' => TokenType::Minus,
+            '*' => TokenType::Star,
+            // Ordinary comments were consumed as trivia
+            '/' if self.peek() == '/' => self.doc_comment(),
+            '/' => TokenType::Slash,
+            '%' => TokenType::Percent,
             '!' => {
                 if self.match_char('=') {
-                    Token {
-                        kind: TokenType::NotEqual,
-                        line,
-                        column,
-                    }
+                    TokenType::NotEqual
                 } else {
-                    Token {
-                        kind: TokenType::Not,
-                        line,
-                        column,
-                    }
+                    TokenType::Not
                 }
             }
-            '&' if self.match_char('&') => Token {
-                kind: TokenType::And,
-                line,
-                column,
-            },
-            '|' if self.match_char('|') => Token {
-                kind: TokenType::Or,
-                line,
-                column,
-            },
-            '-' if self.match_char('>') => Token {
-                kind: TokenType::Arrow,
-                line,
-                column,
-            },
+            '&' if self.match_char('&') => TokenType::And,
+            '&' => TokenType::Ampersand,
+            '|' if self.match_char('|') => TokenType::Or,
+            '|' => TokenType::Pipe,
+            '-' if self.match_char('>') => TokenType::Arrow,
             '<' => {
                 if self.match_char('=') {
-                    Token {
-                        kind: TokenType::LessEqual,
-                        line,
-                        column,
-                    }
+                    TokenType::LessEqual
                 } else {
-                    Token {
-                        kind: TokenType::LessThan,
-                        line,
-                        column,
-                    }
+                    TokenType::LessThan
                 }
             }
             '>' => {
                 if self.match_char('=') {
-                    Token {
-                        kind: TokenType::GreaterEqual,
-                        line,
-                        column,
-                    }
+                    TokenType::GreaterEqual
                 } else {
-                    Token {
-                        kind: TokenType::GreaterThan,
-                        line,
-                        column,
-                    }
+                    TokenType::GreaterThan
                 }
             }
-            '"' => Token {
-                kind: TokenType::StringLiteral(self.string()),
-                line,
-                column,
-            },
-            c if c.is_ascii_digit() => Token {
-                kind: TokenType::IntegerLiteral(self.number()),
-                line,
-                column,
-            },
+            '"' => self.string(),
+            '\'' => self.char_literal(),
+            'r' if self.at_raw_string() => self.raw_string(),
+            c if c.is_ascii_digit() => self.number(c),
             c if c.is_alphabetic() || c == '_' => {
                 let ident = self.identifier();
-                let kind = match ident.as_str() {
+                match ident.as_str() {
                     "fn" => TokenType::Fn,
                     "actor" => TokenType::Actor,
                     "let" => TokenType::Let,
@@ -410,29 +781,36 @@ impl Lexer {
                     "continue" => TokenType::Continue,
                     "if" => TokenType::If,
                     "else" => TokenType::Else,
+                    "match" => TokenType::Match,
                     "extern" => TokenType::Extern,
                     "unsafe" => TokenType::Unsafe,
+                    "move" => TokenType::Move,
+                    "pub" => TokenType::Pub,
+                    "mod" => TokenType::Mod,
+                    "import" => TokenType::Import,
+                    "use" => TokenType::Use,
                     "ui" => TokenType::Ui,
                     "child" => TokenType::Child,
                     "children" => TokenType::Children,
+                    "char" => TokenType::Char,
                     "i32" => TokenType::I32,
+                    "i64" => TokenType::I64,
+                    "u8" => TokenType::U8,
+                    "u64" => TokenType::U64,
+                    "f32" => TokenType::F32,
+                    "f64" => TokenType::F64,
                     "str" => TokenType::Str,
                     "bool" => TokenType::Bool,
                     "ptr" => TokenType::Ptr,
                     "Option" => TokenType::Option,
                     "Result" => TokenType::Result,
                     _ => TokenType::Identifier(ident),
-                };
-                Token { kind, line, column }
+                }
             }
             _ => {
                 // In MVP: report error or skip
                 // Here a diagnostic could be recorded
-                Token {
-                    kind: TokenType::Identifier("INVALID".to_string()),
-                    line,
-                    column,
-                }
+                TokenType::Identifier("INVALID".to_string())
             }
         }
     }
@@ -450,4 +828,211 @@ mod tests {
         assert!(tokens.iter().any(|t| matches!(t.kind, TokenType::Fn)));
         assert!(tokens.iter().any(|t| matches!(t.kind, TokenType::StringLiteral(_))));
     }
+
+    #[test]
+    fn test_token_spans() {
+        let source = "fn main()\n{ x }".to_string();
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.tokenize();
+        let main = &tokens[1];
+        assert_eq!(main.span.start.offset, 3);
+        assert_eq!(main.span.end.offset, 7);
+        let x = &tokens[5];
+        assert_eq!(x.kind, TokenType::Identifier("x".to_string()));
+        assert_eq!((x.span.start.line, x.span.start.column), (2, 3));
+        assert_eq!(x.span.start.offset, 12);
+    }
+
+    #[test]
+    fn test_non_ascii_positions() {
+        let source = "// ¿Qué?\nlet año = \"😀\"; x".to_string();
+        let tokens = Lexer::new(source.clone()).tokenize();
+        assert_eq!(tokens[1].kind, TokenType::Identifier("año".to_string()));
+        assert_eq!(tokens[3].kind, TokenType::StringLiteral("😀".to_string()));
+
+        let x = &tokens[5].span.start;
+        assert_eq!((x.line, x.column, x.utf16_column), (2, 16, 17));
+        assert_eq!(x.offset, 30);
+        assert_eq!(&source[x.offset..], "x");
+        assert_eq!(x.lsp_position(), (1, 16));
+    }
+
+    /// Generated source of about `lines` lines mixing code, non-ASCII
+    /// comments and string literals
+    fn generated_source(lines: usize) -> String {
+        let mut source = String::new();
+        for i in 0..lines / 6 {
+            source.push_str(&format!(
+                "// Función número {i}: calcula el año\n\
+                 fn f{i}(x: i32) -> i32 {{\n\
+                 \tlet s = \"cañón ✓ {i}\";\n\
+                 \tlet y = x * 0x{i:x} + 1_000;\n\
+                 \treturn y;\n\
+                 }}\n"
+            ));
+        }
+        source
+    }
+
+    #[test]
+    fn test_large_generated_file() {
+        let source = generated_source(6_000);
+        let tokens = Lexer::new(source.clone()).tokenize();
+        // 28 tokens per function, plus Eof
+        assert_eq!(tokens.len(), 28 * 1_000 + 1);
+        let last = &tokens[tokens.len() - 2];
+        assert_eq!(last.kind, TokenType::RightBrace);
+        assert_eq!(last.span.start.line, 6_000);
+        assert_eq!(last.span.end.offset, source.len() - 1);
+    }
+
+    /// Throughput benchmark; run with
+    /// `cargo test --release bench_lexer -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_lexer() {
+        let source = generated_source(600_000);
+        let start = std::time::Instant::now();
+        let tokens = Lexer::new(source.clone()).tokenize();
+        let elapsed = start.elapsed();
+        println!(
+            "lexed {} bytes into {} tokens in {:?} ({:.1} MB/s)",
+            source.len(),
+            tokens.len(),
+            elapsed,
+            source.len() as f64 / elapsed.as_secs_f64() / 1e6
+        );
+    }
+
+    fn kinds(source: &str) -> (Vec<TokenType>, usize) {
+        let mut lexer = Lexer::new(source.to_string());
+        let kinds = lexer.tokenize().into_iter().map(|t| t.kind).collect();
+        (kinds, lexer.diagnostics().error_count())
+    }
+
+    #[test]
+    fn test_numeric_literals() {
+        use NumberSuffix::*;
+        let (tokens, errors) = kinds("0xFF 0b1010 0o17 1_000_000 10u8 5000000000 1.5e3 2.0f32 3f64 1e-2 0x10u64 1..2");
+        assert_eq!(errors, 0);
+        assert_eq!(
+            tokens,
+            vec![
+                TokenType::IntegerLiteral(255, None),
+                TokenType::IntegerLiteral(10, None),
+                TokenType::IntegerLiteral(15, None),
+                TokenType::IntegerLiteral(1_000_000, None),
+                TokenType::IntegerLiteral(10, Some(U8)),
+                TokenType::IntegerLiteral(5_000_000_000, None),
+                TokenType::FloatLiteral(1500.0, None),
+                TokenType::FloatLiteral(2.0, Some(F32)),
+                TokenType::FloatLiteral(3.0, Some(F64)),
+                TokenType::FloatLiteral(0.01, None),
+                TokenType::IntegerLiteral(16, Some(U64)),
+                TokenType::IntegerLiteral(1, None),
+                TokenType::Dot,
+                TokenType::Dot,
+                TokenType::IntegerLiteral(2, None),
+                TokenType::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_numeric_literal_errors() {
+        for source in [
+            "256u8",
+            "9223372036854775808",
+            "18446744073709551616u64",
+            "2147483648i32",
+            "0b102",
+            "0x",
+            "10q",
+            "1.5u8",
+            "0x1f64u8",
+            "1e999",
+            "1e39f32",
+        ] {
+            let (tokens, errors) = kinds(source);
+            assert_eq!(errors, 1, "{}", source);
+            assert_eq!(tokens.len(), 2, "{}", source);
+        }
+        assert_eq!(kinds("18446744073709551615u64").1, 0);
+    }
+
+    #[test]
+    fn test_string_and_char_literals() {
+        let source = r##"
+            "tab\t\r\0\\\"\x41\u{e9}\u{1F600}"
+            "multi
+line" "joined \
+                here"
+            r"C:\raw\n" r#"say "hi""# 'a' '\n' '\'' '\u{263A}' 'é' rx
+        "##;
+        let (tokens, errors) = kinds(source);
+        assert_eq!(errors, 0);
+        assert_eq!(
+            tokens,
+            vec![
+                TokenType::StringLiteral("tab\t\r\0\\\"A\u{e9}\u{1F600}".to_string()),
+                TokenType::StringLiteral("multi\nline".to_string()),
+                TokenType::StringLiteral("joined here".to_string()),
+                TokenType::StringLiteral("C:\\raw\\n".to_string()),
+                TokenType::StringLiteral("say \"hi\"".to_string()),
+                TokenType::CharLiteral('a'),
+                TokenType::CharLiteral('\n'),
+                TokenType::CharLiteral('\''),
+                TokenType::CharLiteral('\u{263A}'),
+                TokenType::CharLiteral('é'),
+                TokenType::Identifier("rx".to_string()),
+                TokenType::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_string_and_char_errors() {
+        for source in [
+            "\"open",
+            "r#\"open\"",
+            "\"\\q\"",
+            "\"\\x80\"",
+            "\"\\x4\"",
+            "\"\\u{110000}\"",
+            "\"\\u{}\"",
+            "\"\\u41\"",
+            "''",
+            "'ab'",
+            "'a",
+        ] {
+            let (tokens, errors) = kinds(source);
+            assert_eq!(errors, 1, "{}", source);
+            assert_eq!(tokens.len(), 2, "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_comments() {
+        let source = "/* a /* nested */ b */ x //// plain\n/// Docs\n//! Inner\ny /* open".to_string();
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.tokenize_lossless();
+        let kinds: Vec<TokenType> = tokens.iter().map(|t| t.token.kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenType::Identifier("x".to_string()),
+                TokenType::DocComment(" Docs".to_string()),
+                TokenType::InnerDocComment(" Inner".to_string()),
+                TokenType::Identifier("y".to_string()),
+                TokenType::Eof,
+            ]
+        );
+        assert_eq!(tokens[0].leading_trivia[0].kind, TriviaKind::BlockComment);
+        assert_eq!(tokens[0].leading_trivia[0].text, "/* a /* nested */ b */");
+        // An unterminated comment is reported, and still kept as trivia
+        assert_eq!(tokens[4].leading_trivia[1].text, "/* open");
+        assert_eq!(lexer.diagnostics().error_count(), 1);
+        assert_eq!(self::kinds("/* a /* b */ c").1, 1);
+        assert_eq!(self::kinds("/* a /* b */ c */").1, 0);
+    }
 }
@@ -19,7 +19,11 @@ mod codegen;
 mod actor_runtime;
 mod traits;
 mod diagnostics;
+mod exhaustiveness;
+mod captures;
+mod modules;
 mod formatter;
+mod syntax;
 mod linter;
 
 #[derive(Parser)]
@@ -117,18 +121,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 }
 
 fn build_command(input: &str, target: &str, no_link: bool) -> Result<(), Box<dyn std::error::Error>> {
-    // 1. Read source file
-    let source = fs::read_to_string(input)?;
     let input_path = Path::new(input);
     let output_stem = input_path.file_stem().unwrap().to_str().unwrap();
 
-    // 2. Lexing
-    let mut lexer = lexer::Lexer::new(source);
-    let tokens = lexer.tokenize();
-
-    // 3. Parsing
-    let mut parser = parser::Parser::new(tokens);
-    let declarations = parser.parse()?;
+    // 1-3. Reading, lexing and parsing the file and the modules it uses
+    let declarations = load_program(input);
 
     // 4. Type Checking
     let mut type_checker = type_checker::TypeChecker::new();
@@ -148,7 +145,17 @@ fn build_command(input: &str, target: &str, no_link: bool) -> Result<(), Box<dyn
         std::process::exit(1);
     }
     
-    // 7. Optimization
+    // 7. Monomorphisation: generic functions and structs are instantiated
+    // for the type arguments the type checker found
+    let declarations = match generics::monomorphize(declarations, type_checker.instantiations(), type_checker.literal_types()) {
+        Ok(declarations) => declarations,
+        Err(e) => {
+            eprintln!("❌ Generics error: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // 8. Optimization
     let mut optimizer = optimizer::Optimizer::new();
     let declarations = optimizer.optimize_program(declarations);
     let opt_count = optimizer.get_optimizations_count();
@@ -156,7 +163,7 @@ fn build_command(input: &str, target: &str, no_link: bool) -> Result<(), Box<dyn
         eprintln!("✓ Applied {} optimizations", opt_count);
     }
 
-    // 8. C code generation
+    // 9. C code generation
     let mut codegen = codegen::c::CGenerator::new();
     let c_code = codegen.generate_program(declarations);
 
@@ -199,6 +206,45 @@ fn build_command(input: &str, target: &str, no_link: bool) -> Result<(), Box<dyn
     Ok(())
 }
 
+/// Parse `input` and every module it imports into one program, exiting on
+/// syntax or module errors
+fn load_program(input: &str) -> Vec<parser::Declaration> {
+    let entry = Path::new(input);
+    let loader = modules::ModuleLoader::new(entry);
+    // Each file gets its own id, so spans from different files differ
+    let mut files = 0;
+    let parse = |path: &Path, source| {
+        files += 1;
+        parse_source(&path.display().to_string(), source, files - 1)
+    };
+    match loader.load(entry, parse) {
+        Ok(declarations) => declarations,
+        Err(e) => {
+            eprintln!("❌ Module error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Lex and parse a source file, reporting every syntax error before exiting
+fn parse_source(input: &str, source: String, file_id: diagnostics::FileId) -> Vec<parser::Declaration> {
+    let tree = syntax::SyntaxTree::parse_file(&source, file_id);
+
+    let mut parser = parser::Parser::new(tree.significant_tokens());
+    let declarations = parser.parse_program();
+
+    let error_count = tree.diagnostics().error_count() + parser.diagnostics().error_count();
+    if error_count > 0 {
+        let diagnostics = tree.diagnostics().diagnostics().iter();
+        for diag in diagnostics.chain(parser.diagnostics().diagnostics()) {
+            eprintln!("{}:{}", input, diag);
+        }
+        eprintln!("❌ {} syntax error(s) in {}", error_count, input);
+        std::process::exit(1);
+    }
+    declarations
+}
+
 fn fmt_command(input: &str, write: bool) -> Result<(), Box<dyn std::error::Error>> {
     let source = fs::read_to_string(input)?;
     let formatter = formatter::Formatter::default();
@@ -208,7 +254,7 @@ fn fmt_command(input: &str, write: bool) -> Result<(), Box<dyn std::error::Error
         fs::write(input, &formatted)?;
         eprintln!("✅ Formatted: {}", input);
     } else {
-        println!("{}", formatted);
+        print!("{}", formatted);
     }
     Ok(())
 }
@@ -229,15 +275,8 @@ fn lint_command(input: &str) -> Result<(), Box<dyn std::error::Error>> {
 }
 
 fn check_command(input: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let source = fs::read_to_string(input)?;
-    
-    // Lexing
-    let mut lexer = lexer::Lexer::new(source);
-    let tokens = lexer.tokenize();
-
-    // Parsing
-    let mut parser = parser::Parser::new(tokens);
-    let declarations = parser.parse()?;
+    // Lexing and parsing, with imported modules
+    let declarations = load_program(input);
 
     // Type checking
     let mut checker = type_checker::TypeChecker::new();
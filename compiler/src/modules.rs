@@ -0,0 +1,1113 @@
+// modules.rs — U v0.8 Module System
+// MIT License — Copyright (c) 2025 Webcien and U contributors
+//
+// Loads every module a program uses and merges them into one program
+// Features:
+// - `mod name;` loads `name.ul` (or `name/mod.ul`) beside the declaring file
+// - `import a::b;` loads `a/b.ul` from the crate root (the entry file's
+//   directory); `import std::x;` loads `x.ul` from the bundled `std/` or
+//   `stdlib/` directory
+// - `use a::b;` and `use a::{b, c};` name items of another module
+// - `pub` items are visible everywhere, others only in their module and the
+//   modules declared inside it
+// - Qualified paths: `shapes::area(r)`, `shapes::Shape::Circle(r)`,
+//   `collections::Vec`, `crate::helper()`
+//
+// Items of the entry module keep their names. Functions, types, enums and
+// traits of any other module are renamed `a__b__name` after its path, so two
+// modules may define the same name and the C output stays one flat file.
+// Actors, UI blocks and extern functions stay global. So that no two items
+// get the same C name, no item name may contain `__`, and no module name
+// may contain it or end in `_`.
+
+use crate::diagnostics::Span;
+use crate::parser::{
+    ArmBody, Declaration, Expression, ExpressionKind, Import, ImportKind, Pattern, PatternKind, Statement,
+    StatementKind, Type, VariantPayload, Widget, WidgetValue,
+};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ModuleError {
+    /// No file for a `mod` or `import`
+    NotFound {
+        module: String,
+        searched: Vec<PathBuf>,
+        file: PathBuf,
+        span: Span,
+    },
+    /// A path that names no module or item
+    UnknownItem { path: String, file: PathBuf, span: Span },
+    /// An item used outside the modules that can see it
+    Private { path: String, file: PathBuf, span: Span },
+    /// A name bound twice in one module
+    Duplicate { name: String, file: PathBuf, span: Span },
+    /// An item or module name the C names of module items could clash with
+    ReservedName { name: String, file: PathBuf, span: Span },
+    Io { file: PathBuf, message: String },
+}
+
+impl fmt::Display for ModuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModuleError::NotFound { module, searched, file, span } => {
+                let searched: Vec<String> = searched.iter().map(|p| p.display().to_string()).collect();
+                write!(
+                    f,
+                    "{}:{}: cannot find module `{}` (searched {})",
+                    file.display(),
+                    span,
+                    module,
+                    searched.join(", ")
+                )
+            }
+            ModuleError::UnknownItem { path, file, span } => {
+                write!(f, "{}:{}: cannot find `{}`", file.display(), span, path)
+            }
+            ModuleError::Private { path, file, span } => {
+                write!(f, "{}:{}: `{}` is private to its module", file.display(), span, path)
+            }
+            ModuleError::Duplicate { name, file, span } => {
+                write!(f, "{}:{}: `{}` is defined more than once", file.display(), span, name)
+            }
+            ModuleError::ReservedName { name, file, span } => write!(
+                f,
+                "{}:{}: `{}` is reserved: C names of module items join the module path and name with `__`",
+                file.display(),
+                span,
+                name
+            ),
+            ModuleError::Io { file, message } => write!(f, "{}: {}", file.display(), message),
+        }
+    }
+}
+
+impl std::error::Error for ModuleError {}
+
+/// Module errors are boxed, like the parser's diagnostics
+type ModuleResult<T> = Result<T, Box<ModuleError>>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ItemKind {
+    Function,
+    Type,
+    Enum,
+    Trait,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Item {
+    kind: ItemKind,
+    public: bool,
+}
+
+/// What a name bound by `mod`, `import` or `use` refers to
+#[derive(Debug, Clone)]
+enum Binding {
+    Module(usize),
+    /// Module and name of the item
+    Item(usize, String),
+}
+
+struct Module {
+    /// `[]` for the entry module, `[shapes]`, `[std, core]`, ...
+    path: Vec<String>,
+    file: PathBuf,
+    /// The module that declared this one with `mod`
+    parent: Option<usize>,
+    declarations: Vec<Declaration>,
+    items: HashMap<String, Item>,
+    /// Modules declared with `mod`, reachable as `this::child`
+    children: HashMap<String, usize>,
+    /// Names bound by `mod`, `import` and `use`
+    scope: HashMap<String, Binding>,
+}
+
+impl Module {
+    /// Prefix of the C names of this module's items
+    fn prefix(&self) -> String {
+        self.path.iter().map(|segment| format!("{}__", segment)).collect()
+    }
+}
+
+/// Loads the modules of a program and merges them into one
+pub struct ModuleLoader {
+    /// Directory of the entry file; `import` paths start here
+    root_dir: PathBuf,
+    /// Where `import std::...` looks, in order
+    std_dirs: Vec<PathBuf>,
+    modules: Vec<Module>,
+    by_file: HashMap<PathBuf, usize>,
+    /// Modules whose imports have all been loaded, dependencies first
+    order: Vec<usize>,
+    /// `use` declarations, bound once every module is loaded, with the
+    /// module they loaded themselves if their path is not a binding
+    uses: Vec<(usize, Import, Option<usize>)>,
+}
+
+/// Check that an item's name cannot clash with the C name of an item of
+/// another module
+fn check_item_name(name: &str, file: &Path, span: Span) -> ModuleResult<()> {
+    if name.contains("__") {
+        return Err(Box::new(ModuleError::ReservedName {
+            name: name.to_string(),
+            file: file.to_path_buf(),
+            span,
+        }));
+    }
+    Ok(())
+}
+
+impl ModuleLoader {
+    /// Loader for the program whose entry file is `entry`
+    pub fn new(entry: &Path) -> Self {
+        let home = std::env::var_os("U_HOME")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join(".."));
+        Self {
+            root_dir: entry.parent().map(Path::to_path_buf).unwrap_or_default(),
+            std_dirs: vec![home.join("std"), home.join("stdlib")],
+            modules: Vec::new(),
+            by_file: HashMap::new(),
+            order: Vec::new(),
+            uses: Vec::new(),
+        }
+    }
+
+    /// Load `entry` and every module it uses, returning the declarations of
+    /// all of them with names resolved. `parse` turns a file's source into
+    /// declarations (and reports its syntax errors).
+    pub fn load(
+        mut self,
+        entry: &Path,
+        mut parse: impl FnMut(&Path, String) -> Vec<Declaration>,
+    ) -> ModuleResult<Vec<Declaration>> {
+        self.load_module(entry.to_path_buf(), Vec::new(), None, &mut parse)?;
+        for (module, import, loaded) in std::mem::take(&mut self.uses) {
+            self.bind_use(module, &import, loaded)?;
+        }
+
+        let mut program = Vec::new();
+        for id in self.order.clone() {
+            let declarations = std::mem::take(&mut self.modules[id].declarations);
+            let mut resolver = Resolver {
+                loader: &self,
+                module: id,
+                locals: Vec::new(),
+                type_params: Vec::new(),
+            };
+            for mut decl in declarations {
+                if matches!(decl, Declaration::Import(_)) {
+                    continue;
+                }
+                resolver.declaration(&mut decl)?;
+                program.push(decl);
+            }
+        }
+        Ok(program)
+    }
+
+    fn load_module(
+        &mut self,
+        file: PathBuf,
+        path: Vec<String>,
+        parent: Option<usize>,
+        parse: &mut dyn FnMut(&Path, String) -> Vec<Declaration>,
+    ) -> ModuleResult<usize> {
+        let canonical = fs::canonicalize(&file).unwrap_or_else(|_| file.clone());
+        if let Some(&id) = self.by_file.get(&canonical) {
+            return Ok(id);
+        }
+        let source = fs::read_to_string(&file).map_err(|e| {
+            Box::new(ModuleError::Io {
+                file: file.clone(),
+                message: e.to_string(),
+            })
+        })?;
+        let declarations = parse(&file, source);
+
+        let mut items = HashMap::new();
+        for decl in &declarations {
+            let global = match decl {
+                Declaration::Actor(a) => vec![&a.name],
+                Declaration::UiBlock(ui) => vec![&ui.name],
+                Declaration::ExternBlock(block) => block.functions.iter().map(|f| &f.name).collect(),
+                _ => Vec::new(),
+            };
+            for name in global {
+                check_item_name(name, &file, decl.span())?;
+            }
+            let (name, item) = match decl {
+                Declaration::Function(f) => (&f.name, Item { kind: ItemKind::Function, public: f.public }),
+                Declaration::TypeDef(t) => (&t.name, Item { kind: ItemKind::Type, public: t.public }),
+                Declaration::Enum(e) => (&e.name, Item { kind: ItemKind::Enum, public: e.public }),
+                Declaration::Trait(t) => (&t.name, Item { kind: ItemKind::Trait, public: t.public }),
+                _ => continue,
+            };
+            check_item_name(name, &file, decl.span())?;
+            if items.insert(name.clone(), item).is_some() {
+                return Err(Box::new(ModuleError::Duplicate {
+                    name: name.clone(),
+                    file,
+                    span: decl.span(),
+                }));
+            }
+        }
+
+        let id = self.modules.len();
+        self.by_file.insert(canonical, id);
+        let imports: Vec<Import> = declarations
+            .iter()
+            .filter_map(|decl| match decl {
+                Declaration::Import(import) => Some(import.clone()),
+                _ => None,
+            })
+            .collect();
+        self.modules.push(Module {
+            path,
+            file,
+            parent,
+            declarations,
+            items,
+            children: HashMap::new(),
+            scope: HashMap::new(),
+        });
+
+        // `use` may name modules bound by any `mod` or `import` of the file
+        let (uses, imports): (Vec<Import>, Vec<Import>) =
+            imports.into_iter().partition(|import| import.kind == ImportKind::Use);
+        for import in imports.into_iter().chain(uses) {
+            // The last segment of a `use` path is an item, checked where it
+            // is declared
+            let modules = match import.kind {
+                ImportKind::Use => &import.path[..import.path.len() - 1],
+                _ => &import.path[..],
+            };
+            for segment in modules {
+                if segment.contains("__") || segment.ends_with('_') {
+                    return Err(Box::new(ModuleError::ReservedName {
+                        name: segment.clone(),
+                        file: self.modules[id].file.clone(),
+                        span: import.span,
+                    }));
+                }
+            }
+            match import.kind {
+                ImportKind::Mod => {
+                    let name = import.path[0].clone();
+                    let dir = self.child_dir(id);
+                    let file = self.find_file(id, &import, &[dir], &import.path)?;
+                    let mut path = self.modules[id].path.clone();
+                    path.push(name.clone());
+                    let child = self.load_module(file, path, Some(id), parse)?;
+                    self.modules[id].children.insert(name.clone(), child);
+                    self.bind(id, name, Binding::Module(child), import.span)?;
+                }
+                ImportKind::Import => {
+                    let child = self.import_module(id, &import, parse)?;
+                    let name = import.path.last().unwrap().clone();
+                    self.bind(id, name, Binding::Module(child), import.span)?;
+                }
+                ImportKind::Use => {
+                    // `use std::core::max;` loads the module it names
+                    let loaded = if self.modules[id].scope.contains_key(&import.path[0]) || import.path[0] == "crate" {
+                        None
+                    } else {
+                        Some(self.import_module(id, &import, parse)?)
+                    };
+                    self.uses.push((id, import, loaded));
+                }
+            }
+        }
+        self.order.push(id);
+        Ok(id)
+    }
+
+    /// Load the module an `import` (or a `use` of an unbound path) names
+    fn import_module(
+        &mut self,
+        id: usize,
+        import: &Import,
+        parse: &mut dyn FnMut(&Path, String) -> Vec<Declaration>,
+    ) -> ModuleResult<usize> {
+        let (dirs, segments, path) = match import.path[0].as_str() {
+            "std" => (self.std_dirs.clone(), &import.path[1..], import.path.clone()),
+            "crate" => (vec![self.root_dir.clone()], &import.path[1..], import.path[1..].to_vec()),
+            _ => (vec![self.root_dir.clone()], &import.path[..], import.path.clone()),
+        };
+        let file = self.find_file(id, import, &dirs, segments)?;
+        self.load_module(file, path, None, parse)
+    }
+
+    /// Directory holding the files of the modules `id` declares with `mod`:
+    /// its own directory for the entry file and `mod.ul`, else `dir/name/`
+    fn child_dir(&self, id: usize) -> PathBuf {
+        let file = &self.modules[id].file;
+        let dir = file.parent().map(Path::to_path_buf).unwrap_or_default();
+        match file.file_stem().and_then(|s| s.to_str()) {
+            Some(stem) if id != 0 && stem != "mod" => dir.join(stem),
+            _ => dir,
+        }
+    }
+
+    /// `a/b.ul` or `a/b/mod.ul` in the first of `dirs` that has one
+    fn find_file(&self, id: usize, import: &Import, dirs: &[PathBuf], segments: &[String]) -> ModuleResult<PathBuf> {
+        let mut searched = Vec::new();
+        if !segments.is_empty() {
+            let relative: PathBuf = segments.iter().collect();
+            for dir in dirs {
+                for candidate in [dir.join(&relative).with_extension("ul"), dir.join(&relative).join("mod.ul")] {
+                    if candidate.is_file() {
+                        return Ok(candidate);
+                    }
+                    searched.push(candidate);
+                }
+            }
+        }
+        Err(Box::new(ModuleError::NotFound {
+            module: import.path.join("::"),
+            searched,
+            file: self.modules[id].file.clone(),
+            span: import.span,
+        }))
+    }
+
+    fn bind(&mut self, id: usize, name: String, binding: Binding, span: Span) -> ModuleResult<()> {
+        let module = &mut self.modules[id];
+        if module.items.contains_key(&name) || module.scope.contains_key(&name) {
+            return Err(Box::new(ModuleError::Duplicate {
+                name,
+                file: module.file.clone(),
+                span,
+            }));
+        }
+        module.scope.insert(name, binding);
+        Ok(())
+    }
+
+    /// Bind the items of a `use` in the module that declares it
+    fn bind_use(&mut self, id: usize, import: &Import, loaded: Option<usize>) -> ModuleResult<()> {
+        let Some(target) = loaded.or_else(|| self.module_path(id, &import.path)) else {
+            return Err(Box::new(ModuleError::UnknownItem {
+                path: import.path.join("::"),
+                file: self.modules[id].file.clone(),
+                span: import.span,
+            }));
+        };
+        for name in &import.items {
+            let path = format!("{}::{}", import.path.join("::"), name);
+            let file = self.modules[id].file.clone();
+            let span = import.span;
+            let binding = if let Some(&child) = self.modules[target].children.get(name) {
+                Binding::Module(child)
+            } else if let Some(item) = self.modules[target].items.get(name) {
+                if !item.public && !self.can_see(id, target) {
+                    return Err(Box::new(ModuleError::Private { path, file, span }));
+                }
+                Binding::Item(target, name.clone())
+            } else {
+                return Err(Box::new(ModuleError::UnknownItem { path, file, span }));
+            };
+            self.bind(id, name.clone(), binding, span)?;
+        }
+        Ok(())
+    }
+
+    /// The module a path such as `shapes` or `crate::geometry::shapes`
+    /// names from module `id`, if its first segment is `crate` or a module
+    /// binding
+    fn module_path(&self, id: usize, segments: &[String]) -> Option<usize> {
+        let (first, rest) = segments.split_first()?;
+        let mut module = match self.modules[id].scope.get(first) {
+            Some(Binding::Module(module)) => *module,
+            _ if first == "crate" => 0,
+            _ => return None,
+        };
+        for segment in rest {
+            module = *self.modules[module].children.get(segment)?;
+        }
+        Some(module)
+    }
+
+    /// Whether private items of `owner` are visible in module `id`: in the
+    /// module itself and the modules declared inside it
+    fn can_see(&self, id: usize, owner: usize) -> bool {
+        let mut module = Some(id);
+        while let Some(m) = module {
+            if m == owner {
+                return true;
+            }
+            module = self.modules[m].parent;
+        }
+        false
+    }
+}
+
+/// Rewrites the names in one module's declarations to the merged program's
+struct Resolver<'a> {
+    loader: &'a ModuleLoader,
+    module: usize,
+    /// Variables in scope, innermost last; they shadow items
+    locals: Vec<HashSet<String>>,
+    type_params: Vec<String>,
+}
+
+impl Resolver<'_> {
+    fn current(&self) -> &Module {
+        &self.loader.modules[self.module]
+    }
+
+    fn mangle(&self, module: usize, name: &str) -> String {
+        format!("{}{}", self.loader.modules[module].prefix(), name)
+    }
+
+    fn is_local(&self, name: &str) -> bool {
+        self.locals.iter().any(|scope| scope.contains(name))
+    }
+
+    fn declare(&mut self, name: &str) {
+        if let Some(scope) = self.locals.last_mut() {
+            scope.insert(name.to_string());
+        }
+    }
+
+    /// Module and kind of the item an unqualified name refers to
+    fn lookup(&self, name: &str) -> Option<(usize, ItemKind)> {
+        if let Some(item) = self.current().items.get(name) {
+            return Some((self.module, item.kind));
+        }
+        match self.current().scope.get(name) {
+            Some(Binding::Item(module, item)) => {
+                Some((*module, self.loader.modules[*module].items[item].kind))
+            }
+            _ => None,
+        }
+    }
+
+    /// C name for an unqualified item name; names of variables, built-ins,
+    /// actors and extern functions are left alone
+    fn unqualified(&self, name: &str, kinds: &[ItemKind]) -> String {
+        match self.lookup(name) {
+            Some((module, kind)) if kinds.contains(&kind) => self.mangle(module, name),
+            _ => name.to_string(),
+        }
+    }
+
+    /// Item `name` of `module`, if it is visible from here
+    fn item_in(&self, module: usize, name: &str, path: &str, span: Span) -> ModuleResult<ItemKind> {
+        let Some(item) = self.loader.modules[module].items.get(name) else {
+            return Err(self.unknown(path, span));
+        };
+        if !item.public && !self.loader.can_see(self.module, module) {
+            return Err(Box::new(ModuleError::Private {
+                path: path.to_string(),
+                file: self.current().file.clone(),
+                span,
+            }));
+        }
+        Ok(item.kind)
+    }
+
+    fn unknown(&self, path: &str, span: Span) -> Box<ModuleError> {
+        Box::new(ModuleError::UnknownItem {
+            path: path.to_string(),
+            file: self.current().file.clone(),
+            span,
+        })
+    }
+
+    /// C name and kind of the item a `::` path names, or `None` if the
+    /// path does not start with a module (`Shape::Circle`)
+    fn qualified(&self, path: &str, span: Span) -> ModuleResult<Option<(String, ItemKind)>> {
+        let segments: Vec<String> = path.split("::").map(str::to_string).collect();
+        let (name, modules) = segments.split_last().unwrap();
+        if modules.is_empty() {
+            return Ok(None);
+        }
+        match self.loader.module_path(self.module, modules) {
+            Some(module) => {
+                let kind = self.item_in(module, name, path, span)?;
+                Ok(Some((self.mangle(module, name), kind)))
+            }
+            None if segments[0] == "crate"
+                || matches!(self.current().scope.get(&segments[0]), Some(Binding::Module(_))) =>
+            {
+                Err(self.unknown(path, span))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// C name and kind of item `name` if `module` is a module path, as in
+    /// `shapes::area`; `None` for a variant path such as `shapes::Shape::Circle`
+    fn module_item(&self, module: &str, name: &str, span: Span) -> ModuleResult<Option<(String, ItemKind)>> {
+        let segments: Vec<String> = module.split("::").map(str::to_string).collect();
+        match self.loader.module_path(self.module, &segments) {
+            Some(module_id) => {
+                let kind = self.item_in(module_id, name, &format!("{}::{}", module, name), span)?;
+                Ok(Some((self.mangle(module_id, name), kind)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// C name of the enum in a variant path: `Shape`, `shapes::Shape`
+    fn enum_name(&self, path: &str, span: Span) -> ModuleResult<String> {
+        if !path.contains("::") {
+            return Ok(self.unqualified(path, &[ItemKind::Enum]));
+        }
+        match self.qualified(path, span)? {
+            Some((name, ItemKind::Enum)) => Ok(name),
+            _ => Err(self.unknown(path, span)),
+        }
+    }
+
+    fn type_name(&self, name: &mut String, span: Span) -> ModuleResult<()> {
+        if self.type_params.contains(name) {
+            return Ok(());
+        }
+        let kinds = [ItemKind::Type, ItemKind::Enum, ItemKind::Trait];
+        if !name.contains("::") {
+            *name = self.unqualified(name, &kinds);
+            return Ok(());
+        }
+        match self.qualified(name, span)? {
+            Some((resolved, kind)) if kinds.contains(&kind) => {
+                *name = resolved;
+                Ok(())
+            }
+            _ => Err(self.unknown(name, span)),
+        }
+    }
+
+    fn ty(&self, ty: &mut Type, span: Span) -> ModuleResult<()> {
+        match ty {
+            Type::Custom(name) | Type::Dyn(name) => self.type_name(name, span)?,
+            Type::Generic { name, type_args } => {
+                self.type_name(name, span)?;
+                for arg in type_args {
+                    self.ty(arg, span)?;
+                }
+            }
+            Type::Option(inner) | Type::Array(inner, _) | Type::Slice(inner) | Type::Range(inner) | Type::Ref(inner) => {
+                self.ty(inner, span)?
+            }
+            Type::Result(ok, err) => {
+                self.ty(ok, span)?;
+                self.ty(err, span)?;
+            }
+            Type::Tuple(items) => {
+                for item in items {
+                    self.ty(item, span)?;
+                }
+            }
+            Type::FunctionPointer { params, return_type } => {
+                for param in params {
+                    self.ty(param, span)?;
+                }
+                if let Some(ret) = return_type {
+                    self.ty(ret, span)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn params(&mut self, params: &mut [(String, Type)], span: Span) -> ModuleResult<()> {
+        for (name, ty) in params {
+            self.ty(ty, span)?;
+            self.declare(name);
+        }
+        Ok(())
+    }
+
+    fn declaration(&mut self, decl: &mut Declaration) -> ModuleResult<()> {
+        let module = self.module;
+        match decl {
+            Declaration::Function(f) => {
+                f.name = self.mangle(module, &f.name);
+                self.function(f)?;
+            }
+            Declaration::Actor(actor) => {
+                for method in &mut actor.methods {
+                    self.function(method)?;
+                }
+            }
+            Declaration::Trait(t) => {
+                t.name = self.mangle(module, &t.name);
+                self.type_params = t.type_params.clone();
+                for method in &mut t.methods {
+                    for (_, ty) in &mut method.params {
+                        self.ty(ty, method.span)?;
+                    }
+                    if let Some(ret) = &mut method.return_type {
+                        self.ty(ret, method.span)?;
+                    }
+                }
+                self.type_params.clear();
+            }
+            Declaration::TypeDef(t) => {
+                t.name = self.mangle(module, &t.name);
+                self.type_params = t.type_params.clone();
+                for (_, trait_name) in &mut t.bounds {
+                    self.type_name(trait_name, t.span)?;
+                }
+                for (_, ty) in &mut t.fields {
+                    self.ty(ty, t.span)?;
+                }
+                self.type_params.clear();
+            }
+            Declaration::Enum(e) => {
+                e.name = self.mangle(module, &e.name);
+                for variant in &mut e.variants {
+                    match &mut variant.payload {
+                        VariantPayload::Unit => {}
+                        VariantPayload::Tuple(types) => {
+                            for ty in types {
+                                self.ty(ty, variant.span)?;
+                            }
+                        }
+                        VariantPayload::Struct(fields) => {
+                            for (_, ty) in fields {
+                                self.ty(ty, variant.span)?;
+                            }
+                        }
+                    }
+                }
+            }
+            Declaration::TraitImpl(i) => {
+                self.type_name(&mut i.trait_name, i.span)?;
+                self.type_name(&mut i.type_name, i.span)?;
+                for arg in &mut i.type_args {
+                    self.ty(arg, i.span)?;
+                }
+                for method in &mut i.methods {
+                    self.function(method)?;
+                }
+            }
+            Declaration::ExternBlock(block) => {
+                for f in &mut block.functions {
+                    for (_, ty) in &mut f.params {
+                        self.ty(ty, f.span)?;
+                    }
+                    if let Some(ret) = &mut f.return_type {
+                        self.ty(ret, f.span)?;
+                    }
+                }
+            }
+            Declaration::UiBlock(ui) => self.widget(&mut ui.root)?,
+            Declaration::Import(_) | Declaration::Error(_) => {}
+        }
+        Ok(())
+    }
+
+    fn function(&mut self, f: &mut crate::parser::Function) -> ModuleResult<()> {
+        self.type_params = f.type_params.clone();
+        for (_, trait_name) in &mut f.bounds {
+            self.type_name(trait_name, f.span)?;
+        }
+        self.locals.push(HashSet::new());
+        self.params(&mut f.params, f.span)?;
+        if let Some(ret) = &mut f.return_type {
+            self.ty(ret, f.span)?;
+        }
+        self.block(&mut f.body)?;
+        self.locals.pop();
+        self.type_params.clear();
+        Ok(())
+    }
+
+    fn widget(&mut self, widget: &mut Widget) -> ModuleResult<()> {
+        for (_, value) in &mut widget.properties {
+            match value {
+                WidgetValue::Identifier(name) => *name = self.unqualified(name, &[ItemKind::Function]),
+                WidgetValue::Widget(child) => self.widget(child)?,
+                WidgetValue::WidgetList(children) => {
+                    for child in children {
+                        self.widget(child)?;
+                    }
+                }
+                WidgetValue::Closure(closure) => self.expression(closure)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn block(&mut self, body: &mut [Statement]) -> ModuleResult<()> {
+        self.locals.push(HashSet::new());
+        for stmt in body {
+            self.statement(stmt)?;
+        }
+        self.locals.pop();
+        Ok(())
+    }
+
+    fn statement(&mut self, stmt: &mut Statement) -> ModuleResult<()> {
+        match &mut stmt.kind {
+            StatementKind::Let { name, ty, value, .. } => {
+                if let Some(ty) = ty {
+                    self.ty(ty, stmt.span)?;
+                }
+                if let Some(value) = value {
+                    self.expression(value)?;
+                }
+                self.declare(name);
+            }
+            StatementKind::Expr(expr) | StatementKind::Return(expr) => self.expression(expr)?,
+            StatementKind::If { condition, then_branch, else_branch } => {
+                self.expression(condition)?;
+                self.block(then_branch)?;
+                if let Some(else_branch) = else_branch {
+                    self.block(else_branch)?;
+                }
+            }
+            StatementKind::While { condition, body } => {
+                self.expression(condition)?;
+                self.block(body)?;
+            }
+            StatementKind::For { variable, iterable, body } => {
+                self.expression(iterable)?;
+                self.locals.push(HashSet::from([variable.clone()]));
+                self.block(body)?;
+                self.locals.pop();
+            }
+            StatementKind::Unsafe { body } => self.block(body)?,
+            StatementKind::Break | StatementKind::Continue | StatementKind::Error => {}
+        }
+        Ok(())
+    }
+
+    fn expressions(&mut self, exprs: &mut [Expression]) -> ModuleResult<()> {
+        for expr in exprs {
+            self.expression(expr)?;
+        }
+        Ok(())
+    }
+
+    fn expression(&mut self, expr: &mut Expression) -> ModuleResult<()> {
+        let span = expr.span;
+        match &mut expr.kind {
+            ExpressionKind::Literal(_) => {}
+            ExpressionKind::Identifier(name) => {
+                if !self.is_local(name) {
+                    *name = self.unqualified(name, &[ItemKind::Function]);
+                }
+            }
+            ExpressionKind::FunctionCall { name, arguments } => {
+                if !self.is_local(name) {
+                    *name = self.unqualified(name, &[ItemKind::Function]);
+                }
+                self.expressions(arguments)?;
+            }
+            ExpressionKind::MethodCall { receiver, arguments, .. } => {
+                self.expression(receiver)?;
+                self.expressions(arguments)?;
+            }
+            ExpressionKind::Binary { left, right, .. } => {
+                self.expression(left)?;
+                self.expression(right)?;
+            }
+            ExpressionKind::Unary { operand, .. } => self.expression(operand)?,
+            ExpressionKind::Assignment { target, value } => {
+                self.expression(target)?;
+                self.expression(value)?;
+            }
+            ExpressionKind::StructLiteral { name, type_args, fields } => {
+                *name = self.unqualified(name, &[ItemKind::Type]);
+                for arg in type_args {
+                    self.ty(arg, span)?;
+                }
+                for (_, value) in fields {
+                    self.expression(value)?;
+                }
+            }
+            ExpressionKind::FieldAccess { object, .. } => self.expression(object)?,
+            ExpressionKind::ArrayLiteral(elements) | ExpressionKind::Tuple(elements) => {
+                self.expressions(elements)?
+            }
+            ExpressionKind::Index { object, index } => {
+                self.expression(object)?;
+                self.expression(index)?;
+            }
+            ExpressionKind::Range { start, end, step, .. } => {
+                self.expression(start)?;
+                self.expression(end)?;
+                if let Some(step) = step {
+                    self.expression(step)?;
+                }
+            }
+            ExpressionKind::EnumVariant { enum_name, variant, payload } => {
+                let path = format!("{}::{}", enum_name, variant);
+                match self.module_item(enum_name, variant, span)? {
+                    // `shapes::area(r)`, `shapes::Point { x, y }` or the
+                    // function `shapes::area` itself
+                    Some((name, kind)) => {
+                        let payload = std::mem::replace(payload, VariantPayload::Unit);
+                        expr.kind = match (kind, payload) {
+                            (ItemKind::Function, VariantPayload::Tuple(arguments)) => {
+                                ExpressionKind::FunctionCall { name, arguments }
+                            }
+                            (ItemKind::Function, VariantPayload::Unit) => ExpressionKind::Identifier(name),
+                            (ItemKind::Type, VariantPayload::Struct(fields)) => ExpressionKind::StructLiteral {
+                                name,
+                                type_args: Vec::new(),
+                                fields,
+                            },
+                            _ => return Err(self.unknown(&path, span)),
+                        };
+                        // The name is final; only the values are left
+                        match &mut expr.kind {
+                            ExpressionKind::FunctionCall { arguments, .. } => self.expressions(arguments)?,
+                            ExpressionKind::StructLiteral { fields, .. } => {
+                                for (_, value) in fields {
+                                    self.expression(value)?;
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                    None => {
+                        *enum_name = self.enum_name(enum_name, span)?;
+                        match payload {
+                            VariantPayload::Unit => {}
+                            VariantPayload::Tuple(items) => self.expressions(items)?,
+                            VariantPayload::Struct(fields) => {
+                                for (_, value) in fields {
+                                    self.expression(value)?;
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            ExpressionKind::Match { scrutinee, arms } => {
+                self.expression(scrutinee)?;
+                for arm in arms {
+                    self.locals.push(HashSet::new());
+                    self.pattern(&mut arm.pattern)?;
+                    if let Some(guard) = &mut arm.guard {
+                        self.expression(guard)?;
+                    }
+                    match &mut arm.body {
+                        ArmBody::Expression(value) => self.expression(value)?,
+                        ArmBody::Block(body) => self.block(body)?,
+                    }
+                    self.locals.pop();
+                }
+            }
+            ExpressionKind::Closure { params, return_type, body, .. } => {
+                self.locals.push(HashSet::new());
+                self.params(params, span)?;
+                if let Some(ret) = return_type {
+                    self.ty(ret, span)?;
+                }
+                self.block(body)?;
+                self.locals.pop();
+            }
+        }
+        Ok(())
+    }
+
+    /// Resolve the names a pattern uses and declare the ones it binds
+    fn pattern(&mut self, pattern: &mut Pattern) -> ModuleResult<()> {
+        let span = pattern.span;
+        match &mut pattern.kind {
+            PatternKind::Binding(name) => self.declare(name),
+            PatternKind::Wildcard | PatternKind::Literal(_) => {}
+            PatternKind::Tuple(items) => {
+                for item in items {
+                    self.pattern(item)?;
+                }
+            }
+            PatternKind::Struct { name, fields, .. } => {
+                self.type_name(name, span)?;
+                for (_, item) in fields {
+                    self.pattern(item)?;
+                }
+            }
+            PatternKind::Variant { enum_name: Some(enum_name), variant, payload, rest } => {
+                let path = format!("{}::{}", enum_name, variant);
+                match self.module_item(enum_name, variant, span)? {
+                    // `shapes::Point { x, y }`
+                    Some((name, ItemKind::Type)) => {
+                        let VariantPayload::Struct(mut fields) = std::mem::replace(payload, VariantPayload::Unit) else {
+                            return Err(self.unknown(&path, span));
+                        };
+                        for (_, item) in &mut fields {
+                            self.pattern(item)?;
+                        }
+                        pattern.kind = PatternKind::Struct { name, fields, rest: *rest };
+                    }
+                    Some(_) => return Err(self.unknown(&path, span)),
+                    None => {
+                        *enum_name = self.enum_name(enum_name, span)?;
+                        self.payload_patterns(payload)?;
+                    }
+                }
+            }
+            PatternKind::Variant { enum_name: None, payload, .. } => self.payload_patterns(payload)?,
+        }
+        Ok(())
+    }
+
+    fn payload_patterns(&mut self, payload: &mut VariantPayload<Pattern>) -> ModuleResult<()> {
+        match payload {
+            VariantPayload::Unit => {}
+            VariantPayload::Tuple(items) => {
+                for item in items {
+                    self.pattern(item)?;
+                }
+            }
+            VariantPayload::Struct(fields) => {
+                for (_, item) in fields {
+                    self.pattern(item)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    /// Write `files` to a fresh directory and load its `main.ul`
+    fn load(test: &str, files: &[(&str, &str)]) -> ModuleResult<Vec<Declaration>> {
+        let dir = std::env::temp_dir().join(format!("u_modules_{}_{}", test, std::process::id()));
+        for (name, source) in files {
+            let path = dir.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, source).unwrap();
+        }
+        let entry = dir.join("main.ul");
+        let program = ModuleLoader::new(&entry)
+            .load(&entry, |_, source| Parser::new(Lexer::new(source).tokenize()).parse().unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+        program
+    }
+
+    fn function<'a>(program: &'a [Declaration], name: &str) -> &'a crate::parser::Function {
+        program
+            .iter()
+            .find_map(|d| match d {
+                Declaration::Function(f) if f.name == name => Some(f),
+                _ => None,
+            })
+            .unwrap_or_else(|| panic!("no function {}", name))
+    }
+
+    fn returned_call(f: &crate::parser::Function) -> &str {
+        match &f.body[0].kind {
+            StatementKind::Return(Expression { kind: ExpressionKind::FunctionCall { name, .. }, .. }) => name,
+            _ => panic!("expected a returned call"),
+        }
+    }
+
+    #[test]
+    fn test_module_resolution() {
+        let program = load(
+            "resolution",
+            &[
+                (
+                    "main.ul",
+                    "mod shapes; import util; use shapes::area;\n\
+                     fn name() -> i32 { return 0; }\n\
+                     fn main() { let a = area(1); let b = util::name(); let c = shapes::Shape::Dot; }",
+                ),
+                (
+                    "shapes.ul",
+                    "pub enum Shape { Dot }\n\
+                     pub fn area(r: i32) -> i32 { return name(); }\n\
+                     fn name() -> i32 { return crate::name(); }\n\
+                     pub fn size(s: &Shape) -> i32 { return 1; }",
+                ),
+                ("util.ul", "pub fn name() -> i32 { return 2; }"),
+            ],
+        )
+        .unwrap();
+
+        // Dependencies come first; only the entry module keeps its names
+        let names: Vec<String> = program
+            .iter()
+            .filter_map(|d| match d {
+                Declaration::Function(f) => Some(f.name.clone()),
+                Declaration::Enum(e) => Some(e.name.clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(names, ["shapes__Shape", "shapes__area", "shapes__name", "shapes__size", "util__name", "name", "main"]);
+        assert_eq!(returned_call(function(&program, "shapes__area")), "shapes__name");
+        assert_eq!(returned_call(function(&program, "shapes__name")), "name");
+        // Types are resolved inside references too
+        assert_eq!(function(&program, "shapes__size").params[0].1.to_string(), "&shapes__Shape");
+
+        let main = function(&program, "main");
+        let values: Vec<&ExpressionKind> = main
+            .body
+            .iter()
+            .map(|stmt| match &stmt.kind {
+                StatementKind::Let { value: Some(value), .. } => &value.kind,
+                _ => panic!("expected let"),
+            })
+            .collect();
+        assert!(matches!(values[0], ExpressionKind::FunctionCall { name, .. } if name == "shapes__area"));
+        assert!(matches!(values[1], ExpressionKind::FunctionCall { name, .. } if name == "util__name"));
+        assert!(matches!(values[2], ExpressionKind::EnumVariant { enum_name, .. } if enum_name == "shapes__Shape"));
+    }
+
+    #[test]
+    fn test_module_errors() {
+        let private = load(
+            "private",
+            &[("main.ul", "mod a; fn main() { a::hidden(); }"), ("a.ul", "fn hidden() {}")],
+        );
+        assert!(matches!(private.map_err(|e| *e), Err(ModuleError::Private { path, .. }) if path == "a::hidden"));
+
+        let missing = load("missing", &[("main.ul", "import nowhere;")]);
+        assert!(matches!(missing.map_err(|e| *e), Err(ModuleError::NotFound { module, .. }) if module == "nowhere"));
+
+        let unknown = load(
+            "unknown",
+            &[("main.ul", "mod a; use a::nothing;"), ("a.ul", "pub fn f() {}")],
+        );
+        assert!(matches!(unknown.map_err(|e| *e), Err(ModuleError::UnknownItem { path, .. }) if path == "a::nothing"));
+
+        let duplicate = load(
+            "duplicate",
+            &[("main.ul", "mod a; use a::f; fn f() {}"), ("a.ul", "pub fn f() {}")],
+        );
+        assert!(matches!(duplicate.map_err(|e| *e), Err(ModuleError::Duplicate { name, .. }) if name == "f"));
+
+        // Names that could clash with the C name of another module's item
+        let reserved = |test: &str, files: &[(&str, &str)]| match load(test, files).map_err(|e| *e) {
+            Err(ModuleError::ReservedName { name, .. }) => name,
+            other => panic!("expected a reserved name, got {:?}", other.map(|_| ())),
+        };
+        assert_eq!(
+            reserved("reserved_item", &[("main.ul", "mod shapes; fn shapes__helper() {}"), ("shapes.ul", "pub fn helper() {}")]),
+            "shapes__helper"
+        );
+        assert_eq!(reserved("reserved_nested", &[("main.ul", "mod a; fn main() {}"), ("a.ul", "pub fn b__c() {}")]), "b__c");
+        assert_eq!(reserved("reserved_module", &[("main.ul", "mod a_; fn main() {}"), ("a_.ul", "pub fn b() {}")]), "a_");
+
+        // Locals shadow module items
+        let program = load(
+            "shadow",
+            &[("main.ul", "mod a; use a::f; fn main() { let f = || 1; f(); }"), ("a.ul", "pub fn f() {}")],
+        )
+        .unwrap();
+        let main = function(&program, "main");
+        assert!(matches!(
+            &main.body[1].kind,
+            StatementKind::Expr(Expression { kind: ExpressionKind::FunctionCall { name, .. }, .. }) if name == "f"
+        ));
+    }
+}
@@ -3,7 +3,7 @@
 //
 // Optimizations for the U language compiler
 
-use crate::parser::{Declaration, Expression, Statement, Literal};
+use crate::parser::{Declaration, Expression, ExpressionKind, Statement, StatementKind, Literal};
 
 pub struct Optimizer {
     optimizations_applied: usize,
@@ -26,157 +26,148 @@ impl Optimizer {
     fn optimize_declaration(&mut self, decl: Declaration) -> Declaration {
         match decl {
             Declaration::Function(mut func) => {
-                func.body = func.body.into_iter()
-                    .map(|stmt| self.optimize_statement(stmt))
-                    .collect();
+                func.body = self.optimize_block(func.body);
                 Declaration::Function(func)
             }
             _ => decl,
         }
     }
 
-    fn optimize_statement(&mut self, stmt: Statement) -> Statement {
-        match stmt {
-            Statement::Let { name, mutable, value } => {
-                let optimized_value = self.optimize_expression(value);
-                Statement::Let {
+    fn optimize_block(&mut self, body: Vec<Statement>) -> Vec<Statement> {
+        body.into_iter()
+            .filter_map(|stmt| self.optimize_statement(stmt))
+            .collect()
+    }
+
+    /// Optimize a statement; `None` where it does nothing at all
+    fn optimize_statement(&mut self, stmt: Statement) -> Option<Statement> {
+        let span = stmt.span;
+        let kind = match stmt.kind {
+            StatementKind::Let { name, mutable, ty, value } => {
+                let optimized_value = value.map(|value| self.optimize_expression(value));
+                StatementKind::Let {
                     name,
                     mutable,
+                    ty,
                     value: optimized_value,
                 }
             }
-            Statement::Expr(expr) => {
-                Statement::Expr(self.optimize_expression(expr))
+            StatementKind::Expr(expr) => {
+                StatementKind::Expr(self.optimize_expression(expr))
             }
-            Statement::If { condition, then_branch, else_branch } => {
+            StatementKind::If { condition, then_branch, else_branch } => {
                 let optimized_condition = self.optimize_expression(condition);
                 
-                // Constant folding for if statements
-                if let Expression::Literal(Literal::Boolean(b)) = optimized_condition {
+                // Constant folding for if statements: only the branch taken
+                // is kept, still in an `if true` so that its variables stay
+                // scoped to it
+                if let ExpressionKind::Literal(Literal::Boolean(b)) = optimized_condition.kind {
                     self.optimizations_applied += 1;
-                    if b {
-                        // If condition is always true, return then branch
-                        return Statement::Expr(Expression::Literal(Literal::Integer(0))); // Placeholder
-                    } else {
-                        // If condition is always false, return else branch or nothing
-                        if let Some(_) = else_branch {
-                            return Statement::Expr(Expression::Literal(Literal::Integer(0))); // Placeholder
-                        } else {
-                            return Statement::Expr(Expression::Literal(Literal::Integer(0))); // Placeholder
-                        }
-                    }
+                    let taken = if b { Some(then_branch) } else { else_branch };
+                    return taken.map(|branch| {
+                        let condition = Expression::new(ExpressionKind::Literal(Literal::Boolean(true)), optimized_condition.span);
+                        let kind = StatementKind::If {
+                            condition,
+                            then_branch: self.optimize_block(branch),
+                            else_branch: None,
+                        };
+                        Statement::new(kind, span)
+                    });
                 }
                 
-                let optimized_then = then_branch.into_iter()
-                    .map(|s| self.optimize_statement(s))
-                    .collect();
+                let optimized_then = self.optimize_block(then_branch);
+                let optimized_else = else_branch.map(|branch| self.optimize_block(branch));
                 
-                let optimized_else = else_branch.map(|branch| {
-                    branch.into_iter()
-                        .map(|s| self.optimize_statement(s))
-                        .collect()
-                });
-                
-                Statement::If {
+                StatementKind::If {
                     condition: optimized_condition,
                     then_branch: optimized_then,
                     else_branch: optimized_else,
                 }
             }
-            Statement::While { condition, body } => {
+            StatementKind::While { condition, body } => {
                 let optimized_condition = self.optimize_expression(condition);
-                let optimized_body = body.into_iter()
-                    .map(|s| self.optimize_statement(s))
-                    .collect();
+                let optimized_body = self.optimize_block(body);
                 
-                Statement::While {
+                StatementKind::While {
                     condition: optimized_condition,
                     body: optimized_body,
                 }
             }
-            Statement::Return(expr) => {
-                Statement::Return(self.optimize_expression(expr))
+            StatementKind::Return(expr) => {
+                StatementKind::Return(self.optimize_expression(expr))
             }
-            _ => stmt,
-        }
+            other => other,
+        };
+        Some(Statement::new(kind, span))
     }
 
     fn optimize_expression(&mut self, expr: Expression) -> Expression {
-        match expr {
+        let span = expr.span;
+        match expr.kind {
             // Constant folding for binary operations
-            Expression::Binary { left, operator, right } => {
+            ExpressionKind::Binary { left, operator, right } => {
                 let optimized_left = self.optimize_expression(*left);
                 let optimized_right = self.optimize_expression(*right);
                 
-                // Try to fold constants
-                if let (Expression::Literal(Literal::Integer(l)), 
-                        Expression::Literal(Literal::Integer(r))) = (&optimized_left, &optimized_right) {
+                // Try to fold constants. Only plain i32 literals are folded, and
+                // only when the result is still one (literals are unsigned, so
+                // negative results are left to the C compiler)
+                if let (ExpressionKind::Literal(Literal::Integer(l, None)), 
+                        ExpressionKind::Literal(Literal::Integer(r, None))) = (&optimized_left.kind, &optimized_right.kind) {
                     
                     use crate::parser::BinaryOp;
+                    let i32_max = i32::MAX as u64;
                     let result = match operator {
-                        BinaryOp::Add => l + r,
-                        BinaryOp::Subtract => l - r,
-                        BinaryOp::Multiply => l * r,
-                        BinaryOp::Divide => if *r != 0 { l / r } else { return Expression::Binary {
-                            left: Box::new(optimized_left),
-                            operator,
-                            right: Box::new(optimized_right),
-                        }},
-                        _ => return Expression::Binary {
-                            left: Box::new(optimized_left),
-                            operator,
-                            right: Box::new(optimized_right),
-                        },
+                        BinaryOp::Add => l.checked_add(*r),
+                        BinaryOp::Subtract => l.checked_sub(*r),
+                        BinaryOp::Multiply => l.checked_mul(*r),
+                        BinaryOp::Divide => l.checked_div(*r),
+                        _ => None,
                     };
-                    
-                    self.optimizations_applied += 1;
-                    return Expression::Literal(Literal::Integer(result));
+                    if let Some(result) = result.filter(|n| *l <= i32_max && *r <= i32_max && *n <= i32_max) {
+                        self.optimizations_applied += 1;
+                        return Expression::new(ExpressionKind::Literal(Literal::Integer(result, None)), span);
+                    }
                 }
                 
-                Expression::Binary {
+                Expression::new(ExpressionKind::Binary {
                     left: Box::new(optimized_left),
                     operator,
                     right: Box::new(optimized_right),
-                }
+                }, span)
             }
             
-            // Constant folding for unary operations
-            Expression::Unary { operator, operand } => {
+            // Unary operations: a negated literal has no unsigned literal
+            // form, so only the operand is optimized
+            ExpressionKind::Unary { operator, operand } => {
                 let optimized_operand = self.optimize_expression(*operand);
-                
-                if let Expression::Literal(Literal::Integer(n)) = optimized_operand {
-                    use crate::parser::UnaryOp;
-                    match operator {
-                        UnaryOp::Negate => {
-                            self.optimizations_applied += 1;
-                            // Negate by converting to i32 first
-                            let signed = n as i32;
-                            let negated = (-signed) as u32;
-                            return Expression::Literal(Literal::Integer(negated));
-                        }
-                        _ => {}
-                    }
-                }
-                
-                Expression::Unary {
+                Expression::new(ExpressionKind::Unary {
                     operator,
                     operand: Box::new(optimized_operand),
-                }
+                }, span)
             }
             
             // Dead code elimination for function calls
-            Expression::FunctionCall { name, arguments } => {
+            ExpressionKind::FunctionCall { name, arguments } => {
                 let optimized_args = arguments.into_iter()
                     .map(|arg| self.optimize_expression(arg))
                     .collect();
                 
-                Expression::FunctionCall {
+                Expression::new(ExpressionKind::FunctionCall {
                     name,
                     arguments: optimized_args,
-                }
+                }, span)
+            }
+
+            ExpressionKind::EnumVariant { enum_name, variant, payload } => {
+                Expression::new(ExpressionKind::EnumVariant {
+                    enum_name,
+                    variant,
+                    payload: payload.map(|value| self.optimize_expression(value)),
+                }, span)
             }
             
-            _ => expr,
+            other => Expression::new(other, span),
         }
     }
 
@@ -188,23 +179,35 @@ impl Optimizer {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::parser::{BinaryOp, Literal};
+    use crate::diagnostics::Span;
+    use crate::parser::{BinaryOp, Literal, Type};
+
+    fn int(n: u64) -> Expression {
+        Expression::new(ExpressionKind::Literal(Literal::Integer(n, None)), Span::default())
+    }
+
+    fn binary(left: Expression, operator: BinaryOp, right: Expression) -> Expression {
+        Expression::new(
+            ExpressionKind::Binary {
+                left: Box::new(left),
+                operator,
+                right: Box::new(right),
+            },
+            Span::default(),
+        )
+    }
 
     #[test]
     fn test_constant_folding() {
         let mut optimizer = Optimizer::new();
         
         // 2 + 3 should fold to 5
-        let expr = Expression::Binary {
-            left: Box::new(Expression::Literal(Literal::Integer(2))),
-            operator: BinaryOp::Add,
-            right: Box::new(Expression::Literal(Literal::Integer(3))),
-        };
+        let expr = binary(int(2), BinaryOp::Add, int(3));
         
         let optimized = optimizer.optimize_expression(expr);
         
-        match optimized {
-            Expression::Literal(Literal::Integer(5)) => {},
+        match optimized.kind {
+            ExpressionKind::Literal(Literal::Integer(5, None)) => {},
             _ => panic!("Expected constant folding to produce 5"),
         }
         
@@ -216,23 +219,67 @@ mod tests {
         let mut optimizer = Optimizer::new();
         
         // (2 + 3) * 4 should fold to 5 * 4 = 20
-        let expr = Expression::Binary {
-            left: Box::new(Expression::Binary {
-                left: Box::new(Expression::Literal(Literal::Integer(2))),
-                operator: BinaryOp::Add,
-                right: Box::new(Expression::Literal(Literal::Integer(3))),
-            }),
-            operator: BinaryOp::Multiply,
-            right: Box::new(Expression::Literal(Literal::Integer(4))),
-        };
+        let expr = binary(binary(int(2), BinaryOp::Add, int(3)), BinaryOp::Multiply, int(4));
         
         let optimized = optimizer.optimize_expression(expr);
         
-        match optimized {
-            Expression::Literal(Literal::Integer(20)) => {},
+        match optimized.kind {
+            ExpressionKind::Literal(Literal::Integer(20, None)) => {},
             _ => panic!("Expected nested constant folding to produce 20"),
         }
         
         assert_eq!(optimizer.get_optimizations_count(), 2);
     }
+
+    #[test]
+    fn test_folding_keeps_literal_types() {
+        let mut optimizer = Optimizer::new();
+
+        // 2 - 3 has no unsigned literal form and 2^31 overflows i32
+        let negative = binary(int(2), BinaryOp::Subtract, int(3));
+        let overflow = binary(int(1 << 16), BinaryOp::Multiply, int(1 << 15));
+        let suffixed = binary(
+            Expression::new(ExpressionKind::Literal(Literal::Integer(2, Some(Type::U8))), Span::default()),
+            BinaryOp::Add,
+            int(3),
+        );
+        for expr in [negative, overflow, suffixed] {
+            let optimized = optimizer.optimize_expression(expr);
+            assert!(matches!(optimized.kind, ExpressionKind::Binary { .. }));
+        }
+        assert_eq!(optimizer.get_optimizations_count(), 0);
+    }
+
+    #[test]
+    fn test_constant_conditions_keep_the_taken_branch() {
+        let mut optimizer = Optimizer::new();
+        let boolean = |b: bool| Expression::new(ExpressionKind::Literal(Literal::Boolean(b)), Span::default());
+        let branch = |n: u64| vec![Statement::new(StatementKind::Expr(int(n)), Span::default())];
+        let if_statement = |b: bool, else_branch: Option<Vec<Statement>>| {
+            let kind = StatementKind::If { condition: boolean(b), then_branch: branch(1), else_branch };
+            Statement::new(kind, Span::default())
+        };
+
+        // `if true { 1 } else { 2 }` keeps `1` and `if false { 1 } else { 2 }`
+        // keeps `2`, each still in a block of its own
+        let body = optimizer.optimize_block(vec![if_statement(true, Some(branch(2))), if_statement(false, Some(branch(2)))]);
+        let kept: Vec<_> = body
+            .iter()
+            .map(|stmt| match &stmt.kind {
+                StatementKind::If { condition: Expression { kind: ExpressionKind::Literal(Literal::Boolean(true)), .. }, then_branch, else_branch: None } => match then_branch.as_slice() {
+                    [Statement { kind: StatementKind::Expr(expr), .. }] => match expr.kind {
+                        ExpressionKind::Literal(Literal::Integer(n, None)) => n,
+                        _ => panic!("Expected the branch's statement to be kept"),
+                    },
+                    _ => panic!("Expected the branch's statements to be kept"),
+                },
+                _ => panic!("Expected the taken branch in a block"),
+            })
+            .collect();
+        assert_eq!(kept, vec![1, 2]);
+
+        // `if false { 1 }` does nothing
+        assert!(optimizer.optimize_block(vec![if_statement(false, None)]).is_empty());
+        assert_eq!(optimizer.get_optimizations_count(), 3);
+    }
 }
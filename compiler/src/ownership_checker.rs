@@ -11,8 +11,13 @@
 // 6. No explicit lifetimes; compiler infers validity by scope
 // 7. Ownership transfer occurs on assignment or function call
 
-use crate::parser::{Declaration, Expression, Function, Statement, Type, Literal};
-use std::collections::HashMap;
+use crate::captures::{captures, Capture};
+use crate::parser::{
+    ArmBody, BinaryOp, Declaration, EnumDef, Expression, ExpressionKind, Function, Pattern, PatternKind, Statement, StatementKind,
+    Type, TypeDef, UnaryOp, Widget, WidgetValue,
+};
+use crate::traits::{self, TraitRegistry};
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 
 #[derive(Debug, Clone)]
@@ -103,26 +108,83 @@ pub type Result<T> = std::result::Result<T, OwnershipError>;
 enum OwnershipState {
     Owned,           // Value is owned by current scope
     Moved,           // Value has been moved out
+    MaybeMoved,      // Value has been moved out on some paths here
     Borrowed,        // Value is immutably borrowed
-    Invalid,         // Value is in invalid state
+    Invalid,         // Value borrows one that has been moved out
+}
+
+impl OwnershipState {
+    /// Whether the value may have been moved out, so cannot be used
+    fn is_moved(&self) -> bool {
+        matches!(self, OwnershipState::Moved | OwnershipState::MaybeMoved)
+    }
 }
 
 /// Information about a variable's ownership
 #[derive(Debug, Clone)]
 struct OwnershipInfo {
     name: String,
-    ty: Type,
+    /// `None` where the checker cannot tell, which moves like a type that
+    /// is not `Copy`
+    ty: Option<Type>,
     mutable: bool,
     state: OwnershipState,
     scope_level: usize,
     declared_at: String,
     moved_at: Option<String>,
+    /// Variables this value borrows: those a reference or slice points
+    /// into, and those a closure captures without moving them or through
+    /// references
+    borrows: Vec<String>,
+}
+
+/// State and move site of every variable in scope that is moved on some
+/// path to the point being checked, by scope index and name
+type Moves = HashMap<(usize, String), (OwnershipState, String)>;
+
+/// Paths that leave the loop being checked other than through its start
+#[derive(Default)]
+struct LoopExits {
+    /// Moves at each `break`, with the path through it
+    breaks: Vec<(Moves, String)>,
+    /// Moves at each `continue`, which goes on to the next iteration
+    continues: Vec<Moves>,
 }
 
 /// Ownership checker implementing the 7 formal rules
+///
+/// Moves are tracked along the control flow of each function: the paths
+/// through an `if` or `match` join after it, a variable moved on only some
+/// of them is maybe moved, and a loop body is checked again with the
+/// moves of its previous iteration until they settle.
 pub struct OwnershipChecker {
     scopes: Vec<HashMap<String, OwnershipInfo>>,
     current_scope: usize,
+    /// Captures borrowed by each closure whose body is being checked,
+    /// innermost last
+    borrowed_captures: Vec<HashSet<String>>,
+    /// Loops around the statement being checked, innermost last
+    loops: Vec<LoopExits>,
+    /// Whether the statement being checked can be reached from the start
+    /// of its function, rather than only after a `return`, `break` or
+    /// `continue`
+    reachable: bool,
+    /// Traits and their impls, and which types are `Copy`
+    traits: TraitRegistry,
+    /// Return type of every function, `None` where it depends on the
+    /// function's type parameters
+    functions: HashMap<String, Option<Type>>,
+    /// Every struct
+    structs: HashMap<String, TypeDef>,
+    /// Every enum
+    enums: HashMap<String, EnumDef>,
+    /// `(T, Trait)` for every bound on the type parameters of the function
+    /// being checked
+    bounds: Vec<(String, String)>,
+    /// Return type of the function or closure whose body is being checked
+    return_type: Option<Type>,
+    /// Name of every actor
+    actors: HashSet<String>,
 }
 
 impl OwnershipChecker {
@@ -130,11 +192,56 @@ impl OwnershipChecker {
         OwnershipChecker {
             scopes: vec![HashMap::new()],
             current_scope: 0,
+            borrowed_captures: Vec::new(),
+            loops: Vec::new(),
+            reachable: true,
+            traits: traits::create_builtin_traits(),
+            functions: HashMap::new(),
+            structs: HashMap::new(),
+            enums: HashMap::new(),
+            bounds: Vec::new(),
+            return_type: None,
+            actors: HashSet::new(),
         }
     }
 
     /// Check ownership rules for a list of declarations
     pub fn check_program(&mut self, declarations: &[Declaration]) -> Result<()> {
+        // The type checker has reported traits and impls that do not fit
+        for decl in declarations {
+            match decl {
+                Declaration::Function(f) => {
+                    let return_type = f.return_type.clone().filter(|ty| !ty.mentions_any(&f.type_params));
+                    self.functions.insert(f.name.clone(), return_type);
+                }
+                Declaration::ExternBlock(block) => {
+                    for f in &block.functions {
+                        self.functions.insert(f.name.clone(), f.return_type.clone());
+                    }
+                }
+                Declaration::Trait(t) => {
+                    let _ = self.traits.register_trait(traits::Trait::from(t));
+                }
+                Declaration::TypeDef(t) => {
+                    self.traits.declare_type(decl);
+                    self.structs.insert(t.name.clone(), t.clone());
+                }
+                Declaration::Enum(e) => {
+                    self.traits.declare_type(decl);
+                    self.enums.insert(e.name.clone(), e.clone());
+                }
+                Declaration::Actor(a) => {
+                    self.actors.insert(a.name.clone());
+                }
+                _ => {}
+            }
+        }
+        for decl in declarations {
+            if let Declaration::TraitImpl(i) = decl {
+                let _ = self.traits.register_impl(traits::TraitImpl::from(i));
+            }
+        }
+
         for decl in declarations {
             self.check_declaration(decl)?;
         }
@@ -143,19 +250,52 @@ impl OwnershipChecker {
 
     fn check_declaration(&mut self, decl: &Declaration) -> Result<()> {
         match decl {
-            Declaration::Function(func) => self.check_function(func),
-            _ => Ok(()), // Actors, traits, etc. handled separately
+            Declaration::Function(func) => self.check_function(func, None),
+            Declaration::Actor(actor) => {
+                for method in &actor.methods {
+                    self.check_function(method, None)?;
+                }
+                Ok(())
+            }
+            Declaration::TraitImpl(i) => {
+                for method in &i.methods {
+                    self.check_function(method, Some(&i.type_name))?;
+                }
+                Ok(())
+            }
+            // Named handlers are functions, checked as such; inline ones
+            // are checked here
+            Declaration::UiBlock(ui) => self.check_widget(&ui.root),
+            _ => Ok(()), // Traits declare no bodies; types and externs have none
         }
     }
 
-    fn check_function(&mut self, func: &Function) -> Result<()> {
+    /// Check a function, or a method of `receiver`, whose body sees the
+    /// value it is called on as `self`
+    fn check_function(&mut self, func: &Function, receiver: Option<&str>) -> Result<()> {
         self.enter_scope();
+        self.reachable = true;
+        self.bounds = func.bounds.clone();
+        self.return_type = func.return_type.clone();
+        // `self` is a pointer to the value the method is called on: what
+        // it points to can be changed, but not moved out
+        if let Some(receiver) = receiver {
+            self.declare_variable(
+                "self".to_string(),
+                Some(Type::Custom(receiver.to_string())),
+                true,
+                "method receiver".to_string(),
+            );
+            if let Some(info) = self.scopes[self.current_scope].get_mut("self") {
+                info.state = OwnershipState::Borrowed;
+            }
+        }
         
         // Add parameters to scope
         for (param_name, param_type) in &func.params {
             self.declare_variable(
                 param_name.clone(),
-                param_type.clone(),
+                Some(param_type.clone()),
                 false, // Parameters are immutable by default
                 "function parameter".to_string(),
             );
@@ -170,63 +310,110 @@ impl OwnershipChecker {
         Ok(())
     }
 
+    /// Check the inline handlers of `widget` and the widgets inside it,
+    /// each as the body of a function of its own
+    fn check_widget(&mut self, widget: &Widget) -> Result<()> {
+        for (_, value) in &widget.properties {
+            match value {
+                WidgetValue::Closure(handler) => {
+                    self.enter_scope();
+                    self.reachable = true;
+                    self.bounds = Vec::new();
+                    self.return_type = None;
+                    self.check_expression(handler)?;
+                    self.exit_scope();
+                }
+                WidgetValue::Widget(child) => self.check_widget(child)?,
+                WidgetValue::WidgetList(children) => {
+                    for child in children {
+                        self.check_widget(child)?;
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
     fn check_statement(&mut self, stmt: &Statement) -> Result<()> {
-        match stmt {
-            Statement::Let { name, mutable, value } => {
-                self.check_variable_declaration(name, *mutable, value)
+        match &stmt.kind {
+            StatementKind::Let { name, mutable, ty, value } => {
+                self.check_variable_declaration(name, *mutable, ty.as_ref(), value.as_ref())
             }
-            Statement::Expr(expr) => {
+            StatementKind::Expr(expr) => {
                 self.check_expression(expr)?;
                 Ok(())
             }
-            Statement::If { condition, then_branch, else_branch } => {
+            StatementKind::If { condition, then_branch, else_branch } => {
                 self.check_expression(condition)?;
-                
-                self.enter_scope();
-                for stmt in then_branch {
-                    self.check_statement(stmt)?;
-                }
-                self.exit_scope();
-                
-                if let Some(else_stmts) = else_branch {
-                    self.enter_scope();
-                    for stmt in else_stmts {
-                        self.check_statement(stmt)?;
+                let before = self.moves();
+                let mut paths = Vec::new();
+                for (branch, label) in [(then_branch.as_slice(), "then"), (else_branch.as_deref().unwrap_or_default(), "else")] {
+                    self.restore(&before);
+                    self.reachable = true;
+                    self.check_block(branch)?;
+                    if self.reachable {
+                        paths.push((self.moves(), format!("when the if at {} takes its {} branch", stmt.span, label)));
                     }
-                    self.exit_scope();
                 }
+                self.join(&before, &paths);
                 Ok(())
             }
-            Statement::While { condition, body } => {
-                self.check_expression(condition)?;
-                self.enter_scope();
-                for stmt in body {
-                    self.check_statement(stmt)?;
+            StatementKind::While { condition, body } => {
+                self.check_loop(&format!("while loop at {}", stmt.span), Some(condition), None, body)
+            }
+            StatementKind::For { variable, iterable, body } => {
+                self.check_expression(iterable)?;
+                let item = match self.infer_type(iterable) {
+                    Some(Type::Range(item) | Type::Array(item, _) | Type::Slice(item)) => Some(*item),
+                    // The loop consumes an iterator, which `next` advances
+                    Some(Type::Custom(iterator)) => {
+                        if let ExpressionKind::Identifier(name) = &iterable.kind {
+                            self.mark_as_moved(name, &format!("for loop at {}", iterable.span))?;
+                        }
+                        match self.method_return(&Type::Custom(iterator), "next") {
+                            Some(Type::Option(item)) => Some(*item),
+                            _ => None,
+                        }
+                    }
+                    _ => None,
+                };
+                self.check_loop(&format!("for loop at {}", stmt.span), None, Some((variable, item)), body)
+            }
+            StatementKind::Break => {
+                let moves = self.moves();
+                if let Some(exits) = self.loops.last_mut() {
+                    exits.breaks.push((moves, format!("when the loop ends at the break at {}", stmt.span)));
                 }
-                self.exit_scope();
+                self.reachable = false;
                 Ok(())
             }
-            Statement::For { variable, iterable, body } => {
-                self.check_expression(iterable)?;
-                self.enter_scope();
-                // Declare loop variable
-                self.declare_variable(
-                    variable.clone(),
-                    Type::I32, // Simplified
-                    false,
-                    "for loop".to_string(),
-                );
-                for stmt in body {
-                    self.check_statement(stmt)?;
+            StatementKind::Continue => {
+                let moves = self.moves();
+                if let Some(exits) = self.loops.last_mut() {
+                    exits.continues.push(moves);
                 }
-                self.exit_scope();
+                self.reachable = false;
                 Ok(())
             }
-            Statement::Return(expr) => {
+            StatementKind::Return(expr) => {
                 self.check_expression(expr)?;
+                // The function ends here, so only a field or element being
+                // moved out matters
+                if !matches!(expr.kind, ExpressionKind::Identifier(_)) {
+                    self.move_out(expr, &format!("return at {}", expr.span))?;
+                }
+                // Rule 4: borrows cannot outlive the scope that made them
+                if let Some(reference) = self.borrows_as(expr, self.return_type.as_ref()).into_iter().next() {
+                    return Err(OwnershipError::ReferenceOutOfScope {
+                        reference,
+                        scope: format!("(borrowed by the value returned at {})", expr.span),
+                    });
+                }
+                self.reachable = false;
                 Ok(())
             }
-            Statement::Unsafe { body } => {
+            StatementKind::Unsafe { body } => {
                 // Unsafe blocks bypass ownership checks
                 for stmt in body {
                     self.check_statement(stmt)?;
@@ -237,112 +424,561 @@ impl OwnershipChecker {
         }
     }
 
+    fn check_block(&mut self, body: &[Statement]) -> Result<()> {
+        self.enter_scope();
+        for stmt in body {
+            self.check_statement(stmt)?;
+        }
+        self.exit_scope();
+        Ok(())
+    }
+
+    /// Check a loop, called `name` in move sites, whose body starts each
+    /// iteration with the moves of the previous one as well as those from
+    /// before the loop. The body is checked again while that adds moves,
+    /// so a use of a value an earlier iteration moved is caught.
+    fn check_loop(
+        &mut self,
+        name: &str,
+        condition: Option<&Expression>,
+        variable: Option<(&String, Option<Type>)>,
+        body: &[Statement],
+    ) -> Result<()> {
+        let again = format!("on an earlier iteration of the {}", name);
+        let mut start = self.moves();
+        loop {
+            self.restore(&start);
+            self.reachable = true;
+            if let Some(condition) = condition {
+                self.check_expression(condition)?;
+            }
+            let done = self.moves();
+
+            self.loops.push(LoopExits::default());
+            self.enter_scope();
+            if let Some((variable, item)) = &variable {
+                self.declare_variable(variable.to_string(), item.clone(), false, "for loop".to_string());
+            }
+            let result = body.iter().try_for_each(|stmt| self.check_statement(stmt));
+            self.exit_scope();
+            let exits = self.loops.pop().unwrap_or_default();
+            result?;
+
+            let mut next = vec![(start.clone(), String::new())];
+            if self.reachable {
+                next.push((self.moves(), again.clone()));
+            }
+            next.extend(exits.continues.into_iter().map(|moves| (moves, again.clone())));
+            let next = self.merge(&next);
+            if same_states(&next, &start) {
+                let mut after = vec![(done, String::new())];
+                after.extend(exits.breaks);
+                let after = self.merge(&after);
+                self.restore(&after);
+                self.reachable = true;
+                return Ok(());
+            }
+            start = next;
+        }
+    }
+
+    /// Continue after a branch with the paths out of it that reach its
+    /// end; with none, what follows is unreachable
+    fn join(&mut self, before: &Moves, paths: &[(Moves, String)]) {
+        if paths.is_empty() {
+            self.restore(before);
+            self.reachable = false;
+        } else {
+            let merged = self.merge(paths);
+            self.restore(&merged);
+            self.reachable = true;
+        }
+    }
+
+    /// Join the moves of the paths that reach a point, each with a
+    /// description of the path. A variable moved on every path is moved;
+    /// one moved on only some is maybe moved, and its move site says on
+    /// which. A borrower of a value moved on some path is invalid.
+    fn merge(&self, paths: &[(Moves, String)]) -> Moves {
+        let mut keys: Vec<&(usize, String)> = paths
+            .iter()
+            .flat_map(|(moves, _)| moves.keys())
+            .filter(|(scope, _)| *scope < self.scopes.len())
+            .collect();
+        keys.sort();
+        keys.dedup();
+        let mut merged = Moves::new();
+        for key in keys {
+            let states: Vec<Option<&(OwnershipState, String)>> = paths.iter().map(|(moves, _)| moves.get(key)).collect();
+            if let Some(&Some(first)) = states.first().filter(|first| states.iter().all(|s| s == *first)) {
+                merged.insert(key.clone(), first.clone());
+                continue;
+            }
+            let everywhere = states.iter().all(Option::is_some);
+            let state = if everywhere && states.iter().all(|s| matches!(s, Some((OwnershipState::Moved, _)))) {
+                OwnershipState::Moved
+            } else if states.iter().flatten().all(|(state, _)| *state == OwnershipState::Invalid) {
+                // A reference that may borrow a moved value cannot be used
+                OwnershipState::Invalid
+            } else {
+                OwnershipState::MaybeMoved
+            };
+            let mut sites: Vec<String> = Vec::new();
+            for ((_, path), moved) in paths.iter().zip(&states) {
+                let Some((_, at)) = moved else {
+                    continue;
+                };
+                let site = if everywhere || path.is_empty() { at.clone() } else { format!("{} {}", at, path) };
+                if !sites.contains(&site) {
+                    sites.push(site);
+                }
+            }
+            merged.insert(key.clone(), (state, sites.join(" or ")));
+        }
+        merged
+    }
+
+    /// Moves that reach the statement being checked
+    fn moves(&self) -> Moves {
+        let mut moves = Moves::new();
+        for (index, scope) in self.scopes.iter().enumerate() {
+            for (name, info) in scope {
+                if info.state.is_moved() || info.state == OwnershipState::Invalid {
+                    let at = info.moved_at.clone().unwrap_or_else(|| "unknown".to_string());
+                    moves.insert((index, name.clone()), (info.state.clone(), at));
+                }
+            }
+        }
+        moves
+    }
+
+    /// Set the variables in scope to the state `moves` gives them; any
+    /// other is owned
+    fn restore(&mut self, moves: &Moves) {
+        for (index, scope) in self.scopes.iter_mut().enumerate() {
+            for (name, info) in scope.iter_mut() {
+                if let Some((state, at)) = moves.get(&(index, name.clone())) {
+                    info.state = state.clone();
+                    info.moved_at = Some(at.clone());
+                } else if info.state.is_moved() || info.state == OwnershipState::Invalid {
+                    info.state = OwnershipState::Owned;
+                    info.moved_at = None;
+                }
+            }
+        }
+    }
+
     fn check_variable_declaration(
         &mut self,
         name: &str,
         mutable: bool,
-        value: &Expression,
+        ty: Option<&Type>,
+        value: Option<&Expression>,
     ) -> Result<()> {
+        let Some(value) = value else {
+            // The type checker allows the one assignment that initialises
+            // a `let` declared without a value
+            self.declare_variable(name.to_string(), ty.cloned(), true, "variable declaration".to_string());
+            return Ok(());
+        };
+
         // Rule 1: Check if value is being moved
         self.check_expression(value)?;
+        let borrows = self.borrows_as(value, ty);
+        // Rule 7: the new variable takes ownership of the value
+        self.move_out(value, &format!("variable declaration at {}", value.span))?;
         
         // Declare the new variable
-        let ty = self.infer_type(value);
+        let ty = ty.cloned().or_else(|| self.infer_type(value));
         self.declare_variable(
             name.to_string(),
             ty,
             mutable,
             "variable declaration".to_string(),
         );
+        if let Some(info) = self.scopes[self.current_scope].get_mut(name) {
+            info.borrows = borrows;
+        }
         
         Ok(())
     }
 
     fn check_expression(&mut self, expr: &Expression) -> Result<()> {
-        match expr {
-            Expression::Identifier(name) => {
+        match &expr.kind {
+            // `None` is a constructor unless a variable shadows it
+            ExpressionKind::Identifier(name) if name == "None" && self.get_variable_info(name).is_err() => Ok(()),
+            ExpressionKind::Identifier(name) => {
                 // Rule 1 & 7: Check if variable has been moved
                 let info = self.get_variable_info(name)?;
-                if info.state == OwnershipState::Moved {
-                    return Err(OwnershipError::UseAfterMove {
-                        variable: name.clone(),
-                        moved_at: info.moved_at.unwrap_or_else(|| "unknown".to_string()),
-                        used_at: "here".to_string(),
-                    });
-                }
-                Ok(())
+                self.check_usable(&info, &expr.span.to_string())
             }
-            Expression::FunctionCall { name: _, arguments } => {
-                // Rule 7: Function calls transfer ownership of arguments
+            ExpressionKind::FunctionCall { name, arguments } => {
+                // Calling a closure uses the variable holding it
+                if let Ok(info) = self.get_variable_info(name) {
+                    self.check_usable(&info, &format!("call at {}", expr.span))?;
+                }
+
+                // Rule 7: Function calls transfer ownership of arguments,
+                // except to the built-in `print`, which only reads its one
+                let print = name == "print" && self.get_variable_info(name).is_err();
+                let send = (name == "send" || name == "spawn") && !self.functions.contains_key(name);
                 for arg in arguments {
                     self.check_expression(arg)?;
+                    if send {
+                        self.check_message(arg, "an actor")?;
+                    }
                     
-                    // If argument is a variable, mark it as moved
-                    if let Expression::Identifier(var_name) = arg {
-                        self.mark_as_moved(var_name, "function call")?;
+                    if !print {
+                        self.move_out(arg, &format!("function call at {}", arg.span))?;
                     }
                 }
                 Ok(())
             }
-            Expression::MethodCall { receiver, method, arguments } => {
-                // Rule 3: Check for .clone() calls
+            ExpressionKind::MethodCall { receiver, method, arguments } => {
+                // Calling a method on the actor itself, such as `spawn`,
+                // sends it the arguments
+                if let Some(actor) = self.actor_named(receiver) {
+                    for arg in arguments {
+                        self.check_expression(arg)?;
+                        self.check_message(arg, &actor)?;
+                    }
+                    return Ok(());
+                }
+
+                // Check receiver, which the method is given a pointer to
+                self.check_expression(receiver)?;
+                let receiver_ty = self.infer_type(receiver).map(|ty| match ty {
+                    Type::Ref(inner) => *inner,
+                    ty => ty,
+                });
+
+                // Rule 3: Check for .clone() calls, which copy the value a
+                // reference points to
                 if method == "clone" {
-                    let info = self.get_variable_info(receiver)?;
-                    if !self.is_cloneable(&info.ty) {
+                    if let Some(ty) = receiver_ty.as_ref().filter(|ty| !self.is_cloneable(ty)) {
                         return Err(OwnershipError::InvalidClone {
-                            variable: receiver.clone(),
-                            reason: "type does not implement Clone".to_string(),
+                            variable: receiver.root_variable().unwrap_or("value").to_string(),
+                            reason: format!("type {} is not Copy and does not implement Clone", ty),
                         });
                     }
                 }
+                let actor = match &receiver_ty {
+                    Some(Type::Custom(name)) if self.actors.contains(name) => Some(name.clone()),
+                    _ => None,
+                };
                 
-                // Check receiver
-                let info = self.get_variable_info(receiver)?;
-                if info.state == OwnershipState::Moved {
-                    return Err(OwnershipError::UseAfterMove {
-                        variable: receiver.clone(),
-                        moved_at: info.moved_at.unwrap_or_else(|| "unknown".to_string()),
-                        used_at: "method call".to_string(),
-                    });
-                }
-                
+                // Rule 7: so do method calls, and messages to an actor
                 for arg in arguments {
                     self.check_expression(arg)?;
+                    if let Some(actor) = &actor {
+                        self.check_message(arg, actor)?;
+                    }
+                    self.move_out(arg, &format!("method call at {}", arg.span))?;
                 }
                 Ok(())
             }
-            Expression::Binary { left, right, .. } => {
+            ExpressionKind::Binary { left, right, .. } => {
                 self.check_expression(left)?;
                 self.check_expression(right)?;
                 Ok(())
             }
-            Expression::Unary { operand, .. } => {
+            ExpressionKind::Unary { operand, .. } => {
                 self.check_expression(operand)?;
                 Ok(())
             }
-            Expression::Assignment { target, value } => {
-                // Rule 2: Check mutability
-                let info = self.get_variable_info(target)?;
+            ExpressionKind::Assignment { target, value } => {
+                // Rule 2: Check mutability of the variable the target is rooted in
+                let root = target.root_variable().unwrap_or_default();
+                let info = self.get_variable_info(root)?;
                 if !info.mutable {
                     return Err(OwnershipError::MutabilityConflict {
-                        variable: target.to_string(),
+                        variable: root.to_string(),
                         declared_as: "immutable".to_string(),
                         used_as: "mutable (assignment)".to_string(),
                     });
                 }
+                // Rule 5: a closure cannot change what it only borrows
+                if self.borrowed_captures.last().is_some_and(|borrowed| borrowed.contains(root)) {
+                    return Err(OwnershipError::MutableBorrow {
+                        variable: root.to_string(),
+                        location: format!("{} in a closure that borrows it", expr.span),
+                    });
+                }
+                // nor can anything change what a reference points to
+                if matches!(info.ty, Some(Type::Ref(_))) && !matches!(target.kind, ExpressionKind::Identifier(_)) {
+                    return Err(OwnershipError::MutabilityConflict {
+                        variable: root.to_string(),
+                        declared_as: "an immutable reference".to_string(),
+                        used_as: format!("mutable (assignment through it at {})", expr.span),
+                    });
+                }
+                match &target.kind {
+                    ExpressionKind::FieldAccess { object, .. } => self.check_expression(object)?,
+                    ExpressionKind::Index { object, index } => {
+                        self.check_expression(object)?;
+                        self.check_expression(index)?;
+                    }
+                    _ => {}
+                }
                 
                 // Rule 7: Check ownership transfer
                 self.check_expression(value)?;
-                
+                self.move_out(value, &format!("assignment at {}", value.span))?;
+
+                // A variable assigned as a whole owns its new value, even
+                // if its old one was moved
+                if let ExpressionKind::Identifier(name) = &target.kind {
+                    // Rule 4: which cannot borrow anything that goes out of
+                    // scope before the variable does
+                    let borrows = self.borrows_as(value, info.ty.as_ref());
+                    for borrowed in &borrows {
+                        // (a temporary lives in the scope being checked)
+                        let level = self.get_variable_info(borrowed).map_or(self.current_scope, |b| b.scope_level);
+                        if level > info.scope_level {
+                            return Err(OwnershipError::ReferenceOutOfScope {
+                                reference: borrowed.clone(),
+                                scope: format!("(borrowed by '{}', which outlives it, at {})", name, expr.span),
+                            });
+                        }
+                    }
+                    self.revive(name);
+                    self.add_borrows(name, borrows);
+                }
+                Ok(())
+            }
+            ExpressionKind::EnumVariant { payload, .. } => {
+                // Rule 7: The new value takes ownership of its payload
+                for (_, value) in payload.fields() {
+                    self.check_expression(value)?;
+                    self.move_out(value, &format!("enum constructor at {}", value.span))?;
+                }
+                Ok(())
+            }
+            ExpressionKind::StructLiteral { fields, .. } => {
+                // Rule 7: The new value takes ownership of its fields
+                for (_, value) in fields {
+                    self.check_expression(value)?;
+                    self.move_out(value, &format!("struct literal at {}", value.span))?;
+                }
+                Ok(())
+            }
+            ExpressionKind::ArrayLiteral(elements) => {
+                // Rule 7: The array takes ownership of its elements
+                for element in elements {
+                    self.check_expression(element)?;
+                    self.move_out(element, &format!("array literal at {}", element.span))?;
+                }
+                Ok(())
+            }
+            ExpressionKind::FieldAccess { object, .. } => self.check_expression(object),
+            ExpressionKind::Index { object, index } => {
+                self.check_expression(object)?;
+                self.check_expression(index)
+            }
+            ExpressionKind::Range { start, end, step, .. } => {
+                self.check_expression(start)?;
+                self.check_expression(end)?;
+                if let Some(step) = step {
+                    self.check_expression(step)?;
+                }
+                Ok(())
+            }
+            ExpressionKind::Closure { params, return_type, body, by_move } => {
+                self.check_closure(params, return_type.as_ref(), body, *by_move)
+            }
+            ExpressionKind::Match { scrutinee, arms } => {
+                self.check_expression(scrutinee)?;
+                let before = self.moves();
+                let mut paths = Vec::new();
+                for arm in arms {
+                    self.restore(&before);
+                    self.reachable = true;
+                    self.enter_scope();
+                    let scrutinee_ty = self.infer_type(scrutinee);
+                    let mut bindings = Vec::new();
+                    self.bindings(&arm.pattern, scrutinee_ty.as_ref(), &mut bindings);
+                    // Rule 7: an arm that binds a value that is not `Copy`
+                    // moves it out of the scrutinee, which no longer owns it
+                    if bindings.iter().any(|(_, ty)| !ty.as_ref().is_some_and(|ty| self.is_copy(ty))) {
+                        self.move_out(scrutinee, &format!("match arm at {}", arm.span))?;
+                    }
+                    for (name, ty) in bindings {
+                        self.declare_variable(name, ty, false, format!("pattern at {}", arm.pattern.span));
+                    }
+                    if let Some(guard) = &arm.guard {
+                        self.check_expression(guard)?;
+                    }
+                    match &arm.body {
+                        ArmBody::Expression(value) => self.check_expression(value)?,
+                        ArmBody::Block(body) => {
+                            for stmt in body {
+                                self.check_statement(stmt)?;
+                            }
+                        }
+                    }
+                    self.exit_scope();
+                    if self.reachable {
+                        paths.push((self.moves(), format!("when the match at {} takes the arm at {}", expr.span, arm.span)));
+                    }
+                }
+                self.join(&before, &paths);
                 Ok(())
             }
             _ => Ok(()),
         }
     }
 
+    /// A closure moves the captures it consumes, or all of them with
+    /// `move`, and borrows the rest for as long as it lives
+    fn check_closure(
+        &mut self,
+        params: &[(String, Type)],
+        return_type: Option<&Type>,
+        body: &[Statement],
+        by_move: bool,
+    ) -> Result<()> {
+        let captures = self.closure_captures(params, body);
+        for capture in &captures {
+            let info = self.get_variable_info(&capture.name)?;
+            self.check_usable(&info, &format!("closure capture at {}", capture.span))?;
+            // A closure may be called any number of times, so its body
+            // cannot move out what it holds
+            if capture.consumed && !info.ty.as_ref().is_some_and(|ty| self.is_copy(ty)) {
+                return Err(OwnershipError::MultipleOwners {
+                    variable: capture.name.clone(),
+                    location: format!(
+                        "closure capture at {}: a closure can be called more than once, so cannot move out a capture; use .clone()",
+                        capture.span
+                    ),
+                });
+            }
+        }
+        let borrowed = captures
+            .iter()
+            .filter(|c| !by_move && !c.consumed)
+            .map(|c| c.name.clone())
+            .collect();
+
+        self.enter_scope();
+        for (name, ty) in params {
+            self.declare_variable(name.clone(), Some(ty.clone()), false, "closure parameter".to_string());
+        }
+        self.borrowed_captures.push(borrowed);
+        // The body runs when the closure is called, apart from the flow here
+        let loops = std::mem::take(&mut self.loops);
+        let reachable = self.reachable;
+        let outer_return = std::mem::replace(&mut self.return_type, return_type.cloned());
+        let result = body.iter().try_for_each(|stmt| self.check_statement(stmt));
+        self.loops = loops;
+        self.reachable = reachable;
+        self.return_type = outer_return;
+        self.borrowed_captures.pop();
+        self.exit_scope();
+        result?;
+
+        for capture in captures.iter().filter(|c| by_move || c.consumed) {
+            self.mark_as_moved(&capture.name, &format!("closure capture at {}", capture.span))?;
+        }
+        Ok(())
+    }
+
+    /// The actor `expr` names, unless a variable shadows it
+    fn actor_named(&self, expr: &Expression) -> Option<String> {
+        match &expr.kind {
+            ExpressionKind::Identifier(name) if self.actors.contains(name) && self.get_variable_info(name).is_err() => {
+                Some(name.clone())
+            }
+            _ => None,
+        }
+    }
+
+    /// Check that `payload`, sent to `actor`, owns what it holds: the
+    /// actor runs apart from the sender, so the payload moves to it and
+    /// must not borrow the sender's variables
+    fn check_message(&self, payload: &Expression, actor: &str) -> Result<()> {
+        match self.borrows_of(payload).first() {
+            Some(borrowed) => Err(OwnershipError::ReferenceOutOfScope {
+                reference: borrowed.clone(),
+                scope: format!("(borrowed by a message sent to {} at {}, which may outlive it)", actor, payload.span),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Captures of a closure that name variables in scope
+    fn closure_captures(&self, params: &[(String, Type)], body: &[Statement]) -> Vec<Capture> {
+        captures(params, body)
+            .into_iter()
+            .filter(|c| self.get_variable_info(&c.name).is_ok())
+            .collect()
+    }
+
+    /// Variables the value of `expr` borrows, if it is a reference, slice
+    /// or closure. A reference into a value a reference points to borrows
+    /// what that one does; one returned by a call may point into what the
+    /// call is given.
+    fn borrows_of(&self, expr: &Expression) -> Vec<String> {
+        let mut borrows = match &expr.kind {
+            ExpressionKind::Closure { params, body, by_move, .. } => {
+                let captures = self.closure_captures(params, body);
+                let mut borrows: Vec<String> =
+                    captures.iter().filter(|c| !by_move && !c.consumed).map(|c| c.name.clone()).collect();
+                for capture in &captures {
+                    borrows.extend(self.get_variable_info(&capture.name).map(|info| info.borrows).unwrap_or_default());
+                }
+                borrows
+            }
+            ExpressionKind::Identifier(name) => {
+                self.get_variable_info(name).map(|info| info.borrows).unwrap_or_default()
+            }
+            ExpressionKind::Unary { operator: UnaryOp::Borrow, operand } => match operand.root_variable() {
+                Some(root) => match self.get_variable_info(root) {
+                    Ok(OwnershipInfo { ty: Some(Type::Ref(_)), borrows, .. }) => borrows,
+                    _ => vec![root.to_string()],
+                },
+                None => Vec::new(),
+            },
+            ExpressionKind::FunctionCall { arguments, .. } if matches!(self.infer_type(expr), Some(Type::Ref(_) | Type::Slice(_))) => {
+                // including the arrays it may have been given as slices
+                let returned = self.infer_type(expr);
+                arguments.iter().flat_map(|arg| self.borrows_as(arg, returned.as_ref())).collect()
+            }
+            ExpressionKind::MethodCall { receiver, arguments, .. } if matches!(self.infer_type(expr), Some(Type::Ref(_) | Type::Slice(_))) => {
+                // Methods take the value they are called on by pointer
+                let receiver = match self.infer_type(receiver) {
+                    Some(Type::Ref(_)) => (**receiver).clone(),
+                    _ => Expression::new(ExpressionKind::Unary { operator: UnaryOp::Borrow, operand: receiver.clone() }, expr.span),
+                };
+                let returned = self.infer_type(expr);
+                std::iter::once(&receiver).chain(arguments).flat_map(|arg| self.borrows_as(arg, returned.as_ref())).collect()
+            }
+            _ => Vec::new(),
+        };
+        borrows.sort();
+        borrows.dedup();
+        borrows
+    }
+
+    /// Variables `value` borrows once taken as a value of type `ty`: an
+    /// array taken as a slice borrows what holds it, like `&` does, or
+    /// the temporary array itself, which lives until its block ends
+    fn borrows_as(&self, value: &Expression, ty: Option<&Type>) -> Vec<String> {
+        if matches!(ty, Some(Type::Slice(_))) && matches!(self.infer_type(value), Some(Type::Array(..))) {
+            if value.root_variable().is_none() {
+                return vec![format!("the array at {}", value.span)];
+            }
+            let borrow = Expression::new(
+                ExpressionKind::Unary { operator: UnaryOp::Borrow, operand: Box::new(value.clone()) },
+                value.span,
+            );
+            return self.borrows_of(&borrow);
+        }
+        self.borrows_of(value)
+    }
+
     // Helper methods
 
-    fn declare_variable(&mut self, name: String, ty: Type, mutable: bool, location: String) {
+    fn declare_variable(&mut self, name: String, ty: Option<Type>, mutable: bool, location: String) {
         let info = OwnershipInfo {
             name: name.clone(),
             ty,
@@ -351,6 +987,7 @@ impl OwnershipChecker {
             scope_level: self.current_scope,
             declared_at: location,
             moved_at: None,
+            borrows: Vec::new(),
         };
         
         self.scopes[self.current_scope].insert(name, info);
@@ -370,35 +1007,275 @@ impl OwnershipChecker {
         })
     }
 
+    /// Move the value of `value` out to a new owner at `location`. A
+    /// variable is marked as moved; a field or element cannot be moved
+    /// out of the value holding it, which still owns and drops it, so it
+    /// must be cloned unless it is `Copy`. A raw `ptr` is only read out,
+    /// as what it points to is not owned by the checker's rules.
+    fn move_out(&mut self, value: &Expression, location: &str) -> Result<()> {
+        match &value.kind {
+            ExpressionKind::Identifier(name) => self.mark_as_moved(name, location),
+            ExpressionKind::FieldAccess { .. } | ExpressionKind::Index { .. } => match self.infer_type(value) {
+                Some(ty) if ty != Type::Ptr && !self.is_copy(&ty) => Err(OwnershipError::MultipleOwners {
+                    variable: value.root_variable().unwrap_or("value").to_string(),
+                    location: format!("{}: a field or element of type {} cannot be moved out; use .clone()", location, ty),
+                }),
+                _ => Ok(()),
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Mark `name` as moved out, unless its value is `Copy` and so is
+    /// copied instead. A borrowed value, such as `self`, cannot be; the
+    /// variables that borrow one that is become invalid.
     fn mark_as_moved(&mut self, name: &str, location: &str) -> Result<()> {
+        if let Ok(OwnershipInfo { ty: Some(ty), .. }) = self.get_variable_info(name) {
+            if self.is_copy(&ty) {
+                return Ok(());
+            }
+        }
         for scope in self.scopes.iter_mut().rev() {
             if let Some(info) = scope.get_mut(name) {
+                if info.state == OwnershipState::Borrowed {
+                    return Err(OwnershipError::MultipleOwners {
+                        variable: name.to_string(),
+                        location: format!("{}: a borrowed value cannot be moved out; use .clone()", location),
+                    });
+                }
                 info.state = OwnershipState::Moved;
                 info.moved_at = Some(location.to_string());
-                return Ok(());
+                break;
+            }
+        }
+        // Rule 4: what borrows the value can no longer be used
+        for scope in self.scopes.iter_mut() {
+            for info in scope.values_mut().filter(|info| info.name != name && info.borrows.iter().any(|b| b == name)) {
+                info.state = OwnershipState::Invalid;
+                info.moved_at = Some(format!("'{}', which was moved at {}", name, location));
             }
         }
         Ok(())
     }
 
+    /// Check that the variable `info` describes can be used at `used_at`:
+    /// it has not been moved out, nor has a value it borrows
+    fn check_usable(&self, info: &OwnershipInfo, used_at: &str) -> Result<()> {
+        let moved_at = || info.moved_at.clone().unwrap_or_else(|| "unknown".to_string());
+        match info.state {
+            OwnershipState::Moved | OwnershipState::MaybeMoved => Err(OwnershipError::UseAfterMove {
+                variable: info.name.clone(),
+                moved_at: moved_at(),
+                used_at: used_at.to_string(),
+            }),
+            OwnershipState::Invalid => Err(OwnershipError::ReferenceOutOfScope {
+                reference: info.name.clone(),
+                scope: format!("(it borrows {}, and is used at {})", moved_at(), used_at),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Record that `name` may also borrow `borrows`, after an assignment
+    /// on some path
+    fn add_borrows(&mut self, name: &str, borrows: Vec<String>) {
+        for scope in self.scopes.iter_mut().rev() {
+            if let Some(info) = scope.get_mut(name) {
+                for borrowed in borrows {
+                    if !info.borrows.contains(&borrowed) {
+                        info.borrows.push(borrowed);
+                    }
+                }
+                return;
+            }
+        }
+    }
+
+    /// Mark `name` as owning a value again after an assignment
+    fn revive(&mut self, name: &str) {
+        for scope in self.scopes.iter_mut().rev() {
+            if let Some(info) = scope.get_mut(name) {
+                info.state = OwnershipState::Owned;
+                info.moved_at = None;
+                return;
+            }
+        }
+    }
+
+    /// Collect the variables a `match` arm's pattern binds, with the type
+    /// of each where known, given the type of the value it matches
+    fn bindings(&self, pattern: &Pattern, ty: Option<&Type>, bindings: &mut Vec<(String, Option<Type>)>) {
+        match &pattern.kind {
+            PatternKind::Binding(name) => bindings.push((name.clone(), ty.cloned())),
+            PatternKind::Wildcard | PatternKind::Literal(_) => {}
+            PatternKind::Tuple(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    let item_ty = match ty {
+                        Some(Type::Tuple(types)) => types.get(i),
+                        _ => None,
+                    };
+                    self.bindings(item, item_ty, bindings);
+                }
+            }
+            PatternKind::Struct { name, fields, .. } => {
+                for (field, item) in fields {
+                    let field_ty = self.field_type(ty.unwrap_or(&Type::Custom(name.clone())), field);
+                    self.bindings(item, field_ty.as_ref(), bindings);
+                }
+            }
+            PatternKind::Variant { enum_name, variant, payload, .. } => {
+                let declared = match enum_name {
+                    Some(enum_name) => self
+                        .enums
+                        .get(enum_name)
+                        .and_then(|e| e.variants.iter().find(|v| v.name == *variant))
+                        .map(|v| v.payload.fields().into_iter().map(|(field, ty)| (field, ty.clone())).collect())
+                        .unwrap_or_default(),
+                    None => {
+                        let inner = match (variant.as_str(), ty) {
+                            ("Some", Some(Type::Option(inner))) | ("Ok", Some(Type::Result(inner, _))) => Some(&**inner),
+                            ("Err", Some(Type::Result(_, inner))) => Some(&**inner),
+                            _ => None,
+                        };
+                        inner.map(|ty| vec![("0".to_string(), ty.clone())]).unwrap_or_default()
+                    }
+                };
+                for (field, item) in payload.fields() {
+                    let field_ty = declared.iter().find(|(name, _)| *name == field).map(|(_, ty)| ty);
+                    self.bindings(item, field_ty, bindings);
+                }
+            }
+        }
+    }
+
+    /// Whether values of `ty` are copied rather than moved
+    fn is_copy(&self, ty: &Type) -> bool {
+        let copy_params: Vec<String> = self.bound_params("Copy").collect();
+        self.traits.is_copy(ty, &copy_params)
+    }
+
+    /// Whether `.clone()` can copy values of `ty`: `Copy` types and `str`
+    /// can, and any type with an impl of the built-in `Clone` trait or a
+    /// `Clone` bound
     fn is_cloneable(&self, ty: &Type) -> bool {
-        // Basic types are cloneable
-        matches!(ty, Type::I32 | Type::Str | Type::Bool)
+        match ty {
+            Type::Custom(name) if self.bound_params("Clone").any(|param| param == *name) => true,
+            _ => self.is_copy(ty) || self.traits.satisfies(ty, "Clone"),
+        }
+    }
+
+    /// Type parameters of the function being checked bounded by `trait_name`
+    fn bound_params<'a>(&'a self, trait_name: &'a str) -> impl Iterator<Item = String> + 'a {
+        self.bounds.iter().filter(move |(_, t)| t == trait_name).map(|(param, _)| param.clone())
+    }
+
+    /// Type of `field` on a value of type `ty`, a struct, with the struct's
+    /// type parameters replaced by the arguments in `ty`; `None` where
+    /// those are not known
+    fn field_type(&self, ty: &Type, field: &str) -> Option<Type> {
+        let (Type::Custom(name) | Type::Generic { name, .. }) = ty else {
+            return None;
+        };
+        let TypeDef { type_params, fields, .. } = self.structs.get(name)?;
+        let (_, field_ty) = fields.iter().find(|(name, _)| name == field)?;
+        let type_args = match ty {
+            Type::Generic { type_args, .. } => type_args.as_slice(),
+            _ => &[],
+        };
+        if type_args.len() != type_params.len() {
+            return Some(field_ty.clone()).filter(|ty| !ty.mentions_any(type_params));
+        }
+        let bindings = type_params.iter().map(String::as_str).zip(type_args).collect();
+        Some(field_ty.substitute(&bindings))
     }
 
-    fn infer_type(&self, expr: &Expression) -> Type {
-        match expr {
-            Expression::Literal(lit) => match lit {
-                Literal::Integer(_) => Type::I32,
-                Literal::String(_) => Type::Str,
-                Literal::Boolean(_) => Type::Bool,
+    /// Return type of the trait method `method` on a value of type `ty`
+    fn method_return(&self, ty: &Type, method: &str) -> Option<Type> {
+        let (Type::Custom(name) | Type::Generic { name, .. }) = ty else {
+            return None;
+        };
+        let (_, signature) = self.traits.resolve_method(name, method).ok()??;
+        let self_type = Type::Custom(name.clone());
+        Some(signature.return_type.as_ref()?.substitute(&HashMap::from([("Self", &self_type)])))
+    }
+
+    /// Type of the value of `expr`, where it can be told without the type
+    /// checker's inference
+    fn infer_type(&self, expr: &Expression) -> Option<Type> {
+        match &expr.kind {
+            ExpressionKind::Literal(lit) => Some(lit.ty()),
+            ExpressionKind::Identifier(name) => self.get_variable_info(name).ok()?.ty,
+            ExpressionKind::FunctionCall { name, arguments } => {
+                // A call of a closure
+                if let Ok(info) = self.get_variable_info(name) {
+                    return match info.ty? {
+                        Type::FunctionPointer { return_type, .. } => return_type.map(|ty| *ty),
+                        _ => None,
+                    };
+                }
+                match (self.functions.get(name), arguments.as_slice()) {
+                    (Some(return_type), _) => return_type.clone(),
+                    (None, [value]) if name == "Some" => Some(Type::Option(Box::new(self.infer_type(value)?))),
+                    (None, _) => None,
+                }
+            }
+            // Spawning an actor gives a handle to it
+            ExpressionKind::MethodCall { receiver, method, .. } if method == "spawn" && self.actor_named(receiver).is_some() => {
+                self.actor_named(receiver).map(Type::Custom)
+            }
+            ExpressionKind::MethodCall { receiver, method, .. } => {
+                let receiver_ty = match self.infer_type(receiver)? {
+                    Type::Ref(inner) => *inner,
+                    ty => ty,
+                };
+                match self.method_return(&receiver_ty, method) {
+                    Some(ty) => Some(ty),
+                    None if method == "clone" => Some(receiver_ty),
+                    None if method == "len" => Some(Type::I32),
+                    None => None,
+                }
+            }
+            ExpressionKind::Binary { left, operator, right } => match operator {
+                BinaryOp::Add | BinaryOp::Subtract | BinaryOp::Multiply | BinaryOp::Divide | BinaryOp::Modulo => {
+                    self.infer_type(left).or_else(|| self.infer_type(right))
+                }
+                _ => Some(Type::Bool),
+            },
+            ExpressionKind::Unary { operator: UnaryOp::Not, .. } => Some(Type::Bool),
+            ExpressionKind::Unary { operator: UnaryOp::Borrow, operand } => Some(Type::Ref(Box::new(self.infer_type(operand)?))),
+            ExpressionKind::Unary { operator: UnaryOp::Deref, operand } => match self.infer_type(operand)? {
+                Type::Ref(inner) => Some(*inner),
+                _ => None,
+            },
+            ExpressionKind::Unary { operand, .. } => self.infer_type(operand),
+            ExpressionKind::EnumVariant { enum_name, .. } => Some(Type::Custom(enum_name.clone())),
+            ExpressionKind::StructLiteral { name, type_args, .. } if type_args.is_empty() => Some(Type::Custom(name.clone())),
+            ExpressionKind::StructLiteral { name, type_args, .. } => {
+                Some(Type::Generic { name: name.clone(), type_args: type_args.clone() })
+            }
+            ExpressionKind::FieldAccess { object, field } => match self.infer_type(object)? {
+                Type::Ref(inner) => self.field_type(&inner, field),
+                ty => self.field_type(&ty, field),
             },
-            Expression::Identifier(name) => {
-                self.get_variable_info(name)
-                    .map(|info| info.ty)
-                    .unwrap_or(Type::I32)
+            ExpressionKind::ArrayLiteral(elements) => {
+                let element = self.infer_type(elements.first()?)?;
+                Some(Type::Array(Box::new(element), elements.len()))
             }
-            _ => Type::I32, // Default fallback
+            ExpressionKind::Index { object, .. } => match self.infer_type(object)? {
+                Type::Array(element, _) | Type::Slice(element) => Some(*element),
+                Type::Ref(inner) => match *inner {
+                    Type::Array(element, _) | Type::Slice(element) => Some(*element),
+                    _ => None,
+                },
+                _ => None,
+            },
+            ExpressionKind::Range { start, .. } => Some(Type::Range(Box::new(self.infer_type(start)?))),
+            ExpressionKind::Tuple(items) => items.iter().map(|item| self.infer_type(item)).collect::<Option<_>>().map(Type::Tuple),
+            ExpressionKind::Closure { params, return_type, .. } => Some(Type::FunctionPointer {
+                params: params.iter().map(|(_, ty)| ty.clone()).collect(),
+                return_type: return_type.clone().map(Box::new),
+            }),
+            ExpressionKind::Match { .. } | ExpressionKind::Assignment { .. } => None,
         }
     }
 
@@ -415,11 +1292,18 @@ impl OwnershipChecker {
     }
 }
 
+/// Whether the same variables are moved, or maybe moved, in `a` and `b`,
+/// wherever they were moved
+fn same_states(a: &Moves, b: &Moves) -> bool {
+    a.len() == b.len() && a.iter().all(|(key, (state, _))| b.get(key).is_some_and(|(other, _)| other == state))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::lexer::Lexer;
     use crate::parser::Parser;
+    use crate::type_checker::TypeChecker;
 
     #[test]
     fn test_ownership_basic() {
@@ -459,4 +1343,278 @@ fn main() {
         let result = checker.check_program(&decls);
         assert!(result.is_err());
     }
+
+    fn check(source: &str) -> Result<()> {
+        let mut parser = Parser::new(Lexer::new(source.to_string()).tokenize());
+        let decls = parser.parse().unwrap();
+        OwnershipChecker::new().check_program(&decls)
+    }
+
+    /// Source of `items` followed by a `main` whose body is `body`
+    fn with_main(items: &str, body: &str) -> String {
+        format!("{}fn main() {{ {} }}", items, body)
+    }
+
+    #[test]
+    fn test_closure_captures() {
+        // Borrowed captures stay usable, while the closure is not used
+        // after they move; moved ones do not
+        assert!(check("fn main() { let s = \"a\"; let f = || s; f(); let t = s; }").is_ok());
+        assert!(matches!(
+            check("fn main() { let s = \"a\"; let f = || s; let t = s; f(); }"),
+            Err(OwnershipError::ReferenceOutOfScope { reference, .. }) if reference == "f"
+        ));
+        assert!(matches!(
+            check("fn main() { let s = \"a\"; let f = move || s; let t = s; }"),
+            Err(OwnershipError::UseAfterMove { .. })
+        ));
+        // nor can a closure's body move out a capture, as it may be called
+        // more than once
+        assert!(matches!(
+            check("fn take(s: str) { }\nfn main() { let s = \"a\"; let f = || take(s); }"),
+            Err(OwnershipError::MultipleOwners { variable, location }) if variable == "s" && location.contains("called more than once")
+        ));
+        assert!(matches!(
+            check("fn take(s: str) { }\nfn main() { let s = \"a\"; let f = move || { let t = s; }; }"),
+            Err(OwnershipError::MultipleOwners { .. })
+        ));
+        assert!(check("fn take(s: str) { }\nfn main() { let s = \"a\"; let f = move || take(s.clone()); f(); f(); }").is_ok());
+        assert!(check("fn take(n: i32) { }\nfn main() { let n = 1; let f = || take(n); f(); f(); }").is_ok());
+        assert!(matches!(
+            check("fn take(s: str) { }\nfn main() { let s = \"a\"; take(s); let f = || s; }"),
+            Err(OwnershipError::UseAfterMove { .. })
+        ));
+        // A closure can only change what it owns
+        assert!(check("fn main() { var n = 0; let f = move || { n = n + 1; }; }").is_ok());
+        assert!(matches!(
+            check("fn main() { var n = 0; let f = || { n = n + 1; }; }"),
+            Err(OwnershipError::MutableBorrow { .. })
+        ));
+        // Borrows cannot leave the function
+        assert!(check("fn f(n: i32) -> fn(i32) -> i32 { return move |x: i32| x + n; }").is_ok());
+        assert!(matches!(
+            check("fn f(n: i32) -> fn(i32) -> i32 { let g = |x: i32| x + n; return g; }"),
+            Err(OwnershipError::ReferenceOutOfScope { .. })
+        ));
+        // Passing a closure moves it
+        assert!(matches!(
+            check("fn run(f: fn()) { }\nfn main() { let f = || print(1); run(f); f(); }"),
+            Err(OwnershipError::UseAfterMove { .. })
+        ));
+    }
+
+    #[test]
+    fn test_moves_across_branches_and_loops() {
+        let items = "type B { name: str }\nfn take(b: B) { }\n";
+        let program = |body: &str| with_main(items, &format!("let c = true; var b = B {{ name: \"a\" }}; {}", body));
+        let moved_at = |body: &str| match check(&program(body)) {
+            Err(OwnershipError::UseAfterMove { moved_at, .. }) => moved_at,
+            other => panic!("expected a use after move, got {:?}", other),
+        };
+        // A move on one path of a branch reaches the join after it
+        assert!(moved_at("if c { take(b); } take(b);").contains("takes its then branch"));
+        assert!(check(&program("if c { take(b); } else { take(b); }")).is_ok());
+        assert!(check(&program("if c { take(b); return 0; } take(b);")).is_ok());
+        assert!(moved_at("match c { true => { take(b); } _ => { } } take(b);").contains("takes the arm at"));
+
+        // A move in a loop body reaches the next iteration
+        assert!(moved_at("while c { take(b); }").contains("earlier iteration of the while loop"));
+        assert!(check(&program("while c { take(b); b = B { name: \"b\" }; } take(b);")).is_ok());
+        assert!(moved_at("while c { if c { take(b); break; } } take(b);").contains("at the break"));
+        assert!(check(&program("while c { if c { take(b); return 0; } } take(b);")).is_ok());
+        assert!(moved_at("while c { if c { take(b); continue; } b = B { name: \"b\" }; }").contains("earlier iteration"));
+        assert!(moved_at("for i in 0..3 { take(b); }").contains("earlier iteration of the for loop"));
+    }
+
+    #[test]
+    fn test_copy_and_clone() {
+        let items = "type Point { x: i32, y: i32 }\ntype Named { name: str, at: Point }\ntype Tagged { name: str }\n\
+            impl Clone for Named { fn clone() -> Named { return Named { name: self.name.clone(), at: self.at }; } }\n\
+            fn take<T>(value: T) { }\n";
+        // Primitives and structs of them are copied, not moved
+        assert!(check(&with_main(items, "let n = 1; take(n); take(n); let b = true; take(b); take(b);")).is_ok());
+        assert!(check(&with_main(items, "let p = Point { x: 1, y: 2 }; take(p); take(p); let q = p.clone();")).is_ok());
+        assert!(matches!(
+            check(&with_main(items, "let s = \"a\"; take(s); take(s);")),
+            Err(OwnershipError::UseAfterMove { .. })
+        ));
+        assert!(matches!(
+            check(&with_main(items, "let n = Named { name: \"a\", at: Point { x: 1, y: 2 } }; take(n); take(n);")),
+            Err(OwnershipError::UseAfterMove { .. })
+        ));
+        // Anything else is copied with `.clone()`, which needs `Clone`
+        assert!(check(&with_main(items, "let n = Named { name: \"a\", at: Point { x: 1, y: 2 } }; take(n.clone()); take(n);")).is_ok());
+        assert!(check(&with_main(items, "let s = \"a\"; take(s.clone()); take(s);")).is_ok());
+        // A field or element is cloned out of the value that owns it
+        let owner = "let n = Named { name: \"a\", at: Point { x: 1, y: 2 } }; let a = [n.clone()];";
+        assert!(check(&with_main(items, &format!("{} take(n.at); take(n.at); take(n.name.clone()); take(a[0].clone());", owner))).is_ok());
+        for moves in ["take(n.name);", "let m = a[0];", "let t = Tagged { name: n.name };", "take(a[0]);"] {
+            assert!(matches!(
+                check(&with_main(items, &format!("{} {}", owner, moves))),
+                Err(OwnershipError::MultipleOwners { location, .. }) if location.contains("use .clone()")
+            ));
+        }
+        // which would otherwise drop it again
+        let drops = "type Buf { n: i32 }\ntype Holder { b: Buf }\nimpl Drop for Buf { fn drop() { } }\nfn eat(b: Buf) { }\n";
+        assert!(matches!(
+            check(&with_main(drops, "let h = Holder { b: Buf { n: 1 } }; eat(h.b); eat(h.b);")),
+            Err(OwnershipError::MultipleOwners { variable, .. }) if variable == "h"
+        ));
+        // A field of a generic struct has the type it is instantiated with
+        let boxes = format!("{}type Box<T> {{ t: T }}\nfn mk<T>(t: T) -> Box<T> {{ return Box {{ t: t }}; }}\n", items);
+        assert!(check(&format!("{}fn get(b: Box<i32>) -> i32 {{ return b.t; }}", boxes)).is_ok());
+        assert!(check(&with_main(&boxes, "let b = Box { t: 5 }; let a = b.t; let c = mk(1).t;")).is_ok());
+        assert!(matches!(
+            check(&format!("{}fn get(b: Box<str>) -> str {{ return b.t; }}", boxes)),
+            Err(OwnershipError::MultipleOwners { .. })
+        ));
+        assert!(matches!(
+            check(&format!("{}fn name(n: Named) -> str {{ return n.name; }}", items)),
+            Err(OwnershipError::MultipleOwners { variable, .. }) if variable == "n"
+        ));
+        // Binding or assigning a value moves it, so only one owner drops it
+        assert!(matches!(
+            check(&with_main(items, "let t = Tagged { name: \"a\" }; let u = t; take(t);")),
+            Err(OwnershipError::UseAfterMove { .. })
+        ));
+        assert!(matches!(
+            check(&with_main(items, "let t = Tagged { name: \"a\" }; var u = Tagged { name: \"b\" }; u = t; take(t);")),
+            Err(OwnershipError::UseAfterMove { .. })
+        ));
+        assert!(matches!(
+            check(&with_main(items, "let t = Tagged { name: \"a\" }; let u = t.clone();")),
+            Err(OwnershipError::InvalidClone { .. })
+        ));
+        // as does a match arm binding part of it, unless that part is Copy
+        let arms = "enum W { Two(Tagged, Tagged), Num(i32) }\n";
+        let matched = |body: &str| check(&with_main(&format!("{}{}", items, arms), body));
+        assert!(matched("let o = Some(1); match o { Some(n) => take(n), None => {} } take(o);").is_ok());
+        assert!(matched("let w = W::Num(1); match w { W::Num(n) => take(n), _ => {} } take(w);").is_ok());
+        assert!(matched("let o = Some(Tagged { name: \"a\" }); match o { Some(_) => {} None => {} } take(o);").is_ok());
+        assert!(matches!(
+            matched("let o = Some(Tagged { name: \"a\" }); match o { Some(t) => take(t), None => {} } take(o);"),
+            Err(OwnershipError::UseAfterMove { variable, .. }) if variable == "o"
+        ));
+        assert!(matches!(
+            matched("let w = W::Num(1); match w { W::Two(a, _) => take(a), _ => {} } take(w);"),
+            Err(OwnershipError::UseAfterMove { variable, .. }) if variable == "w"
+        ));
+        assert!(matches!(
+            matched("let n = Named { name: \"a\", at: Point { x: 1, y: 2 } }; match n.name { s => take(s) }"),
+            Err(OwnershipError::MultipleOwners { location, .. }) if location.contains("use .clone()")
+        ));
+        // Within a generic function, a type parameter is Copy by its bound
+        assert!(check(&format!("{}fn twice<T: Copy>(v: T) {{ take(v); take(v); }}", items)).is_ok());
+        assert!(matches!(
+            check(&format!("{}fn twice<T>(v: T) {{ take(v); take(v); }}", items)),
+            Err(OwnershipError::UseAfterMove { .. })
+        ));
+    }
+
+    #[test]
+    fn test_reference_scopes() {
+        let items = "type P { x: i32 }\nfn first(a: &i32, b: &i32) -> &i32 { return a; }\n";
+        // A reference to a value of the caller can be returned
+        assert!(check(items).is_ok());
+        assert!(check(&format!("{}fn x(p: &P) -> &i32 {{ return &p.x; }}", items)).is_ok());
+        assert!(matches!(
+            check(&format!("{}fn f() -> &i32 {{ let n = 1; return &n; }}", items)),
+            Err(OwnershipError::ReferenceOutOfScope { reference, .. }) if reference == "n"
+        ));
+        assert!(matches!(
+            check(&format!("{}fn f() -> &i32 {{ let a = 1; let b = 2; let r = first(&a, &b); return r; }}", items)),
+            Err(OwnershipError::ReferenceOutOfScope { .. })
+        ));
+        // A reference cannot be stored where it outlives what it borrows
+        assert!(check(&with_main(items, "let a = 1; let b = 2; var r = &a; if true { r = &b; }")).is_ok());
+        assert!(matches!(
+            check(&with_main(items, "let a = 1; var r = &a; while true { let b = 2; r = &b; }")),
+            Err(OwnershipError::ReferenceOutOfScope { reference, .. }) if reference == "b"
+        ));
+        // nor be used to change what it points to
+        assert!(matches!(
+            check(&with_main(items, "var p = P { x: 1 }; var r = &p; r.x = 2;")),
+            Err(OwnershipError::MutabilityConflict { .. })
+        ));
+        // Borrowing moves nothing, and references are copied
+        assert!(check(&with_main(items, "let s = \"a\"; let r = &s; let t = r; let u = s;")).is_ok());
+        // but a value cannot move while a reference to it is used later
+        let peek = "type N { name: str }\nfn take(n: N) { }\nfn peek(n: &N) { }\n";
+        let moved = |body: &str| check(&with_main(&format!("{}{}", items, peek), body));
+        assert!(moved("let b = N { name: \"b\" }; let r = &b; peek(r); take(b);").is_ok());
+        assert!(moved("let b = N { name: \"b\" }; let r = &b; var c = b; c = N { name: \"c\" };").is_ok());
+        assert!(matches!(
+            moved("let b = N { name: \"b\" }; let r = &b; take(b); peek(r);"),
+            Err(OwnershipError::ReferenceOutOfScope { reference, scope }) if reference == "r" && scope.contains("borrows 'b', which was moved")
+        ));
+        assert!(matches!(
+            moved("let b = N { name: \"b\" }; let r = &b; let s = r; if true { take(b); } peek(s);"),
+            Err(OwnershipError::ReferenceOutOfScope { reference, .. }) if reference == "s"
+        ));
+        assert!(moved("let b = N { name: \"b\" }; var r = &b; take(b); let c = N { name: \"c\" }; r = &c; peek(r);").is_ok());
+
+        // An array taken as a slice is borrowed like one taken by `&`
+        let slices = "fn first(xs: [i32]) -> [i32] { return xs; }\n";
+        assert!(check(&format!("{}fn f(xs: [i32]) -> [i32] {{ return first(xs); }}", slices)).is_ok());
+        assert!(check(&with_main(slices, "let a = [1, 2]; let s: [i32] = first(a); let t: [i32] = [3];")).is_ok());
+        assert!(matches!(
+            check(&format!("{}fn f() -> [i32] {{ let a = [1, 2]; return a; }}", slices)),
+            Err(OwnershipError::ReferenceOutOfScope { reference, .. }) if reference == "a"
+        ));
+        assert!(matches!(
+            check(&format!("{}fn f() -> [i32] {{ let a = [1, 2]; return first(a); }}", slices)),
+            Err(OwnershipError::ReferenceOutOfScope { reference, .. }) if reference == "a"
+        ));
+        assert!(matches!(
+            check(&format!("{}fn f() -> [i32] {{ return [1, 2]; }}", slices)),
+            Err(OwnershipError::ReferenceOutOfScope { reference, .. }) if reference.starts_with("the array at")
+        ));
+        assert!(matches!(
+            check(&with_main(slices, "var s: [i32] = [0]; if true { let b = [4, 5]; s = b; }")),
+            Err(OwnershipError::ReferenceOutOfScope { reference, .. }) if reference == "b"
+        ));
+    }
+
+    #[test]
+    fn test_actors_impls_and_handlers() {
+        let items = "type Named { name: str }\ntrait Show { fn show() -> i32; }\nfn take(s: str) { }\n\
+            actor Worker { fn run(s: str) { } fn peek(n: &Named) { } }\n";
+        let program = |decls: &str, body: &str| with_main(&format!("{}{}\n", items, decls), body);
+        let moved = |source: &str| matches!(check(source), Err(OwnershipError::UseAfterMove { .. }));
+        // Bodies of actor methods, impl methods and inline ui handlers
+        assert!(moved(&program("actor Log { fn log(line: str) { take(line); take(line); } }", "")));
+        assert!(moved(&program("impl Show for Named { fn show() -> i32 { let s = self.name.clone(); take(s); take(s); return 0; } }", "")));
+        assert!(moved(&program("ui App { Button { onClick: || { let s = \"a\"; take(s); take(s); } } }", "")));
+        assert!(check(&program("impl Show for Named { fn show() -> i32 { let s = self.name.clone(); take(s); return 0; } }", "")).is_ok());
+        // `self` can be changed through, but not moved out
+        let counter = "type Counter { n: i32 }\nimpl Iterator for Counter { fn next() -> Option<i32> { self.n = self.n + 1; return Some(self.n); } }\n";
+        assert!(check(&program(counter, "")).is_ok());
+        let eat = "fn eat(n: Named) { }\nimpl Show for Named { fn show() -> i32 { eat(self); return 0; } }";
+        assert!(matches!(
+            check(&program(eat, "")),
+            Err(OwnershipError::MultipleOwners { variable, .. }) if variable == "self"
+        ));
+        let clone = "impl Clone for Named { fn clone() -> Named { return Named { name: self.name.clone() }; } }\n";
+        assert!(check(&program(&format!("{}{}", clone, eat.replace("eat(self)", "eat(self.clone())")), "")).is_ok());
+
+        // Messages move their payload, which must not borrow the sender's
+        assert!(check(&program("", "let w = Worker.spawn(); let s = \"a\"; w.run(s);")).is_ok());
+        assert!(moved(&program("", "let w = Worker.spawn(); let s = \"a\"; w.run(s); take(s);")));
+        assert!(matches!(
+            check(&program("", "let w = Worker.spawn(); let n = Named { name: \"a\" }; w.peek(&n);")),
+            Err(OwnershipError::ReferenceOutOfScope { reference, .. }) if reference == "n"
+        ));
+
+        // The type checker, which runs first, accepts the messages
+        let checked = |source: &str| {
+            let decls = Parser::new(Lexer::new(source.to_string()).tokenize()).parse().unwrap();
+            TypeChecker::new().check_program(decls.clone()).unwrap();
+            OwnershipChecker::new().check_program(&decls)
+        };
+        assert!(checked(&program("", "let w = Worker.spawn(); let s = \"a\"; w.run(s);")).is_ok());
+        assert!(matches!(
+            checked(&program("", "let w = Worker.spawn(); let s = \"a\"; w.run(s); take(s);")),
+            Err(OwnershipError::UseAfterMove { variable, .. }) if variable == "s"
+        ));
+    }
 }
@@ -1,17 +1,40 @@
 // parser.rs — U v0.8 Parser with Traits and Generics
 // MIT License — Copyright (c) 2025 Webcien and U contributors
 
-use crate::lexer::{Token, TokenType};
+use crate::diagnostics::{Diagnostic, DiagnosticCollector, Span};
+use crate::lexer::{NumberSuffix, Token, TokenType};
+use std::collections::HashMap;
+use std::fmt;
 
-#[derive(Debug, Clone)]
+/// Syntax errors are reported as diagnostics located at the offending token
+type ParseResult<T> = Result<T, Box<Diagnostic>>;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Type {
+    Char,
     I32,
+    I64,
+    U8,
+    U64,
+    F32,
+    F64,
     Str,
     Bool,
     Ptr,
     Option(Box<Type>),
     Result(Box<Type>, Box<Type>),
     Custom(String),
+    /// Only produced for `match (a, b) { ... }` scrutinees
+    Tuple(Vec<Type>),
+    /// `[T; N]`: `N` values stored inline
+    Array(Box<Type>, usize),
+    /// `[T]`: a view of values stored elsewhere, with their count
+    Slice(Box<Type>),
+    /// `Range<T>`: the value of `a..b`, `a..=b` or a stepped range
+    Range(Box<Type>),
+    /// `&T`: an immutable reference to a value owned elsewhere, valid only
+    /// within the scope of that value
+    Ref(Box<Type>),
     Generic {
         name: String,
         type_args: Vec<Type>,
@@ -20,10 +43,109 @@ pub enum Type {
         params: Vec<Type>,
         return_type: Option<Box<Type>>,
     },
+    /// `dyn Trait`: any value whose type implements the trait, reached
+    /// through a vtable
+    Dyn(String),
+}
+
+impl Type {
+    /// Replace type parameters (parsed as `Custom` names) by the types
+    /// bound to them
+    pub fn substitute(&self, bindings: &HashMap<&str, &Type>) -> Type {
+        match self {
+            Type::Custom(name) => bindings.get(name.as_str()).map_or_else(|| self.clone(), |bound| (*bound).clone()),
+            Type::Option(inner) => Type::Option(Box::new(inner.substitute(bindings))),
+            Type::Result(ok, err) => Type::Result(Box::new(ok.substitute(bindings)), Box::new(err.substitute(bindings))),
+            Type::Tuple(items) => Type::Tuple(items.iter().map(|t| t.substitute(bindings)).collect()),
+            Type::Array(element, len) => Type::Array(Box::new(element.substitute(bindings)), *len),
+            Type::Slice(element) => Type::Slice(Box::new(element.substitute(bindings))),
+            Type::Range(item) => Type::Range(Box::new(item.substitute(bindings))),
+            Type::Ref(inner) => Type::Ref(Box::new(inner.substitute(bindings))),
+            Type::Generic { name, type_args } => Type::Generic {
+                name: name.clone(),
+                type_args: type_args.iter().map(|t| t.substitute(bindings)).collect(),
+            },
+            Type::FunctionPointer { params, return_type } => Type::FunctionPointer {
+                params: params.iter().map(|t| t.substitute(bindings)).collect(),
+                return_type: return_type.as_ref().map(|t| Box::new(t.substitute(bindings))),
+            },
+            _ => self.clone(),
+        }
+    }
+
+    /// Whether the type mentions any of the type parameters `names`
+    pub fn mentions_any(&self, names: &[String]) -> bool {
+        match self {
+            Type::Custom(name) => names.contains(name),
+            Type::Option(inner) | Type::Array(inner, _) | Type::Slice(inner) | Type::Range(inner) | Type::Ref(inner) => {
+                inner.mentions_any(names)
+            }
+            Type::Result(ok, err) => ok.mentions_any(names) || err.mentions_any(names),
+            Type::Tuple(items) | Type::Generic { type_args: items, .. } => items.iter().any(|t| t.mentions_any(names)),
+            Type::FunctionPointer { params, return_type } => {
+                params.iter().any(|t| t.mentions_any(names)) || return_type.as_ref().is_some_and(|t| t.mentions_any(names))
+            }
+            _ => false,
+        }
+    }
+}
+
+/// How the type is written in U source
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let list = |types: &[Type]| types.iter().map(Type::to_string).collect::<Vec<_>>().join(", ");
+        match self {
+            Type::Char => write!(f, "char"),
+            Type::I32 => write!(f, "i32"),
+            Type::I64 => write!(f, "i64"),
+            Type::U8 => write!(f, "u8"),
+            Type::U64 => write!(f, "u64"),
+            Type::F32 => write!(f, "f32"),
+            Type::F64 => write!(f, "f64"),
+            Type::Str => write!(f, "str"),
+            Type::Bool => write!(f, "bool"),
+            Type::Ptr => write!(f, "ptr"),
+            Type::Option(inner) => write!(f, "Option<{}>", inner),
+            Type::Result(ok, err) => write!(f, "Result<{}, {}>", ok, err),
+            Type::Custom(name) => write!(f, "{}", name),
+            Type::Array(element, len) => write!(f, "[{}; {}]", element, len),
+            Type::Slice(element) => write!(f, "[{}]", element),
+            Type::Range(item) => write!(f, "Range<{}>", item),
+            Type::Ref(inner) => write!(f, "&{}", inner),
+            Type::Tuple(items) => write!(f, "({})", list(items)),
+            Type::Generic { name, type_args } => write!(f, "{}<{}>", name, list(type_args)),
+            Type::FunctionPointer { params, return_type: Some(ret) } => write!(f, "fn({}) -> {}", list(params), ret),
+            Type::FunctionPointer { params, return_type: None } => write!(f, "fn({})", list(params)),
+            Type::Dyn(trait_name) => write!(f, "dyn {}", trait_name),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Expression {
+    pub kind: ExpressionKind,
+    pub span: Span,
+}
+
+impl Expression {
+    pub fn new(kind: ExpressionKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+
+    /// The variable a place expression (`x`, `x.a.b`, `x[i]`) is rooted in
+    pub fn root_variable(&self) -> Option<&str> {
+        match &self.kind {
+            ExpressionKind::Identifier(name) => Some(name),
+            ExpressionKind::FieldAccess { object, .. } | ExpressionKind::Index { object, .. } => {
+                object.root_variable()
+            }
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
-pub enum Expression {
+pub enum ExpressionKind {
     Literal(Literal),
     Identifier(String),
     FunctionCall {
@@ -31,7 +153,9 @@ pub enum Expression {
         arguments: Vec<Expression>,
     },
     MethodCall {
-        receiver: String,
+        /// The value the method is called on: `p` in `p.area()`, or any
+        /// other expression, as in `shapes[0].area()` or `make().area()`
+        receiver: Box<Expression>,
         method: String,
         arguments: Vec<Expression>,
     },
@@ -44,10 +168,146 @@ pub enum Expression {
         operator: UnaryOp,
         operand: Box<Expression>,
     },
+    /// `target = value`, where `target` is a variable or a field of one
     Assignment {
-        target: String,
+        target: Box<Expression>,
         value: Box<Expression>,
     },
+    /// `Point { x: 1, y: 2 }` or `Vec<T> { ... }`; a field without a value
+    /// takes the variable of the same name
+    StructLiteral {
+        name: String,
+        type_args: Vec<Type>,
+        fields: Vec<(String, Expression)>,
+    },
+    /// `object.field`
+    FieldAccess {
+        object: Box<Expression>,
+        field: String,
+    },
+    /// `[a, b, c]`
+    ArrayLiteral(Vec<Expression>),
+    /// `object[index]`
+    Index {
+        object: Box<Expression>,
+        index: Box<Expression>,
+    },
+    /// `start..end`, `start..=end` or `(start..end).step_by(step)`
+    Range {
+        start: Box<Expression>,
+        end: Box<Expression>,
+        inclusive: bool,
+        step: Option<Box<Expression>>,
+    },
+    /// `Shape::Circle(5)`, `Shape::Rect { w: 1, h: 2 }` or `Message::Quit`
+    EnumVariant {
+        enum_name: String,
+        variant: String,
+        payload: VariantPayload<Expression>,
+    },
+    Match {
+        scrutinee: Box<Expression>,
+        arms: Vec<MatchArm>,
+    },
+    /// `(a, b)`; only valid as a `match` scrutinee
+    Tuple(Vec<Expression>),
+    /// `|x: i32| -> i32 { x + 1 }`, `|x: i32| x + 1` or `move || ...`. A
+    /// value body, like the final expression of a block body, becomes a
+    /// `return` of it.
+    Closure {
+        params: Vec<(String, Type)>,
+        return_type: Option<Type>,
+        body: Vec<Statement>,
+        /// `move`: every capture is moved into the closure
+        by_move: bool,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub guard: Option<Expression>,
+    pub body: ArmBody,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum ArmBody {
+    /// `pattern => value,`
+    Expression(Expression),
+    /// `pattern => { ... }`, which produces no value
+    Block(Vec<Statement>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    pub kind: PatternKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum PatternKind {
+    /// `_`
+    Wildcard,
+    /// `x`: matches anything and binds it
+    Binding(String),
+    Literal(Literal),
+    /// `(p, q)`
+    Tuple(Vec<Pattern>),
+    /// `Point { x, y: 0, .. }`; a field without a pattern binds its name
+    Struct {
+        name: String,
+        fields: Vec<(String, Pattern)>,
+        rest: bool,
+    },
+    /// `Shape::Circle(r)` or, with no `enum_name`, one of the built-in
+    /// `Some(p)`, `None`, `Ok(p)` and `Err(p)`
+    Variant {
+        enum_name: Option<String>,
+        variant: String,
+        payload: VariantPayload<Pattern>,
+        /// Whether a struct-like payload ends in `..`
+        rest: bool,
+    },
+}
+
+/// Payload of an enum variant: field types in a declaration, field values
+/// in a constructor
+#[derive(Debug, Clone)]
+pub enum VariantPayload<T> {
+    /// `Quit`
+    Unit,
+    /// `Circle(i32)`
+    Tuple(Vec<T>),
+    /// `Rect { w: i32, h: i32 }`
+    Struct(Vec<(String, T)>),
+}
+
+impl<T> VariantPayload<T> {
+    pub fn map<U>(self, mut f: impl FnMut(T) -> U) -> VariantPayload<U> {
+        match self {
+            VariantPayload::Unit => VariantPayload::Unit,
+            VariantPayload::Tuple(items) => VariantPayload::Tuple(items.into_iter().map(f).collect()),
+            VariantPayload::Struct(fields) => VariantPayload::Struct(
+                fields.into_iter().map(|(name, item)| (name, f(item))).collect(),
+            ),
+        }
+    }
+
+    /// Payload fields in order; tuple fields are named `0`, `1`, ...
+    pub fn fields(&self) -> Vec<(String, &T)> {
+        match self {
+            VariantPayload::Unit => Vec::new(),
+            VariantPayload::Tuple(items) => items
+                .iter()
+                .enumerate()
+                .map(|(i, item)| (i.to_string(), item))
+                .collect(),
+            VariantPayload::Struct(fields) => {
+                fields.iter().map(|(name, item)| (name.clone(), item)).collect()
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -71,21 +331,71 @@ pub enum BinaryOp {
 pub enum UnaryOp {
     Not,
     Negate,
+    /// `&x`: borrow a place immutably
+    Borrow,
+    /// `*r`: read the value a reference points to
+    Deref,
 }
 
 #[derive(Debug, Clone)]
 pub enum Literal {
-    Integer(u32),
+    /// Value and the type given by a suffix (`10u8`), if any
+    Integer(u64, Option<Type>),
+    Float(f64, Option<Type>),
     String(String),
+    Char(char),
     Boolean(bool),
 }
 
+impl Literal {
+    /// Type of the literal: its suffix, else `i32` for integers that fit
+    /// (`i64` otherwise) and `f64` for floats
+    pub fn ty(&self) -> Type {
+        match self {
+            Literal::Integer(_, Some(ty)) | Literal::Float(_, Some(ty)) => ty.clone(),
+            Literal::Integer(n, None) if *n <= i32::MAX as u64 => Type::I32,
+            Literal::Integer(_, None) => Type::I64,
+            Literal::Float(_, None) => Type::F64,
+            Literal::String(_) => Type::Str,
+            Literal::Char(_) => Type::Char,
+            Literal::Boolean(_) => Type::Bool,
+        }
+    }
+}
+
+fn suffix_type(suffix: &NumberSuffix) -> Type {
+    match suffix {
+        NumberSuffix::I32 => Type::I32,
+        NumberSuffix::I64 => Type::I64,
+        NumberSuffix::U8 => Type::U8,
+        NumberSuffix::U64 => Type::U64,
+        NumberSuffix::F32 => Type::F32,
+        NumberSuffix::F64 => Type::F64,
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Statement {
+    pub kind: StatementKind,
+    pub span: Span,
+}
+
+impl Statement {
+    pub fn new(kind: StatementKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
+
 #[derive(Debug, Clone)]
-pub enum Statement {
+pub enum StatementKind {
     Let {
         name: String,
         mutable: bool,
-        value: Expression,
+        /// Declared type: `let x: T`
+        ty: Option<Type>,
+        /// Initial value; without one the variable must be assigned before
+        /// it is read, and a `let` only once
+        value: Option<Expression>,
     },
     Expr(Expression),
     Return(Expression),
@@ -108,6 +418,8 @@ pub enum Statement {
     Unsafe {
         body: Vec<Statement>,
     },
+    /// Placeholder for a statement that failed to parse
+    Error,
 }
 
 #[derive(Debug, Clone)]
@@ -117,12 +429,21 @@ pub struct Function {
     pub return_type: Option<Type>,
     pub body: Vec<Statement>,
     pub type_params: Vec<String>,
+    /// `(T, Trait)` for every trait a type parameter must implement, from
+    /// `<T: Trait>` and `where T: Trait`
+    pub bounds: Vec<(String, String)>,
+    /// Declared `pub`: visible outside its module
+    pub public: bool,
+    pub doc: Option<String>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
 pub struct Actor {
     pub name: String,
     pub methods: Vec<Function>,
+    pub doc: Option<String>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
@@ -130,6 +451,10 @@ pub struct Trait {
     pub name: String,
     pub methods: Vec<TraitMethod>,
     pub type_params: Vec<String>,
+    /// Declared `pub`: visible outside its module
+    pub public: bool,
+    pub doc: Option<String>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
@@ -137,6 +462,7 @@ pub struct TraitMethod {
     pub name: String,
     pub params: Vec<(String, Type)>,
     pub return_type: Option<Type>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
@@ -144,6 +470,29 @@ pub struct TypeDef {
     pub name: String,
     pub fields: Vec<(String, Type)>,
     pub type_params: Vec<String>,
+    /// `(T, Trait)` for every trait a type parameter must implement
+    pub bounds: Vec<(String, String)>,
+    /// Declared `pub`: visible outside its module
+    pub public: bool,
+    pub doc: Option<String>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct EnumDef {
+    pub name: String,
+    pub variants: Vec<EnumVariant>,
+    /// Declared `pub`: visible outside its module
+    pub public: bool,
+    pub doc: Option<String>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct EnumVariant {
+    pub name: String,
+    pub payload: VariantPayload<Type>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
@@ -152,6 +501,22 @@ pub struct TraitImpl {
     pub type_name: String,
     pub methods: Vec<Function>,
     pub type_args: Vec<Type>,
+    pub span: Span,
+}
+
+impl TraitImpl {
+    /// Item type of an `impl Iterator for T` whose `next()` returns
+    /// `Option<Item>`; `None` for any other impl
+    pub fn iterator_item(&self) -> Option<Type> {
+        if self.trait_name != "Iterator" {
+            return None;
+        }
+        let next = self.methods.iter().find(|m| m.name == "next" && m.params.is_empty())?;
+        match &next.return_type {
+            Some(Type::Option(item)) => Some((**item).clone()),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -160,21 +525,68 @@ pub enum Declaration {
     Actor(Actor),
     Trait(Trait),
     TypeDef(TypeDef),
+    Enum(EnumDef),
     TraitImpl(TraitImpl),
     ExternBlock(ExternBlock),
     UiBlock(UiBlock),
+    Import(Import),
+    /// Placeholder for a declaration that failed to parse
+    Error(Span),
+}
+
+impl Declaration {
+    pub fn span(&self) -> Span {
+        match self {
+            Declaration::Function(f) => f.span,
+            Declaration::Actor(a) => a.span,
+            Declaration::Trait(t) => t.span,
+            Declaration::TypeDef(t) => t.span,
+            Declaration::Enum(e) => e.span,
+            Declaration::TraitImpl(i) => i.span,
+            Declaration::ExternBlock(e) => e.span,
+            Declaration::UiBlock(u) => u.span,
+            Declaration::Import(i) => i.span,
+            Declaration::Error(span) => *span,
+        }
+    }
+}
+
+/// `mod shapes;`, `import std::core;` or `use shapes::{area, Shape};`
+#[derive(Debug, Clone)]
+pub struct Import {
+    pub kind: ImportKind,
+    /// Module path: `[shapes]`, `[std, core]`; for `use`, the path up to
+    /// the imported items
+    pub path: Vec<String>,
+    /// Items a `use` brings into scope; empty for `mod` and `import`
+    pub items: Vec<String>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImportKind {
+    /// `mod name;`: the file `name.ul` (or `name/mod.ul`) next to this one
+    Mod,
+    /// `import a::b;`: a module of the crate or of the bundled stdlib,
+    /// named `b` in this module
+    Import,
+    /// `use a::b;`: the item `b` of module `a`, named `b` in this module
+    Use,
 }
 
 #[derive(Debug, Clone)]
 pub struct UiBlock {
     pub name: String,
     pub root: Widget,
+    pub doc: Option<String>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
 pub struct Widget {
     pub widget_type: String,
     pub properties: Vec<(String, WidgetValue)>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
@@ -187,12 +599,15 @@ pub enum WidgetValue {
     WidgetList(Vec<Widget>),
     Boolean(bool),
     Float(f32),
+    /// An inline handler such as `onClick: || { ... }`
+    Closure(Box<Expression>),
 }
 
 #[derive(Debug, Clone)]
 pub struct ExternBlock {
     pub abi: String,
     pub functions: Vec<ExternFunction>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
@@ -201,24 +616,209 @@ pub struct ExternFunction {
     pub params: Vec<(String, Type)>,
     pub return_type: Option<Type>,
     pub is_variadic: bool,
+    pub span: Span,
 }
 
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    diagnostics: DiagnosticCollector,
+    /// `///` lines, keyed by the index of the token they precede
+    outer_docs: HashMap<usize, Vec<String>>,
+    /// `//!` lines, keyed by the index of the token they precede
+    inner_docs: HashMap<usize, Vec<String>>,
+    /// Set while parsing the condition of `if`/`while`/`for`, where a `{`
+    /// opens the body rather than a struct-like payload
+    no_struct_literal: bool,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, current: 0 }
+        // Doc comments are pulled out of the token stream so they never get
+        // in the way of the grammar; items look them up by position
+        let mut significant = Vec::with_capacity(tokens.len());
+        let mut outer_docs: HashMap<usize, Vec<String>> = HashMap::new();
+        let mut inner_docs: HashMap<usize, Vec<String>> = HashMap::new();
+        for token in tokens {
+            match token.kind {
+                TokenType::DocComment(text) => {
+                    outer_docs.entry(significant.len()).or_default().push(text)
+                }
+                TokenType::InnerDocComment(text) => {
+                    inner_docs.entry(significant.len()).or_default().push(text)
+                }
+                _ => significant.push(token),
+            }
+        }
+        Self {
+            tokens: significant,
+            current: 0,
+            diagnostics: DiagnosticCollector::new(),
+            outer_docs,
+            inner_docs,
+            no_struct_literal: false,
+        }
     }
 
+    /// Parse the whole file, failing if any syntax error was found.
+    ///
+    /// The error string lists every diagnostic, one per line.
     pub fn parse(&mut self) -> Result<Vec<Declaration>, String> {
+        let declarations = self.parse_program();
+        if self.diagnostics.has_errors() {
+            Err(self
+                .diagnostics
+                .diagnostics()
+                .iter()
+                .map(|d| d.to_string())
+                .collect::<Vec<_>>()
+                .join("\n"))
+        } else {
+            Ok(declarations)
+        }
+    }
+
+    /// Parse the whole file, recovering from syntax errors.
+    ///
+    /// Always returns an AST; declarations and statements that failed to
+    /// parse are replaced by `Error` nodes and reported in `diagnostics()`.
+    pub fn parse_program(&mut self) -> Vec<Declaration> {
         let mut declarations = Vec::new();
         while !self.is_at_end() {
-            declarations.push(self.parse_declaration()?);
+            let start = self.peek().span;
+            match self.parse_declaration() {
+                Ok(decl) => declarations.push(decl),
+                Err(diag) => {
+                    self.diagnostics.add(*diag);
+                    self.synchronize_declaration();
+                    declarations.push(Declaration::Error(self.span_from(start)));
+                }
+            }
+        }
+        declarations
+    }
+
+    /// Diagnostics collected while parsing
+    pub fn diagnostics(&self) -> &DiagnosticCollector {
+        &self.diagnostics
+    }
+
+    /// Documentation from `//!` comments at the top of the file
+    pub fn module_doc(&self) -> Option<String> {
+        self.inner_docs.get(&0).map(|lines| join_doc_lines(lines))
+    }
+
+    /// Documentation of an item: `///` lines before its keyword at index
+    /// `keyword`, then `//!` lines just inside the `{` at index `body`
+    fn item_doc(&self, keyword: usize, body: usize) -> Option<String> {
+        let lines: Vec<String> = self
+            .outer_docs
+            .get(&keyword)
+            .into_iter()
+            .chain(self.inner_docs.get(&(body + 1)))
+            .flatten()
+            .cloned()
+            .collect();
+        if lines.is_empty() {
+            None
+        } else {
+            Some(join_doc_lines(&lines))
+        }
+    }
+
+    /// Whether the current token can only start a top-level declaration
+    fn at_declaration_keyword(&self) -> bool {
+        matches!(
+            self.peek().kind,
+            TokenType::Fn
+                | TokenType::Actor
+                | TokenType::Ui
+                | TokenType::Extern
+                | TokenType::Pub
+                | TokenType::Mod
+                | TokenType::Import
+                | TokenType::Use
+        )
+    }
+
+    /// Whether the current token begins a declaration, including the
+    /// contextual `trait`, `type`, `enum` and `impl` keywords
+    fn at_declaration_start(&self) -> bool {
+        if self.at_declaration_keyword() {
+            return true;
+        }
+        match &self.peek().kind {
+            TokenType::Identifier(s) => s == "trait" || s == "type" || s == "enum" || s == "impl",
+            _ => false,
+        }
+    }
+
+    /// Skip tokens until the start of the next top-level declaration.
+    ///
+    /// Braces are balanced so that a broken header does not leave us inside
+    /// the body of the declaration it belongs to.
+    fn synchronize_declaration(&mut self) {
+        let start = self.current;
+        let mut depth = 0usize;
+        while !self.is_at_end() {
+            match self.peek().kind {
+                TokenType::LeftBrace => depth += 1,
+                TokenType::RightBrace => {
+                    depth = depth.saturating_sub(1);
+                    if depth == 0 {
+                        self.advance();
+                        return;
+                    }
+                }
+                _ if depth == 0 && self.current > start && self.at_declaration_start() => return,
+                _ => {}
+            }
+            self.advance();
+        }
+    }
+
+    /// Skip tokens until the end of the current statement.
+    ///
+    /// Stops after a `;`, after a nested block closes, or before a `}` that
+    /// closes the enclosing block or a token that starts a new statement.
+    fn synchronize_statement(&mut self) {
+        let start = self.current;
+        let mut depth = 0usize;
+        while !self.is_at_end() {
+            match self.peek().kind {
+                TokenType::Semicolon if depth == 0 => {
+                    self.advance();
+                    return;
+                }
+                TokenType::LeftBrace => depth += 1,
+                TokenType::RightBrace => {
+                    if depth == 0 {
+                        return;
+                    }
+                    depth -= 1;
+                    if depth == 0 {
+                        self.advance();
+                        return;
+                    }
+                }
+                TokenType::Let
+                | TokenType::Var
+                | TokenType::If
+                | TokenType::While
+                | TokenType::For
+                | TokenType::Return
+                | TokenType::Break
+                | TokenType::Continue
+                | TokenType::Unsafe
+                    if depth == 0 && self.current > start =>
+                {
+                    return
+                }
+                _ if depth == 0 && self.at_declaration_keyword() => return,
+                _ => {}
+            }
+            self.advance();
         }
-        Ok(declarations)
     }
 
     fn is_at_end(&self) -> bool {
@@ -247,7 +847,9 @@ impl Parser {
             match (&self.peek().kind, &kind) {
                 (TokenType::Identifier(_), TokenType::Identifier(_)) => true,
                 (TokenType::StringLiteral(_), TokenType::StringLiteral(_)) => true,
-                (TokenType::IntegerLiteral(_), TokenType::IntegerLiteral(_)) => true,
+                (TokenType::CharLiteral(_), TokenType::CharLiteral(_)) => true,
+                (TokenType::IntegerLiteral(..), TokenType::IntegerLiteral(..)) => true,
+                (TokenType::FloatLiteral(..), TokenType::FloatLiteral(..)) => true,
                 (a, b) => a == b,
             }
         }
@@ -262,16 +864,34 @@ impl Parser {
         }
     }
 
-    fn consume(&mut self, kind: TokenType, message: &str) -> Result<&Token, String> {
+    fn consume(&mut self, kind: TokenType, message: &str) -> ParseResult<&Token> {
         if self.check(kind) {
             Ok(self.advance())
         } else {
-            Err(format!("{} at line {}", message, self.peek().line))
+            Err(self.error(message))
         }
     }
 
-    fn parse_declaration(&mut self) -> Result<Declaration, String> {
-        if self.match_token(TokenType::Ui) {
+    /// Syntax error located at the current token
+    fn error(&self, message: &str) -> Box<Diagnostic> {
+        Box::new(Diagnostic::error(message.to_string()).with_span(self.peek().span))
+    }
+
+    /// Span running from `start` through the most recently consumed token
+    fn span_from(&self, start: Span) -> Span {
+        start.to(self.previous().span)
+    }
+
+    fn parse_declaration(&mut self) -> ParseResult<Declaration> {
+        if self.check(TokenType::Pub) {
+            self.parse_public_declaration()
+        } else if self.match_token(TokenType::Mod) {
+            self.parse_import(ImportKind::Mod)
+        } else if self.match_token(TokenType::Import) {
+            self.parse_import(ImportKind::Import)
+        } else if self.match_token(TokenType::Use) {
+            self.parse_import(ImportKind::Use)
+        } else if self.match_token(TokenType::Ui) {
             Ok(Declaration::UiBlock(self.parse_ui_block()?))
         } else if self.match_token(TokenType::Extern) {
             Ok(Declaration::ExternBlock(self.parse_extern_block()?))
@@ -279,13 +899,9 @@ impl Parser {
             Ok(Declaration::Function(self.parse_function()?))
         } else if self.match_token(TokenType::Actor) {
             Ok(Declaration::Actor(self.parse_actor()?))
-        } else if self.peek().kind == TokenType::Identifier(String::new()) {
-            // Check for trait, type, or impl
-            let ident = if let TokenType::Identifier(s) = &self.peek().kind {
-                s.clone()
-            } else {
-                String::new()
-            };
+        } else if let TokenType::Identifier(ident) = &self.peek().kind {
+            // Check for trait, type, enum, or impl
+            let ident = ident.clone();
 
             if ident == "trait" {
                 self.advance();
@@ -293,47 +909,114 @@ impl Parser {
             } else if ident == "type" {
                 self.advance();
                 Ok(Declaration::TypeDef(self.parse_type_def()?))
+            } else if ident == "enum" {
+                self.advance();
+                Ok(Declaration::Enum(self.parse_enum()?))
             } else if ident == "impl" {
                 self.advance();
                 Ok(Declaration::TraitImpl(self.parse_trait_impl()?))
             } else {
-                Err(format!(
-                    "Expected 'extern', 'fn', 'actor', 'trait', 'type', or 'impl', got {:?} at line {}",
-                    self.peek().kind,
-                    self.peek().line
-                ))
+                Err(self.error(&format!(
+                    "Expected 'extern', 'fn', 'actor', 'trait', 'type', 'enum', or 'impl', got {:?}",
+                    self.peek().kind
+                )))
             }
         } else {
-            Err(format!(
-                "Expected declaration, got {:?} at line {}",
-                self.peek().kind,
-                self.peek().line
-            ))
+            Err(self.error(&format!(
+                "Expected declaration, got {:?}",
+                self.peek().kind
+            )))
+        }
+    }
+
+    /// `pub` before a function, type, enum or trait
+    fn parse_public_declaration(&mut self) -> ParseResult<Declaration> {
+        let start = self.advance().span;
+        // Docs written before `pub` belong to the item's keyword
+        if let Some(mut lines) = self.outer_docs.remove(&(self.current - 1)) {
+            lines.extend(self.outer_docs.remove(&self.current).unwrap_or_default());
+            self.outer_docs.insert(self.current, lines);
+        }
+        if self.check(TokenType::Pub) {
+            return Err(self.error("Duplicate 'pub'"));
+        }
+        let mut declaration = self.parse_declaration()?;
+        match &mut declaration {
+            Declaration::Function(f) => {
+                f.public = true;
+                f.span = start.to(f.span);
+            }
+            Declaration::Trait(t) => {
+                t.public = true;
+                t.span = start.to(t.span);
+            }
+            Declaration::TypeDef(t) => {
+                t.public = true;
+                t.span = start.to(t.span);
+            }
+            Declaration::Enum(e) => {
+                e.public = true;
+                e.span = start.to(e.span);
+            }
+            other => {
+                return Err(Box::new(
+                    Diagnostic::error("'pub' is only allowed on fn, type, enum and trait items".to_string())
+                        .with_span(start.to(other.span())),
+                ));
+            }
+        }
+        Ok(declaration)
+    }
+
+    /// `mod name;`, `import a::b;`, `use a::b;` or `use a::{b, c};` after
+    /// the keyword
+    fn parse_import(&mut self, kind: ImportKind) -> ParseResult<Declaration> {
+        let start = self.previous().span;
+        let mut path = vec![self.parse_identifier("Expected module name")?];
+        let mut items = Vec::new();
+        while kind != ImportKind::Mod && self.match_token(TokenType::ColonColon) {
+            if kind == ImportKind::Use && self.match_token(TokenType::LeftBrace) {
+                while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+                    items.push(self.parse_identifier("Expected item name")?);
+                    if !self.check(TokenType::RightBrace) {
+                        self.consume(TokenType::Comma, "Expected ',' between items")?;
+                    }
+                }
+                self.consume(TokenType::RightBrace, "Expected '}' after items")?;
+                break;
+            }
+            path.push(self.parse_identifier("Expected name after '::'")?);
+        }
+        if kind == ImportKind::Use && items.is_empty() {
+            if path.len() < 2 {
+                return Err(self.error("Expected '::' and an item after module name"));
+            }
+            items.push(path.pop().unwrap());
         }
+        self.consume(TokenType::Semicolon, "Expected ';' after module path")?;
+        Ok(Declaration::Import(Import {
+            kind,
+            path,
+            items,
+            span: self.span_from(start),
+        }))
     }
 
-    fn parse_function(&mut self) -> Result<Function, String> {
+    fn parse_function(&mut self) -> ParseResult<Function> {
+        let keyword = self.current - 1;
+        let start = self.previous().span;
         if !self.check(TokenType::Identifier(String::new())) {
-            return Err(format!("Expected function name, got {:?}", self.peek().kind));
+            return Err(self.error(&format!("Expected function name, got {:?}", self.peek().kind)));
         }
         let name_token = self.advance();
         let name = match &name_token.kind {
             TokenType::Identifier(s) => s.clone(),
-            _ => return Err("Invalid function name".to_string()),
+            _ => return Err(self.error("Invalid function name")),
         };
 
         // Parse type parameters if present
-        let type_params = if self.match_token(TokenType::LessThan) {
-            let mut params = Vec::new();
-            params.push(self.parse_type_param()?);
-            while self.match_token(TokenType::Comma) {
-                params.push(self.parse_type_param()?);
-            }
-            self.consume(TokenType::GreaterThan, "Expected '>' after type parameters")?;
-            params
-        } else {
-            Vec::new()
-        };
+        let mut bounds = Vec::new();
+        let type_params = self.parse_type_params(&mut bounds)?;
 
         self.consume(TokenType::LeftParen, "Expected '(' after function name")?;
         let mut params = Vec::new();
@@ -350,7 +1033,9 @@ impl Parser {
         } else {
             None
         };
+        self.parse_where_clause(&type_params, &mut bounds)?;
 
+        let body_start = self.current;
         let body = self.parse_block()?;
         Ok(Function {
             name,
@@ -358,44 +1043,58 @@ impl Parser {
             return_type,
             body,
             type_params,
+            bounds,
+            public: false,
+            doc: self.item_doc(keyword, body_start),
+            span: self.span_from(start),
         })
     }
 
-    fn parse_actor(&mut self) -> Result<Actor, String> {
+    fn parse_actor(&mut self) -> ParseResult<Actor> {
+        let keyword = self.current - 1;
+        let start = self.previous().span;
         let name_token = self.consume(TokenType::Identifier(String::new()), "Expected actor name")?;
         let name = match &name_token.kind {
             TokenType::Identifier(s) => s.clone(),
-            _ => return Err("Invalid actor name".to_string()),
+            _ => return Err(self.error("Invalid actor name")),
         };
 
-        let body = self.parse_block()?;
+        let body_start = self.current;
+        self.consume(TokenType::LeftBrace, "Expected '{' after actor name")?;
         let mut methods = Vec::new();
-        for _stmt in body {
-            return Err("Actor parsing not fully implemented in v0.8 parser".to_string());
+        while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+            if self.match_token(TokenType::Fn) {
+                methods.push(self.parse_function()?);
+            } else {
+                return Err(self.error("Expected 'fn' in actor"));
+            }
         }
+        self.consume(TokenType::RightBrace, "Expected '}' after actor body")?;
 
-        Ok(Actor { name, methods })
+        Ok(Actor {
+            name,
+            methods,
+            doc: self.item_doc(keyword, body_start),
+            span: self.span_from(start),
+        })
     }
 
-    fn parse_trait(&mut self) -> Result<Trait, String> {
+    fn parse_trait(&mut self) -> ParseResult<Trait> {
+        let keyword = self.current - 1;
+        let start = self.previous().span;
         let name_token = self.consume(TokenType::Identifier(String::new()), "Expected trait name")?;
         let name = match &name_token.kind {
             TokenType::Identifier(s) => s.clone(),
-            _ => return Err("Invalid trait name".to_string()),
+            _ => return Err(self.error("Invalid trait name")),
         };
 
-        let type_params = if self.match_token(TokenType::LessThan) {
-            let mut params = Vec::new();
-            params.push(self.parse_type_param()?);
-            while self.match_token(TokenType::Comma) {
-                params.push(self.parse_type_param()?);
-            }
-            self.consume(TokenType::GreaterThan, "Expected '>' after type parameters")?;
-            params
-        } else {
-            Vec::new()
-        };
+        let mut bounds = Vec::new();
+        let type_params = self.parse_type_params(&mut bounds)?;
+        if !bounds.is_empty() {
+            return Err(self.error("Type parameters of a trait cannot have bounds"));
+        }
 
+        let body_start = self.current;
         self.consume(TokenType::LeftBrace, "Expected '{' after trait name")?;
         let mut methods = Vec::new();
 
@@ -404,7 +1103,7 @@ impl Parser {
                 let method = self.parse_trait_method()?;
                 methods.push(method);
             } else {
-                return Err("Expected 'fn' in trait".to_string());
+                return Err(self.error("Expected 'fn' in trait"));
             }
         }
 
@@ -414,14 +1113,18 @@ impl Parser {
             name,
             methods,
             type_params,
+            public: false,
+            doc: self.item_doc(keyword, body_start),
+            span: self.span_from(start),
         })
     }
 
-    fn parse_trait_method(&mut self) -> Result<TraitMethod, String> {
+    fn parse_trait_method(&mut self) -> ParseResult<TraitMethod> {
+        let start = self.previous().span;
         let name_token = self.consume(TokenType::Identifier(String::new()), "Expected method name")?;
         let name = match &name_token.kind {
             TokenType::Identifier(s) => s.clone(),
-            _ => return Err("Invalid method name".to_string()),
+            _ => return Err(self.error("Invalid method name")),
         };
 
         self.consume(TokenType::LeftParen, "Expected '(' after method name")?;
@@ -446,28 +1149,24 @@ impl Parser {
             name,
             params,
             return_type,
+            span: self.span_from(start),
         })
     }
 
-    fn parse_type_def(&mut self) -> Result<TypeDef, String> {
+    fn parse_type_def(&mut self) -> ParseResult<TypeDef> {
+        let keyword = self.current - 1;
+        let start = self.previous().span;
         let name_token = self.consume(TokenType::Identifier(String::new()), "Expected type name")?;
         let name = match &name_token.kind {
             TokenType::Identifier(s) => s.clone(),
-            _ => return Err("Invalid type name".to_string()),
+            _ => return Err(self.error("Invalid type name")),
         };
 
-        let type_params = if self.match_token(TokenType::LessThan) {
-            let mut params = Vec::new();
-            params.push(self.parse_type_param()?);
-            while self.match_token(TokenType::Comma) {
-                params.push(self.parse_type_param()?);
-            }
-            self.consume(TokenType::GreaterThan, "Expected '>' after type parameters")?;
-            params
-        } else {
-            Vec::new()
-        };
+        let mut bounds = Vec::new();
+        let type_params = self.parse_type_params(&mut bounds)?;
+        self.parse_where_clause(&type_params, &mut bounds)?;
 
+        let body_start = self.current;
         self.consume(TokenType::LeftBrace, "Expected '{' after type name")?;
         let mut fields = Vec::new();
 
@@ -475,7 +1174,7 @@ impl Parser {
             let field_name_token = self.consume(TokenType::Identifier(String::new()), "Expected field name")?;
             let field_name = match &field_name_token.kind {
                 TokenType::Identifier(s) => s.clone(),
-                _ => return Err("Invalid field name".to_string()),
+                _ => return Err(self.error("Invalid field name")),
             };
 
             self.consume(TokenType::Colon, "Expected ':' after field name")?;
@@ -494,14 +1193,88 @@ impl Parser {
             name,
             fields,
             type_params,
+            bounds,
+            public: false,
+            doc: self.item_doc(keyword, body_start),
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_enum(&mut self) -> ParseResult<EnumDef> {
+        let keyword = self.current - 1;
+        let start = self.previous().span;
+        let name = self.parse_identifier("Expected enum name")?;
+
+        let body_start = self.current;
+        self.consume(TokenType::LeftBrace, "Expected '{' after enum name")?;
+        let mut variants = Vec::new();
+
+        while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+            let variant_start = self.peek().span;
+            let variant_name = self.parse_identifier("Expected variant name")?;
+            let payload = if self.match_token(TokenType::LeftParen) {
+                let mut types = Vec::new();
+                if !self.check(TokenType::RightParen) {
+                    types.push(self.parse_type()?);
+                    while self.match_token(TokenType::Comma) {
+                        types.push(self.parse_type()?);
+                    }
+                }
+                self.consume(TokenType::RightParen, "Expected ')' after variant types")?;
+                VariantPayload::Tuple(types)
+            } else if self.match_token(TokenType::LeftBrace) {
+                let mut fields = Vec::new();
+                while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+                    fields.push(self.parse_param()?);
+                    if !self.check(TokenType::RightBrace) {
+                        self.consume(TokenType::Comma, "Expected ',' after field")?;
+                    }
+                }
+                self.consume(TokenType::RightBrace, "Expected '}' after variant fields")?;
+                VariantPayload::Struct(fields)
+            } else {
+                VariantPayload::Unit
+            };
+
+            variants.push(EnumVariant {
+                name: variant_name,
+                payload,
+                span: self.span_from(variant_start),
+            });
+
+            if !self.check(TokenType::RightBrace) {
+                self.consume(TokenType::Comma, "Expected ',' after variant")?;
+            }
+        }
+
+        self.consume(TokenType::RightBrace, "Expected '}' after enum body")?;
+
+        Ok(EnumDef {
+            name,
+            variants,
+            public: false,
+            doc: self.item_doc(keyword, body_start),
+            span: self.span_from(start),
         })
     }
 
-    fn parse_trait_impl(&mut self) -> Result<TraitImpl, String> {
+    fn parse_identifier(&mut self, message: &str) -> ParseResult<String> {
+        match &self.peek().kind {
+            TokenType::Identifier(s) => {
+                let name = s.clone();
+                self.advance();
+                Ok(name)
+            }
+            _ => Err(self.error(message)),
+        }
+    }
+
+    fn parse_trait_impl(&mut self) -> ParseResult<TraitImpl> {
+        let start = self.previous().span;
         let trait_name_token = self.consume(TokenType::Identifier(String::new()), "Expected trait name")?;
         let trait_name = match &trait_name_token.kind {
             TokenType::Identifier(s) => s.clone(),
-            _ => return Err("Invalid trait name".to_string()),
+            _ => return Err(self.error("Invalid trait name")),
         };
 
         let type_args = if self.match_token(TokenType::LessThan) {
@@ -516,16 +1289,12 @@ impl Parser {
             Vec::new()
         };
 
-        if let TokenType::Identifier(s) = &self.peek().kind {
-            if s == "for" {
-                self.advance();
-            }
-        }
+        self.match_token(TokenType::For);
 
         let type_name_token = self.consume(TokenType::Identifier(String::new()), "Expected type name")?;
         let type_name = match &type_name_token.kind {
             TokenType::Identifier(s) => s.clone(),
-            _ => return Err("Invalid type name".to_string()),
+            _ => return Err(self.error("Invalid type name")),
         };
 
         self.consume(TokenType::LeftBrace, "Expected '{' after impl header")?;
@@ -536,7 +1305,7 @@ impl Parser {
                 let method = self.parse_function()?;
                 methods.push(method);
             } else {
-                return Err("Expected 'fn' in impl block".to_string());
+                return Err(self.error("Expected 'fn' in impl block"));
             }
         }
 
@@ -547,22 +1316,77 @@ impl Parser {
             type_name,
             methods,
             type_args,
+            span: self.span_from(start),
         })
     }
 
-    fn parse_type_param(&mut self) -> Result<String, String> {
+    /// Type parameters in `<...>`, if given, adding the traits each must
+    /// implement (`T: Ord + Clone`) to `bounds`
+    fn parse_type_params(&mut self, bounds: &mut Vec<(String, String)>) -> ParseResult<Vec<String>> {
+        if !self.match_token(TokenType::LessThan) {
+            return Ok(Vec::new());
+        }
+        let mut params = vec![self.parse_type_param(bounds)?];
+        while self.match_token(TokenType::Comma) {
+            params.push(self.parse_type_param(bounds)?);
+        }
+        self.consume(TokenType::GreaterThan, "Expected '>' after type parameters")?;
+        Ok(params)
+    }
+
+    fn parse_type_param(&mut self, bounds: &mut Vec<(String, String)>) -> ParseResult<String> {
         let token = self.consume(TokenType::Identifier(String::new()), "Expected type parameter")?;
-        match &token.kind {
-            TokenType::Identifier(s) => Ok(s.clone()),
-            _ => Err("Invalid type parameter".to_string()),
+        let name = match &token.kind {
+            TokenType::Identifier(s) => s.clone(),
+            _ => return Err(self.error("Invalid type parameter")),
+        };
+        if self.match_token(TokenType::Colon) {
+            self.parse_bounds(&name, bounds)?;
+        }
+        Ok(name)
+    }
+
+    /// `A + B`: the traits type parameter `param` must implement
+    fn parse_bounds(&mut self, param: &str, bounds: &mut Vec<(String, String)>) -> ParseResult<()> {
+        loop {
+            let mut trait_name = self.parse_identifier("Expected trait name in bound")?;
+            while self.match_token(TokenType::ColonColon) {
+                let segment = self.parse_identifier("Expected trait name after '::'")?;
+                trait_name = format!("{}::{}", trait_name, segment);
+            }
+            bounds.push((param.to_string(), trait_name));
+            if !self.match_token(TokenType::Plus) {
+                return Ok(());
+            }
+        }
+    }
+
+    /// `where T: A + B, U: C` before a body, which bounds the type
+    /// parameters as `<T: A + B, U: C>` would. `where` is contextual, like
+    /// `dyn`.
+    fn parse_where_clause(&mut self, type_params: &[String], bounds: &mut Vec<(String, String)>) -> ParseResult<()> {
+        if !matches!(&self.peek().kind, TokenType::Identifier(s) if s == "where") {
+            return Ok(());
+        }
+        self.advance();
+        loop {
+            let param = self.parse_identifier("Expected type parameter in 'where' clause")?;
+            if !type_params.contains(&param) {
+                return Err(self.error(&format!("'{}' in 'where' clause is not a type parameter", param)));
+            }
+            self.consume(TokenType::Colon, "Expected ':' after type parameter in 'where' clause")?;
+            self.parse_bounds(&param, bounds)?;
+            if !self.match_token(TokenType::Comma) || self.check(TokenType::LeftBrace) {
+                return Ok(());
+            }
         }
     }
 
-    fn parse_param(&mut self) -> Result<(String, Type), String> {
+    fn parse_param(&mut self) -> ParseResult<(String, Type)> {
         let name_token = self.consume(TokenType::Identifier(String::new()), "Expected parameter name")?;
         let name = match &name_token.kind {
             TokenType::Identifier(s) => s.clone(),
-            _ => return Err("Invalid parameter name".to_string()),
+            _ => return Err(self.error("Invalid parameter name")),
         };
 
         self.consume(TokenType::Colon, "Expected ':' after parameter name")?;
@@ -570,8 +1394,25 @@ impl Parser {
         Ok((name, param_type))
     }
 
-    fn parse_type(&mut self) -> Result<Type, String> {
-        if self.match_token(TokenType::Fn) {
+    fn parse_type(&mut self) -> ParseResult<Type> {
+        if self.match_token(TokenType::Ampersand) {
+            Ok(Type::Ref(Box::new(self.parse_type()?)))
+        } else if self.match_token(TokenType::LeftBracket) {
+            // Array `[T; N]` or slice `[T]`
+            let element = Box::new(self.parse_type()?);
+            let ty = if self.match_token(TokenType::Semicolon) {
+                let len = match self.peek().kind {
+                    TokenType::IntegerLiteral(n, None) => n as usize,
+                    _ => return Err(self.error("Expected array length after ';'")),
+                };
+                self.advance();
+                Type::Array(element, len)
+            } else {
+                Type::Slice(element)
+            };
+            self.consume(TokenType::RightBracket, "Expected ']' after array type")?;
+            Ok(ty)
+        } else if self.match_token(TokenType::Fn) {
             // Function pointer: fn(T1, T2) -> R
             self.consume(TokenType::LeftParen, "Expected '(' after 'fn'")?;
             let mut params = Vec::new();
@@ -588,8 +1429,20 @@ impl Parser {
                 None
             };
             Ok(Type::FunctionPointer { params, return_type })
+        } else if self.match_token(TokenType::Char) {
+            Ok(Type::Char)
         } else if self.match_token(TokenType::I32) {
             Ok(Type::I32)
+        } else if self.match_token(TokenType::I64) {
+            Ok(Type::I64)
+        } else if self.match_token(TokenType::U8) {
+            Ok(Type::U8)
+        } else if self.match_token(TokenType::U64) {
+            Ok(Type::U64)
+        } else if self.match_token(TokenType::F32) {
+            Ok(Type::F32)
+        } else if self.match_token(TokenType::F64) {
+            Ok(Type::F64)
         } else if self.match_token(TokenType::Str) {
             Ok(Type::Str)
         } else if self.match_token(TokenType::Bool) {
@@ -610,10 +1463,24 @@ impl Parser {
             Ok(Type::Result(Box::new(ok_type), Box::new(err_type)))
         } else if self.check(TokenType::Identifier(String::new())) {
             let name_token = self.advance();
-            let name = match &name_token.kind {
+            let mut name = match &name_token.kind {
                 TokenType::Identifier(s) => s.clone(),
-                _ => return Err("Invalid type name".to_string()),
+                _ => return Err(self.error("Invalid type name")),
             };
+            // `dyn` is contextual, like `trait` and `impl`
+            if name == "dyn" && self.check(TokenType::Identifier(String::new())) {
+                let mut trait_name = self.parse_identifier("Expected trait name after 'dyn'")?;
+                while self.match_token(TokenType::ColonColon) {
+                    let segment = self.parse_identifier("Expected trait name after '::'")?;
+                    trait_name = format!("{}::{}", trait_name, segment);
+                }
+                return Ok(Type::Dyn(trait_name));
+            }
+            // A type of another module: `collections::Vec`
+            while self.match_token(TokenType::ColonColon) {
+                let segment = self.parse_identifier("Expected type name after '::'")?;
+                name = format!("{}::{}", name, segment);
+            }
 
             if self.match_token(TokenType::LessThan) {
                 let mut type_args = Vec::new();
@@ -622,162 +1489,229 @@ impl Parser {
                     type_args.push(self.parse_type()?);
                 }
                 self.consume(TokenType::GreaterThan, "Expected '>' after type arguments")?;
+                if name == "Range" && type_args.len() == 1 {
+                    return Ok(Type::Range(Box::new(type_args.remove(0))));
+                }
                 Ok(Type::Generic { name, type_args })
             } else {
                 Ok(Type::Custom(name))
             }
         } else {
-            Err(format!(
-                "Expected type, got {:?} at line {}",
-                self.peek().kind,
-                self.peek().line
-            ))
+            Err(self.error(&format!(
+                "Expected type, got {:?}",
+                self.peek().kind
+            )))
         }
     }
 
-    fn parse_block(&mut self) -> Result<Vec<Statement>, String> {
+    fn parse_block(&mut self) -> ParseResult<Vec<Statement>> {
         self.consume(TokenType::LeftBrace, "Expected '{'")?;
         let mut statements = Vec::new();
-        while !self.check(TokenType::RightBrace) && !self.is_at_end() {
-            statements.push(self.parse_statement()?);
+        while !self.check(TokenType::RightBrace)
+            && !self.is_at_end()
+            && !self.at_declaration_keyword()
+        {
+            let start = self.peek().span;
+            match self.parse_statement() {
+                Ok(stmt) => statements.push(stmt),
+                Err(diag) => {
+                    self.diagnostics.add(*diag);
+                    self.synchronize_statement();
+                    statements.push(Statement::new(StatementKind::Error, self.span_from(start)));
+                }
+            }
+        }
+        if !self.match_token(TokenType::RightBrace) {
+            // Unterminated block: report it but keep the statements parsed so far
+            let diag = self.error("Expected '}'");
+            self.diagnostics.add(*diag);
         }
-        self.consume(TokenType::RightBrace, "Expected '}'")?;
         Ok(statements)
     }
 
-    fn parse_statement(&mut self) -> Result<Statement, String> {
-        if self.match_token(TokenType::Unsafe) {
+    fn parse_statement(&mut self) -> ParseResult<Statement> {
+        let start = self.peek().span;
+        let kind = if self.match_token(TokenType::Unsafe) {
             let body = self.parse_block()?;
-            Ok(Statement::Unsafe { body })
+            StatementKind::Unsafe { body }
         } else if self.match_token(TokenType::If) {
-            let condition = self.parse_expression()?;
+            let condition = self.parse_condition()?;
             let then_branch = self.parse_block()?;
             let else_branch = if self.match_token(TokenType::Else) {
                 Some(self.parse_block()?)
             } else {
                 None
             };
-            Ok(Statement::If {
+            StatementKind::If {
                 condition,
                 then_branch,
                 else_branch,
-            })
+            }
         } else if self.match_token(TokenType::While) {
-            let condition = self.parse_expression()?;
+            let condition = self.parse_condition()?;
             let body = self.parse_block()?;
-            Ok(Statement::While { condition, body })
+            StatementKind::While { condition, body }
         } else if self.match_token(TokenType::For) {
             let var_token = self.consume(TokenType::Identifier(String::new()), "Expected variable name in for loop")?;
             let variable = match &var_token.kind {
                 TokenType::Identifier(s) => s.clone(),
-                _ => return Err("Invalid variable name".to_string()),
+                _ => return Err(self.error("Invalid variable name")),
             };
             self.consume(TokenType::In, "Expected 'in' in for loop")?;
-            let iterable = self.parse_expression()?;
+            let iterable = self.parse_condition()?;
             let body = self.parse_block()?;
-            Ok(Statement::For { variable, iterable, body })
+            StatementKind::For { variable, iterable, body }
         } else if self.match_token(TokenType::Break) {
             self.consume(TokenType::Semicolon, "Expected ';' after break")?;
-            Ok(Statement::Break)
+            StatementKind::Break
         } else if self.match_token(TokenType::Continue) {
             self.consume(TokenType::Semicolon, "Expected ';' after continue")?;
-            Ok(Statement::Continue)
+            StatementKind::Continue
         } else if self.match_token(TokenType::Let) {
-            let name_token = self.consume(TokenType::Identifier(String::new()), "Expected variable name after 'let'")?;
-            let name = match &name_token.kind {
-                TokenType::Identifier(s) => s.clone(),
-                _ => return Err("Invalid variable name".to_string()),
-            };
-            self.consume(TokenType::Equal, "Expected '=' after variable name")?;
-            let value = self.parse_expression()?;
-            self.consume(TokenType::Semicolon, "Expected ';' after let statement")?;
-            Ok(Statement::Let {
-                name,
-                mutable: false,
-                value,
-            })
+            self.parse_let(false)?
         } else if self.match_token(TokenType::Var) {
-            let name_token = self.consume(TokenType::Identifier(String::new()), "Expected variable name after 'var'")?;
-            let name = match &name_token.kind {
-                TokenType::Identifier(s) => s.clone(),
-                _ => return Err("Invalid variable name".to_string()),
-            };
-            self.consume(TokenType::Equal, "Expected '=' after variable name")?;
-            let value = self.parse_expression()?;
-            self.consume(TokenType::Semicolon, "Expected ';' after var statement")?;
-            Ok(Statement::Let {
-                name,
-                mutable: true,
-                value,
-            })
+            self.parse_let(true)?
         } else if self.match_token(TokenType::Return) {
             let expr = self.parse_expression()?;
             self.consume(TokenType::Semicolon, "Expected ';' after return")?;
-            Ok(Statement::Return(expr))
+            StatementKind::Return(expr)
         } else {
             let expr = self.parse_expression()?;
-            self.consume(TokenType::Semicolon, "Expected ';' after expression")?;
-            Ok(Statement::Expr(expr))
-        }
+            // Like a block, a `match` statement needs no `;`
+            if matches!(expr.kind, ExpressionKind::Match { .. }) {
+                self.match_token(TokenType::Semicolon);
+            } else {
+                self.consume(TokenType::Semicolon, "Expected ';' after expression")?;
+            }
+            StatementKind::Expr(expr)
+        };
+        Ok(Statement::new(kind, self.span_from(start)))
     }
 
-    fn parse_expression(&mut self) -> Result<Expression, String> {
+    /// Parse the rest of `let name[: T] [= value];`, or of `var ...`; the
+    /// value may only be left out after a type
+    fn parse_let(&mut self, mutable: bool) -> ParseResult<StatementKind> {
+        let keyword = if mutable { "var" } else { "let" };
+        let name_token = self.consume(
+            TokenType::Identifier(String::new()),
+            &format!("Expected variable name after '{}'", keyword),
+        )?;
+        let name = match &name_token.kind {
+            TokenType::Identifier(s) => s.clone(),
+            _ => return Err(self.error("Invalid variable name")),
+        };
+        let ty = if self.match_token(TokenType::Colon) {
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+        let value = if ty.is_none() || self.check(TokenType::Equal) {
+            self.consume(TokenType::Equal, "Expected '=' after variable name")?;
+            Some(self.parse_expression()?)
+        } else {
+            None
+        };
+        self.consume(TokenType::Semicolon, &format!("Expected ';' after {} statement", keyword))?;
+        Ok(StatementKind::Let { name, mutable, ty, value })
+    }
+
+    fn parse_expression(&mut self) -> ParseResult<Expression> {
         self.parse_assignment()
     }
 
-    fn parse_assignment(&mut self) -> Result<Expression, String> {
-        let expr = self.parse_or()?;
+    /// Parse an expression followed by a `{ ... }` body
+    fn parse_condition(&mut self) -> ParseResult<Expression> {
+        let outer = std::mem::replace(&mut self.no_struct_literal, true);
+        let result = self.parse_expression();
+        self.no_struct_literal = outer;
+        result
+    }
+
+    fn parse_assignment(&mut self) -> ParseResult<Expression> {
+        let expr = self.parse_range()?;
 
         if self.match_token(TokenType::Equal) {
-            if let Expression::Identifier(target) = expr {
+            if expr.root_variable().is_some() {
                 let value = self.parse_assignment()?;
-                return Ok(Expression::Assignment {
-                    target,
-                    value: Box::new(value),
-                });
+                let span = expr.span.to(value.span);
+                return Ok(Expression::new(
+                    ExpressionKind::Assignment {
+                        target: Box::new(expr),
+                        value: Box::new(value),
+                    },
+                    span,
+                ));
             } else {
-                return Err("Invalid assignment target".to_string());
+                return Err(self.error("Invalid assignment target"));
             }
         }
 
         Ok(expr)
     }
 
-    fn parse_or(&mut self) -> Result<Expression, String> {
+    /// Build a binary node spanning both operands
+    fn binary(left: Expression, operator: BinaryOp, right: Expression) -> Expression {
+        let span = left.span.to(right.span);
+        Expression::new(
+            ExpressionKind::Binary {
+                left: Box::new(left),
+                operator,
+                right: Box::new(right),
+            },
+            span,
+        )
+    }
+
+    /// Whether the next tokens are the `..` of a range
+    fn at_range(&self) -> bool {
+        self.check(TokenType::Dot) && self.peek_kind(1) == Some(&TokenType::Dot)
+    }
+
+    /// `a..b` and `a..=b` bind more loosely than any binary operator
+    fn parse_range(&mut self) -> ParseResult<Expression> {
+        let start = self.parse_or()?;
+        if !self.at_range() {
+            return Ok(start);
+        }
+        self.advance();
+        self.advance();
+        let inclusive = self.match_token(TokenType::Equal);
+        let end = self.parse_or()?;
+        let span = start.span.to(end.span);
+        Ok(Expression::new(
+            ExpressionKind::Range {
+                start: Box::new(start),
+                end: Box::new(end),
+                inclusive,
+                step: None,
+            },
+            span,
+        ))
+    }
+
+    fn parse_or(&mut self) -> ParseResult<Expression> {
         let mut expr = self.parse_and()?;
         while self.match_token(TokenType::Or) {
             let right = self.parse_and()?;
-            expr = Expression::Binary {
-                left: Box::new(expr),
-                operator: BinaryOp::Or,
-                right: Box::new(right),
-            };
+            expr = Self::binary(expr, BinaryOp::Or, right);
         }
         Ok(expr)
     }
 
-    fn parse_and(&mut self) -> Result<Expression, String> {
+    fn parse_and(&mut self) -> ParseResult<Expression> {
         let mut expr = self.parse_equality()?;
         while self.match_token(TokenType::And) {
             let right = self.parse_equality()?;
-            expr = Expression::Binary {
-                left: Box::new(expr),
-                operator: BinaryOp::And,
-                right: Box::new(right),
-            };
+            expr = Self::binary(expr, BinaryOp::And, right);
         }
         Ok(expr)
     }
 
-    fn parse_equality(&mut self) -> Result<Expression, String> {
+    fn parse_equality(&mut self) -> ParseResult<Expression> {
         let mut expr = self.parse_comparison()?;
         while let Some(op) = self.match_equality_op() {
             let right = self.parse_comparison()?;
-            expr = Expression::Binary {
-                left: Box::new(expr),
-                operator: op,
-                right: Box::new(right),
-            };
+            expr = Self::binary(expr, op, right);
         }
         Ok(expr)
     }
@@ -792,15 +1726,11 @@ impl Parser {
         }
     }
 
-    fn parse_comparison(&mut self) -> Result<Expression, String> {
+    fn parse_comparison(&mut self) -> ParseResult<Expression> {
         let mut expr = self.parse_addition()?;
         while let Some(op) = self.match_comparison_op() {
             let right = self.parse_addition()?;
-            expr = Expression::Binary {
-                left: Box::new(expr),
-                operator: op,
-                right: Box::new(right),
-            };
+            expr = Self::binary(expr, op, right);
         }
         Ok(expr)
     }
@@ -819,15 +1749,11 @@ impl Parser {
         }
     }
 
-    fn parse_addition(&mut self) -> Result<Expression, String> {
+    fn parse_addition(&mut self) -> ParseResult<Expression> {
         let mut expr = self.parse_multiplication()?;
         while let Some(op) = self.match_addition_op() {
             let right = self.parse_multiplication()?;
-            expr = Expression::Binary {
-                left: Box::new(expr),
-                operator: op,
-                right: Box::new(right),
-            };
+            expr = Self::binary(expr, op, right);
         }
         Ok(expr)
     }
@@ -842,15 +1768,11 @@ impl Parser {
         }
     }
 
-    fn parse_multiplication(&mut self) -> Result<Expression, String> {
+    fn parse_multiplication(&mut self) -> ParseResult<Expression> {
         let mut expr = self.parse_unary()?;
         while let Some(op) = self.match_multiplication_op() {
             let right = self.parse_unary()?;
-            expr = Expression::Binary {
-                left: Box::new(expr),
-                operator: op,
-                right: Box::new(right),
-            };
+            expr = Self::binary(expr, op, right);
         }
         Ok(expr)
     }
@@ -867,34 +1789,39 @@ impl Parser {
         }
     }
 
-    fn parse_unary(&mut self) -> Result<Expression, String> {
-        if self.match_token(TokenType::Not) {
-            let operand = self.parse_unary()?;
-            return Ok(Expression::Unary {
-                operator: UnaryOp::Not,
-                operand: Box::new(operand),
-            });
-        }
+    fn parse_unary(&mut self) -> ParseResult<Expression> {
+        let start = self.peek().span;
+        let operator = if self.match_token(TokenType::Not) {
+            UnaryOp::Not
+        } else if self.match_token(TokenType::Minus) {
+            UnaryOp::Negate
+        } else if self.match_token(TokenType::Ampersand) {
+            UnaryOp::Borrow
+        } else if self.match_token(TokenType::Star) {
+            UnaryOp::Deref
+        } else {
+            return self.parse_call();
+        };
 
-        if self.match_token(TokenType::Minus) {
-            let operand = self.parse_unary()?;
-            return Ok(Expression::Unary {
-                operator: UnaryOp::Negate,
+        let operand = self.parse_unary()?;
+        let span = start.to(operand.span);
+        Ok(Expression::new(
+            ExpressionKind::Unary {
+                operator,
                 operand: Box::new(operand),
-            });
-        }
-
-        self.parse_call()
+            },
+            span,
+        ))
     }
 
-    fn parse_call(&mut self) -> Result<Expression, String> {
+    fn parse_call(&mut self) -> ParseResult<Expression> {
         let mut expr = self.parse_primary()?;
 
         loop {
             if self.match_token(TokenType::LeftParen) {
-                let name = match expr {
-                    Expression::Identifier(ref n) => n.clone(),
-                    _ => return Err("Can only call functions".to_string()),
+                let name = match expr.kind {
+                    ExpressionKind::Identifier(ref n) => n.clone(),
+                    _ => return Err(self.error("Can only call functions")),
                 };
 
                 let mut arguments = Vec::new();
@@ -906,41 +1833,70 @@ impl Parser {
                 }
                 self.consume(TokenType::RightParen, "Expected ')' after arguments")?;
 
-                expr = Expression::FunctionCall { name, arguments };
-            } else if self.match_token(TokenType::Dot) {
-                let method_token = self.consume(TokenType::Identifier(String::new()), "Expected method name")?;
-                let method = match &method_token.kind {
-                    TokenType::Identifier(s) => s.clone(),
-                    _ => return Err("Invalid method name".to_string()),
-                };
-
-                let receiver = match expr {
-                    Expression::Identifier(ref n) => n.clone(),
-                    _ => return Err("Can only call methods on identifiers".to_string()),
-                };
+                expr = Expression::new(
+                    ExpressionKind::FunctionCall { name, arguments },
+                    self.span_from(expr.span),
+                );
+            } else if self.match_token(TokenType::LeftBracket) {
+                // Brackets lift the struct literal restriction, like parentheses
+                let outer = std::mem::replace(&mut self.no_struct_literal, false);
+                let index = self.parse_expression();
+                self.no_struct_literal = outer;
+                let index = index?;
+                self.consume(TokenType::RightBracket, "Expected ']' after index")?;
+                let span = self.span_from(expr.span);
+                expr = Expression::new(
+                    ExpressionKind::Index {
+                        object: Box::new(expr),
+                        index: Box::new(index),
+                    },
+                    span,
+                );
+            } else if self.check(TokenType::Dot) && !self.at_range() {
+                self.advance();
+                let member = self.parse_identifier("Expected field or method name after '.'")?;
+                // `(a..b).step_by(n)` is part of the range, not a method call
+                let unstepped = matches!(expr.kind, ExpressionKind::Range { step: None, .. });
+                if member == "step_by" && unstepped && self.match_token(TokenType::LeftParen) {
+                    let value = self.parse_expression()?;
+                    self.consume(TokenType::RightParen, "Expected ')' after step")?;
+                    if let ExpressionKind::Range { step, .. } = &mut expr.kind {
+                        *step = Some(Box::new(value));
+                    }
+                    expr.span = self.span_from(expr.span);
+                    continue;
+                }
+                if !self.match_token(TokenType::LeftParen) {
+                    let span = self.span_from(expr.span);
+                    expr = Expression::new(
+                        ExpressionKind::FieldAccess {
+                            object: Box::new(expr),
+                            field: member,
+                        },
+                        span,
+                    );
+                    continue;
+                }
+                let method = member;
 
-                if self.match_token(TokenType::LeftParen) {
-                    let mut arguments = Vec::new();
-                    if !self.check(TokenType::RightParen) {
+                let mut arguments = Vec::new();
+                if !self.check(TokenType::RightParen) {
+                    arguments.push(self.parse_expression()?);
+                    while self.match_token(TokenType::Comma) {
                         arguments.push(self.parse_expression()?);
-                        while self.match_token(TokenType::Comma) {
-                            arguments.push(self.parse_expression()?);
-                        }
                     }
-                    self.consume(TokenType::RightParen, "Expected ')' after method arguments")?;
+                }
+                self.consume(TokenType::RightParen, "Expected ')' after method arguments")?;
 
-                    expr = Expression::MethodCall {
-                        receiver,
+                let span = self.span_from(expr.span);
+                expr = Expression::new(
+                    ExpressionKind::MethodCall {
+                        receiver: Box::new(expr),
                         method,
                         arguments,
-                    };
-                } else {
-                    expr = Expression::MethodCall {
-                        receiver,
-                        method,
-                        arguments: Vec::new(),
-                    };
-                }
+                    },
+                    span,
+                );
             } else {
                 break;
             }
@@ -949,47 +1905,491 @@ impl Parser {
         Ok(expr)
     }
 
-    fn parse_primary(&mut self) -> Result<Expression, String> {
+    fn parse_primary(&mut self) -> ParseResult<Expression> {
+        let start = self.peek().span;
         if self.match_token(TokenType::LeftParen) {
-            let expr = self.parse_expression()?;
+            // Parentheses lift the struct literal restriction
+            let outer = std::mem::replace(&mut self.no_struct_literal, false);
+            let inner = self.parse_parenthesized();
+            self.no_struct_literal = outer;
+            let kind = inner?;
             self.consume(TokenType::RightParen, "Expected ')' after expression")?;
-            return Ok(expr);
+            return Ok(Expression::new(kind, self.span_from(start)));
         }
 
-        match &self.peek().kind {
-            TokenType::IntegerLiteral(n) => {
-                let n = *n;
+        if self.match_token(TokenType::Match) {
+            return self.parse_match(start);
+        }
+
+        if self.at_closure() {
+            return self.parse_closure(start);
+        }
+
+        if self.match_token(TokenType::LeftBracket) {
+            let outer = std::mem::replace(&mut self.no_struct_literal, false);
+            let elements = self.parse_array_elements();
+            self.no_struct_literal = outer;
+            let elements = elements?;
+            return Ok(Expression::new(ExpressionKind::ArrayLiteral(elements), self.span_from(start)));
+        }
+
+        if let TokenType::Identifier(enum_name) = &self.peek().kind {
+            if self.peek_kind(1) == Some(&TokenType::ColonColon) {
+                let enum_name = enum_name.clone();
+                self.advance();
+                self.advance();
+                let enum_name = self.parse_path_prefix(enum_name);
+                return self.parse_enum_variant(enum_name, start);
+            }
+            if !self.no_struct_literal {
+                if let Some(literal) = self.parse_struct_literal(start)? {
+                    return Ok(literal);
+                }
+            }
+        }
+
+        let kind = match &self.peek().kind {
+            TokenType::IntegerLiteral(n, suffix) => {
+                ExpressionKind::Literal(Literal::Integer(*n, suffix.as_ref().map(suffix_type)))
+            }
+            TokenType::FloatLiteral(x, suffix) => {
+                ExpressionKind::Literal(Literal::Float(*x, suffix.as_ref().map(suffix_type)))
+            }
+            TokenType::StringLiteral(s) => ExpressionKind::Literal(Literal::String(s.clone())),
+            TokenType::CharLiteral(c) => ExpressionKind::Literal(Literal::Char(*c)),
+            TokenType::Identifier(name) if name == "true" || name == "false" => {
+                ExpressionKind::Literal(Literal::Boolean(name == "true"))
+            }
+            TokenType::Identifier(name) => ExpressionKind::Identifier(name.clone()),
+            _ => {
+                return Err(self.error(&format!(
+                    "Unexpected token: {:?}",
+                    self.peek().kind
+                )))
+            }
+        };
+        self.advance();
+        Ok(Expression::new(kind, start))
+    }
+
+    /// Extend the path `first`, whose `::` was just consumed, by every
+    /// further `segment::`, leaving the final segment: `a::b::` gives `a::b`
+    fn parse_path_prefix(&mut self, first: String) -> String {
+        let mut path = first;
+        while let (Some(TokenType::Identifier(segment)), Some(TokenType::ColonColon)) =
+            (self.peek_kind(0), self.peek_kind(1))
+        {
+            path = format!("{}::{}", path, segment);
+            self.advance();
+            self.advance();
+        }
+        path
+    }
+
+    /// Kind of the token `offset` places after the current one
+    fn peek_kind(&self, offset: usize) -> Option<&TokenType> {
+        self.tokens.get(self.current + offset).map(|t| &t.kind)
+    }
+
+    /// Parse `Name { ... }` or `Name<T> { ... }` if that is what follows the
+    /// current identifier. Otherwise nothing is consumed: `a < b` is a
+    /// comparison and `x { ... }` is left for the caller.
+    fn parse_struct_literal(&mut self, start: Span) -> ParseResult<Option<Expression>> {
+        let checkpoint = self.current;
+        let name = self.parse_identifier("Expected struct name")?;
+        let mut type_args = Vec::new();
+        if self.match_token(TokenType::LessThan) {
+            let args = self.parse_type().and_then(|first| {
+                type_args.push(first);
+                while self.match_token(TokenType::Comma) {
+                    type_args.push(self.parse_type()?);
+                }
+                self.consume(TokenType::GreaterThan, "Expected '>' after type arguments")?;
+                Ok(())
+            });
+            if args.is_err() {
+                self.current = checkpoint;
+                return Ok(None);
+            }
+        }
+
+        // `{ }`, `{ field: ...`, `{ field, ...` or `{ field }`
+        let opens_fields = self.check(TokenType::LeftBrace)
+            && match self.peek_kind(1) {
+                Some(TokenType::RightBrace) => true,
+                Some(TokenType::Identifier(_)) => matches!(
+                    self.peek_kind(2),
+                    Some(TokenType::Colon | TokenType::Comma | TokenType::RightBrace)
+                ),
+                _ => false,
+            };
+        if !opens_fields {
+            self.current = checkpoint;
+            return Ok(None);
+        }
+        self.advance();
+        let fields = self.parse_field_values("Expected '}' after struct fields")?;
+        Ok(Some(Expression::new(
+            ExpressionKind::StructLiteral {
+                name,
+                type_args,
+                fields,
+            },
+            self.span_from(start),
+        )))
+    }
+
+    /// Parse `field: value, ...` up to and including the closing `}`
+    fn parse_field_values(&mut self, closing: &str) -> ParseResult<Vec<(String, Expression)>> {
+        let mut fields = Vec::new();
+        while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+            let field_span = self.peek().span;
+            let field = self.parse_identifier("Expected field name")?;
+            let value = if self.match_token(TokenType::Colon) {
+                self.parse_expression()?
+            } else {
+                Expression::new(ExpressionKind::Identifier(field.clone()), field_span)
+            };
+            fields.push((field, value));
+            if !self.check(TokenType::RightBrace) {
+                self.consume(TokenType::Comma, "Expected ',' after field")?;
+            }
+        }
+        self.consume(TokenType::RightBrace, closing)?;
+        Ok(fields)
+    }
+
+    /// Elements of an array literal up to and including the closing `]`
+    fn parse_array_elements(&mut self) -> ParseResult<Vec<Expression>> {
+        let mut elements = Vec::new();
+        while !self.check(TokenType::RightBracket) && !self.is_at_end() {
+            elements.push(self.parse_expression()?);
+            if !self.check(TokenType::RightBracket) {
+                self.consume(TokenType::Comma, "Expected ',' between array elements")?;
+            }
+        }
+        self.consume(TokenType::RightBracket, "Expected ']' after array elements")?;
+        Ok(elements)
+    }
+
+    /// Contents of `( ... )`: a grouped expression or a tuple
+    fn parse_parenthesized(&mut self) -> ParseResult<ExpressionKind> {
+        let first = self.parse_expression()?;
+        if !self.check(TokenType::Comma) {
+            return Ok(first.kind);
+        }
+        let mut items = vec![first];
+        while self.match_token(TokenType::Comma) {
+            if self.check(TokenType::RightParen) {
+                break;
+            }
+            items.push(self.parse_expression()?);
+        }
+        Ok(ExpressionKind::Tuple(items))
+    }
+
+    /// Whether a closure starts here: `|`, `||` (no parameters) or `move`
+    fn at_closure(&self) -> bool {
+        self.check(TokenType::Pipe) || self.check(TokenType::Or) || self.check(TokenType::Move)
+    }
+
+    /// Parse `[move] |params| [-> T] body`. Parameters need a type; with a
+    /// return type the body must be a block.
+    fn parse_closure(&mut self, start: Span) -> ParseResult<Expression> {
+        let by_move = self.match_token(TokenType::Move);
+        let mut params = Vec::new();
+        if !self.match_token(TokenType::Or) {
+            self.consume(TokenType::Pipe, "Expected '|' to start closure parameters")?;
+            while !self.check(TokenType::Pipe) && !self.is_at_end() {
+                params.push(self.parse_param()?);
+                if !self.check(TokenType::Pipe) {
+                    self.consume(TokenType::Comma, "Expected ',' between closure parameters")?;
+                }
+            }
+            self.consume(TokenType::Pipe, "Expected '|' after closure parameters")?;
+        }
+        let return_type = if self.match_token(TokenType::Arrow) {
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+
+        let body = if return_type.is_some() || self.check(TokenType::LeftBrace) {
+            let outer = std::mem::replace(&mut self.no_struct_literal, false);
+            let body = self.parse_closure_body();
+            self.no_struct_literal = outer;
+            body?
+        } else {
+            let value = self.parse_expression()?;
+            let span = value.span;
+            vec![Statement::new(StatementKind::Return(value), span)]
+        };
+        Ok(Expression::new(
+            ExpressionKind::Closure {
+                params,
+                return_type,
+                body,
+                by_move,
+            },
+            self.span_from(start),
+        ))
+    }
+
+    /// A closure's `{ ... }` body, whose final expression, if it has no
+    /// `;`, is the closure's value
+    fn parse_closure_body(&mut self) -> ParseResult<Vec<Statement>> {
+        self.consume(TokenType::LeftBrace, "Expected '{' before closure body")?;
+        let mut statements = Vec::new();
+        while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+            let starts_statement = matches!(
+                self.peek().kind,
+                TokenType::Unsafe
+                    | TokenType::If
+                    | TokenType::While
+                    | TokenType::For
+                    | TokenType::Break
+                    | TokenType::Continue
+                    | TokenType::Let
+                    | TokenType::Var
+                    | TokenType::Return
+            );
+            if starts_statement {
+                statements.push(self.parse_statement()?);
+                continue;
+            }
+            let start = self.peek().span;
+            let expr = self.parse_expression()?;
+            // A `match` whose arms are blocks produces no value
+            let has_value = match &expr.kind {
+                ExpressionKind::Match { arms, .. } => {
+                    arms.iter().all(|arm| matches!(arm.body, ArmBody::Expression(_)))
+                }
+                _ => true,
+            };
+            let kind = if has_value && self.check(TokenType::RightBrace) {
+                StatementKind::Return(expr)
+            } else {
+                if matches!(expr.kind, ExpressionKind::Match { .. }) {
+                    self.match_token(TokenType::Semicolon);
+                } else {
+                    self.consume(TokenType::Semicolon, "Expected ';' after expression")?;
+                }
+                StatementKind::Expr(expr)
+            };
+            statements.push(Statement::new(kind, self.span_from(start)));
+        }
+        self.consume(TokenType::RightBrace, "Expected '}' after closure body")?;
+        Ok(statements)
+    }
+
+    /// Parse `match scrutinee { pattern [if guard] => body, ... }` after `match`
+    fn parse_match(&mut self, start: Span) -> ParseResult<Expression> {
+        let scrutinee = self.parse_condition()?;
+        self.consume(TokenType::LeftBrace, "Expected '{' after match scrutinee")?;
+        let mut arms = Vec::new();
+
+        while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+            let arm_start = self.peek().span;
+            let pattern = self.parse_pattern()?;
+            let guard = if self.match_token(TokenType::If) {
+                Some(self.parse_expression()?)
+            } else {
+                None
+            };
+            self.consume(TokenType::FatArrow, "Expected '=>' after match pattern")?;
+
+            let body = if self.check(TokenType::LeftBrace) {
+                let body = ArmBody::Block(self.parse_block()?);
+                self.match_token(TokenType::Comma);
+                body
+            } else {
+                let body = ArmBody::Expression(self.parse_expression()?);
+                if !self.check(TokenType::RightBrace) {
+                    self.consume(TokenType::Comma, "Expected ',' after match arm")?;
+                }
+                body
+            };
+
+            arms.push(MatchArm {
+                pattern,
+                guard,
+                body,
+                span: self.span_from(arm_start),
+            });
+        }
+
+        self.consume(TokenType::RightBrace, "Expected '}' after match arms")?;
+
+        Ok(Expression::new(
+            ExpressionKind::Match {
+                scrutinee: Box::new(scrutinee),
+                arms,
+            },
+            self.span_from(start),
+        ))
+    }
+
+    fn parse_pattern(&mut self) -> ParseResult<Pattern> {
+        let start = self.peek().span;
+        let kind = match self.peek().kind.clone() {
+            TokenType::LeftParen => {
+                self.advance();
+                let mut items = Vec::new();
+                while !self.check(TokenType::RightParen) && !self.is_at_end() {
+                    items.push(self.parse_pattern()?);
+                    if !self.check(TokenType::RightParen) {
+                        self.consume(TokenType::Comma, "Expected ',' between tuple patterns")?;
+                    }
+                }
+                self.consume(TokenType::RightParen, "Expected ')' after tuple pattern")?;
+                PatternKind::Tuple(items)
+            }
+            TokenType::IntegerLiteral(n, suffix) => {
+                self.advance();
+                PatternKind::Literal(Literal::Integer(n, suffix.as_ref().map(suffix_type)))
+            }
+            TokenType::FloatLiteral(x, suffix) => {
                 self.advance();
-                Ok(Expression::Literal(Literal::Integer(n)))
+                PatternKind::Literal(Literal::Float(x, suffix.as_ref().map(suffix_type)))
             }
             TokenType::StringLiteral(s) => {
-                let s = s.clone();
                 self.advance();
-                Ok(Expression::Literal(Literal::String(s)))
+                PatternKind::Literal(Literal::String(s))
+            }
+            TokenType::CharLiteral(c) => {
+                self.advance();
+                PatternKind::Literal(Literal::Char(c))
             }
             TokenType::Identifier(name) => {
-                let name = name.clone();
                 self.advance();
-                Ok(Expression::Identifier(name))
+                if name == "_" {
+                    PatternKind::Wildcard
+                } else if name == "true" || name == "false" {
+                    PatternKind::Literal(Literal::Boolean(name == "true"))
+                } else if self.match_token(TokenType::ColonColon) {
+                    let enum_name = self.parse_path_prefix(name);
+                    let variant = self.parse_identifier("Expected variant name after '::'")?;
+                    let (payload, rest) = self.parse_pattern_payload()?;
+                    PatternKind::Variant {
+                        enum_name: Some(enum_name),
+                        variant,
+                        payload,
+                        rest,
+                    }
+                } else if matches!(name.as_str(), "Some" | "None" | "Ok" | "Err") {
+                    let (payload, rest) = self.parse_pattern_payload()?;
+                    PatternKind::Variant {
+                        enum_name: None,
+                        variant: name,
+                        payload,
+                        rest,
+                    }
+                } else if self.match_token(TokenType::LeftBrace) {
+                    let (fields, rest) = self.parse_field_patterns()?;
+                    PatternKind::Struct { name, fields, rest }
+                } else {
+                    PatternKind::Binding(name)
+                }
             }
-            _ => Err(format!(
-                "Unexpected token: {:?} at line {}",
-                self.peek().kind,
-                self.peek().line
-            )),
+            _ => {
+                return Err(self.error(&format!("Expected pattern, got {:?}", self.peek().kind)));
+            }
+        };
+        Ok(Pattern {
+            kind,
+            span: self.span_from(start),
+        })
+    }
+
+    /// Payload of a variant pattern, and whether it ends in `..`
+    fn parse_pattern_payload(&mut self) -> ParseResult<(VariantPayload<Pattern>, bool)> {
+        if self.match_token(TokenType::LeftParen) {
+            let mut items = Vec::new();
+            while !self.check(TokenType::RightParen) && !self.is_at_end() {
+                items.push(self.parse_pattern()?);
+                if !self.check(TokenType::RightParen) {
+                    self.consume(TokenType::Comma, "Expected ',' between patterns")?;
+                }
+            }
+            self.consume(TokenType::RightParen, "Expected ')' after variant patterns")?;
+            Ok((VariantPayload::Tuple(items), false))
+        } else if self.match_token(TokenType::LeftBrace) {
+            let (fields, rest) = self.parse_field_patterns()?;
+            Ok((VariantPayload::Struct(fields), rest))
+        } else {
+            Ok((VariantPayload::Unit, false))
         }
     }
 
-    fn parse_extern_block(&mut self) -> Result<ExternBlock, String> {
+    /// `x, y: pattern, ..}` after the `{` of a struct-like pattern
+    fn parse_field_patterns(&mut self) -> ParseResult<(Vec<(String, Pattern)>, bool)> {
+        let mut fields = Vec::new();
+        let mut rest = false;
+        while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+            if self.match_token(TokenType::Dot) {
+                self.consume(TokenType::Dot, "Expected '..'")?;
+                rest = true;
+                break;
+            }
+            let field_start = self.peek().span;
+            let field = self.parse_identifier("Expected field name in pattern")?;
+            let pattern = if self.match_token(TokenType::Colon) {
+                self.parse_pattern()?
+            } else {
+                Pattern {
+                    kind: PatternKind::Binding(field.clone()),
+                    span: field_start,
+                }
+            };
+            fields.push((field, pattern));
+            if !self.check(TokenType::RightBrace) {
+                self.consume(TokenType::Comma, "Expected ',' after field pattern")?;
+            }
+        }
+        self.consume(TokenType::RightBrace, "Expected '}' after field patterns")?;
+        Ok((fields, rest))
+    }
+
+    /// Parse a variant constructor after `Enum::`
+    fn parse_enum_variant(&mut self, enum_name: String, start: Span) -> ParseResult<Expression> {
+        let variant = self.parse_identifier("Expected variant name after '::'")?;
+        let payload = if self.match_token(TokenType::LeftParen) {
+            let mut arguments = Vec::new();
+            if !self.check(TokenType::RightParen) {
+                arguments.push(self.parse_expression()?);
+                while self.match_token(TokenType::Comma) {
+                    arguments.push(self.parse_expression()?);
+                }
+            }
+            self.consume(TokenType::RightParen, "Expected ')' after variant arguments")?;
+            VariantPayload::Tuple(arguments)
+        } else if !self.no_struct_literal && self.match_token(TokenType::LeftBrace) {
+            VariantPayload::Struct(self.parse_field_values("Expected '}' after variant fields")?)
+        } else {
+            VariantPayload::Unit
+        };
+
+        Ok(Expression::new(
+            ExpressionKind::EnumVariant {
+                enum_name,
+                variant,
+                payload,
+            },
+            self.span_from(start),
+        ))
+    }
+
+    fn parse_extern_block(&mut self) -> ParseResult<ExternBlock> {
         // extern "C" { ... }
+        let start = self.previous().span;
         let abi_token = self.consume(TokenType::StringLiteral(String::new()), "Expected ABI string after 'extern'")?;
         let abi = match &abi_token.kind {
             TokenType::StringLiteral(s) => s.clone(),
-            _ => return Err("Expected ABI string".to_string()),
+            _ => return Err(self.error("Expected ABI string")),
         };
         
         if abi != "C" {
-            return Err(format!("Only 'C' ABI is supported, got '{}'", abi));
+            return Err(self.error(&format!("Only 'C' ABI is supported, got '{}'", abi)));
         }
         
         self.consume(TokenType::LeftBrace, "Expected '{' after ABI string")?;
@@ -1001,17 +2401,22 @@ impl Parser {
         
         self.consume(TokenType::RightBrace, "Expected '}' after extern block")?;
         
-        Ok(ExternBlock { abi, functions })
+        Ok(ExternBlock {
+            abi,
+            functions,
+            span: self.span_from(start),
+        })
     }
     
-    fn parse_extern_function(&mut self) -> Result<ExternFunction, String> {
+    fn parse_extern_function(&mut self) -> ParseResult<ExternFunction> {
         // fn name(params...) -> return_type;
+        let start = self.peek().span;
         self.consume(TokenType::Fn, "Expected 'fn' in extern block")?;
         
         let name_token = self.consume(TokenType::Identifier(String::new()), "Expected function name")?;
         let name = match &name_token.kind {
             TokenType::Identifier(s) => s.clone(),
-            _ => return Err("Invalid function name".to_string()),
+            _ => return Err(self.error("Invalid function name")),
         };
         
         self.consume(TokenType::LeftParen, "Expected '(' after function name")?;
@@ -1060,34 +2465,48 @@ impl Parser {
             params,
             return_type,
             is_variadic,
+            span: self.span_from(start),
         })
     }
 
-    fn parse_ui_block(&mut self) -> Result<UiBlock, String> {
+    fn parse_ui_block(&mut self) -> ParseResult<UiBlock> {
         // ui <name> { <widget> }
+        let keyword = self.current - 1;
+        let start = self.previous().span;
         let name = if let TokenType::Identifier(s) = &self.peek().kind {
             let n = s.clone();
             self.advance();
             n
         } else {
-            return Err(format!("Expected UI block name at line {}", self.peek().line));
+            return Err(self.error("Expected UI block name"));
         };
         
+        let body_start = self.current;
         self.consume(TokenType::LeftBrace, "Expected '{' after UI block name")?;
         let root = self.parse_widget()?;
         self.consume(TokenType::RightBrace, "Expected '}' after UI block")?;
         
-        Ok(UiBlock { name, root })
+        Ok(UiBlock {
+            name,
+            root,
+            doc: self.item_doc(keyword, body_start),
+            span: self.span_from(start),
+        })
     }
 
-    fn parse_widget(&mut self) -> Result<Widget, String> {
+    fn widget_int(&self, value: u64) -> ParseResult<u32> {
+        u32::try_from(value).map_err(|_| self.error("Widget value too large"))
+    }
+
+    fn parse_widget(&mut self) -> ParseResult<Widget> {
         // WidgetName { prop: value, ... }
+        let start = self.peek().span;
         let widget_type = if let TokenType::Identifier(s) = &self.peek().kind {
             let t = s.clone();
             self.advance();
             t
         } else {
-            return Err(format!("Expected widget type at line {}", self.peek().line));
+            return Err(self.error("Expected widget type"));
         };
         
         self.consume(TokenType::LeftBrace, "Expected '{' after widget type")?;
@@ -1099,7 +2518,7 @@ impl Parser {
                 self.advance();
                 n
             } else {
-                return Err(format!("Expected property name at line {}", self.peek().line));
+                return Err(self.error("Expected property name"));
             };
             
             self.consume(TokenType::Colon, "Expected ':' after property name")?;
@@ -1115,11 +2534,22 @@ impl Parser {
         
         self.consume(TokenType::RightBrace, "Expected '}' after widget properties")?;
         
-        Ok(Widget { widget_type, properties })
+        Ok(Widget {
+            widget_type,
+            properties,
+            span: self.span_from(start),
+        })
     }
 
-    fn parse_widget_value(&mut self) -> Result<WidgetValue, String> {
+    fn parse_widget_value(&mut self) -> ParseResult<WidgetValue> {
         // Can be: int, string, rgb(r,g,b), identifier, widget, [widget, ...]
+        // or a closure
+
+        if self.at_closure() {
+            let start = self.peek().span;
+            let closure = self.parse_closure(start)?;
+            return Ok(WidgetValue::Closure(Box::new(closure)));
+        }
         
         // Check if it's a widget (starts with uppercase identifier)
         if let TokenType::Identifier(s) = &self.peek().kind {
@@ -1130,9 +2560,13 @@ impl Parser {
             }
         }
         
-        if let TokenType::IntegerLiteral(n) = self.peek().kind {
+        if let TokenType::IntegerLiteral(n, _) = self.peek().kind {
+            let n = self.widget_int(n)?;
             self.advance();
             Ok(WidgetValue::Int(n))
+        } else if let TokenType::FloatLiteral(x, _) = self.peek().kind {
+            self.advance();
+            Ok(WidgetValue::Float(x as f32))
         } else if let TokenType::StringLiteral(s) = &self.peek().kind {
             let val = s.clone();
             self.advance();
@@ -1151,27 +2585,30 @@ impl Parser {
             // Check if it's rgb(...)
             if ident == "rgb" && self.check(TokenType::LeftParen) {
                 self.advance(); // consume '('
-                let r = if let TokenType::IntegerLiteral(n) = self.peek().kind {
+                let r = if let TokenType::IntegerLiteral(n, _) = self.peek().kind {
+                    let n = self.widget_int(n)?;
                     self.advance();
                     n
                 } else {
-                    return Err(format!("Expected integer for red component at line {}", self.peek().line));
+                    return Err(self.error("Expected integer for red component"));
                 };
                 self.consume(TokenType::Comma, "Expected ',' in rgb()")?;
                 
-                let g = if let TokenType::IntegerLiteral(n) = self.peek().kind {
+                let g = if let TokenType::IntegerLiteral(n, _) = self.peek().kind {
+                    let n = self.widget_int(n)?;
                     self.advance();
                     n
                 } else {
-                    return Err(format!("Expected integer for green component at line {}", self.peek().line));
+                    return Err(self.error("Expected integer for green component"));
                 };
                 self.consume(TokenType::Comma, "Expected ',' in rgb()")?;
                 
-                let b = if let TokenType::IntegerLiteral(n) = self.peek().kind {
+                let b = if let TokenType::IntegerLiteral(n, _) = self.peek().kind {
+                    let n = self.widget_int(n)?;
                     self.advance();
                     n
                 } else {
-                    return Err(format!("Expected integer for blue component at line {}", self.peek().line));
+                    return Err(self.error("Expected integer for blue component"));
                 };
                 self.consume(TokenType::RightParen, "Expected ')' after rgb()")?;
                 
@@ -1198,11 +2635,20 @@ impl Parser {
             self.consume(TokenType::RightBracket, "Expected ']' after widget array")?;
             Ok(WidgetValue::WidgetList(widgets))
         } else {
-            Err(format!("Unexpected token in widget value at line {}", self.peek().line))
+            Err(self.error("Unexpected token in widget value"))
         }
     }
 }
 
+/// Join doc comment lines, dropping the conventional space after the marker
+fn join_doc_lines(lines: &[String]) -> String {
+    lines
+        .iter()
+        .map(|line| line.strip_prefix(' ').unwrap_or(line))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1227,4 +2673,454 @@ mod tests {
         let result = parser.parse();
         assert!(result.is_ok());
     }
+
+    fn parse_recovering(source: &str) -> (Vec<Declaration>, Vec<Diagnostic>) {
+        let mut lexer = Lexer::new(source.to_string());
+        let tokens = lexer.tokenize();
+        let mut parser = Parser::new(tokens);
+        let decls = parser.parse_program();
+        (decls, parser.diagnostics().diagnostics().to_vec())
+    }
+
+    #[test]
+    fn test_recovers_multiple_statement_errors() {
+        let source = "fn main() {\n    let x = ;\n    let y = 2;\n    foo(;\n    return y;\n}\nfn other() { }";
+        let (decls, errors) = parse_recovering(source);
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].span.unwrap().start.line, 2);
+        assert_eq!(errors[1].span.unwrap().start.line, 4);
+        assert_eq!(decls.len(), 2);
+
+        let main = match &decls[0] {
+            Declaration::Function(f) => f,
+            _ => panic!("expected partial function"),
+        };
+        let kinds: Vec<bool> = main
+            .body
+            .iter()
+            .map(|s| matches!(s.kind, StatementKind::Error))
+            .collect();
+        assert_eq!(kinds, vec![true, false, true, false]);
+    }
+
+    #[test]
+    fn test_recovers_at_top_level_keywords() {
+        let source = "fn broken( { }\nlet stray = 1;\nactor Worker { fn run() { } }\nfn ok() { }";
+        let (decls, errors) = parse_recovering(source);
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(decls[0], Declaration::Error(_)));
+        assert!(matches!(decls[1], Declaration::Error(_)));
+        assert!(matches!(&decls[2], Declaration::Actor(a) if a.methods.len() == 1));
+        assert!(matches!(&decls[3], Declaration::Function(f) if f.name == "ok"));
+    }
+
+    #[test]
+    fn test_unterminated_block_keeps_following_declaration() {
+        let source = "fn main() {\n    let x = 1;\nfn next() { }";
+        let (decls, errors) = parse_recovering(source);
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(&decls[0], Declaration::Function(f) if f.body.len() == 1));
+        assert!(matches!(&decls[1], Declaration::Function(f) if f.name == "next"));
+    }
+
+    #[test]
+    fn test_ast_spans() {
+        let source = "fn main() {\n    let x = 1 + foo(2);\n}".to_string();
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.tokenize();
+        let mut parser = Parser::new(tokens);
+        let decls = parser.parse().unwrap();
+
+        let f = match &decls[0] {
+            Declaration::Function(f) => f,
+            _ => panic!("expected function"),
+        };
+        assert_eq!(f.span.start.offset, 0);
+        assert_eq!(f.span.end.offset, 37);
+
+        let stmt = &f.body[0];
+        assert_eq!((stmt.span.start.line, stmt.span.start.column), (2, 5));
+        let value = match &stmt.kind {
+            StatementKind::Let { value: Some(value), .. } => value,
+            _ => panic!("expected let"),
+        };
+        assert_eq!(value.span.start.offset, 24);
+        assert_eq!(value.span.end.offset, 34);
+        match &value.kind {
+            ExpressionKind::Binary { right, .. } => {
+                assert_eq!(right.span.start.offset, 28);
+                assert_eq!(right.span.end.offset, 34);
+            }
+            _ => panic!("expected binary expression"),
+        }
+    }
+
+    #[test]
+    fn test_doc_comments() {
+        let source = "//! A module\n/// Adds numbers.\n///\n/// Twice.\nfn add() {\n    //! Inner note\n    /// stray\n    return 1;\n}\n/* not docs */\ntype P { /// x coord\n x: i32 }\nfn plain() {}";
+        let mut parser = Parser::new(Lexer::new(source.to_string()).tokenize());
+        let decls = parser.parse().unwrap();
+        assert_eq!(parser.module_doc().as_deref(), Some("A module"));
+        match &decls[0] {
+            Declaration::Function(f) => {
+                assert_eq!(f.doc.as_deref(), Some("Adds numbers.\n\nTwice.\nInner note"));
+                assert_eq!(f.body.len(), 1);
+            }
+            _ => panic!("expected function"),
+        }
+        match &decls[1] {
+            Declaration::TypeDef(t) => assert_eq!(t.doc, None),
+            _ => panic!("expected type"),
+        }
+        match &decls[2] {
+            Declaration::Function(f) => assert_eq!(f.doc, None),
+            _ => panic!("expected function"),
+        }
+    }
+
+    #[test]
+    fn test_enum_declaration_and_constructors() {
+        let source = "enum Shape { Circle(i32), Rect { w: i32, h: i32 }, Empty, }\nfn main() { let r = Shape::Rect { w: 1, h: 2 }; if s == Shape::Empty { } }";
+        let mut parser = Parser::new(Lexer::new(source.to_string()).tokenize());
+        let decls = parser.parse().unwrap();
+        let shape = match &decls[0] {
+            Declaration::Enum(e) => e,
+            _ => panic!("expected enum"),
+        };
+        let variants: Vec<&str> = shape.variants.iter().map(|v| v.name.as_str()).collect();
+        assert_eq!(variants, ["Circle", "Rect", "Empty"]);
+        assert!(matches!(&shape.variants[0].payload, VariantPayload::Tuple(types) if types == &[Type::I32]));
+        assert_eq!(shape.variants[1].payload.fields().len(), 2);
+        assert!(matches!(shape.variants[2].payload, VariantPayload::Unit));
+
+        let body = match &decls[1] {
+            Declaration::Function(f) => &f.body,
+            _ => panic!("expected function"),
+        };
+        match &body[0].kind {
+            StatementKind::Let { value: Some(value), .. } => match &value.kind {
+                ExpressionKind::EnumVariant { enum_name, variant, payload } => {
+                    assert_eq!((enum_name.as_str(), variant.as_str()), ("Shape", "Rect"));
+                    assert!(matches!(payload, VariantPayload::Struct(fields) if fields.len() == 2));
+                }
+                _ => panic!("expected constructor"),
+            },
+            _ => panic!("expected let"),
+        }
+        // In a condition the `{` opens the body, not a payload
+        match &body[1].kind {
+            StatementKind::If { then_branch, .. } => assert!(then_branch.is_empty()),
+            _ => panic!("expected if"),
+        }
+    }
+
+    #[test]
+    fn test_struct_literals_and_fields() {
+        let source = "fn main() { let v = Vec<T> { data: 0, len }; v.a.b = x.c; if a < b { } while p { } }";
+        let mut parser = Parser::new(Lexer::new(source.to_string()).tokenize());
+        let decls = parser.parse().unwrap();
+        let body = match &decls[0] {
+            Declaration::Function(f) => &f.body,
+            _ => panic!("expected function"),
+        };
+        match &body[0].kind {
+            StatementKind::Let { value: Some(value), .. } => match &value.kind {
+                ExpressionKind::StructLiteral { name, type_args, fields } => {
+                    assert_eq!(name, "Vec");
+                    assert_eq!(type_args, &[Type::Custom("T".to_string())]);
+                    let names: Vec<&str> = fields.iter().map(|(f, _)| f.as_str()).collect();
+                    assert_eq!(names, ["data", "len"]);
+                    // Shorthand takes the variable of the same name
+                    assert!(matches!(&fields[1].1.kind, ExpressionKind::Identifier(n) if n == "len"));
+                }
+                _ => panic!("expected struct literal"),
+            },
+            _ => panic!("expected let"),
+        }
+        match &body[1].kind {
+            StatementKind::Expr(expr) => match &expr.kind {
+                ExpressionKind::Assignment { target, value } => {
+                    assert_eq!(target.root_variable(), Some("v"));
+                    assert!(matches!(&target.kind, ExpressionKind::FieldAccess { field, .. } if field == "b"));
+                    assert!(matches!(&value.kind, ExpressionKind::FieldAccess { field, .. } if field == "c"));
+                }
+                _ => panic!("expected assignment"),
+            },
+            _ => panic!("expected expression statement"),
+        }
+        // A comparison and a condition are not struct literals
+        assert!(matches!(&body[2].kind, StatementKind::If { .. }));
+        assert!(matches!(&body[3].kind, StatementKind::While { body, .. } if body.is_empty()));
+
+        let mut parser = Parser::new(Lexer::new("fn main() { f() = 1; }".to_string()).tokenize());
+        assert!(parser.parse().is_err());
+
+        // Methods are called on any expression, not just a variable
+        let mut parser = Parser::new(Lexer::new("fn main() { h.b.area(); make().area(); }".to_string()).tokenize());
+        let decls = parser.parse().unwrap();
+        let body = match &decls[0] {
+            Declaration::Function(f) => &f.body,
+            _ => panic!("expected function"),
+        };
+        let receivers: Vec<&ExpressionKind> = body
+            .iter()
+            .map(|stmt| match &stmt.kind {
+                StatementKind::Expr(Expression { kind: ExpressionKind::MethodCall { receiver, .. }, .. }) => &receiver.kind,
+                _ => panic!("expected method call"),
+            })
+            .collect();
+        assert!(matches!(receivers[0], ExpressionKind::FieldAccess { field, .. } if field == "b"));
+        assert!(matches!(receivers[1], ExpressionKind::FunctionCall { .. }));
+    }
+
+    #[test]
+    fn test_arrays_and_indexing() {
+        let source = "fn f(a: [i32; 3], s: [[u8; 2]]) { let x = [a[0], s[1][0]]; x[i + 1] = 2; }";
+        let mut parser = Parser::new(Lexer::new(source.to_string()).tokenize());
+        let decls = parser.parse().unwrap();
+        let f = match &decls[0] {
+            Declaration::Function(f) => f,
+            _ => panic!("expected function"),
+        };
+        assert_eq!(f.params[0].1, Type::Array(Box::new(Type::I32), 3));
+        assert_eq!(f.params[1].1, Type::Slice(Box::new(Type::Array(Box::new(Type::U8), 2))));
+        match &f.body[0].kind {
+            StatementKind::Let { value: Some(value), .. } => match &value.kind {
+                ExpressionKind::ArrayLiteral(elements) => {
+                    assert_eq!(elements.len(), 2);
+                    // `s[1][0]` indexes the result of `s[1]`
+                    match &elements[1].kind {
+                        ExpressionKind::Index { object, .. } => {
+                            assert!(matches!(object.kind, ExpressionKind::Index { .. }));
+                        }
+                        _ => panic!("expected index"),
+                    }
+                }
+                _ => panic!("expected array literal"),
+            },
+            _ => panic!("expected let"),
+        }
+        match &f.body[1].kind {
+            StatementKind::Expr(expr) => match &expr.kind {
+                ExpressionKind::Assignment { target, .. } => assert_eq!(target.root_variable(), Some("x")),
+                _ => panic!("expected assignment"),
+            },
+            _ => panic!("expected expression statement"),
+        }
+    }
+
+    #[test]
+    fn test_ranges_and_iterator_impls() {
+        let source = "impl Iterator<i32> for Counter { fn next() -> Option<i32> { return None; } }\n\
+            fn f(r: Range<u8>) { for i in 0..n + 1 { } for j in (1..=10).step_by(2) { } }";
+        let mut parser = Parser::new(Lexer::new(source.to_string()).tokenize());
+        let decls = parser.parse().unwrap();
+        match &decls[0] {
+            Declaration::TraitImpl(i) => {
+                assert_eq!(i.type_name, "Counter");
+                assert_eq!(i.iterator_item(), Some(Type::I32));
+            }
+            _ => panic!("expected impl"),
+        }
+        let f = match &decls[1] {
+            Declaration::Function(f) => f,
+            _ => panic!("expected function"),
+        };
+        assert_eq!(f.params[0].1, Type::Range(Box::new(Type::U8)));
+        // The range binds more loosely than `+`
+        match &f.body[0].kind {
+            StatementKind::For { iterable, .. } => match &iterable.kind {
+                ExpressionKind::Range { end, inclusive: false, step: None, .. } => {
+                    assert!(matches!(end.kind, ExpressionKind::Binary { .. }));
+                }
+                _ => panic!("expected range"),
+            },
+            _ => panic!("expected for"),
+        }
+        match &f.body[1].kind {
+            StatementKind::For { iterable, .. } => {
+                assert!(matches!(iterable.kind, ExpressionKind::Range { inclusive: true, step: Some(_), .. }));
+            }
+            _ => panic!("expected for"),
+        }
+    }
+
+    #[test]
+    fn test_closures() {
+        let source = "fn main() { let f = |x: i32, y: i32| x + y; let g = move || -> i32 { let n = 1; n }; \
+            let h = || { print(1); }; }";
+        let mut parser = Parser::new(Lexer::new(source.to_string()).tokenize());
+        let decls = parser.parse().unwrap();
+        let Declaration::Function(main) = &decls[0] else {
+            panic!("expected function");
+        };
+        let closure = |i: usize| match &main.body[i].kind {
+            StatementKind::Let { value: Some(value), .. } => match &value.kind {
+                ExpressionKind::Closure { params, return_type, body, by_move } => {
+                    (params.clone(), return_type.clone(), body.clone(), *by_move)
+                }
+                _ => panic!("expected closure"),
+            },
+            _ => panic!("expected let"),
+        };
+        // A value body is returned
+        let (params, return_type, body, by_move) = closure(0);
+        assert_eq!(params, vec![("x".to_string(), Type::I32), ("y".to_string(), Type::I32)]);
+        assert_eq!(return_type, None);
+        assert!(!by_move);
+        assert!(matches!(&body[..], [Statement { kind: StatementKind::Return(_), .. }]));
+        // So is the final expression of a block body, unless it ends in `;`
+        let (params, return_type, body, by_move) = closure(1);
+        assert!(params.is_empty() && by_move);
+        assert_eq!(return_type, Some(Type::I32));
+        assert!(matches!(body[1].kind, StatementKind::Return(_)));
+        let (_, _, body, _) = closure(2);
+        assert!(matches!(body[0].kind, StatementKind::Expr(_)));
+
+        let mut parser = Parser::new(Lexer::new("fn main() { let f = |x| x; }".to_string()).tokenize());
+        assert!(parser.parse().is_err());
+    }
+
+    #[test]
+    fn test_modules_and_paths() {
+        let source = "mod shapes; import std::core; use shapes::{area, Shape};\n\
+            /// Doubles\npub fn double(x: collections::Vec) -> i32 { return shapes::Shape::Circle(geo::area(x)); }\n\
+            pub type P { x: i32 }";
+        let mut parser = Parser::new(Lexer::new(source.to_string()).tokenize());
+        let decls = parser.parse().unwrap();
+        let imports: Vec<(ImportKind, Vec<String>, Vec<String>)> = decls
+            .iter()
+            .filter_map(|d| match d {
+                Declaration::Import(i) => Some((i.kind, i.path.clone(), i.items.clone())),
+                _ => None,
+            })
+            .collect();
+        let strings = |items: &[&str]| items.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            imports,
+            vec![
+                (ImportKind::Mod, strings(&["shapes"]), vec![]),
+                (ImportKind::Import, strings(&["std", "core"]), vec![]),
+                (ImportKind::Use, strings(&["shapes"]), strings(&["area", "Shape"])),
+            ]
+        );
+
+        let Declaration::Function(double) = &decls[3] else {
+            panic!("expected function");
+        };
+        assert!(double.public);
+        assert_eq!(double.doc.as_deref(), Some("Doubles"));
+        assert_eq!(double.params[0].1, Type::Custom("collections::Vec".to_string()));
+        // The path before the last segment names the enum (or module)
+        let StatementKind::Return(value) = &double.body[0].kind else {
+            panic!("expected return");
+        };
+        let ExpressionKind::EnumVariant { enum_name, variant, payload } = &value.kind else {
+            panic!("expected variant");
+        };
+        assert_eq!((enum_name.as_str(), variant.as_str()), ("shapes::Shape", "Circle"));
+        let VariantPayload::Tuple(args) = payload else {
+            panic!("expected tuple payload");
+        };
+        assert!(matches!(&args[0].kind, ExpressionKind::EnumVariant { enum_name, .. } if enum_name == "geo"));
+        assert!(matches!(&decls[4], Declaration::TypeDef(t) if t.public));
+
+        for source in ["pub actor A { }", "use shapes;", "pub pub fn f() {}"] {
+            let mut parser = Parser::new(Lexer::new(source.to_string()).tokenize());
+            assert!(parser.parse().is_err(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_let_annotations() {
+        let source = "fn main() { let a: u8 = 1; var b: [i32]; let c = 2; }";
+        let mut parser = Parser::new(Lexer::new(source.to_string()).tokenize());
+        let decls = parser.parse().unwrap();
+        let Declaration::Function(main) = &decls[0] else {
+            panic!("expected function");
+        };
+        let lets: Vec<(&str, bool, Option<&Type>, bool)> = main
+            .body
+            .iter()
+            .map(|stmt| match &stmt.kind {
+                StatementKind::Let { name, mutable, ty, value } => (name.as_str(), *mutable, ty.as_ref(), value.is_some()),
+                _ => panic!("expected let"),
+            })
+            .collect();
+        let slice = Type::Slice(Box::new(Type::I32));
+        assert_eq!(
+            lets,
+            vec![("a", false, Some(&Type::U8), true), ("b", true, Some(&slice), false), ("c", false, None, true)]
+        );
+
+        // Only a declared type lets the value be left out
+        for source in ["fn main() { let a; }", "fn main() { var a: i32 }"] {
+            let mut parser = Parser::new(Lexer::new(source.to_string()).tokenize());
+            assert!(parser.parse().is_err(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_dyn_types() {
+        let source = "fn total(a: dyn Shape, b: [dyn geo::Shape]) { let dyn = 1; }";
+        let mut parser = Parser::new(Lexer::new(source.to_string()).tokenize());
+        let decls = parser.parse().unwrap();
+        let Declaration::Function(f) = &decls[0] else {
+            panic!("expected function");
+        };
+        assert_eq!(f.params[0].1, Type::Dyn("Shape".to_string()));
+        assert_eq!(f.params[1].1, Type::Slice(Box::new(Type::Dyn("geo::Shape".to_string()))));
+        assert_eq!(f.params[0].1.to_string(), "dyn Shape");
+        // `dyn` is only a keyword before a trait name
+        assert!(matches!(&f.body[0].kind, StatementKind::Let { name, .. } if name == "dyn"));
+    }
+
+    #[test]
+    fn test_trait_bounds() {
+        let source = "fn max<T: Ord + geo::Shape, U>(a: T, b: U) -> T where U: Clone, { return a; }\n\
+            type Pair<T> where T: Eq { left: T, right: T }";
+        let mut parser = Parser::new(Lexer::new(source.to_string()).tokenize());
+        let decls = parser.parse().unwrap();
+        let (Declaration::Function(f), Declaration::TypeDef(t)) = (&decls[0], &decls[1]) else {
+            panic!("expected a function and a type");
+        };
+        assert_eq!(f.type_params, ["T", "U"]);
+        let bound = |param: &str, trait_name: &str| (param.to_string(), trait_name.to_string());
+        assert_eq!(f.bounds, [bound("T", "Ord"), bound("T", "geo::Shape"), bound("U", "Clone")]);
+        assert_eq!(t.bounds, [bound("T", "Eq")]);
+
+        // A `where` clause only bounds the item's own type parameters
+        let source = "fn show<T>(value: T) where U: Display { }";
+        let mut parser = Parser::new(Lexer::new(source.to_string()).tokenize());
+        assert!(parser.parse().is_err());
+    }
+
+    #[test]
+    fn test_reference_types() {
+        let source = "fn f(a: &Point, b: &[str]) -> &i32 { let r = &a.x; let n = *r * 2; return r; }";
+        let mut parser = Parser::new(Lexer::new(source.to_string()).tokenize());
+        let decls = parser.parse().unwrap();
+        let Declaration::Function(f) = &decls[0] else {
+            panic!("expected function");
+        };
+        assert_eq!(f.params[0].1, Type::Ref(Box::new(Type::Custom("Point".to_string()))));
+        assert_eq!(f.params[1].1.to_string(), "&[str]");
+        assert_eq!(f.return_type, Some(Type::Ref(Box::new(Type::I32))));
+        let StatementKind::Let { value: Some(borrow), .. } = &f.body[0].kind else {
+            panic!("expected let");
+        };
+        assert!(matches!(
+            &borrow.kind,
+            ExpressionKind::Unary { operator: UnaryOp::Borrow, operand } if matches!(operand.kind, ExpressionKind::FieldAccess { .. })
+        ));
+        // A leading `*` reads through a reference; a later one multiplies
+        let StatementKind::Let { value: Some(product), .. } = &f.body[1].kind else {
+            panic!("expected let");
+        };
+        assert!(matches!(
+            &product.kind,
+            ExpressionKind::Binary { left, operator: BinaryOp::Multiply, .. }
+                if matches!(left.kind, ExpressionKind::Unary { operator: UnaryOp::Deref, .. })
+        ));
+    }
 }
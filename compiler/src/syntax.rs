@@ -0,0 +1,285 @@
+// syntax.rs — U v0.8 Concrete Syntax Tree
+// MIT License — Copyright (c) 2025 Webcien and U contributors
+//
+// Lossless syntax tree for tooling
+// Features:
+// - Every token keeps its exact source text and leading trivia
+// - Whitespace and comments survive, so printing the tree round-trips the
+//   source byte-for-byte
+// - Tokens are grouped into declarations and brace-delimited blocks
+// - The AST is derived from the significant tokens of the tree
+
+use crate::diagnostics::{DiagnosticCollector, FileId};
+use crate::lexer::{Lexer, LosslessToken, Token, TokenType};
+use std::fmt;
+
+/// Kind of an interior node of the tree
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyntaxKind {
+    /// The whole file; its last child is always the Eof token
+    Root,
+    /// A top-level `fn`, `actor`, `trait`, `type`, `enum`, `impl`, `ui` or `extern`
+    /// item, together with its `///` doc comments
+    Declaration,
+    /// `{ ... }`, including both braces (the `}` is missing if unterminated)
+    Block,
+}
+
+#[derive(Debug, Clone)]
+pub struct SyntaxNode {
+    pub kind: SyntaxKind,
+    pub children: Vec<SyntaxElement>,
+}
+
+#[derive(Debug, Clone)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(LosslessToken),
+}
+
+impl SyntaxNode {
+    fn new(kind: SyntaxKind) -> Self {
+        Self {
+            kind,
+            children: Vec::new(),
+        }
+    }
+
+    /// Direct child nodes, skipping tokens
+    pub fn child_nodes(&self) -> impl Iterator<Item = &SyntaxNode> {
+        self.children.iter().filter_map(|child| match child {
+            SyntaxElement::Node(node) => Some(node),
+            SyntaxElement::Token(_) => None,
+        })
+    }
+
+    /// All tokens below this node, in source order
+    pub fn tokens(&self) -> Vec<&LosslessToken> {
+        let mut tokens = Vec::new();
+        self.collect_tokens(&mut tokens);
+        tokens
+    }
+
+    fn collect_tokens<'a>(&'a self, tokens: &mut Vec<&'a LosslessToken>) {
+        for child in &self.children {
+            match child {
+                SyntaxElement::Node(node) => node.collect_tokens(tokens),
+                SyntaxElement::Token(token) => tokens.push(token),
+            }
+        }
+    }
+}
+
+impl fmt::Display for SyntaxNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for token in self.tokens() {
+            for trivia in &token.leading_trivia {
+                f.write_str(&trivia.text)?;
+            }
+            f.write_str(&token.text)?;
+        }
+        Ok(())
+    }
+}
+
+/// Lossless syntax tree of one source file
+#[derive(Debug, Clone)]
+pub struct SyntaxTree {
+    root: SyntaxNode,
+    diagnostics: DiagnosticCollector,
+}
+
+impl SyntaxTree {
+    pub fn parse(source: &str) -> Self {
+        Self::parse_file(source, 0)
+    }
+
+    pub fn parse_file(source: &str, file_id: FileId) -> Self {
+        let mut lexer = Lexer::with_file_id(source.to_string(), file_id);
+        let root = build_tree(lexer.tokenize_lossless());
+        Self {
+            root,
+            diagnostics: lexer.diagnostics().clone(),
+        }
+    }
+
+    pub fn root(&self) -> &SyntaxNode {
+        &self.root
+    }
+
+    /// Lexical errors, such as malformed literals
+    pub fn diagnostics(&self) -> &DiagnosticCollector {
+        &self.diagnostics
+    }
+
+    /// Tokens without trivia, ready for `parser::Parser`
+    pub fn significant_tokens(&self) -> Vec<Token> {
+        self.root
+            .tokens()
+            .into_iter()
+            .map(|t| t.token.clone())
+            .collect()
+    }
+}
+
+impl fmt::Display for SyntaxTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.root.fmt(f)
+    }
+}
+
+/// Same rule as the parser's declaration recovery: keywords plus the
+/// contextual `trait`, `type`, `enum` and `impl` identifiers
+pub fn starts_declaration(kind: &TokenType) -> bool {
+    match kind {
+        TokenType::Fn
+        | TokenType::Actor
+        | TokenType::Ui
+        | TokenType::Extern
+        | TokenType::Pub
+        | TokenType::Mod
+        | TokenType::Import
+        | TokenType::Use => true,
+        TokenType::Identifier(s) => s == "trait" || s == "type" || s == "enum" || s == "impl",
+        _ => false,
+    }
+}
+
+fn build_tree(tokens: Vec<LosslessToken>) -> SyntaxNode {
+    // stack[0] is the root; open declarations and blocks sit above it
+    let mut stack = vec![SyntaxNode::new(SyntaxKind::Root)];
+
+    for token in tokens {
+        match token.token.kind {
+            TokenType::Eof => {
+                while stack.len() > 1 {
+                    close_node(&mut stack);
+                }
+            }
+            TokenType::LeftBrace => {
+                stack.push(SyntaxNode::new(SyntaxKind::Block));
+            }
+            // Doc comments open the declaration they document
+            ref kind
+                if stack.len() == 1
+                    && (starts_declaration(kind) || matches!(kind, TokenType::DocComment(_))) =>
+            {
+                stack.push(SyntaxNode::new(SyntaxKind::Declaration));
+            }
+            ref kind
+                if in_declaration(&stack)
+                    && starts_declaration(kind)
+                    && !only_item_prefix(&stack[1]) =>
+            {
+                // Previous declaration ended without a body
+                close_node(&mut stack);
+                stack.push(SyntaxNode::new(SyntaxKind::Declaration));
+            }
+            _ => {}
+        }
+
+        let kind = token.token.kind.clone();
+        stack
+            .last_mut()
+            .unwrap()
+            .children
+            .push(SyntaxElement::Token(token));
+
+        match kind {
+            TokenType::RightBrace if stack.last().unwrap().kind == SyntaxKind::Block => {
+                close_node(&mut stack);
+                if in_declaration(&stack) {
+                    // A declaration ends with its body
+                    close_node(&mut stack);
+                }
+            }
+            TokenType::Semicolon if in_declaration(&stack) => close_node(&mut stack),
+            _ => {}
+        }
+    }
+
+    stack.pop().unwrap()
+}
+
+/// Whether the innermost open node is a declaration (outside any block)
+fn in_declaration(stack: &[SyntaxNode]) -> bool {
+    stack.len() == 2 && stack[1].kind == SyntaxKind::Declaration
+}
+
+/// Whether a declaration holds nothing but doc comments and `pub`, which
+/// belong to the item keyword that follows
+fn only_item_prefix(node: &SyntaxNode) -> bool {
+    node.children.iter().all(|child| {
+        matches!(
+            child,
+            SyntaxElement::Token(t) if matches!(t.token.kind, TokenType::DocComment(_) | TokenType::Pub)
+        )
+    })
+}
+
+fn close_node(stack: &mut Vec<SyntaxNode>) {
+    let node = stack.pop().unwrap();
+    stack
+        .last_mut()
+        .unwrap()
+        .children
+        .push(SyntaxElement::Node(node));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_round_trip(source: &str) {
+        assert_eq!(SyntaxTree::parse(source).to_string(), source);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        assert_round_trip("");
+        assert_round_trip("// only a comment");
+        assert_round_trip(
+            "// header\n\nfn main() {\n\tlet x = 1;  // one\r\n    print(\"a\\n\");\n}\n\n// trailer\n",
+        );
+        assert_round_trip("fn broken( { let = ; \n  // unterminated");
+        assert_round_trip("fn f() { let s = \"é\"; } // ünïcode ✓\n");
+        assert_round_trip("fn f() { x & y; } $ }}");
+    }
+
+    #[test]
+    fn test_tree_structure() {
+        let tree = SyntaxTree::parse("// c\nfn a() { if x { y(); } }\ntype T = i32;\n/// Docs\nfn b() {}");
+        let nodes: Vec<&SyntaxNode> = tree.root().child_nodes().collect();
+        assert_eq!(nodes.len(), 3);
+        assert!(nodes.iter().all(|n| n.kind == SyntaxKind::Declaration));
+
+        // The comment belongs to the first declaration's `fn` token
+        let first = nodes[0].tokens()[0];
+        assert_eq!(first.text, "fn");
+        assert_eq!(first.leading_trivia[0].text, "// c");
+
+        let body = nodes[0].child_nodes().next().unwrap();
+        assert_eq!(body.kind, SyntaxKind::Block);
+        assert_eq!(body.child_nodes().next().unwrap().kind, SyntaxKind::Block);
+
+        let last = nodes[2].tokens();
+        assert_eq!(last[0].text, "/// Docs");
+        assert_eq!(last[1].text, "fn");
+    }
+
+    #[test]
+    fn test_significant_tokens_match_lexer() {
+        let source = "fn main() {\n    // comment\n    print(42);\n}\n";
+        let from_tree: Vec<TokenType> = SyntaxTree::parse(source)
+            .significant_tokens()
+            .into_iter()
+            .map(|t| t.kind)
+            .collect();
+        let from_lexer: Vec<TokenType> = Lexer::new(source.to_string())
+            .tokenize()
+            .into_iter()
+            .map(|t| t.kind)
+            .collect();
+        assert_eq!(from_tree, from_lexer);
+    }
+}
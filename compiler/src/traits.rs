@@ -9,35 +9,15 @@
 // - Trait bounds in function signatures
 // - Default implementations (v0.9+)
 
-use std::collections::HashMap;
-use std::fmt;
-
-/// Type representation for trait system
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub enum TraitType {
-    I32,
-    Str,
-    Bool,
-    Custom(String),
-}
+use crate::parser::{self, Declaration, Function, Type};
+use std::collections::{HashMap, HashSet};
 
-impl fmt::Display for TraitType {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            TraitType::I32 => write!(f, "i32"),
-            TraitType::Str => write!(f, "str"),
-            TraitType::Bool => write!(f, "bool"),
-            TraitType::Custom(name) => write!(f, "{}", name),
-        }
-    }
-}
-
-/// Method signature in a trait
+/// Method signature in a trait; `Self` stands for the implementing type
 #[derive(Debug, Clone)]
 pub struct MethodSignature {
     pub name: String,
-    pub params: Vec<(String, TraitType)>,
-    pub return_type: Option<TraitType>,
+    pub params: Vec<(String, Type)>,
+    pub return_type: Option<Type>,
 }
 
 impl MethodSignature {
@@ -49,15 +29,44 @@ impl MethodSignature {
         }
     }
 
-    pub fn with_param(mut self, name: String, ty: TraitType) -> Self {
+    pub fn with_param(mut self, name: String, ty: Type) -> Self {
         self.params.push((name, ty));
         self
     }
 
-    pub fn with_return(mut self, ty: TraitType) -> Self {
+    pub fn with_return(mut self, ty: Type) -> Self {
         self.return_type = Some(ty);
         self
     }
+
+    /// `fn name(a: A, b: B) -> R`, without parameter names
+    fn describe(&self) -> String {
+        let params: Vec<String> = self.params.iter().map(|(_, ty)| ty.to_string()).collect();
+        match &self.return_type {
+            Some(ret) => format!("fn {}({}) -> {}", self.name, params.join(", "), ret),
+            None => format!("fn {}({})", self.name, params.join(", ")),
+        }
+    }
+}
+
+impl From<&parser::TraitMethod> for MethodSignature {
+    fn from(method: &parser::TraitMethod) -> Self {
+        Self {
+            name: method.name.clone(),
+            params: method.params.clone(),
+            return_type: method.return_type.clone(),
+        }
+    }
+}
+
+impl From<&Function> for MethodSignature {
+    fn from(f: &Function) -> Self {
+        Self {
+            name: f.name.clone(),
+            params: f.params.clone(),
+            return_type: f.return_type.clone(),
+        }
+    }
 }
 
 /// Trait definition
@@ -65,6 +74,8 @@ impl MethodSignature {
 pub struct Trait {
     pub name: String,
     pub methods: Vec<MethodSignature>,
+    /// Parameters an impl gives as `impl Trait<A, B> for T`
+    pub type_params: Vec<String>,
     pub documentation: Option<String>,
 }
 
@@ -73,6 +84,7 @@ impl Trait {
         Self {
             name,
             methods: Vec::new(),
+            type_params: Vec::new(),
             documentation: None,
         }
     }
@@ -96,12 +108,25 @@ impl Trait {
     }
 }
 
+impl From<&parser::Trait> for Trait {
+    fn from(t: &parser::Trait) -> Self {
+        Self {
+            name: t.name.clone(),
+            methods: t.methods.iter().map(MethodSignature::from).collect(),
+            type_params: t.type_params.clone(),
+            documentation: t.doc.clone(),
+        }
+    }
+}
+
 /// Trait implementation for a type
 #[derive(Debug, Clone)]
 pub struct TraitImpl {
     pub trait_name: String,
     pub type_name: String,
-    pub methods: HashMap<String, String>, // method_name -> implementation
+    /// Arguments for the trait's type parameters, if given
+    pub type_args: Vec<Type>,
+    pub methods: Vec<MethodSignature>,
 }
 
 impl TraitImpl {
@@ -109,16 +134,28 @@ impl TraitImpl {
         Self {
             trait_name,
             type_name,
-            methods: HashMap::new(),
+            type_args: Vec::new(),
+            methods: Vec::new(),
         }
     }
 
-    pub fn add_method(&mut self, name: String, implementation: String) {
-        self.methods.insert(name, implementation);
+    pub fn add_method(&mut self, method: MethodSignature) {
+        self.methods.push(method);
     }
 
-    pub fn get_method(&self, name: &str) -> Option<&str> {
-        self.methods.get(name).map(|s| s.as_str())
+    pub fn get_method(&self, name: &str) -> Option<&MethodSignature> {
+        self.methods.iter().find(|m| m.name == name)
+    }
+}
+
+impl From<&parser::TraitImpl> for TraitImpl {
+    fn from(i: &parser::TraitImpl) -> Self {
+        Self {
+            trait_name: i.trait_name.clone(),
+            type_name: i.type_name.clone(),
+            type_args: i.type_args.clone(),
+            methods: i.methods.iter().map(MethodSignature::from).collect(),
+        }
     }
 }
 
@@ -126,6 +163,9 @@ impl TraitImpl {
 pub struct TraitRegistry {
     traits: HashMap<String, Trait>,
     implementations: Vec<TraitImpl>,
+    /// Type parameters and field types of every struct and enum, which
+    /// decide whether it is `Copy`
+    types: HashMap<String, (Vec<String>, Vec<Type>)>,
 }
 
 impl TraitRegistry {
@@ -133,6 +173,71 @@ impl TraitRegistry {
         Self {
             traits: HashMap::new(),
             implementations: Vec::new(),
+            types: HashMap::new(),
+        }
+    }
+
+    /// Record the fields of a struct or enum declaration
+    pub fn declare_type(&mut self, decl: &Declaration) {
+        match decl {
+            Declaration::TypeDef(t) => {
+                let fields = t.fields.iter().map(|(_, ty)| ty.clone()).collect();
+                self.types.insert(t.name.clone(), (t.type_params.clone(), fields));
+            }
+            Declaration::Enum(e) => {
+                let fields = e
+                    .variants
+                    .iter()
+                    .flat_map(|v| v.payload.fields().into_iter().map(|(_, ty)| ty.clone()))
+                    .collect();
+                self.types.insert(e.name.clone(), (Vec::new(), fields));
+            }
+            _ => {}
+        }
+    }
+
+    /// Whether values of `ty` are copied rather than moved: those of every
+    /// primitive but `str` and `ptr`, of references, and of the structs,
+    /// enums, arrays and options made only of such values and without an
+    /// impl of `Drop`. Of type parameters, only those in
+    /// `copy_params`, which a `Copy` bound covers, are.
+    pub fn is_copy(&self, ty: &Type, copy_params: &[String]) -> bool {
+        self.copy_with(ty, copy_params, &mut HashSet::new())
+    }
+
+    fn copy_with(&self, ty: &Type, copy_params: &[String], seen: &mut HashSet<String>) -> bool {
+        match ty {
+            Type::Char | Type::I32 | Type::I64 | Type::U8 | Type::U64 | Type::F32 | Type::F64 | Type::Bool => true,
+            Type::Str | Type::Ptr | Type::Dyn(_) | Type::FunctionPointer { .. } => false,
+            // A slice or reference is a view, which copies without its values
+            Type::Slice(_) | Type::Ref(_) => true,
+            Type::Option(inner) | Type::Array(inner, _) | Type::Range(inner) => self.copy_with(inner, copy_params, seen),
+            Type::Result(ok, err) => self.copy_with(ok, copy_params, seen) && self.copy_with(err, copy_params, seen),
+            Type::Tuple(items) => items.iter().all(|item| self.copy_with(item, copy_params, seen)),
+            Type::Custom(name) if copy_params.contains(name) => true,
+            // A copy would be dropped twice
+            Type::Custom(name) | Type::Generic { name, .. } if self.implements(name, "Drop") => false,
+            Type::Custom(name) | Type::Generic { name, .. } => {
+                let Some((type_params, fields)) = self.types.get(name) else {
+                    return false;
+                };
+                let type_args = match ty {
+                    Type::Generic { type_args, .. } => type_args.as_slice(),
+                    _ => &[],
+                };
+                // A generic type named without its arguments is not known
+                if type_args.len() != type_params.len() {
+                    return false;
+                }
+                // A type met again is as Copy as the rest of its fields
+                if !seen.insert(name.clone()) {
+                    return true;
+                }
+                let bindings: HashMap<&str, &Type> = type_params.iter().map(String::as_str).zip(type_args).collect();
+                let copy = fields.iter().all(|field| self.copy_with(&field.substitute(&bindings), copy_params, seen));
+                seen.remove(name);
+                copy
+            }
         }
     }
 
@@ -141,25 +246,83 @@ impl TraitRegistry {
         if self.traits.contains_key(&trait_def.name) {
             return Err(format!("Trait '{}' already defined", trait_def.name));
         }
+        if let Some(name) = first_duplicate(&trait_def.methods) {
+            return Err(format!("Method '{}' declared twice in trait '{}'", name, trait_def.name));
+        }
         self.traits.insert(trait_def.name.clone(), trait_def);
         Ok(())
     }
 
-    /// Register a trait implementation
+    /// Register a trait implementation, which must provide exactly the
+    /// trait's methods with its signatures
     pub fn register_impl(&mut self, impl_def: TraitImpl) -> Result<(), String> {
-        if !self.traits.contains_key(&impl_def.trait_name) {
+        let Some(trait_def) = self.traits.get(&impl_def.trait_name) else {
             return Err(format!("Trait '{}' not found", impl_def.trait_name));
+        };
+        if impl_def.trait_name == "Copy" {
+            return Err(format!(
+                "Trait 'Copy' cannot be implemented for type '{}': it follows from the types of a type's fields",
+                impl_def.type_name
+            ));
+        }
+        if self.implements(&impl_def.type_name, &impl_def.trait_name) {
+            return Err(format!(
+                "Trait '{}' implemented twice for type '{}'",
+                impl_def.trait_name, impl_def.type_name
+            ));
+        }
+        if !impl_def.type_args.is_empty() && impl_def.type_args.len() != trait_def.type_params.len() {
+            return Err(format!(
+                "Trait '{}' takes {} type argument(s), got {}",
+                trait_def.name,
+                trait_def.type_params.len(),
+                impl_def.type_args.len()
+            ));
+        }
+        if let Some(name) = first_duplicate(&impl_def.methods) {
+            return Err(format!("Method '{}' implemented twice for type '{}'", name, impl_def.type_name));
+        }
+        if let Some(method) = impl_def.methods.iter().find(|m| trait_def.find_method(&m.name).is_none()) {
+            return Err(format!("Method '{}' is not a member of trait '{}'", method.name, trait_def.name));
         }
 
-        let trait_def = &self.traits[&impl_def.trait_name];
-
-        // Verify all trait methods are implemented
+        // `Self` is the implementing type; type parameters the impl does not
+        // give are inferred from its methods
+        let self_type = Type::Custom(impl_def.type_name.clone());
+        let mut bindings: HashMap<String, Type> = HashMap::from([("Self".to_string(), self_type)]);
+        bindings.extend(trait_def.type_params.iter().cloned().zip(impl_def.type_args.iter().cloned()));
         for method in &trait_def.methods {
-            if !impl_def.methods.contains_key(&method.name) {
+            let Some(provided) = impl_def.get_method(&method.name) else {
                 return Err(format!(
                     "Method '{}' not implemented for type '{}'",
                     method.name, impl_def.type_name
                 ));
+            };
+            let params_match = method.params.len() == provided.params.len()
+                && method
+                    .params
+                    .iter()
+                    .zip(&provided.params)
+                    .all(|((_, expected), (_, actual))| unify(expected, actual, &trait_def.type_params, &mut bindings));
+            let return_matches = match (&method.return_type, &provided.return_type) {
+                (Some(expected), Some(actual)) => unify(expected, actual, &trait_def.type_params, &mut bindings),
+                (expected, actual) => expected.is_none() && actual.is_none(),
+            };
+            if !params_match || !return_matches {
+                let bound: HashMap<&str, &Type> = bindings.iter().map(|(name, ty)| (name.as_str(), ty)).collect();
+                let expected = MethodSignature {
+                    name: method.name.clone(),
+                    params: method.params.iter().map(|(name, ty)| (name.clone(), ty.substitute(&bound))).collect(),
+                    return_type: method.return_type.as_ref().map(|ty| ty.substitute(&bound)),
+                };
+                return Err(format!(
+                    "Method '{}' for type '{}' has signature {}, but trait '{}' declares {}",
+                    method.name,
+                    impl_def.type_name,
+                    provided.describe(),
+                    trait_def.name,
+                    expected.describe()
+                ));
             }
         }
 
@@ -167,6 +330,30 @@ impl TraitRegistry {
         Ok(())
     }
 
+    /// Check that `type_name` implements `trait_name`, as a trait bound
+    /// requires
+    pub fn require(&self, type_name: &str, trait_name: &str) -> Result<(), String> {
+        if self.implements(type_name, trait_name) {
+            Ok(())
+        } else {
+            Err(format!("Type '{}' does not implement trait '{}'", type_name, trait_name))
+        }
+    }
+
+    /// Whether values of `ty` implement `trait_name`: through an impl for
+    /// their type, or as a `dyn` of that trait. `Copy` types, and `str`,
+    /// are `Clone` without an impl.
+    pub fn satisfies(&self, ty: &Type, trait_name: &str) -> bool {
+        match (ty, trait_name) {
+            (_, "Copy") => self.is_copy(ty, &[]),
+            (Type::Str, "Clone") => true,
+            (_, "Clone") if self.is_copy(ty, &[]) => true,
+            (Type::Custom(name) | Type::Generic { name, .. }, _) => self.implements(name, trait_name),
+            (Type::Dyn(name), _) => name == trait_name,
+            _ => false,
+        }
+    }
+
     /// Get a trait definition
     pub fn get_trait(&self, name: &str) -> Option<&Trait> {
         self.traits.get(name)
@@ -186,6 +373,48 @@ impl TraitRegistry {
             .find(|impl_def| impl_def.type_name == type_name && impl_def.trait_name == trait_name)
     }
 
+    /// The impl through which `type_name` provides `method`, which must
+    /// come from a single trait
+    pub fn resolve_method(&self, type_name: &str, method: &str) -> Result<Option<(&TraitImpl, &MethodSignature)>, String> {
+        let mut found = self
+            .implementations
+            .iter()
+            .filter(|impl_def| impl_def.type_name == type_name)
+            .filter_map(|impl_def| impl_def.get_method(method).map(|m| (impl_def, m)));
+        let first = found.next();
+        if let (Some((a, _)), Some((b, _))) = (first, found.next()) {
+            return Err(format!(
+                "Method '{}' of type '{}' is ambiguous: both trait '{}' and trait '{}' provide it",
+                method, type_name, a.trait_name, b.trait_name
+            ));
+        }
+        Ok(first)
+    }
+
+    /// The signature of `method` when called on a `dyn Trait`, which cannot
+    /// mention `Self` or the trait's type parameters since the vtable has
+    /// one entry for every implementing type
+    pub fn dyn_method(&self, trait_name: &str, method: &str) -> Result<Option<&MethodSignature>, String> {
+        let Some(trait_def) = self.traits.get(trait_name) else {
+            return Err(format!("Trait '{}' not found", trait_name));
+        };
+        let Some(signature) = trait_def.find_method(method) else {
+            return Ok(None);
+        };
+        let hidden: Vec<String> = ["Self".to_string()].into_iter().chain(trait_def.type_params.iter().cloned()).collect();
+        let mut types = signature.params.iter().map(|(_, ty)| ty).chain(&signature.return_type);
+        if types.any(|ty| ty.mentions_any(&hidden)) {
+            return Err(format!(
+                "Method '{}' of trait '{}' cannot be called through 'dyn {}': its signature {} depends on the implementing type",
+                method,
+                trait_name,
+                trait_name,
+                signature.describe()
+            ));
+        }
+        Ok(Some(signature))
+    }
+
     /// List all traits
     pub fn list_traits(&self) -> Vec<&str> {
         self.traits.keys().map(|s| s.as_str()).collect()
@@ -203,23 +432,35 @@ impl TraitRegistry {
 /// Built-in traits for common operations
 pub fn create_builtin_traits() -> TraitRegistry {
     let mut registry = TraitRegistry::new();
+    let self_type = || Type::Custom("Self".to_string());
 
     // Display trait: types that can be converted to string
     let mut display_trait = Trait::new("Display".to_string());
-    display_trait.add_method(MethodSignature::new("to_string".to_string()));
+    display_trait.add_method(MethodSignature::new("to_string".to_string()).with_return(Type::Str));
     let _ = registry.register_trait(display_trait);
 
-    // Clone trait: types that can be cloned
+    // Clone trait: types that can be cloned, which `Copy` types and `str`
+    // are without an impl
     let mut clone_trait = Trait::new("Clone".to_string());
-    clone_trait.add_method(MethodSignature::new("clone".to_string()));
+    clone_trait.add_method(MethodSignature::new("clone".to_string()).with_return(self_type()));
     let _ = registry.register_trait(clone_trait);
 
+    // Copy trait: a marker for types whose values are copied rather than
+    // moved, which follows from their fields
+    let _ = registry.register_trait(Trait::new("Copy".to_string()));
+
+    // Drop trait: types whose `drop` the compiler calls when a value of
+    // theirs goes out of scope without having been moved
+    let mut drop_trait = Trait::new("Drop".to_string());
+    drop_trait.add_method(MethodSignature::new("drop".to_string()));
+    let _ = registry.register_trait(drop_trait);
+
     // Eq trait: types that support equality
     let mut eq_trait = Trait::new("Eq".to_string());
     eq_trait.add_method(
         MethodSignature::new("eq".to_string())
-            .with_param("other".to_string(), TraitType::Custom("Self".to_string()))
-            .with_return(TraitType::Bool),
+            .with_param("other".to_string(), self_type())
+            .with_return(Type::Bool),
     );
     let _ = registry.register_trait(eq_trait);
 
@@ -227,14 +468,69 @@ pub fn create_builtin_traits() -> TraitRegistry {
     let mut ord_trait = Trait::new("Ord".to_string());
     ord_trait.add_method(
         MethodSignature::new("cmp".to_string())
-            .with_param("other".to_string(), TraitType::Custom("Self".to_string()))
-            .with_return(TraitType::Custom("Ordering".to_string())),
+            .with_param("other".to_string(), self_type())
+            .with_return(Type::Custom("Ordering".to_string())),
     );
     let _ = registry.register_trait(ord_trait);
 
+    // Iterator trait: types that `for` loops can step through
+    let mut iterator_trait = Trait::new("Iterator".to_string());
+    iterator_trait.type_params.push("Item".to_string());
+    iterator_trait.add_method(
+        MethodSignature::new("next".to_string())
+            .with_return(Type::Option(Box::new(Type::Custom("Item".to_string())))),
+    );
+    let _ = registry.register_trait(iterator_trait);
+
     registry
 }
 
+/// Name of the first method given twice in `methods`
+fn first_duplicate(methods: &[MethodSignature]) -> Option<&str> {
+    methods
+        .iter()
+        .enumerate()
+        .find(|(i, m)| methods[..*i].iter().any(|earlier| earlier.name == m.name))
+        .map(|(_, m)| m.name.as_str())
+}
+
+/// Whether `actual` is the trait's `expected` type, binding the trait's
+/// type parameters and `Self` as they are met
+fn unify(expected: &Type, actual: &Type, type_params: &[String], bindings: &mut HashMap<String, Type>) -> bool {
+    let all = |expected: &[Type], actual: &[Type], bindings: &mut HashMap<String, Type>| {
+        expected.len() == actual.len() && expected.iter().zip(actual).all(|(e, a)| unify(e, a, type_params, bindings))
+    };
+    match (expected, actual) {
+        (Type::Custom(name), _) if name == "Self" || type_params.contains(name) => match bindings.get(name) {
+            Some(bound) => bound == actual,
+            None => {
+                bindings.insert(name.clone(), actual.clone());
+                true
+            }
+        },
+        (Type::Option(e), Type::Option(a)) | (Type::Slice(e), Type::Slice(a)) | (Type::Range(e), Type::Range(a)) => {
+            unify(e, a, type_params, bindings)
+        }
+        (Type::Array(e, n), Type::Array(a, m)) => n == m && unify(e, a, type_params, bindings),
+        (Type::Result(e, f), Type::Result(a, b)) => unify(e, a, type_params, bindings) && unify(f, b, type_params, bindings),
+        (Type::Tuple(e), Type::Tuple(a)) => all(e, a, bindings),
+        (Type::Generic { name, type_args: e }, Type::Generic { name: actual_name, type_args: a }) => {
+            name == actual_name && all(e, a, bindings)
+        }
+        (
+            Type::FunctionPointer { params: e, return_type: r },
+            Type::FunctionPointer { params: a, return_type: b },
+        ) => {
+            all(e, a, bindings)
+                && match (r, b) {
+                    (Some(r), Some(b)) => unify(r, b, type_params, bindings),
+                    (r, b) => r.is_none() && b.is_none(),
+                }
+        }
+        _ => expected == actual,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -269,7 +565,7 @@ mod tests {
         registry.register_trait(trait_def).unwrap();
 
         let mut impl_def = TraitImpl::new("Display".to_string(), "i32".to_string());
-        impl_def.add_method("to_string".to_string(), "impl".to_string());
+        impl_def.add_method(MethodSignature::new("to_string".to_string()));
         assert!(registry.register_impl(impl_def).is_ok());
         assert!(registry.implements("i32", "Display"));
     }
@@ -281,5 +577,146 @@ mod tests {
         assert!(registry.get_trait("Clone").is_some());
         assert!(registry.get_trait("Eq").is_some());
         assert!(registry.get_trait("Ord").is_some());
+        assert!(registry.get_trait("Iterator").is_some());
+    }
+
+    #[test]
+    fn test_impl_checking() {
+        let shape = || {
+            Trait::new("Shape".to_string())
+                .with_method(MethodSignature::new("area".to_string()).with_return(Type::I32))
+                .with_method(
+                    MethodSignature::new("scaled".to_string())
+                        .with_param("by".to_string(), Type::I32)
+                        .with_return(Type::Custom("Self".to_string())),
+                )
+        };
+        let square = |methods: Vec<MethodSignature>| {
+            let mut impl_def = TraitImpl::new("Shape".to_string(), "Square".to_string());
+            for method in methods {
+                impl_def.add_method(method);
+            }
+            impl_def
+        };
+        let area = || MethodSignature::new("area".to_string()).with_return(Type::I32);
+        let scaled = |ret: &str| {
+            MethodSignature::new("scaled".to_string())
+                .with_param("factor".to_string(), Type::I32)
+                .with_return(Type::Custom(ret.to_string()))
+        };
+        let register = |impl_def: TraitImpl| {
+            let mut registry = TraitRegistry::new();
+            registry.register_trait(shape()).unwrap();
+            registry.register_impl(impl_def)
+        };
+
+        // Parameter names may differ; `Self` is the implementing type
+        assert!(register(square(vec![area(), scaled("Square")])).is_ok());
+        let err = register(square(vec![area(), scaled("Circle")])).unwrap_err();
+        assert!(err.contains("fn scaled(i32) -> Square"), "{}", err);
+        assert!(register(square(vec![area()])).unwrap_err().contains("not implemented"));
+        assert!(register(square(vec![area(), area(), scaled("Square")])).unwrap_err().contains("twice"));
+        let extra = MethodSignature::new("perimeter".to_string());
+        assert!(register(square(vec![area(), scaled("Square"), extra])).unwrap_err().contains("not a member"));
+
+        let mut registry = TraitRegistry::new();
+        registry.register_trait(shape()).unwrap();
+        assert!(registry.require("Square", "Shape").is_err());
+        registry.register_impl(square(vec![area(), scaled("Square")])).unwrap();
+        assert!(registry.require("Square", "Shape").is_ok());
+        assert!(registry.register_impl(square(vec![area(), scaled("Square")])).is_err());
+        let twice = Trait::new("Twice".to_string()).with_method(area()).with_method(area());
+        assert!(registry.register_trait(twice).is_err());
+    }
+
+    #[test]
+    fn test_iterator_item_inference() {
+        let mut registry = create_builtin_traits();
+        let next = |item: Type| MethodSignature::new("next".to_string()).with_return(Type::Option(Box::new(item)));
+        let mut counter = TraitImpl::new("Iterator".to_string(), "Counter".to_string());
+        counter.add_method(next(Type::I64));
+        assert!(registry.register_impl(counter).is_ok());
+        let mut words = TraitImpl::new("Iterator".to_string(), "Words".to_string());
+        words.type_args.push(Type::Str);
+        words.add_method(next(Type::I32));
+        assert!(registry.register_impl(words).is_err());
+    }
+
+    #[test]
+    fn test_method_resolution() {
+        let mut registry = create_builtin_traits();
+        let area = || MethodSignature::new("area".to_string()).with_return(Type::I32);
+        registry.register_trait(Trait::new("Shape".to_string()).with_method(area())).unwrap();
+        registry.register_trait(Trait::new("Solid".to_string()).with_method(area())).unwrap();
+        let mut shape = TraitImpl::new("Shape".to_string(), "Cube".to_string());
+        shape.add_method(area());
+        registry.register_impl(shape).unwrap();
+
+        let (impl_def, _) = registry.resolve_method("Cube", "area").unwrap().unwrap();
+        assert_eq!(impl_def.trait_name, "Shape");
+        assert!(registry.resolve_method("Cube", "volume").unwrap().is_none());
+        let mut solid = TraitImpl::new("Solid".to_string(), "Cube".to_string());
+        solid.add_method(area());
+        registry.register_impl(solid).unwrap();
+        assert!(registry.resolve_method("Cube", "area").unwrap_err().contains("ambiguous"));
+
+        // Through `dyn`, a method cannot depend on the implementing type
+        assert!(registry.dyn_method("Shape", "area").unwrap().is_some());
+        assert!(registry.dyn_method("Eq", "eq").unwrap_err().contains("fn eq(Self) -> bool"));
+        assert!(registry.dyn_method("Iterator", "next").is_err());
+        assert!(registry.dyn_method("Drawable", "draw").is_err());
+    }
+
+    #[test]
+    fn test_trait_bounds() {
+        let mut registry = create_builtin_traits();
+        let mut display = TraitImpl::new("Display".to_string(), "Point".to_string());
+        display.add_method(MethodSignature::new("to_string".to_string()).with_return(Type::Str));
+        registry.register_impl(display).unwrap();
+
+        assert!(registry.satisfies(&Type::Custom("Point".to_string()), "Display"));
+        assert!(!registry.satisfies(&Type::Custom("Point".to_string()), "Clone"));
+        assert!(registry.satisfies(&Type::Dyn("Clone".to_string()), "Clone"));
+        assert!(!registry.satisfies(&Type::I32, "Display"));
+    }
+
+    #[test]
+    fn test_copy_types() {
+        let source = "type Point { x: i32, y: f64 }\ntype Named { name: str }\ntype Pair<T> { a: T, b: T }\n\
+            enum Shape { Dot(Point), Label(Named) }\nenum Dir { Up, Down }";
+        let mut registry = create_builtin_traits();
+        for decl in crate::parser::Parser::new(crate::lexer::Lexer::new(source.to_string()).tokenize()).parse().unwrap() {
+            registry.declare_type(&decl);
+        }
+        let named = |name: &str| Type::Custom(name.to_string());
+        let pair = |arg: Type| Type::Generic { name: "Pair".to_string(), type_args: vec![arg] };
+        assert!(registry.is_copy(&Type::I32, &[]) && registry.is_copy(&Type::Bool, &[]));
+        assert!(!registry.is_copy(&Type::Str, &[]) && !registry.is_copy(&Type::Ptr, &[]));
+        assert!(registry.is_copy(&named("Point"), &[]) && registry.is_copy(&named("Dir"), &[]));
+        assert!(!registry.is_copy(&named("Named"), &[]) && !registry.is_copy(&named("Shape"), &[]));
+        assert!(registry.is_copy(&pair(named("Point")), &[]) && !registry.is_copy(&pair(Type::Str), &[]));
+        assert!(registry.is_copy(&named("T"), &["T".to_string()]) && !registry.is_copy(&named("T"), &[]));
+
+        // Copy types and `str` are Clone without an impl; Copy has none
+        assert!(registry.satisfies(&named("Point"), "Clone") && registry.satisfies(&Type::Str, "Clone"));
+        assert!(!registry.satisfies(&named("Named"), "Clone"));
+        assert!(registry.register_impl(TraitImpl::new("Copy".to_string(), "Named".to_string())).is_err());
+    }
+
+    #[test]
+    fn test_drop_types() {
+        let source = "type Handle { fd: i32 }\ntype Pair<T> { a: T, b: T }";
+        let mut registry = create_builtin_traits();
+        for decl in crate::parser::Parser::new(crate::lexer::Lexer::new(source.to_string()).tokenize()).parse().unwrap() {
+            registry.declare_type(&decl);
+        }
+        let mut drop = TraitImpl::new("Drop".to_string(), "Handle".to_string());
+        drop.add_method(MethodSignature::new("drop".to_string()));
+        assert!(registry.register_impl(drop).is_ok());
+        let handle = Type::Custom("Handle".to_string());
+        assert!(registry.implements("Handle", "Drop"));
+        // A copy would be dropped twice
+        assert!(!registry.is_copy(&handle, &[]) && !registry.satisfies(&handle, "Clone"));
+        assert!(!registry.is_copy(&Type::Generic { name: "Pair".to_string(), type_args: vec![handle] }, &[]));
     }
 }
@@ -1,8 +1,11 @@
 // type_checker.rs — U v0.8
 // MIT License — Copyright (c) 2025 Webcien and U contributors
 
-use crate::parser::{Declaration, Expression, Function, Literal, Statement, Type, Actor, Trait, TypeDef, TraitImpl};
-use std::collections::HashMap;
+use crate::diagnostics::Span;
+use crate::exhaustiveness::MatchChecker;
+use crate::traits::{self, TraitRegistry};
+use crate::parser::{ArmBody, BinaryOp, Declaration, EnumDef, Expression, ExpressionKind, Function, Literal, MatchArm, Pattern, PatternKind, Statement, StatementKind, Type, UnaryOp, TypeDef, TraitImpl, VariantPayload};
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 
 #[derive(Debug)]
@@ -13,8 +16,49 @@ pub enum TypeError {
         location: String,
     },
     UnknownVariable(String, String),
+    UnassignedVariable(String, String),
+    UnknownFunction(String, String),
+    UnknownMethod(String, String),
     InvalidOwnership(String),
     NullNotAllowed(String),
+    UnknownEnum(String, String),
+    UnknownVariant(String, String),
+    UnknownStruct(String, String),
+    UnknownField(String, String),
+    /// Struct literal that leaves a field out
+    MissingField(String),
+    /// Constant index past the end of an array
+    IndexOutOfBounds(String),
+    /// Unsuffixed integer literal outside the range of the type it takes
+    LiteralOutOfRange(String),
+    /// Function with a return type whose end can be reached
+    MissingReturn(String),
+    /// `for` over a value that is not a range, array, slice or iterator
+    NotIterable(String, String),
+    /// Constructor payload does not match the variant's declaration
+    InvalidPayload(String),
+    DuplicateDefinition(String),
+    /// A type that contains itself by value
+    RecursiveType(String),
+    NonExhaustive {
+        missing: String,
+        location: String,
+    },
+    UnreachablePattern(String),
+    InvalidPattern(String),
+    /// Trait declaration or impl that does not fit, or a missing impl
+    InvalidTrait(String),
+    /// Type parameter of a call or struct literal that neither the values
+    /// given nor the type expected determine
+    UninferredType(String, String),
+    /// Type argument that does not implement a trait its parameter's
+    /// bound names
+    UnsatisfiedBound {
+        bound: String,
+        actual: String,
+        location: String,
+    },
+    Unsupported(String),
 }
 
 pub type Result<T> = std::result::Result<T, TypeError>;
@@ -28,8 +72,52 @@ impl fmt::Display for TypeError {
             TypeError::UnknownVariable(name, context) => {
                 write!(f, "Unknown variable {} {}", name, context)
             }
+            TypeError::UnassignedVariable(name, context) => {
+                write!(f, "Variable {} may be used before it is assigned {}", name, context)
+            }
+            TypeError::UnknownFunction(name, context) => {
+                write!(f, "Unknown function {} {}", name, context)
+            }
+            TypeError::UnknownMethod(name, context) => {
+                write!(f, "Unknown method {} {}", name, context)
+            }
             TypeError::InvalidOwnership(msg) => write!(f, "Ownership error: {}", msg),
             TypeError::NullNotAllowed(msg) => write!(f, "Null not allowed: {}", msg),
+            TypeError::UnknownEnum(name, context) => {
+                write!(f, "Unknown enum {} {}", name, context)
+            }
+            TypeError::UnknownVariant(name, context) => {
+                write!(f, "Unknown variant {} {}", name, context)
+            }
+            TypeError::UnknownStruct(name, context) => {
+                write!(f, "Unknown struct {} {}", name, context)
+            }
+            TypeError::UnknownField(name, context) => {
+                write!(f, "Unknown field {} {}", name, context)
+            }
+            TypeError::MissingField(msg) => write!(f, "Missing field: {}", msg),
+            TypeError::IndexOutOfBounds(msg) => write!(f, "Index out of bounds: {}", msg),
+            TypeError::LiteralOutOfRange(msg) => write!(f, "Literal out of range: {}", msg),
+            TypeError::MissingReturn(msg) => write!(f, "Missing return: {}", msg),
+            TypeError::NotIterable(ty, context) => write!(f, "{} is not iterable {}", ty, context),
+            TypeError::InvalidPayload(msg) => write!(f, "Invalid payload: {}", msg),
+            TypeError::DuplicateDefinition(msg) => write!(f, "Duplicate definition: {}", msg),
+            TypeError::RecursiveType(name) => {
+                write!(f, "Recursive type {} has infinite size", name)
+            }
+            TypeError::NonExhaustive { missing, location } => {
+                write!(f, "Non-exhaustive match {}: {} not covered", location, missing)
+            }
+            TypeError::UnreachablePattern(location) => write!(f, "Unreachable pattern {}", location),
+            TypeError::InvalidPattern(msg) => write!(f, "Invalid pattern: {}", msg),
+            TypeError::InvalidTrait(msg) => write!(f, "Trait error: {}", msg),
+            TypeError::UninferredType(name, context) => {
+                write!(f, "Cannot infer type parameter {} {}", name, context)
+            }
+            TypeError::UnsatisfiedBound { bound, actual, location } => {
+                write!(f, "Trait bound {} not satisfied for {}: {} does not implement it", bound, location, actual)
+            }
+            TypeError::Unsupported(msg) => write!(f, "Unsupported: {}", msg),
         }
     }
 }
@@ -41,19 +129,117 @@ pub struct Symbol {
     pub ty: Type,
     pub mutable: bool,
     pub moved: bool,
+    pub init: Init,
+    /// Loops and closures entered where the variable is declared
+    pub depth: usize,
+}
+
+/// Whether a variable holds a value where it is used
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Init {
+    Assigned,
+    /// Declared without a value and not assigned on any path yet
+    Unassigned,
+    /// Assigned on some paths only
+    MaybeAssigned,
+}
+
+/// Assignment state of the variables not definitely assigned, by scope
+/// index and name
+type Assignments = HashMap<(usize, String), Init>;
+
+/// Parameter and return types of a function or method
+#[derive(Clone, Debug)]
+struct Signature {
+    params: Vec<Type>,
+    return_type: Option<Type>,
+    type_params: Vec<String>,
+    bounds: Vec<(String, String)>,
+    /// An extern `...` function, which takes more arguments after `params`
+    variadic: bool,
+}
+
+impl Signature {
+    fn of(f: &Function) -> Self {
+        Self {
+            params: f.params.iter().map(|(_, ty)| ty.clone()).collect(),
+            return_type: f.return_type.clone(),
+            type_params: f.type_params.clone(),
+            bounds: f.bounds.clone(),
+            variadic: false,
+        }
+    }
+}
+
+/// What a `return` in the function or closure being checked must give
+#[derive(Clone, Debug)]
+enum Returns {
+    /// The declared return type; `None` for a function without one
+    Declared(Option<Type>),
+    /// `main` without a declared type, which may return an exit code
+    ExitCode,
+    /// A closure whose return type follows from its body
+    Inferred,
 }
 
 pub struct TypeChecker {
     scopes: Vec<HashMap<String, Symbol>>,
+    enums: HashMap<String, EnumDef>,
+    structs: HashMap<String, TypeDef>,
+    /// Signature of every function, including extern ones
+    functions: HashMap<String, Signature>,
+    /// Signature of every method of every actor, by actor then method name
+    actors: HashMap<String, HashMap<String, Signature>>,
+    /// Item type of every type with an `impl Iterator`
+    iterators: HashMap<String, Type>,
+    /// Declared and built-in traits, and the impls of them
+    traits: TraitRegistry,
+    /// Type parameters of the function or struct being checked
+    type_params: Vec<String>,
+    /// `(T, Trait)` for every bound on those type parameters
+    bounds: Vec<(String, String)>,
+    /// Innermost last: the function being checked, then enclosing closures
+    returns: Vec<Returns>,
+    /// Loops and closures entered at the statement being checked
+    depth: usize,
+    /// Type arguments of every call of a generic function and of every
+    /// generic struct literal that leaves them out, by its span
+    instantiations: HashMap<Span, Vec<Type>>,
+    /// Integer type other than `i32` that each unsuffixed integer literal
+    /// given one takes, by its span
+    literal_types: HashMap<Span, Type>,
 }
 
 impl TypeChecker {
     pub fn new() -> Self {
         Self {
             scopes: vec![HashMap::new()],
+            enums: HashMap::new(),
+            structs: HashMap::new(),
+            functions: HashMap::new(),
+            actors: HashMap::new(),
+            iterators: HashMap::new(),
+            traits: traits::create_builtin_traits(),
+            type_params: Vec::new(),
+            bounds: Vec::new(),
+            returns: Vec::new(),
+            depth: 0,
+            instantiations: HashMap::new(),
+            literal_types: HashMap::new(),
         }
     }
 
+    /// Type arguments inferred for generic calls and struct literals, in
+    /// terms of the type parameters of the function they appear in
+    pub fn instantiations(&self) -> &HashMap<Span, Vec<Type>> {
+        &self.instantiations
+    }
+
+    /// Types of unsuffixed integer literals that are not `i32`
+    pub fn literal_types(&self) -> &HashMap<Span, Type> {
+        &self.literal_types
+    }
+
     fn current_scope(&mut self) -> &mut HashMap<String, Symbol> {
         self.scopes.last_mut().unwrap()
     }
@@ -69,30 +255,95 @@ impl TypeChecker {
     pub fn check_program(&mut self, declarations: Vec<Declaration>) -> Result<()> {
         for decl in &declarations {
             match decl {
-                Declaration::Function(_) => {
-                    // Function signature registration
+                Declaration::Function(f) => {
+                    self.functions.insert(f.name.clone(), Signature::of(f));
                 }
-                Declaration::Actor(_) => {
-                    // Actor registration deferred to v0.9
+                Declaration::Actor(a) => {
+                    // A spawned actor's handle is typed by the actor's name
+                    let methods = a.methods.iter().map(|m| (m.name.clone(), Signature::of(m))).collect();
+                    if self.structs.contains_key(&a.name)
+                        || self.enums.contains_key(&a.name)
+                        || self.actors.insert(a.name.clone(), methods).is_some()
+                    {
+                        return Err(TypeError::DuplicateDefinition(format!(
+                            "actor {} at {}", a.name, a.span
+                        )));
+                    }
                 }
-                Declaration::Trait(_) => {
-                    // Trait registration deferred to v0.9
+                Declaration::Trait(t) => {
+                    self.traits
+                        .register_trait(traits::Trait::from(t))
+                        .map_err(|e| TypeError::InvalidTrait(format!("{} at {}", e, t.span)))?;
                 }
-                Declaration::TypeDef(_) => {
-                    // Type definition registration deferred to v0.9
+                Declaration::TypeDef(t) => {
+                    self.traits.declare_type(decl);
+                    if self.enums.contains_key(&t.name) || self.structs.insert(t.name.clone(), t.clone()).is_some() {
+                        return Err(TypeError::DuplicateDefinition(format!(
+                            "type {} at {}", t.name, t.span
+                        )));
+                    }
                 }
-                Declaration::TraitImpl(_) => {
-                    // Trait implementation registration deferred to v0.9
+                Declaration::Enum(e) => {
+                    self.traits.declare_type(decl);
+                    if self.structs.contains_key(&e.name) || self.enums.insert(e.name.clone(), e.clone()).is_some() {
+                        return Err(TypeError::DuplicateDefinition(format!(
+                            "enum {} at {}", e.name, e.span
+                        )));
+                    }
                 }
-                Declaration::ExternBlock(_) => {
-                    // FFI: extern function registration
+                Declaration::TraitImpl(i) => {
+                    if i.trait_name == "Iterator" {
+                        self.register_iterator(i)?;
+                    }
+                }
+                Declaration::ExternBlock(block) => {
+                    for f in &block.functions {
+                        let signature = Signature {
+                            params: f.params.iter().map(|(_, ty)| ty.clone()).collect(),
+                            return_type: f.return_type.clone(),
+                            type_params: Vec::new(),
+                            bounds: Vec::new(),
+                            variadic: f.is_variadic,
+                        };
+                        self.functions.insert(f.name.clone(), signature);
+                    }
+                }
+                Declaration::UiBlock(ui) => {
+                    // Widget validation deferred to later; the block renders
+                    // through a generated `render_ui_<name>(canvas)`
+                    let signature = Signature {
+                        params: vec![Type::Ptr],
+                        return_type: None,
+                        type_params: Vec::new(),
+                        bounds: Vec::new(),
+                        variadic: false,
+                    };
+                    self.functions.insert(format!("render_ui_{}", ui.name), signature);
                 }
-                Declaration::UiBlock(_) => {
-                    // UI DSL: widget validation deferred to later
+                Declaration::Import(_) => {
+                    // Imports: resolved by the module loader before checking
+                }
+                Declaration::Error(_) => {
+                    // Syntax error: already reported by the parser
                 }
             }
         }
 
+        // Impls may come before the traits they implement
+        for decl in &declarations {
+            if let Declaration::TraitImpl(i) = decl {
+                if !self.structs.contains_key(&i.type_name) && !self.enums.contains_key(&i.type_name) {
+                    return Err(TypeError::InvalidTrait(format!(
+                        "impl {} for {} at {}, which is not a declared type",
+                        i.trait_name, i.type_name, i.span
+                    )));
+                }
+                self.traits
+                    .register_impl(traits::TraitImpl::from(i))
+                    .map_err(|e| TypeError::InvalidTrait(format!("{} at {}", e, i.span)))?;
+            }
+        }
+
         for decl in declarations {
             self.check_declaration(decl)?;
         }
@@ -101,18 +352,22 @@ impl TypeChecker {
 
     fn check_declaration(&mut self, decl: Declaration) -> Result<()> {
         match decl {
-            Declaration::Function(f) => self.check_function(f)?,
-            Declaration::Actor(_) => {
-                // Actors: message passing verification deferred to v0.9
+            Declaration::Function(f) => self.check_function(f, None)?,
+            Declaration::Actor(a) => {
+                for method in a.methods {
+                    self.check_function(method, None)?;
+                }
             }
             Declaration::Trait(_) => {
-                // Traits: method signature verification deferred to v0.9
+                // Traits: registered with their signatures before checking
             }
-            Declaration::TypeDef(_) => {
-                // Type definitions: field verification deferred to v0.9
-            }
-            Declaration::TraitImpl(_) => {
-                // Trait implementations: method verification deferred to v0.9
+            Declaration::TypeDef(t) => self.check_struct(t)?,
+            Declaration::Enum(e) => self.check_enum(&e)?,
+            Declaration::TraitImpl(i) => {
+                // Signatures were checked against the trait on registration
+                for method in i.methods {
+                    self.check_function(method, Some(&i.type_name))?;
+                }
             }
             Declaration::ExternBlock(_) => {
                 // FFI: extern blocks are validated at parse time
@@ -121,106 +376,532 @@ impl TypeChecker {
             Declaration::UiBlock(_) => {
                 // UI DSL: widget type checking deferred to later
             }
+            Declaration::Import(_) => {
+                // Imports: resolved by the module loader before checking
+            }
+            Declaration::Error(_) => {
+                // Syntax error: already reported by the parser
+            }
+        }
+        Ok(())
+    }
+
+    /// Record the item type of an `impl Iterator<Item> for T`, whose
+    /// `next()` must return `Option<Item>`
+    fn register_iterator(&mut self, i: &TraitImpl) -> Result<()> {
+        let location = format!("impl Iterator for {} at {}", i.type_name, i.span);
+        let Some(item) = i.iterator_item() else {
+            let actual = match i.methods.iter().find(|m| m.name == "next") {
+                Some(next) if !next.params.is_empty() => format!("fn next with {} parameters", next.params.len()),
+                Some(next) => format!(
+                    "fn next() -> {}",
+                    next.return_type.as_ref().map_or("()".to_string(), |ty| self.type_to_string(ty))
+                ),
+                None => "no next method".to_string(),
+            };
+            return Err(TypeError::Mismatch {
+                expected: "fn next() -> Option<Item>".to_string(),
+                actual,
+                location,
+            });
+        };
+        if let Some(declared) = i.type_args.first() {
+            if *declared != item {
+                return Err(TypeError::Mismatch {
+                    expected: format!("next() -> Option<{}>", self.type_to_string(declared)),
+                    actual: format!("next() -> Option<{}>", self.type_to_string(&item)),
+                    location,
+                });
+            }
+        }
+        if self.iterators.insert(i.type_name.clone(), item).is_some() {
+            return Err(TypeError::DuplicateDefinition(location));
         }
         Ok(())
     }
 
-    fn check_function(&mut self, f: Function) -> Result<()> {
+    fn check_enum(&self, e: &EnumDef) -> Result<()> {
+        let mut variants = HashSet::new();
+        for variant in &e.variants {
+            if !variants.insert(&variant.name) {
+                return Err(TypeError::DuplicateDefinition(format!(
+                    "variant {}::{} at {}", e.name, variant.name, variant.span
+                )));
+            }
+            let mut fields = HashSet::new();
+            for (field, ty) in variant.payload.fields() {
+                if !fields.insert(field.clone()) {
+                    return Err(TypeError::DuplicateDefinition(format!(
+                        "field {} of {}::{} at {}", field, e.name, variant.name, variant.span
+                    )));
+                }
+                if self.contains_by_value(ty, &e.name, &mut HashSet::new()) {
+                    return Err(TypeError::RecursiveType(e.name.clone()));
+                }
+                check_references(ty, false, &format!("field {} of {}::{} at {}", field, e.name, variant.name, variant.span))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn check_struct(&mut self, t: TypeDef) -> Result<()> {
+        self.type_params = t.type_params;
+        self.bounds = t.bounds;
+        self.check_bound_traits(&format!("{} at {}", t.name, t.span))?;
+        let mut fields = HashSet::new();
+        for (field, ty) in &t.fields {
+            if !fields.insert(field) {
+                return Err(TypeError::DuplicateDefinition(format!(
+                    "field {} of {} at {}", field, t.name, t.span
+                )));
+            }
+            if self.contains_by_value(ty, &t.name, &mut HashSet::new()) {
+                return Err(TypeError::RecursiveType(t.name.clone()));
+            }
+            check_references(ty, false, &format!("field {} of {} at {}", field, t.name, t.span))?;
+            self.check_type(ty, t.span)?;
+        }
+        Ok(())
+    }
+
+    /// Whether values of `ty` embed a value of type `target`, directly or
+    /// through the fields of another enum or struct
+    fn contains_by_value<'a>(&'a self, ty: &'a Type, target: &str, seen: &mut HashSet<&'a str>) -> bool {
+        match ty {
+            Type::Custom(name) | Type::Generic { name, .. } if name == target => true,
+            Type::Custom(name) | Type::Generic { name, .. } if !seen.insert(name) => false,
+            Type::Custom(name) | Type::Generic { name, .. } => {
+                if let Some(e) = self.enums.get(name) {
+                    e.variants.iter().any(|v| {
+                        v.payload
+                            .fields()
+                            .into_iter()
+                            .any(|(_, ty)| self.contains_by_value(ty, target, seen))
+                    })
+                } else if let Some(s) = self.structs.get(name) {
+                    s.fields.iter().any(|(_, ty)| self.contains_by_value(ty, target, seen))
+                } else {
+                    false
+                }
+            }
+            Type::Option(inner) | Type::Array(inner, _) => self.contains_by_value(inner, target, seen),
+            Type::Result(ok, err) => {
+                self.contains_by_value(ok, target, seen) || self.contains_by_value(err, target, seen)
+            }
+            _ => false,
+        }
+    }
+
+    /// Check a function, or a method of the type `receiver`, which its
+    /// body reaches as `self`
+    fn check_function(&mut self, f: Function, receiver: Option<&str>) -> Result<()> {
         self.enter_scope();
+        if let Some(receiver) = receiver {
+            // Methods take their receiver by pointer and may update it
+            self.declare("self".to_string(), Type::Custom(receiver.to_string()), true);
+        }
+        self.type_params = f.type_params;
+        self.bounds = f.bounds;
+        self.check_bound_traits(&format!("{} at {}", f.name, f.span))?;
+        for ty in f.params.iter().map(|(_, ty)| ty).chain(&f.return_type) {
+            check_references(ty, true, &format!("{} at {}", f.name, f.span))?;
+            self.check_type(ty, f.span)?;
+        }
+        if let Some(ty @ Type::Dyn(_)) = &f.return_type {
+            return Err(dyn_error(ty, &format!("the return type of {} at {}", f.name, f.span)));
+        }
+        // A function with a value to return must not fall off its end
+        let falls_through = f.return_type.as_ref().filter(|_| !diverges(&f.body)).map(|ty| self.type_to_string(ty));
+        self.returns.push(match f.return_type {
+            None if f.name == "main" => Returns::ExitCode,
+            declared => Returns::Declared(declared),
+        });
 
         for (param_name, param_type) in f.params {
-            self.current_scope().insert(
-                param_name,
-                Symbol {
-                    ty: param_type,
-                    mutable: false,
-                    moved: false,
-                },
-            );
+            self.declare(param_name, param_type, false);
         }
 
         for stmt in f.body {
             self.check_statement(stmt)?;
         }
+        if let Some(ty) = falls_through {
+            return Err(TypeError::MissingReturn(format!(
+                "{} at {} returns {} but can reach the end of its body",
+                f.name, f.span, ty
+            )));
+        }
 
+        self.returns.pop();
         self.exit_scope();
         Ok(())
     }
 
+    /// Check that every bound of the item being checked names a trait
+    fn check_bound_traits(&self, item: &str) -> Result<()> {
+        match self.bounds.iter().find(|(_, trait_name)| self.traits.get_trait(trait_name).is_none()) {
+            Some((param, trait_name)) => Err(TypeError::InvalidTrait(format!(
+                "Trait '{}' not found, in bound {}: {} of {}",
+                trait_name, param, trait_name, item
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Check that the generic structs `ty` mentions are given type
+    /// arguments that satisfy their bounds
+    fn check_type(&self, ty: &Type, span: Span) -> Result<()> {
+        match ty {
+            Type::Generic { name, type_args } => {
+                if let Some(def) = self.structs.get(name) {
+                    let location = format!("{} at {}", self.type_to_string(ty), span);
+                    self.check_bounds(&def.type_params, &def.bounds, type_args, &location)?;
+                }
+                type_args.iter().try_for_each(|arg| self.check_type(arg, span))
+            }
+            Type::Option(inner) | Type::Array(inner, _) | Type::Slice(inner) | Type::Range(inner) | Type::Ref(inner) => {
+                self.check_type(inner, span)
+            }
+            Type::Result(ok, err) => {
+                self.check_type(ok, span)?;
+                self.check_type(err, span)
+            }
+            Type::Tuple(items) => items.iter().try_for_each(|item| self.check_type(item, span)),
+            Type::FunctionPointer { params, return_type } => {
+                params.iter().chain(return_type.as_deref()).try_for_each(|ty| self.check_type(ty, span))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Check that the type arguments given for `type_params` implement
+    /// the traits `bounds` requires of them
+    fn check_bounds(&self, type_params: &[String], bounds: &[(String, String)], type_args: &[Type], location: &str) -> Result<()> {
+        for (param, trait_name) in bounds {
+            let Some(ty) = type_params.iter().position(|p| p == param).and_then(|i| type_args.get(i)) else {
+                continue;
+            };
+            if !self.satisfies(ty, trait_name) {
+                return Err(TypeError::UnsatisfiedBound {
+                    bound: format!("{}: {}", param, trait_name),
+                    actual: self.type_to_string(ty),
+                    location: location.to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether values of `ty` implement `trait_name`. A type parameter of
+    /// the item being checked has the traits its own bounds name, and is
+    /// `Clone` if it is `Copy`.
+    fn satisfies(&self, ty: &Type, trait_name: &str) -> bool {
+        match ty {
+            Type::Custom(name) if self.type_params.contains(name) => self
+                .bounds
+                .iter()
+                .any(|(p, t)| p == name && (t == trait_name || (t == "Copy" && trait_name == "Clone"))),
+            _ => self.traits.satisfies(ty, trait_name),
+        }
+    }
+
+    /// The trait method `method` of the bounds on type parameter `param`,
+    /// which must come from a single trait
+    fn bound_method(&self, param: &str, method: &str) -> std::result::Result<Option<&traits::MethodSignature>, String> {
+        let mut found = self
+            .bounds
+            .iter()
+            .filter(|(p, _)| p == param)
+            .filter_map(|(_, trait_name)| self.traits.get_trait(trait_name)?.find_method(method).map(|m| (trait_name, m)));
+        let first = found.next();
+        if let (Some((a, _)), Some((b, _))) = (first, found.next()) {
+            return Err(format!(
+                "Method '{}' of type parameter '{}' is ambiguous: both trait '{}' and trait '{}' provide it",
+                method, param, a, b
+            ));
+        }
+        Ok(first.map(|(_, m)| m))
+    }
+
+    /// Signature of `method` on a value of type `receiver`, found through
+    /// the impls of its type, the bounds of a type parameter or, for
+    /// `dyn Trait`, the trait itself
+    fn method_signature(&self, receiver: &Type, method_name: &str) -> std::result::Result<Option<Signature>, String> {
+        let (method, self_type) = match receiver {
+            Type::Custom(name) if self.type_params.contains(name) => (self.bound_method(name, method_name)?, receiver.clone()),
+            Type::Custom(name) | Type::Generic { name, .. } => {
+                (self.traits.resolve_method(name, method_name)?.map(|(_, m)| m), Type::Custom(name.clone()))
+            }
+            Type::Dyn(trait_name) => (self.traits.dyn_method(trait_name, method_name)?, receiver.clone()),
+            _ => (None, receiver.clone()),
+        };
+        // `Copy` types and `str` clone without an impl of `Clone`
+        if method.is_none() && method_name == "clone" && self.satisfies(receiver, "Clone") {
+            return Ok(Some(Signature {
+                params: Vec::new(),
+                return_type: Some(receiver.clone()),
+                type_params: Vec::new(),
+                bounds: Vec::new(),
+                variadic: false,
+            }));
+        }
+        let bindings = HashMap::from([("Self", &self_type)]);
+        Ok(method.map(|method| Signature {
+            params: method.params.iter().map(|(_, ty)| ty.substitute(&bindings)).collect(),
+            return_type: method.return_type.as_ref().map(|ty| ty.substitute(&bindings)),
+            type_params: Vec::new(),
+            bounds: Vec::new(),
+            variadic: false,
+        }))
+    }
+
     fn check_statement(&mut self, stmt: Statement) -> Result<()> {
-        match stmt {
-            Statement::Let { name, mutable, value } => {
-                let value_ty = self.check_expression(value)?;
-                self.current_scope().insert(
-                    name,
-                    Symbol {
-                        ty: value_ty,
-                        mutable,
-                        moved: false,
-                    },
-                );
-            }
-            Statement::Expr(expr) => {
-                self.check_expression(expr)?;
-            }
-            Statement::Return(expr) => {
-                self.check_expression(expr)?;
-            }
-            Statement::If { condition, then_branch, else_branch } => {
-                let _cond_ty = self.check_expression(condition)?;
-                for stmt in then_branch {
-                    self.check_statement(stmt)?;
-                }
-                if let Some(else_stmts) = else_branch {
-                    for stmt in else_stmts {
-                        self.check_statement(stmt)?;
-                    }
+        match stmt.kind {
+            StatementKind::Let { name, mutable, ty, value: None } => {
+                // The parser only leaves out the value after a type
+                let ty = ty.unwrap_or_else(unit);
+                check_references(&ty, true, &format!("{} at {}", name, stmt.span))?;
+                self.check_type(&ty, stmt.span)?;
+                self.declare(name.clone(), ty, mutable);
+                if let Some(symbol) = self.current_scope().get_mut(&name) {
+                    symbol.init = Init::Unassigned;
                 }
             }
-            Statement::While { condition, body } => {
-                let _cond_ty = self.check_expression(condition)?;
-                for stmt in body {
-                    self.check_statement(stmt)?;
+            StatementKind::Let { name, mutable, ty, value: Some(value) } => {
+                if let Some(ty) = &ty {
+                    self.check_type(ty, stmt.span)?;
                 }
+                let value_span = value.span;
+                let value_ty = self.check_value(value, true, ty.as_ref())?;
+                check_references(&value_ty, true, &format!("{} at {}", name, stmt.span))?;
+                if value_ty == unit() {
+                    return Err(TypeError::Mismatch {
+                        expected: format!("a value for {}", name),
+                        actual: self.type_to_string(&value_ty),
+                        location: value_span.to_string(),
+                    });
+                }
+                self.declare(name, value_ty, mutable);
+            }
+            StatementKind::Expr(expr) => {
+                self.check_value(expr, false, None)?;
             }
-            Statement::For { variable, iterable, body } => {
-                let _iter_ty = self.check_expression(iterable)?;
-                self.current_scope().insert(
-                    variable,
-                    Symbol {
-                        ty: Type::I32,
-                        mutable: false,
-                        moved: false,
-                    },
-                );
-                for stmt in body {
-                    self.check_statement(stmt)?;
+            StatementKind::Return(expr) => self.check_return(expr)?,
+            StatementKind::If { condition, then_branch, else_branch } => {
+                self.check_expected(condition, &Type::Bool)?;
+                let before = self.assignments();
+                let mut paths = Vec::new();
+                for branch in [then_branch, else_branch.unwrap_or_default()] {
+                    self.restore(&before);
+                    let diverges = diverges(&branch);
+                    self.check_block(branch)?;
+                    if !diverges {
+                        paths.push(self.assignments());
+                    }
                 }
+                self.restore(&merge(&before, &paths));
+            }
+            StatementKind::While { condition, body } => {
+                self.check_expected(condition, &Type::Bool)?;
+                self.check_loop_body(None, body)?;
             }
-            Statement::Break | Statement::Continue => {
+            StatementKind::For { variable, iterable, body } => {
+                let iterable_span = iterable.span;
+                let iterable_ty = self.check_expression(iterable)?;
+                let item_ty = self.item_type(&iterable_ty, iterable_span)?;
+                self.check_loop_body(Some((variable, item_ty)), body)?;
+            }
+            StatementKind::Break | StatementKind::Continue => {
                 // Valid in loop context
             }
-            Statement::Unsafe { body } => {
+            StatementKind::Unsafe { body } => {
                 // Unsafe blocks: FFI calls and other unsafe operations
                 // Type checking is relaxed but still performed
-                for stmt in body {
-                    self.check_statement(stmt)?;
-                }
+                self.check_block(body)?;
+            }
+            StatementKind::Error => {
+                // Syntax error: already reported by the parser
             }
         }
         Ok(())
     }
 
-    fn check_expression(&mut self, expr: Expression) -> Result<Type> {
-        match expr {
-            Expression::Literal(lit) => match lit {
-                Literal::Integer(_) => Ok(Type::I32),
-                Literal::String(_) => Ok(Type::Str),
-                Literal::Boolean(_) => Ok(Type::Bool),
+    fn check_block(&mut self, body: Vec<Statement>) -> Result<()> {
+        self.enter_scope();
+        for stmt in body {
+            self.check_statement(stmt)?;
+        }
+        self.exit_scope();
+        Ok(())
+    }
+
+    /// Check the body of a `while` or `for` loop, binding the `for` loop
+    /// variable. The body may run any number of times, so what it assigns
+    /// is only maybe assigned after the loop.
+    fn check_loop_body(&mut self, variable: Option<(String, Type)>, body: Vec<Statement>) -> Result<()> {
+        let before = self.assignments();
+        self.depth += 1;
+        self.enter_scope();
+        if let Some((name, ty)) = variable {
+            self.declare(name, ty, false);
+        }
+        for stmt in body {
+            self.check_statement(stmt)?;
+        }
+        self.exit_scope();
+        self.depth -= 1;
+        let after = self.assignments();
+        self.restore(&merge(&before, &[before.clone(), after]));
+        Ok(())
+    }
+
+    /// Declare a variable that holds a value
+    fn declare(&mut self, name: String, ty: Type, mutable: bool) {
+        let symbol = Symbol {
+            ty,
+            mutable,
+            moved: false,
+            init: Init::Assigned,
+            depth: self.depth,
+        };
+        self.current_scope().insert(name, symbol);
+    }
+
+    /// States of the variables in scope that are not definitely assigned
+    fn assignments(&self) -> Assignments {
+        let mut states = HashMap::new();
+        for (index, scope) in self.scopes.iter().enumerate() {
+            for (name, symbol) in scope {
+                if symbol.init != Init::Assigned {
+                    states.insert((index, name.clone()), symbol.init);
+                }
+            }
+        }
+        states
+    }
+
+    /// Reset the variables in `states` to their state there; any other
+    /// variable a later path mentions is assigned
+    fn restore(&mut self, states: &Assignments) {
+        for (index, scope) in self.scopes.iter_mut().enumerate() {
+            for (name, symbol) in scope.iter_mut() {
+                if let Some(init) = states.get(&(index, name.clone())) {
+                    symbol.init = *init;
+                }
+            }
+        }
+    }
+
+    /// Check a `return` against the type the innermost function or closure
+    /// declares
+    fn check_return(&mut self, expr: Expression) -> Result<()> {
+        let span = expr.span;
+        let (expected, ty) = match self.returns.last().cloned() {
+            Some(Returns::Declared(Some(declared))) => {
+                self.check_value(expr, false, Some(&declared))?;
+                return Ok(());
+            }
+            Some(Returns::Declared(None)) => ("no value", self.check_value(expr, false, None)?),
+            Some(Returns::ExitCode) => match self.check_value(expr, false, None)? {
+                ty if is_integer(&ty) => return Ok(()),
+                ty => ("an integer exit code or no value", ty),
+            },
+            Some(Returns::Inferred) | None => {
+                self.check_value(expr, false, None)?;
+                return Ok(());
+            }
+        };
+        if ty == unit() {
+            return Ok(());
+        }
+        Err(TypeError::Mismatch {
+            expected: expected.to_string(),
+            actual: self.type_to_string(&ty),
+            location: span.to_string(),
+        })
+    }
+
+    /// Check `expr` where a value of type `expected` is needed. Unsuffixed
+    /// literals, `None` and `Some`/`Ok`/`Err` take their type from it.
+    fn check_expected(&mut self, expr: Expression, expected: &Type) -> Result<Type> {
+        let span = expr.span;
+        // An unsuffixed integer literal takes the type only if it fits
+        if let (Some(value), Some((min, max))) = (literal_value(&expr), integer_range(expected)) {
+            if value < min || value > max {
+                return Err(TypeError::LiteralOutOfRange(format!(
+                    "{} at {} does not fit in {} ({} to {})",
+                    value, span, expected, min, max
+                )));
+            }
+        }
+        let adapts = match &expr.kind {
+            ExpressionKind::Literal(lit) => literal_matches(lit, expected),
+            ExpressionKind::Unary { operator: UnaryOp::Negate, operand } => {
+                is_signed(expected) && matches!(&operand.kind, ExpressionKind::Literal(lit) if literal_matches(lit, expected))
+            }
+            ExpressionKind::Identifier(name) => {
+                name == "None" && matches!(expected, Type::Option(_)) && self.lookup(name).is_none()
+            }
+            _ => false,
+        };
+        if adapts {
+            let literal = match &expr.kind {
+                ExpressionKind::Unary { operand, .. } => operand,
+                _ => &expr,
+            };
+            if matches!(literal.kind, ExpressionKind::Literal(Literal::Integer(_, None))) && *expected != Type::I32 {
+                self.literal_types.insert(literal.span, expected.clone());
+            }
+            return Ok(expected.clone());
+        }
+        let ty = match expr.kind {
+            ExpressionKind::FunctionCall { name, mut arguments }
+                if arguments.len() == 1 && self.lookup(&name).is_none() && !self.functions.contains_key(&name) =>
+            {
+                match constructor_payload(&name, expected) {
+                    Some(payload) => {
+                        self.check_expected(arguments.remove(0), payload)?;
+                        return Ok(expected.clone());
+                    }
+                    None => self.check_call(name, arguments, Some(expected), span)?,
+                }
+            }
+            ExpressionKind::FunctionCall { name, arguments } if self.lookup(&name).is_none() => {
+                self.check_call(name, arguments, Some(expected), span)?
+            }
+            ExpressionKind::ArrayLiteral(elements) if !elements.is_empty() => match expected {
+                Type::Array(element, _) | Type::Slice(element) => {
+                    let len = elements.len();
+                    for element_expr in elements {
+                        self.check_expected(element_expr, element)?;
+                    }
+                    Type::Array(element.clone(), len)
+                }
+                _ => self.check_expression(Expression::new(ExpressionKind::ArrayLiteral(elements), span))?,
             },
-            Expression::Identifier(name) => {
+            ExpressionKind::Binary { left, operator, right } => self.check_binary(operator, *left, *right, Some(expected), span)?,
+            kind => self.check_expression(Expression::new(kind, span))?,
+        };
+        if assignable(&ty, expected, &self.traits) {
+            Ok(expected.clone())
+        } else {
+            Err(TypeError::Mismatch {
+                expected: self.type_to_string(expected),
+                actual: self.type_to_string(&ty),
+                location: span.to_string(),
+            })
+        }
+    }
+
+    fn check_expression(&mut self, expr: Expression) -> Result<Type> {
+        let span = expr.span;
+        match expr.kind {
+            ExpressionKind::Literal(lit) => Ok(lit.ty()),
+            ExpressionKind::Identifier(name) => {
                 for scope in self.scopes.iter().rev() {
                     if let Some(symbol) = scope.get(&name) {
+                        if symbol.init != Init::Assigned {
+                            return Err(TypeError::UnassignedVariable(name, format!("at {}", span)));
+                        }
                         if symbol.moved {
                             return Err(TypeError::InvalidOwnership(format!(
                                 "Cannot use moved value '{}'", name
@@ -229,111 +910,1169 @@ impl TypeChecker {
                         return Ok(symbol.ty.clone());
                     }
                 }
+                if name == "None" {
+                    // The payload type is not inferred yet
+                    return Ok(Type::Option(Box::new(Type::I32)));
+                }
                 Err(TypeError::UnknownVariable(
                     name,
-                    "in current scope".to_string(),
+                    format!("at {}", span),
                 ))
             }
-            Expression::FunctionCall { name: _, arguments } => {
-                for arg in arguments {
-                    self.check_expression(arg)?;
-                }
-                Ok(Type::I32)
+            // A variable holding a closure shadows a function of the same name
+            ExpressionKind::FunctionCall { name, arguments } if self.lookup(&name).is_some() => {
+                self.check_closure_call(&name, arguments, span)
             }
-            Expression::MethodCall {
+            ExpressionKind::FunctionCall { name, arguments } => self.check_call(name, arguments, None, span),
+            ExpressionKind::MethodCall {
                 receiver,
-                method: _,
+                method,
                 arguments,
             } => {
-                let _recv_ty = self.check_expression(Expression::Identifier(receiver))?;
-                for arg in arguments {
-                    self.check_expression(arg)?;
+                // `Worker.spawn()` starts an actor and gives a handle to it
+                if let ExpressionKind::Identifier(name) = &receiver.kind {
+                    if self.actors.contains_key(name) && self.lookup(name).is_none() {
+                        if method != "spawn" {
+                            return Err(TypeError::UnknownMethod(
+                                format!("{} on actor {}", method, name),
+                                format!("at {}", span),
+                            ));
+                        }
+                        let signature = Signature {
+                            params: Vec::new(),
+                            return_type: Some(Type::Custom(name.clone())),
+                            type_params: Vec::new(),
+                            bounds: Vec::new(),
+                            variadic: false,
+                        };
+                        let location = format!("spawn of {} at {}", name, span);
+                        return self.check_arguments(&signature, arguments, None, &location, span);
+                    }
+                }
+                // Methods are called through a reference as on the value
+                let receiver_ty = match self.check_expression(*receiver)? {
+                    Type::Ref(inner) => *inner,
+                    ty => ty,
+                };
+                if matches!(receiver_ty, Type::Array(..) | Type::Slice(_)) && method == "len" && arguments.is_empty() {
+                    return Ok(Type::I32);
+                }
+                // The value would be dropped again at the end of its scope
+                if method == "drop" && self.satisfies(&receiver_ty, "Drop") {
+                    return Err(TypeError::InvalidOwnership(format!(
+                        "explicit call of drop at {}: the value is dropped at the end of its scope; use mem::drop to drop it earlier",
+                        span
+                    )));
+                }
+                let location = format!("call of {} at {}", method, span);
+                // A method called on a handle sends the actor a message
+                if let Type::Custom(name) = &receiver_ty {
+                    if let Some(methods) = self.actors.get(name) {
+                        let Some(signature) = methods.get(&method).cloned() else {
+                            return Err(TypeError::UnknownMethod(
+                                format!("{} on actor {}", method, name),
+                                format!("at {}", span),
+                            ));
+                        };
+                        return self.check_arguments(&signature, arguments, None, &location, span);
+                    }
+                }
+                let signature = self.method_signature(&receiver_ty, &method).map_err(|e| {
+                    TypeError::InvalidTrait(format!("{} at {}", e, span))
+                })?;
+                match signature {
+                    Some(signature) => self.check_arguments(&signature, arguments, None, &location, span),
+                    None => Err(TypeError::UnknownMethod(
+                        format!("{} on {}", method, self.type_to_string(&receiver_ty)),
+                        format!("at {}", span),
+                    )),
                 }
-                Ok(Type::I32)
             }
-            Expression::Binary { left, operator: _, right } => {
-                let _left_ty = self.check_expression(*left)?;
-                let _right_ty = self.check_expression(*right)?;
-                Ok(Type::I32)
+            ExpressionKind::Binary { left, operator, right } => self.check_binary(operator, *left, *right, None, span),
+            ExpressionKind::Unary { operator: UnaryOp::Not, operand } => self.check_expected(*operand, &Type::Bool),
+            ExpressionKind::Unary { operator: UnaryOp::Negate, operand } => {
+                let operand_span = operand.span;
+                let ty = self.check_expression(*operand)?;
+                if is_signed(&ty) || self.is_type_param(&ty) {
+                    Ok(ty)
+                } else {
+                    Err(TypeError::Mismatch {
+                        expected: "a signed number".to_string(),
+                        actual: self.type_to_string(&ty),
+                        location: operand_span.to_string(),
+                    })
+                }
             }
-            Expression::Unary { operator: _, operand } => {
-                let _op_ty = self.check_expression(*operand)?;
-                Ok(Type::I32)
+            ExpressionKind::Unary { operator: UnaryOp::Borrow, operand } => {
+                if operand.root_variable().is_none() {
+                    return Err(TypeError::Unsupported(format!(
+                        "borrow at {} of a value that is not a variable or a field or element of one",
+                        span
+                    )));
+                }
+                match self.check_expression(*operand)? {
+                    Type::Ref(inner) => Err(TypeError::Unsupported(format!(
+                        "borrow at {} of a reference to {}; use the reference itself",
+                        span, inner
+                    ))),
+                    ty => Ok(Type::Ref(Box::new(ty))),
+                }
             }
-            Expression::Assignment { target, value } => {
-                let mut found = false;
-                for scope in self.scopes.iter().rev() {
-                    if let Some(symbol) = scope.get(&target) {
-                        if !symbol.mutable {
-                            return Err(TypeError::InvalidOwnership(
-                                "Cannot assign to immutable variable".to_string()
-                            ));
+            ExpressionKind::Unary { operator: UnaryOp::Deref, operand } => {
+                let operand_span = operand.span;
+                match self.check_expression(*operand)? {
+                    Type::Ref(inner) if self.satisfies(&inner, "Copy") => Ok(*inner),
+                    Type::Ref(inner) => Err(TypeError::InvalidOwnership(format!(
+                        "Cannot copy a value of type {} out of a reference at {}: it is not Copy; use .clone()",
+                        inner, span
+                    ))),
+                    ty => Err(TypeError::Mismatch {
+                        expected: "a reference".to_string(),
+                        actual: self.type_to_string(&ty),
+                        location: operand_span.to_string(),
+                    }),
+                }
+            }
+            ExpressionKind::Assignment { target, value } => self.check_assignment(*target, *value, false, span),
+            ExpressionKind::StructLiteral { name, type_args, fields } => {
+                let location = format!("at {}", span);
+                let Some(def) = self.structs.get(&name) else {
+                    return Err(TypeError::UnknownStruct(name, location));
+                };
+                // Without type arguments, those of a generic struct are bound
+                // to the types of its field values
+                let inferred = !def.type_params.is_empty() && type_args.is_empty();
+                let type_params = def.type_params.clone();
+                let bounds = def.bounds.clone();
+                let bindings: HashMap<&str, &Type> = def.type_params.iter().map(String::as_str).zip(&type_args).collect();
+                let declared: Vec<(String, Type)> = def
+                    .fields
+                    .iter()
+                    .map(|(field, ty)| (field.clone(), ty.substitute(&bindings)))
+                    .collect();
+                let mut seen = HashSet::new();
+                let mut inferred_args = HashMap::new();
+                for (field, value) in fields {
+                    let Some((_, field_ty)) = declared.iter().find(|(declared, _)| *declared == field) else {
+                        return Err(TypeError::UnknownField(format!("{} of {}", field, name), location));
+                    };
+                    if !seen.insert(field.clone()) {
+                        return Err(TypeError::DuplicateDefinition(format!("field {} {}", field, location)));
+                    }
+                    if !inferred {
+                        self.check_expected(value, field_ty)?;
+                        continue;
+                    }
+                    let value_span = value.span;
+                    let ty = self.check_expression(value)?;
+                    if !bind_type_params(field_ty, &ty, &type_params, &mut inferred_args, &self.traits) {
+                        return Err(TypeError::Mismatch {
+                            expected: self.type_to_string(field_ty),
+                            actual: self.type_to_string(&ty),
+                            location: value_span.to_string(),
+                        });
+                    }
+                }
+                if let Some((missing, _)) = declared.iter().find(|(field, _)| !seen.contains(field)) {
+                    return Err(TypeError::MissingField(format!("{} of {} {}", missing, name, location)));
+                }
+                if inferred {
+                    let type_args = self.type_args(&type_params, &inferred_args, &format!("{} {}", name, location))?;
+                    self.check_bounds(&type_params, &bounds, &type_args, &format!("{} {}", name, location))?;
+                    self.instantiations.insert(span, type_args.clone());
+                    Ok(Type::Generic { name, type_args })
+                } else if type_args.is_empty() {
+                    Ok(Type::Custom(name))
+                } else {
+                    for (param, ty) in type_params.iter().zip(&type_args) {
+                        check_references(ty, false, &format!("type argument {} of {} {}", param, name, location))?;
+                    }
+                    self.check_bounds(&type_params, &bounds, &type_args, &format!("{} {}", name, location))?;
+                    Ok(Type::Generic { name, type_args })
+                }
+            }
+            ExpressionKind::FieldAccess { object, field } => match self.check_expression(*object)? {
+                // Fields are read through a reference as on the value
+                Type::Ref(inner) => self.field_type(&inner, &field, span),
+                object_ty => self.field_type(&object_ty, &field, span),
+            },
+            ExpressionKind::ArrayLiteral(mut elements) => {
+                let len = elements.len();
+                // The first element that is not an unsuffixed literal gives
+                // the element type, which the others must have
+                let Some(first) = elements.iter().position(|e| !adapts_to_type(e)).or((len > 0).then_some(0)) else {
+                    return Err(TypeError::Unsupported(format!(
+                        "empty array literal at {}, whose element type is unknown",
+                        span
+                    )));
+                };
+                let element_ty = self.check_expression(elements.remove(first))?;
+                for element in elements {
+                    self.check_expected(element, &element_ty)?;
+                }
+                Ok(Type::Array(Box::new(element_ty), len))
+            }
+            ExpressionKind::Index { object, index } => {
+                // Elements are read through a reference as on the value
+                let object_ty = match self.check_expression(*object)? {
+                    Type::Ref(inner) => *inner,
+                    ty => ty,
+                };
+                let constant = match &index.kind {
+                    ExpressionKind::Literal(Literal::Integer(n, _)) => Some(*n),
+                    _ => None,
+                };
+                let index_span = index.span;
+                let index_ty = self.check_expression(*index)?;
+                if !is_integer(&index_ty) {
+                    return Err(TypeError::Mismatch {
+                        expected: "an integer index".to_string(),
+                        actual: self.type_to_string(&index_ty),
+                        location: index_span.to_string(),
+                    });
+                }
+                match object_ty {
+                    Type::Array(_, len) if constant.is_some_and(|n| n >= len as u64) => {
+                        Err(TypeError::IndexOutOfBounds(format!(
+                            "index {} into an array of length {} at {}",
+                            constant.unwrap_or_default(),
+                            len,
+                            index_span
+                        )))
+                    }
+                    Type::Array(element, _) | Type::Slice(element) => Ok(*element),
+                    other => Err(TypeError::Mismatch {
+                        expected: "an array or slice".to_string(),
+                        actual: self.type_to_string(&other),
+                        location: span.to_string(),
+                    }),
+                }
+            }
+            ExpressionKind::Range { start, end, inclusive: _, step } => {
+                let start_span = start.span;
+                let start_literal = is_integer_literal(&start);
+                let start_ty = self.check_expression(*start)?;
+                let end_span = end.span;
+                let end_literal = is_integer_literal(&end);
+                let end_ty = self.check_expression(*end)?;
+                // An unsuffixed literal bound takes the type of the other one
+                let item = if start_literal && is_integer(&end_ty) { end_ty.clone() } else { start_ty.clone() };
+                for (ty, literal, span) in [(start_ty, start_literal, start_span), (end_ty, end_literal, end_span)] {
+                    if !is_integer(&ty) || (ty != item && !literal) {
+                        return Err(TypeError::Mismatch {
+                            expected: if is_integer(&item) { self.type_to_string(&item) } else { "an integer".to_string() },
+                            actual: self.type_to_string(&ty),
+                            location: span.to_string(),
+                        });
+                    }
+                }
+                if let Some(step) = step {
+                    let step_span = step.span;
+                    if matches!(step.kind, ExpressionKind::Literal(Literal::Integer(0, _)) | ExpressionKind::Unary { operator: UnaryOp::Negate, .. }) {
+                        return Err(TypeError::Unsupported(format!(
+                            "range step at {}, which must be positive",
+                            step_span
+                        )));
+                    }
+                    let literal = is_integer_literal(&step);
+                    let step_ty = self.check_expression(*step)?;
+                    if step_ty != item && !(literal && is_integer(&step_ty)) {
+                        return Err(TypeError::Mismatch {
+                            expected: self.type_to_string(&item),
+                            actual: self.type_to_string(&step_ty),
+                            location: step_span.to_string(),
+                        });
+                    }
+                }
+                Ok(Type::Range(Box::new(item)))
+            }
+            ExpressionKind::Match { .. } => Err(TypeError::Unsupported(format!(
+                "match must be a statement, a let value, an assignment value or a return value at {}",
+                span
+            ))),
+            ExpressionKind::Tuple(_) => Err(TypeError::Unsupported(format!(
+                "tuples can only be matched on at {}",
+                span
+            ))),
+            ExpressionKind::Closure { params, return_type, body, .. } => {
+                // The body runs later, if at all: what it assigns stays
+                // unassigned outside it
+                let before = self.assignments();
+                self.depth += 1;
+                self.enter_scope();
+                for (name, ty) in &params {
+                    self.declare(name.clone(), ty.clone(), false);
+                }
+                // Without a declared return type, the closure returns the
+                // type of its final value, if it has one
+                self.returns.push(match &return_type {
+                    Some(ty) => Returns::Declared(Some(ty.clone())),
+                    None => Returns::Inferred,
+                });
+                let mut value_ty = None;
+                let last = body.len().saturating_sub(1);
+                for (i, stmt) in body.into_iter().enumerate() {
+                    match stmt.kind {
+                        StatementKind::Return(value) if i == last && return_type.is_none() => {
+                            let ty = self.check_value(value, false, None)?;
+                            if ty != unit() {
+                                value_ty = Some(ty);
+                            }
                         }
-                        found = true;
-                        break;
+                        kind => self.check_statement(Statement::new(kind, stmt.span))?,
                     }
                 }
-                if !found {
-                    return Err(TypeError::UnknownVariable(
-                        target.clone(),
-                        "in current scope".to_string(),
+                self.returns.pop();
+                self.exit_scope();
+                self.depth -= 1;
+                self.restore(&before);
+                Ok(Type::FunctionPointer {
+                    params: params.into_iter().map(|(_, ty)| ty).collect(),
+                    return_type: return_type.or(value_ty).map(Box::new),
+                })
+            }
+            ExpressionKind::EnumVariant { enum_name, variant, payload } => {
+                let location = format!("at {}", span);
+                let Some(def) = self.enums.get(&enum_name) else {
+                    return Err(TypeError::UnknownEnum(enum_name, location));
+                };
+                let Some(declared) = def.variants.iter().find(|v| v.name == variant) else {
+                    return Err(TypeError::UnknownVariant(
+                        format!("{}::{}", enum_name, variant),
+                        location,
                     ));
+                };
+                if let Some(problem) = payload_mismatch(&declared.payload, &payload) {
+                    return Err(TypeError::InvalidPayload(format!(
+                        "{}::{} {} {}",
+                        enum_name, variant, problem, location
+                    )));
+                }
+                let values = match (declared.payload.clone(), payload) {
+                    (VariantPayload::Tuple(types), VariantPayload::Tuple(values)) => types.into_iter().zip(values).collect(),
+                    (VariantPayload::Struct(types), VariantPayload::Struct(fields)) => fields
+                        .into_iter()
+                        .filter_map(|(field, value)| {
+                            types.iter().find(|(name, _)| *name == field).map(|(_, ty)| (ty.clone(), value))
+                        })
+                        .collect(),
+                    _ => Vec::new(),
+                };
+                for (ty, value) in values {
+                    self.check_expected(value, &ty)?;
                 }
-                let _value_ty = self.check_expression(*value)?;
-                Ok(Type::I32)
+                Ok(Type::Custom(enum_name))
             }
         }
     }
 
-    fn type_to_string(&self, ty: &Type) -> String {
+    fn lookup(&self, name: &str) -> Option<&Symbol> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    /// Check a call through the variable `name`, which must hold a closure
+    fn check_closure_call(&mut self, name: &str, arguments: Vec<Expression>, span: Span) -> Result<Type> {
+        let location = format!("call of {} at {}", name, span);
+        let ty = match self.lookup(name) {
+            Some(symbol) if symbol.init != Init::Assigned => {
+                return Err(TypeError::UnassignedVariable(name.to_string(), format!("at {}", span)));
+            }
+            symbol => symbol.map_or(Type::I32, |symbol| symbol.ty.clone()),
+        };
+        let Type::FunctionPointer { params, return_type } = ty else {
+            return Err(TypeError::Mismatch {
+                expected: "a function".to_string(),
+                actual: self.type_to_string(&ty),
+                location,
+            });
+        };
+        if params.len() != arguments.len() {
+            return Err(TypeError::Mismatch {
+                expected: format!("{} argument(s)", params.len()),
+                actual: arguments.len().to_string(),
+                location,
+            });
+        }
+        for (arg, param) in arguments.into_iter().zip(&params) {
+            self.check_expected(arg, param)?;
+        }
+        Ok(return_type.map_or_else(unit, |ty| *ty))
+    }
+
+    /// Check a call of a function, or a built-in; `expected` is the type
+    /// its value must have, where known
+    fn check_call(&mut self, name: String, mut arguments: Vec<Expression>, expected: Option<&Type>, span: Span) -> Result<Type> {
+        let location = format!("call of {} at {}", name, span);
+        if let Some(signature) = self.functions.get(&name).cloned() {
+            return self.check_arguments(&signature, arguments, expected, &location, span);
+        }
+        match name.as_str() {
+            "print" if arguments.len() == 1 => {
+                let arg_span = arguments[0].span;
+                let ty = self.check_expression(arguments.remove(0))?;
+                if is_integer(&ty) || matches!(ty, Type::F32 | Type::F64 | Type::Bool | Type::Char | Type::Str) {
+                    Ok(unit())
+                } else {
+                    Err(TypeError::Mismatch {
+                        expected: "a number, bool, char or str to print".to_string(),
+                        actual: self.type_to_string(&ty),
+                        location: arg_span.to_string(),
+                    })
+                }
+            }
+            "print" => Err(TypeError::Mismatch {
+                expected: "1 argument(s)".to_string(),
+                actual: arguments.len().to_string(),
+                location,
+            }),
+            // Without an expected type, the other side of the payload is unknown
+            "Some" | "Ok" | "Err" if arguments.len() == 1 => {
+                let payload = Box::new(self.check_expression(arguments.remove(0))?);
+                Ok(match name.as_str() {
+                    "Some" => Type::Option(payload),
+                    "Ok" => Type::Result(payload, Box::new(Type::I32)),
+                    _ => Type::Result(Box::new(Type::I32), payload),
+                })
+            }
+            _ => Err(TypeError::UnknownFunction(name, format!("at {}", span))),
+        }
+    }
+
+    /// Check the arguments of a call against `signature`, binding its type
+    /// parameters to the type expected of its value and to the argument
+    /// types, and give the type it returns
+    fn check_arguments(
+        &mut self,
+        signature: &Signature,
+        arguments: Vec<Expression>,
+        expected: Option<&Type>,
+        location: &str,
+        span: Span,
+    ) -> Result<Type> {
+        let count = signature.params.len();
+        if arguments.len() < count || (arguments.len() > count && !signature.variadic) {
+            return Err(TypeError::Mismatch {
+                expected: format!("{}{} argument(s)", if signature.variadic { "at least " } else { "" }, count),
+                actual: arguments.len().to_string(),
+                location: location.to_string(),
+            });
+        }
+        let mut bindings = HashMap::new();
+        if let (Some(ret), Some(expected)) = (&signature.return_type, expected) {
+            // On a mismatch the return type is reported once the call is checked
+            let mut from_expected = HashMap::new();
+            if bind_type_params(ret, expected, &signature.type_params, &mut from_expected, &self.traits) {
+                bindings = from_expected;
+            }
+        }
+        for (i, arg) in arguments.into_iter().enumerate() {
+            let Some(param) = signature.params.get(i) else {
+                // Variadic arguments take any type
+                self.check_expression(arg)?;
+                continue;
+            };
+            // A parameter whose type parameters are all bound takes the
+            // argument as a value of its type
+            let unbound: Vec<String> = signature.type_params.iter().filter(|p| !bindings.contains_key(*p)).cloned().collect();
+            if !param.mentions_any(&unbound) {
+                let bound: HashMap<&str, &Type> = bindings.iter().map(|(name, ty)| (name.as_str(), ty)).collect();
+                self.check_expected(arg, &param.substitute(&bound))?;
+                continue;
+            }
+            let arg_span = arg.span;
+            let ty = self.check_expression(arg)?;
+            if !bind_type_params(param, &ty, &signature.type_params, &mut bindings, &self.traits) {
+                let bound: HashMap<&str, &Type> = bindings.iter().map(|(name, ty)| (name.as_str(), ty)).collect();
+                return Err(TypeError::Mismatch {
+                    expected: self.type_to_string(&param.substitute(&bound)),
+                    actual: self.type_to_string(&ty),
+                    location: arg_span.to_string(),
+                });
+            }
+        }
+        if !signature.type_params.is_empty() {
+            let type_args = self.type_args(&signature.type_params, &bindings, location)?;
+            self.check_bounds(&signature.type_params, &signature.bounds, &type_args, location)?;
+            self.instantiations.insert(span, type_args);
+        }
+        let bound: HashMap<&str, &Type> = bindings.iter().map(|(name, ty)| (name.as_str(), ty)).collect();
+        Ok(signature.return_type.as_ref().map_or_else(unit, |ty| ty.substitute(&bound)))
+    }
+
+    /// The types bound to `type_params`, in order, all of which must be bound
+    fn type_args(&self, type_params: &[String], bindings: &HashMap<String, Type>, location: &str) -> Result<Vec<Type>> {
+        type_params
+            .iter()
+            .map(|param| {
+                let ty = bindings.get(param).cloned().ok_or_else(|| {
+                    TypeError::UninferredType(param.clone(), format!("of {}; give the value a type", location))
+                })?;
+                check_references(&ty, false, &format!("type argument {} of {}", param, location))?;
+                Ok(ty)
+            })
+            .collect()
+    }
+
+    /// Check a binary operation; `expected` lets two unsuffixed literals
+    /// take the type their result is used as
+    fn check_binary(&mut self, operator: BinaryOp, left: Expression, right: Expression, expected: Option<&Type>, span: Span) -> Result<Type> {
+        if matches!(operator, BinaryOp::And | BinaryOp::Or) {
+            self.check_expected(left, &Type::Bool)?;
+            self.check_expected(right, &Type::Bool)?;
+            return Ok(Type::Bool);
+        }
+        let arithmetic = matches!(
+            operator,
+            BinaryOp::Add | BinaryOp::Subtract | BinaryOp::Multiply | BinaryOp::Divide | BinaryOp::Modulo
+        );
+        let ty = match expected {
+            Some(expected) if arithmetic && is_numeric(expected) && adapts_to_type(&left) && adapts_to_type(&right) => {
+                self.check_expected(left, expected)?;
+                self.check_expected(right, expected)?
+            }
+            // An unsuffixed literal takes the type of the other operand
+            _ if adapts_to_type(&left) && !adapts_to_type(&right) => {
+                let ty = self.check_expression(right)?;
+                self.check_expected(left, &ty)?
+            }
+            _ => {
+                let ty = self.check_expression(left)?;
+                self.check_expected(right, &ty)?
+            }
+        };
+        let type_param = self.is_type_param(&ty);
+        let (allowed, expected) = match operator {
+            BinaryOp::Modulo => (is_integer(&ty) || type_param, "an integer"),
+            _ if arithmetic => (is_numeric(&ty) || type_param, "a number"),
+            BinaryOp::Equal | BinaryOp::NotEqual => (
+                is_numeric(&ty) || type_param || matches!(ty, Type::Bool | Type::Char | Type::Ptr),
+                "a number, bool, char or ptr",
+            ),
+            _ => (is_numeric(&ty) || type_param || ty == Type::Char, "a number or char"),
+        };
+        if !allowed {
+            return Err(TypeError::Mismatch {
+                expected: expected.to_string(),
+                actual: self.type_to_string(&ty),
+                location: span.to_string(),
+            });
+        }
+        Ok(if arithmetic { ty } else { Type::Bool })
+    }
+
+    /// Whether `ty` is a type parameter of the function being checked,
+    /// whose values can only be combined with values of the same type
+    fn is_type_param(&self, ty: &Type) -> bool {
+        matches!(ty, Type::Custom(name) if self.type_params.contains(name))
+    }
+
+    /// Type of `field` on a value of type `ty`, with the struct's type
+    /// parameters replaced by the arguments in `ty`
+    fn field_type(&self, ty: &Type, field: &str, span: Span) -> Result<Type> {
+        let (name, type_args) = match ty {
+            Type::Custom(name) => (name, &[][..]),
+            Type::Generic { name, type_args } => (name, type_args.as_slice()),
+            _ => {
+                return Err(TypeError::Mismatch {
+                    expected: "a struct".to_string(),
+                    actual: self.type_to_string(ty),
+                    location: span.to_string(),
+                })
+            }
+        };
+        let Some(def) = self.structs.get(name) else {
+            return Err(TypeError::UnknownStruct(name.clone(), format!("at {}", span)));
+        };
+        let Some((_, field_ty)) = def.fields.iter().find(|(f, _)| f == field) else {
+            return Err(TypeError::UnknownField(format!("{} of {}", field, name), format!("at {}", span)));
+        };
+        let bindings: HashMap<&str, &Type> = def
+            .type_params
+            .iter()
+            .map(String::as_str)
+            .zip(type_args)
+            .collect();
+        Ok(field_ty.substitute(&bindings))
+    }
+
+    /// Type of the values a `for` loop over a value of type `ty` binds
+    fn item_type(&self, ty: &Type, span: Span) -> Result<Type> {
         match ty {
-            Type::I32 => "i32".to_string(),
-            Type::Str => "str".to_string(),
-            Type::Bool => "bool".to_string(),
-            Type::Ptr => "ptr".to_string(),
-            Type::Option(inner) => format!("Option<{}>", self.type_to_string(inner)),
-            Type::Result(ok, err) => format!("Result<{}, {}>", self.type_to_string(ok), self.type_to_string(err)),
-            Type::Custom(name) => name.clone(),
-            Type::Generic { name, type_args } => {
-                let args = type_args.iter()
-                    .map(|t| self.type_to_string(t))
-                    .collect::<Vec<_>>()
-                    .join(", ");
-                format!("{}<{}>", name, args)
+            Type::Range(item) | Type::Array(item, _) | Type::Slice(item) => Ok((**item).clone()),
+            Type::Custom(name) if self.iterators.contains_key(name) => Ok(self.iterators[name].clone()),
+            _ if is_integer(ty) => Err(TypeError::NotIterable(
+                self.type_to_string(ty),
+                format!("at {}; write a range such as 0..n to count", span),
+            )),
+            Type::Custom(name) => {
+                let missing = self.traits.require(name, "Iterator").err().unwrap_or_default();
+                Err(TypeError::NotIterable(name.clone(), format!("at {}: {}", span, missing)))
             }
-            Type::FunctionPointer { params, return_type } => {
-                let param_str = params.iter()
-                    .map(|t| self.type_to_string(t))
-                    .collect::<Vec<_>>()
-                    .join(", ");
-                if let Some(ret) = return_type {
-                    format!("fn({}) -> {}", param_str, self.type_to_string(ret))
+            _ => Err(TypeError::NotIterable(self.type_to_string(ty), format!("at {}", span))),
+        }
+    }
+
+    /// Check `target = value`. The target must be a `var` or a field or
+    /// element of one, or a `let` declared without a value that has not
+    /// been assigned; `value` may be a `match` when `in_value_position`.
+    fn check_assignment(&mut self, target: Expression, value: Expression, in_value_position: bool, span: Span) -> Result<Type> {
+        let Some(root) = target.root_variable().map(str::to_string) else {
+            return Err(TypeError::Unsupported(format!("assignment target at {}", span)));
+        };
+        let Some(symbol) = self.lookup(&root).cloned() else {
+            return Err(TypeError::UnknownVariable(root, format!("at {}", span)));
+        };
+        let whole = matches!(target.kind, ExpressionKind::Identifier(_));
+        if !symbol.mutable {
+            let problem = match symbol.init {
+                Init::Unassigned if whole && symbol.depth == self.depth => None,
+                Init::Unassigned if whole => Some(format!(
+                    "Cannot assign to immutable variable '{}' in a loop or closure",
+                    root
+                )),
+                Init::MaybeAssigned if whole => Some(format!("Cannot assign twice to immutable variable '{}'", root)),
+                _ => Some("Cannot assign to immutable variable".to_string()),
+            };
+            if let Some(problem) = problem {
+                return Err(TypeError::InvalidOwnership(problem));
+            }
+        }
+        let target_ty = if whole { symbol.ty } else { self.check_expression(target)? };
+        // A `dyn` value points to the value it is made from, which a block
+        // inside the variable's own drops before it
+        let declared_in = self.scopes.iter().rposition(|scope| scope.contains_key(&root));
+        if matches!(target_ty, Type::Dyn(_)) && declared_in != Some(self.scopes.len() - 1) {
+            return Err(TypeError::Unsupported(format!(
+                "assignment to {} at {}: a dyn variable can only be assigned in the block it is declared in",
+                root, span
+            )));
+        }
+        if in_value_position {
+            self.check_value(value, true, Some(&target_ty))?;
+        } else {
+            self.check_expected(value, &target_ty)?;
+        }
+        if let Some(symbol) = self.scopes.iter_mut().rev().find_map(|scope| scope.get_mut(&root)) {
+            symbol.init = Init::Assigned;
+        }
+        Ok(unit())
+    }
+
+    /// Check an expression whose value a statement uses directly. These are
+    /// the only positions a `match` may appear in; `needs_value` is false
+    /// when arms may be blocks.
+    fn check_value(&mut self, expr: Expression, needs_value: bool, expected: Option<&Type>) -> Result<Type> {
+        let span = expr.span;
+        match expr.kind {
+            ExpressionKind::Match { scrutinee, arms } => {
+                self.check_match(*scrutinee, arms, needs_value, expected, span)
+            }
+            ExpressionKind::Assignment { target, value } if matches!(value.kind, ExpressionKind::Match { .. }) => {
+                self.check_assignment(*target, *value, true, span)
+            }
+            kind => match expected {
+                Some(expected) => self.check_expected(Expression::new(kind, span), expected),
+                None => self.check_expression(Expression::new(kind, span)),
+            },
+        }
+    }
+
+    /// Check a `match`; arm values must have the `expected` type or, when
+    /// a value is needed, the type of the first arm
+    fn check_match(
+        &mut self,
+        scrutinee: Expression,
+        arms: Vec<MatchArm>,
+        needs_value: bool,
+        expected: Option<&Type>,
+        span: Span,
+    ) -> Result<Type> {
+        let ty = match scrutinee.kind {
+            ExpressionKind::Tuple(items) => {
+                let mut types = Vec::new();
+                for item in items {
+                    types.push(self.check_expression(item)?);
+                }
+                Type::Tuple(types)
+            }
+            kind => self.check_expression(Expression::new(kind, scrutinee.span))?,
+        };
+
+        let mut arm_bindings = Vec::new();
+        for arm in &arms {
+            let mut bindings = Vec::new();
+            self.check_pattern(&arm.pattern, &ty, &mut bindings)?;
+            arm_bindings.push(bindings);
+        }
+
+        let patterns: Vec<(&Pattern, bool)> = arms
+            .iter()
+            .map(|arm| (&arm.pattern, arm.guard.is_some()))
+            .collect();
+        let report = MatchChecker::new(&self.enums, &self.structs).check(&ty, &patterns);
+        if let Some(&index) = report.unreachable.first() {
+            return Err(TypeError::UnreachablePattern(format!("at {}", arms[index].pattern.span)));
+        }
+        if let Some(missing) = report.missing {
+            return Err(TypeError::NonExhaustive {
+                missing,
+                location: format!("at {}", span),
+            });
+        }
+
+        let mut result = None;
+        let before = self.assignments();
+        let mut paths = Vec::new();
+        for (arm, bindings) in arms.into_iter().zip(arm_bindings) {
+            self.restore(&before);
+            let diverges = matches!(&arm.body, ArmBody::Block(body) if diverges(body));
+            self.enter_scope();
+            for (name, ty) in bindings {
+                self.declare(name, ty, false);
+            }
+            if let Some(guard) = arm.guard {
+                self.check_expected(guard, &Type::Bool)?;
+            }
+            match arm.body {
+                ArmBody::Expression(expr) => {
+                    let arm_expected = match expected {
+                        Some(ty) => Some(ty.clone()),
+                        None if needs_value => result.clone(),
+                        None => None,
+                    };
+                    let ty = self.check_value(expr, needs_value, arm_expected.as_ref())?;
+                    result.get_or_insert(ty);
+                }
+                ArmBody::Block(_) if needs_value => {
+                    return Err(TypeError::Unsupported(format!(
+                        "match arm at {} is a block, which has no value",
+                        arm.span
+                    )));
+                }
+                ArmBody::Block(body) => {
+                    for stmt in body {
+                        self.check_statement(stmt)?;
+                    }
+                }
+            }
+            self.exit_scope();
+            if !diverges {
+                paths.push(self.assignments());
+            }
+        }
+        self.restore(&merge(&before, &paths));
+        Ok(result.unwrap_or_else(unit))
+    }
+
+    /// Check that `pattern` can match values of `ty`, collecting the
+    /// variables it binds
+    fn check_pattern(&self, pattern: &Pattern, ty: &Type, bindings: &mut Vec<(String, Type)>) -> Result<()> {
+        let location = format!("at {}", pattern.span);
+        let mismatch = |actual: String| TypeError::Mismatch {
+            expected: self.type_to_string(ty),
+            actual,
+            location: pattern.span.to_string(),
+        };
+        match &pattern.kind {
+            PatternKind::Wildcard => Ok(()),
+            PatternKind::Binding(_) if matches!(ty, Type::Tuple(_)) => Err(TypeError::Unsupported(format!(
+                "binding a whole tuple {}",
+                location
+            ))),
+            PatternKind::Binding(name) => {
+                if bindings.iter().any(|(bound, _)| bound == name) {
+                    return Err(TypeError::DuplicateDefinition(format!("binding {} {}", name, location)));
+                }
+                bindings.push((name.clone(), ty.clone()));
+                Ok(())
+            }
+            PatternKind::Literal(lit) => {
+                if literal_matches(lit, ty) {
+                    Ok(())
                 } else {
-                    format!("fn({})", param_str)
+                    Err(mismatch(self.type_to_string(&lit.ty())))
+                }
+            }
+            PatternKind::Tuple(items) => match ty {
+                Type::Tuple(types) if types.len() == items.len() => {
+                    for (item, ty) in items.iter().zip(types) {
+                        self.check_pattern(item, ty, bindings)?;
+                    }
+                    Ok(())
                 }
+                _ => Err(mismatch(format!("a tuple of {}", items.len()))),
+            },
+            PatternKind::Struct { name, fields, rest } => match (ty, self.structs.get(name)) {
+                (Type::Custom(ty_name), Some(def)) if ty_name == name => {
+                    self.check_field_patterns(name, &def.fields, fields, *rest, &location, bindings)
+                }
+                _ => Err(mismatch(name.clone())),
+            },
+            PatternKind::Variant { enum_name: Some(enum_name), variant, payload, rest } => {
+                let def = match ty {
+                    Type::Custom(ty_name) if ty_name == enum_name => self.enums.get(enum_name),
+                    _ => None,
+                };
+                let Some(def) = def else {
+                    return Err(mismatch(format!("{}::{}", enum_name, variant)));
+                };
+                let Some(declared) = def.variants.iter().find(|v| &v.name == variant) else {
+                    return Err(TypeError::UnknownVariant(format!("{}::{}", enum_name, variant), location));
+                };
+                let owner = format!("{}::{}", enum_name, variant);
+                match (&declared.payload, payload) {
+                    (VariantPayload::Unit, VariantPayload::Unit) => Ok(()),
+                    (VariantPayload::Tuple(types), VariantPayload::Tuple(items)) if types.len() == items.len() => {
+                        for (item, ty) in items.iter().zip(types) {
+                            self.check_pattern(item, ty, bindings)?;
+                        }
+                        Ok(())
+                    }
+                    (VariantPayload::Struct(declared), VariantPayload::Struct(fields)) => {
+                        self.check_field_patterns(&owner, declared, fields, *rest, &location, bindings)
+                    }
+                    _ => Err(TypeError::InvalidPattern(format!(
+                        "payload does not match the declaration of {} {}",
+                        owner, location
+                    ))),
+                }
+            }
+            PatternKind::Variant { enum_name: None, variant, payload, .. } => {
+                let field = match (variant.as_str(), ty) {
+                    ("Some", Type::Option(inner)) => Some(inner),
+                    ("None", Type::Option(_)) => None,
+                    ("Ok", Type::Result(ok, _)) => Some(ok),
+                    ("Err", Type::Result(_, err)) => Some(err),
+                    _ => return Err(mismatch(variant.clone())),
+                };
+                match (field, payload) {
+                    (None, VariantPayload::Unit) => Ok(()),
+                    (Some(field), VariantPayload::Tuple(items)) if items.len() == 1 => {
+                        self.check_pattern(&items[0], field, bindings)
+                    }
+                    _ => Err(TypeError::InvalidPattern(format!(
+                        "{} {} takes {} value(s)",
+                        variant,
+                        location,
+                        usize::from(field.is_some())
+                    ))),
+                }
+            }
+        }
+    }
+
+    /// Check `{ field: pattern, .. }` against the fields declared by `owner`
+    fn check_field_patterns(
+        &self,
+        owner: &str,
+        declared: &[(String, Type)],
+        fields: &[(String, Pattern)],
+        rest: bool,
+        location: &str,
+        bindings: &mut Vec<(String, Type)>,
+    ) -> Result<()> {
+        let mut seen = HashSet::new();
+        for (field, pattern) in fields {
+            let Some((_, ty)) = declared.iter().find(|(name, _)| name == field) else {
+                return Err(TypeError::InvalidPattern(format!("{} has no field '{}' {}", owner, field, location)));
+            };
+            if !seen.insert(field) {
+                return Err(TypeError::InvalidPattern(format!("field '{}' matched twice {}", field, location)));
+            }
+            self.check_pattern(pattern, ty, bindings)?;
+        }
+        if !rest {
+            if let Some((missing, _)) = declared.iter().find(|(name, _)| !seen.contains(name)) {
+                return Err(TypeError::InvalidPattern(format!(
+                    "pattern for {} does not mention field '{}' {}; add '..' to ignore it",
+                    owner, missing, location
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    fn type_to_string(&self, ty: &Type) -> String {
+        ty.to_string()
+    }
+}
+
+/// Whether a literal pattern can match values of `ty`; unsuffixed numbers
+/// fit any type of their kind
+fn is_integer(ty: &Type) -> bool {
+    matches!(ty, Type::I32 | Type::I64 | Type::U8 | Type::U64)
+}
+
+/// Whether control never reaches the end of `body`
+fn diverges(body: &[Statement]) -> bool {
+    match body.last().map(|stmt| &stmt.kind) {
+        Some(StatementKind::Return(_) | StatementKind::Break | StatementKind::Continue) => true,
+        Some(StatementKind::If { then_branch, else_branch: Some(else_branch), .. }) => {
+            diverges(then_branch) && diverges(else_branch)
+        }
+        Some(StatementKind::Unsafe { body }) => diverges(body),
+        Some(StatementKind::Expr(Expression { kind: ExpressionKind::Match { arms, .. }, .. })) => {
+            arms.iter().all(|arm| matches!(&arm.body, ArmBody::Block(body) if diverges(body)))
+        }
+        _ => false,
+    }
+}
+
+/// Join the assignment states the paths that continue after a branch end
+/// in; a variable is assigned if it is on every path. Without such paths
+/// the code after the branch is unreachable and nothing changes.
+fn merge(before: &Assignments, paths: &[Assignments]) -> Assignments {
+    if paths.is_empty() {
+        return before.clone();
+    }
+    before
+        .keys()
+        .map(|key| {
+            let states: Vec<Init> = paths.iter().map(|path| path.get(key).copied().unwrap_or(Init::Assigned)).collect();
+            let init = if states.iter().all(|init| *init == states[0]) { states[0] } else { Init::MaybeAssigned };
+            (key.clone(), init)
+        })
+        .collect()
+}
+
+fn is_signed(ty: &Type) -> bool {
+    matches!(ty, Type::I32 | Type::I64 | Type::F32 | Type::F64)
+}
+
+fn is_numeric(ty: &Type) -> bool {
+    is_integer(ty) || matches!(ty, Type::F32 | Type::F64)
+}
+
+/// The type of calls, assignments and statements that give no value
+fn unit() -> Type {
+    Type::Tuple(Vec::new())
+}
+
+/// Whether a value of type `actual` can be used where `expected` is needed:
+/// a `str` passes as a `ptr`, an array as a slice of its elements and a
+/// value of a type implementing a trait as a `dyn` of it
+fn assignable(actual: &Type, expected: &Type, traits: &TraitRegistry) -> bool {
+    match (actual, expected) {
+        (Type::Str, Type::Ptr) => true,
+        (Type::Array(element, _), Type::Slice(expected)) => element == expected,
+        (Type::Custom(type_name), Type::Dyn(trait_name)) => traits.implements(type_name, trait_name),
+        _ => actual == expected,
+    }
+}
+
+/// Whether `expr` is a number literal without a suffix, possibly negated,
+/// whose type follows the values it is used with
+fn adapts_to_type(expr: &Expression) -> bool {
+    match &expr.kind {
+        ExpressionKind::Literal(Literal::Integer(_, None) | Literal::Float(_, None)) => true,
+        ExpressionKind::Unary { operator: UnaryOp::Negate, operand } => adapts_to_type(operand),
+        _ => false,
+    }
+}
+
+/// Payload type of `Some`, `Ok` or `Err` where a value of type `expected`
+/// is needed
+fn constructor_payload<'a>(name: &str, expected: &'a Type) -> Option<&'a Type> {
+    match (name, expected) {
+        ("Some", Type::Option(payload)) | ("Ok", Type::Result(payload, _)) | ("Err", Type::Result(_, payload)) => {
+            Some(payload)
+        }
+        _ => None,
+    }
+}
+
+/// Check that `ty`, the type of `what`, has no reference that could
+/// outlive the value it borrows: references are the types of parameters,
+/// variables and return values, so `whole` allows one only as `ty` itself
+/// or as a parameter or return type of a function pointer. A `dyn` value
+/// points to the value it is made from, so is held the same way, though
+/// not returned.
+fn check_references(ty: &Type, whole: bool, what: &str) -> Result<()> {
+    let found = if whole { nested_reference(ty) } else { reference_in(ty) };
+    if let Some(reference) = found {
+        // A slice borrows the array it views, so it goes where a reference can
+        let kind = if matches!(reference, Type::Slice(_)) { "slice" } else { "reference" };
+        return Err(TypeError::Unsupported(format!(
+            "{} type {} in {}: a {} can only be the type of a parameter, variable or return value",
+            kind, reference, what, kind
+        )));
+    }
+    let found = match ty {
+        Type::Dyn(_) if whole => None,
+        _ => dyn_in(ty),
+    };
+    match found {
+        Some(dyn_ty) => Err(dyn_error(dyn_ty, what)),
+        None => Ok(()),
+    }
+}
+
+fn dyn_error(ty: &Type, what: &str) -> TypeError {
+    TypeError::Unsupported(format!(
+        "dyn type {} in {}: a dyn value can only be the type of a parameter or variable",
+        ty, what
+    ))
+}
+
+/// A `dyn` type that is `ty` or part of it, other than a parameter of a
+/// function pointer or what a reference points to
+fn dyn_in(ty: &Type) -> Option<&Type> {
+    match ty {
+        Type::Dyn(_) => Some(ty),
+        Type::Ref(inner) if matches!(**inner, Type::Dyn(_)) => None,
+        Type::Ref(inner) | Type::Option(inner) | Type::Array(inner, _) | Type::Slice(inner) | Type::Range(inner) => {
+            dyn_in(inner)
+        }
+        Type::Result(ok, err) => dyn_in(ok).or_else(|| dyn_in(err)),
+        Type::Tuple(items) | Type::Generic { type_args: items, .. } => items.iter().find_map(dyn_in),
+        Type::FunctionPointer { params, return_type } => params
+            .iter()
+            .filter(|param| !matches!(param, Type::Dyn(_)))
+            .chain(return_type.as_deref())
+            .find_map(dyn_in),
+        _ => None,
+    }
+}
+
+/// A reference or slice that is part of `ty`, other than a parameter or
+/// return type of a function pointer or a slice a reference points to
+fn nested_reference(ty: &Type) -> Option<&Type> {
+    match ty {
+        Type::Ref(inner) => match &**inner {
+            Type::Slice(element) => reference_in(element),
+            inner => reference_in(inner),
+        },
+        Type::Slice(element) => reference_in(element),
+        Type::FunctionPointer { params, return_type } => params.iter().chain(return_type.as_deref()).find_map(nested_reference),
+        _ => reference_in(ty),
+    }
+}
+
+/// A reference or slice that is `ty` or part of it, other than a
+/// parameter or return type of a function pointer
+fn reference_in(ty: &Type) -> Option<&Type> {
+    match ty {
+        Type::Ref(_) | Type::Slice(_) => Some(ty),
+        Type::Option(inner) | Type::Array(inner, _) | Type::Range(inner) => reference_in(inner),
+        Type::Result(ok, err) => reference_in(ok).or_else(|| reference_in(err)),
+        Type::Tuple(items) | Type::Generic { type_args: items, .. } => items.iter().find_map(reference_in),
+        Type::FunctionPointer { .. } => nested_reference(ty),
+        _ => None,
+    }
+}
+
+/// Match the parameter type `param` against an argument of type `actual`,
+/// binding the type parameters it mentions; false if they do not fit
+fn bind_type_params(
+    param: &Type,
+    actual: &Type,
+    type_params: &[String],
+    bindings: &mut HashMap<String, Type>,
+    traits: &TraitRegistry,
+) -> bool {
+    let all = |params: &[Type], actuals: &[Type], bindings: &mut HashMap<String, Type>| {
+        params.len() == actuals.len()
+            && params.iter().zip(actuals).all(|(p, a)| bind_type_params(p, a, type_params, bindings, traits))
+    };
+    match (param, actual) {
+        (Type::Custom(name), _) if type_params.contains(name) => match bindings.get(name) {
+            Some(bound) => bound == actual,
+            None => {
+                bindings.insert(name.clone(), actual.clone());
+                true
             }
+        },
+        (Type::Option(p), Type::Option(a))
+        | (Type::Slice(p), Type::Slice(a) | Type::Array(a, _))
+        | (Type::Range(p), Type::Range(a))
+        | (Type::Ref(p), Type::Ref(a)) => bind_type_params(p, a, type_params, bindings, traits),
+        (Type::Array(p, n), Type::Array(a, m)) => n == m && bind_type_params(p, a, type_params, bindings, traits),
+        (Type::Result(p, q), Type::Result(a, b)) => {
+            bind_type_params(p, a, type_params, bindings, traits) && bind_type_params(q, b, type_params, bindings, traits)
+        }
+        (Type::Tuple(p), Type::Tuple(a)) => all(p, a, bindings),
+        (Type::Generic { name, type_args: p }, Type::Generic { name: actual_name, type_args: a }) => {
+            name == actual_name && all(p, a, bindings)
         }
+        (
+            Type::FunctionPointer { params: p, return_type: r },
+            Type::FunctionPointer { params: a, return_type: b },
+        ) => {
+            all(p, a, bindings)
+                && match (r, b) {
+                    (Some(r), Some(b)) => bind_type_params(r, b, type_params, bindings, traits),
+                    (r, b) => r.is_none() && b.is_none(),
+                }
+        }
+        _ => assignable(actual, param, traits),
     }
 }
 
-#[allow(dead_code)]
-fn _check_actor(_actor: Actor) {
-    // Actor verification deferred to v0.9
+/// Whether `expr` is an integer literal without a suffix, whose type
+/// follows the values it is used with
+/// Value of an unsuffixed integer literal, possibly negated
+fn literal_value(expr: &Expression) -> Option<i128> {
+    match &expr.kind {
+        ExpressionKind::Literal(Literal::Integer(n, None)) => Some(*n as i128),
+        ExpressionKind::Unary { operator: UnaryOp::Negate, operand } => literal_value(operand).map(|n| -n),
+        _ => None,
+    }
 }
 
-#[allow(dead_code)]
-fn _check_trait(_trait: Trait) {
-    // Trait verification deferred to v0.9
+/// Smallest and largest value of an integer type
+fn integer_range(ty: &Type) -> Option<(i128, i128)> {
+    match ty {
+        Type::I32 => Some((i32::MIN as i128, i32::MAX as i128)),
+        Type::I64 => Some((i64::MIN as i128, i64::MAX as i128)),
+        Type::U8 => Some((0, u8::MAX as i128)),
+        Type::U64 => Some((0, u64::MAX as i128)),
+        _ => None,
+    }
+}
+
+fn is_integer_literal(expr: &Expression) -> bool {
+    matches!(expr.kind, ExpressionKind::Literal(Literal::Integer(_, None)))
 }
 
-#[allow(dead_code)]
-fn _check_type_def(_typedef: TypeDef) {
-    // Type definition verification deferred to v0.9
+fn literal_matches(lit: &Literal, ty: &Type) -> bool {
+    match lit {
+        Literal::Integer(_, None) => matches!(ty, Type::I32 | Type::I64 | Type::U8 | Type::U64),
+        Literal::Float(_, None) => matches!(ty, Type::F32 | Type::F64),
+        _ => lit.ty() == *ty,
+    }
 }
 
-#[allow(dead_code)]
-fn _check_trait_impl(_impl: TraitImpl) {
-    // Trait implementation verification deferred to v0.9
+/// Describe how a constructor's payload differs in shape from the
+/// declaration, if it does
+fn payload_mismatch(declared: &VariantPayload<Type>, given: &VariantPayload<Expression>) -> Option<String> {
+    match (declared, given) {
+        (VariantPayload::Unit, VariantPayload::Unit) => None,
+        (VariantPayload::Tuple(types), VariantPayload::Tuple(values)) => {
+            if types.len() == values.len() {
+                None
+            } else {
+                Some(format!("expects {} value(s), got {}", types.len(), values.len()))
+            }
+        }
+        (VariantPayload::Struct(types), VariantPayload::Struct(values)) => {
+            let mut given = HashSet::new();
+            for (field, _) in values {
+                if !types.iter().any(|(name, _)| name == field) {
+                    return Some(format!("has no field '{}'", field));
+                }
+                if !given.insert(field) {
+                    return Some(format!("sets field '{}' twice", field));
+                }
+            }
+            types
+                .iter()
+                .find(|(name, _)| !given.contains(name))
+                .map(|(name, _)| format!("is missing field '{}'", name))
+        }
+        (VariantPayload::Unit, _) => Some("takes no payload".to_string()),
+        (VariantPayload::Tuple(_), _) => Some("expects values in parentheses".to_string()),
+        (VariantPayload::Struct(_), _) => Some("expects named fields in braces".to_string()),
+    }
 }
 
 #[cfg(test)]
@@ -351,4 +2090,481 @@ mod tests {
         let mut checker = TypeChecker::new();
         assert!(checker.check_program(decls).is_ok());
     }
+
+    fn check(source: &str) -> Result<()> {
+        let mut parser = Parser::new(Lexer::new(source.to_string()).tokenize());
+        TypeChecker::new().check_program(parser.parse().unwrap())
+    }
+
+    /// Source of `items` followed by a `main` whose body is `body`
+    fn with_main(items: &str, body: &str) -> String {
+        format!("{}fn main() {{ {} }}", items, body)
+    }
+
+    #[test]
+    fn test_enum_constructors() {
+        let shape = "enum Shape { Circle(i32), Rect { w: i32, h: i32 }, Empty }\n";
+
+        assert!(check(&with_main(shape, "let a = Shape::Circle(1); let b = Shape::Rect { h: 2, w: 1 }; let c = Shape::Empty;")).is_ok());
+        assert!(matches!(check(&with_main(shape, "let a = Shape::Square;")), Err(TypeError::UnknownVariant(..))));
+        assert!(matches!(check(&with_main(shape, "let a = Color::Red;")), Err(TypeError::UnknownEnum(..))));
+        for bad in [
+            "let a = Shape::Circle(1, 2);",
+            "let a = Shape::Circle;",
+            "let a = Shape::Rect { w: 1 };",
+            "let a = Shape::Rect { w: 1, h: 2, d: 3 };",
+            "let a = Shape::Empty(1);",
+        ] {
+            assert!(matches!(check(&with_main(shape, bad)), Err(TypeError::InvalidPayload(_))), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_enum_declarations() {
+        assert!(matches!(
+            check("enum E { A, A }"),
+            Err(TypeError::DuplicateDefinition(_))
+        ));
+        assert!(matches!(
+            check("enum E { A { x: i32, x: i32 } }"),
+            Err(TypeError::DuplicateDefinition(_))
+        ));
+        assert!(matches!(check("enum List { Cons(i32, List), Nil }"), Err(TypeError::RecursiveType(_))));
+        assert!(matches!(
+            check("enum A { X(B) }\nenum B { Y(Option<A>) }"),
+            Err(TypeError::RecursiveType(_))
+        ));
+    }
+
+    #[test]
+    fn test_match_exhaustiveness() {
+        let shape = "enum Shape { Circle(i32), Rect { w: i32, h: i32 }, Empty }\n";
+        let program = |body: &str| with_main(shape, &format!("let s = Shape::Empty; {}", body));
+
+        assert!(check(&program("let a = match s { Shape::Circle(r) => r, Shape::Rect { w, .. } => w, Shape::Empty => 0, };")).is_ok());
+        assert!(check(&program("match (s, 1) { (Shape::Empty, 0) => print(0), (_, n) if n > 1 => print(n), _ => {} }")).is_ok());
+        match check(&program("match s { Shape::Circle(_) => print(1), Shape::Empty => print(2), }")) {
+            Err(TypeError::NonExhaustive { missing, .. }) => assert_eq!(missing, "Shape::Rect { .. }"),
+            other => panic!("expected a non-exhaustive match, got {:?}", other),
+        }
+        assert!(matches!(
+            check(&program("match s { Shape::Circle(r) if r > 0 => print(r), Shape::Rect { .. } => print(1), Shape::Empty => print(2), }")),
+            Err(TypeError::NonExhaustive { .. })
+        ));
+        assert!(matches!(
+            check(&program("match s { _ => print(1), Shape::Empty => print(2), }")),
+            Err(TypeError::UnreachablePattern(_))
+        ));
+    }
+
+    #[test]
+    fn test_match_patterns() {
+        let shape = "enum Shape { Circle(i32), Rect { w: i32, h: i32 }, Empty }\n";
+        let program = |body: &str| with_main(shape, &format!("let s = Shape::Empty; let n = 1; {}", body));
+
+        for (bad, expected) in [
+            ("match n { \"a\" => print(1), _ => print(2), }", "Mismatch"),
+            ("match s { Shape::Circle(a, b) => print(a), _ => print(2), }", "InvalidPattern"),
+            ("match s { Shape::Rect { w } => print(w), _ => print(2), }", "InvalidPattern"),
+            ("match s { Shape::Rect { w, d, .. } => print(w), _ => print(2), }", "InvalidPattern"),
+            ("match s { Shape::Square => print(1), _ => print(2), }", "UnknownVariant"),
+            ("match (n, n) { (a, a) => print(a), }", "DuplicateDefinition"),
+            ("let a = match n { 1 => { print(1); } _ => 2, };", "Unsupported"),
+            ("print(match n { _ => 2, });", "Unsupported"),
+        ] {
+            let err = check(&program(bad)).expect_err(bad);
+            assert!(format!("{:?}", err).starts_with(expected), "{}: {:?}", bad, err);
+        }
+        // Arm bindings are scoped to their arm
+        assert!(matches!(
+            check(&program("match s { Shape::Circle(r) => print(r), _ => print(r), }")),
+            Err(TypeError::UnknownVariable(..))
+        ));
+    }
+
+    #[test]
+    fn test_structs() {
+        let types = "type Point { x: i32, y: i32 }\ntype Wrap<T> { inner: T, point: Point }\n";
+
+        assert!(check(&with_main(types, "var p = Point { y: 2, x: 1 }; p.x = p.y; let w = Wrap<str> { inner: \"a\", point: p }; let s = w.point.x;")).is_ok());
+        assert!(matches!(check(&with_main(types, "let p = Point { x: 1 };")), Err(TypeError::MissingField(_))));
+        assert!(matches!(check(&with_main(types, "let p = Point { x: 1, y: 2, z: 3 };")), Err(TypeError::UnknownField(..))));
+        assert!(matches!(check(&with_main(types, "let p = Point { x: 1, x: 1, y: 2 };")), Err(TypeError::DuplicateDefinition(_))));
+        assert!(matches!(check(&with_main(types, "let p = Pt { x: 1 };")), Err(TypeError::UnknownStruct(..))));
+        assert!(matches!(check(&with_main(types, "var p = Point { x: 1, y: 2 }; p.z = 1;")), Err(TypeError::UnknownField(..))));
+        assert!(matches!(check(&with_main(types, "let p = Point { x: 1, y: 2 }; p.x = 1;")), Err(TypeError::InvalidOwnership(_))));
+        assert!(matches!(check(&with_main(types, "let n = 1; let m = n.x;")), Err(TypeError::Mismatch { .. })));
+
+        assert!(matches!(check("type L { next: L }"), Err(TypeError::RecursiveType(_))));
+        assert!(matches!(check("type A { b: B }\nenum B { X(A) }"), Err(TypeError::RecursiveType(_))));
+        assert!(matches!(check("type A { x: i32 }\nenum A { X }"), Err(TypeError::DuplicateDefinition(_))));
+    }
+
+    #[test]
+    fn test_generic_inference() {
+        let items = "type Box<T> { item: T }\n\
+            fn empty<T>() -> Option<T> { return None; }\n\
+            fn wrap<T>(item: T) -> Box<T> { return Box { item: item }; }\n";
+        let checked = |body: &str| {
+            let mut parser = Parser::new(Lexer::new(with_main(items, body)).tokenize());
+            let mut checker = TypeChecker::new();
+            checker.check_program(parser.parse().unwrap()).map(|_| checker)
+        };
+
+        // Type arguments come from the arguments or from the expected type
+        let checker = checked("let b: Box<i64> = wrap(1); let e: Option<str> = empty();").unwrap();
+        let mut found: Vec<String> = checker.instantiations().values().map(|args| format!("{:?}", args)).collect();
+        found.sort();
+        assert_eq!(found, ["[Custom(\"T\")]", "[I64]", "[Str]"]);
+        assert!(checked("let b = Box { item: true }; let c: Box<bool> = b;").is_ok());
+        assert!(matches!(
+            checked("let e = empty();").err(),
+            Some(TypeError::UninferredType(param, _)) if param == "T"
+        ));
+        assert!(matches!(checked("let b: Box<str> = wrap(1);").err(), Some(TypeError::Mismatch { .. })));
+    }
+
+    #[test]
+    fn test_arrays() {
+        assert!(check("fn sum(xs: [i32]) -> i32 { return xs[0]; }\nfn main() { var a = [1, 2, 3]; a[2] = a[0]; let n = [[1], [2]]; let m = n[1][0]; }").is_ok());
+        assert!(matches!(check("fn main() { let a = [1, 2]; let b = a[2]; }"), Err(TypeError::IndexOutOfBounds(_))));
+        assert!(matches!(check("fn main() { let a = [1, \"x\"]; }"), Err(TypeError::Mismatch { .. })));
+        assert!(matches!(check("fn main() { let a = [1]; let b = a[\"x\"]; }"), Err(TypeError::Mismatch { .. })));
+        assert!(matches!(check("fn main() { let n = 1; let b = n[0]; }"), Err(TypeError::Mismatch { .. })));
+        assert!(matches!(check("fn main() { let a = []; }"), Err(TypeError::Unsupported(_))));
+        assert!(matches!(check("fn main() { let a = [1]; a[0] = 2; }"), Err(TypeError::InvalidOwnership(_))));
+    }
+
+    #[test]
+    fn test_for_loops() {
+        let counter = "type Counter { n: i32 }\n\
+            impl Iterator for Counter { fn next() -> Option<i32> { return None; } }\n";
+        assert!(check(&format!(
+            "{}fn f(xs: [str]) {{ for s in xs {{ let t = s; }} }}\n\
+             fn main() {{ let n = 3i64; for i in 0..n {{ }} for c in (Counter {{ n: 0 }}) {{ }} for x in [1, 2] {{ }} }}",
+            counter
+        ))
+        .is_ok());
+        // The loop variable has the item type and is scoped to the loop
+        assert!(matches!(
+            check(&format!("{}fn main() {{ for c in (Counter {{ n: 0 }}) {{ let s = c.n; }} }}", counter)),
+            Err(TypeError::Mismatch { .. })
+        ));
+        assert!(matches!(check("fn main() { for i in 0..3 { } let j = i; }"), Err(TypeError::UnknownVariable(..))));
+        assert!(matches!(check("fn main() { for i in 10 { } }"), Err(TypeError::NotIterable(..))));
+        assert!(matches!(check("type P { x: i32 }\nfn main() { let p = P { x: 1 }; for i in p { } }"), Err(TypeError::NotIterable(..))));
+        assert!(matches!(check("fn main() { let a = 1u8; for i in a..300i64 { } }"), Err(TypeError::Mismatch { .. })));
+        assert!(matches!(check("fn main() { for i in (0..9).step_by(0) { } }"), Err(TypeError::Unsupported(_))));
+        assert!(matches!(
+            check("type C { n: i32 }\nimpl Iterator<i64> for C { fn next() -> Option<i32> { return None; } }"),
+            Err(TypeError::Mismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_closures() {
+        assert!(check(
+            "fn apply(f: fn(i32) -> i32, x: i32) -> i32 { return f(x); }\n\
+             fn main() { let n = 2; let add = |x: i32| x + n; let y = apply(add, 1); let z = add(3); }"
+        )
+        .is_ok());
+        // The closure's type follows its final value, or its declaration
+        assert!(matches!(
+            check("type P { x: i32 }\nfn main() { let f = |x: i32| P { x: x }; let p = f(1); let x = p.x; }"),
+            Ok(())
+        ));
+        assert!(matches!(
+            check("fn main() { let f = || -> str { \"a\" }; let s = f(); let t = s.x; }"),
+            Err(TypeError::Mismatch { .. })
+        ));
+        // Parameters are scoped to the body
+        assert!(matches!(check("fn main() { let f = |x: i32| x; let y = x; }"), Err(TypeError::UnknownVariable(..))));
+        assert!(matches!(check("fn main() { let f = |x: i32| x; f(1, 2); }"), Err(TypeError::Mismatch { .. })));
+        assert!(matches!(check("fn main() { let n = 1; n(2); }"), Err(TypeError::Mismatch { .. })));
+        assert!(matches!(check("fn main() { let f = |x: i32| x; f(true); }"), Err(TypeError::Mismatch { .. })));
+    }
+
+    #[test]
+    fn test_signatures() {
+        assert!(check(
+            "fn first<T>(xs: [T]) -> T { return xs[0]; }
+             fn find(xs: [i64], n: i64) -> Option<i64> { for x in xs { if x == n { return Some(x); } } return None; }
+             fn main() { let a = [1i64, 2]; let b = first(a) + 1; let c = find(a, 2); return 0; }"
+        )
+        .is_ok());
+        let mismatch = |source: &str| matches!(check(source), Err(TypeError::Mismatch { .. }));
+        assert!(mismatch("fn f(x: i32) -> i32 { return x; }
+fn main() { f(1, 2); }"));
+        assert!(mismatch("fn f(x: i32) -> i32 { return x; }
+fn main() { f(\"a\"); }"));
+        assert!(mismatch("fn same<T>(a: T, b: T) -> T { return a; }
+fn main() { same(1, true); }"));
+        assert!(mismatch("fn f() -> str { return 1; }"));
+        assert!(mismatch("fn f() { return 1; }"));
+        assert!(mismatch("fn f() { }
+fn main() { let x = f(); }"));
+        assert!(mismatch("fn main() { var x = 1; x = \"a\"; }"));
+        assert!(mismatch("type P { x: u8 }
+fn main() { let p = P { x: 1.5 }; }"));
+        assert!(matches!(check("fn main() { nope(); }"), Err(TypeError::UnknownFunction(..))));
+        assert!(matches!(check("type P { x: i32 }
+fn main() { let p = P { x: 1 }; p.go(); }"), Err(TypeError::UnknownMethod(..))));
+        // Extern `...` functions take extra arguments of any type
+        assert!(check("extern \"C\" { fn printf(format: ptr, ...); }
+fn main() { unsafe { printf(\"%d %s\", 1, \"a\"); } }").is_ok());
+        // A function returning a value returns it on every path
+        let missing = |source: &str| matches!(check(source), Err(TypeError::MissingReturn(_)));
+        assert!(missing("fn f() -> i32 { }"));
+        assert!(missing("fn f() -> i32 { if true { return 1; } }"));
+        assert!(missing("fn f(o: Option<i32>) -> i32 { match o { Some(x) => { return x; } None => { } } }"));
+        assert!(check("fn f(o: Option<i32>) -> i32 { match o { Some(x) => { return x; } None => { return 0; } } }").is_ok());
+        assert!(check("fn f(b: bool) -> i32 { if b { return 1; } else { return 2; } }").is_ok());
+    }
+
+    #[test]
+    fn test_operators_and_conditions() {
+        assert!(check(
+            "fn main() { let a = 1u8 + 2; let b = 2.5 * 2.0; let c = -b; let d = a % 3 == 1 || !(c < 0.0); if d { } while a > 5 { } }"
+        )
+        .is_ok());
+        let mismatch = |source: &str| matches!(check(source), Err(TypeError::Mismatch { .. }));
+        assert!(mismatch("fn main() { if 1 { } }"));
+        assert!(mismatch("fn main() { while \"a\" { } }"));
+        assert!(mismatch("fn main() { let x = 1 + true; }"));
+        assert!(mismatch("fn main() { let x = 1u8 + 1i64; }"));
+        assert!(mismatch("fn main() { let x = \"a\" < \"b\"; }"));
+        assert!(mismatch("fn main() { let x = 1.5 % 2.0; }"));
+        assert!(mismatch("fn main() { let x = -1u8; }"));
+        assert!(mismatch("fn main() { let x = !1; }"));
+        assert!(mismatch("fn main() { let x = 1 && true; }"));
+    }
+
+    #[test]
+    fn test_literal_ranges() {
+        assert!(check("fn main() { let a: u8 = 255; let b: i32 = -2147483648; let c: u64 = 18446744073709551615; let d = a + 1; }").is_ok());
+        let out_of_range = |source: &str| matches!(check(source), Err(TypeError::LiteralOutOfRange(_)));
+        assert!(out_of_range("fn main() { let x: u8 = 300; }"));
+        assert!(out_of_range("fn main() { let x: i32 = 3000000000; }"));
+        assert!(out_of_range("fn main() { let x: u64 = -1; }"));
+        assert!(out_of_range("fn main() { let a = 1u8; let b = a + 256; }"));
+        assert!(out_of_range("fn take(x: u8) { }\nfn main() { take(1000); }"));
+    }
+
+    #[test]
+    fn test_let_annotations_and_assignment() {
+        assert!(check(
+            "fn main() { let a: i64 = 1; let o: Option<u8> = None; let s: [i32] = [1, 2]; \
+             let b: i32; if a > 0 { b = 1; } else { b = 2; } let c = b; \
+             let d: str; match c { 1 => { d = \"one\"; } _ => { return 1; } } let e = d; \
+             var f: i32; f = 3; f = f + 1; }"
+        )
+        .is_ok());
+        assert!(matches!(check("fn main() { let a: u8 = 1i64; }"), Err(TypeError::Mismatch { .. })));
+        let unassigned = |source: &str| matches!(check(source), Err(TypeError::UnassignedVariable(..)));
+        assert!(unassigned("fn main() { let a: i32; let b = a; }"));
+        assert!(unassigned("fn main() { var a: i32; if true { a = 1; } let b = a; }"));
+        assert!(unassigned("fn main() { var a: i32; while true { a = 1; } let b = a; }"));
+        assert!(unassigned("fn main() { var a: i32; let f = || { a = 1; }; let b = a; }"));
+        assert!(unassigned("fn main() { var a: i32; a = a + 1; }"));
+        // A `let` takes exactly one assignment, outside loops and closures
+        let reassigned = |source: &str| matches!(check(source), Err(TypeError::InvalidOwnership(_)));
+        assert!(reassigned("fn main() { let a: i32; a = 1; a = 2; }"));
+        assert!(reassigned("fn main() { let a: i32; if true { a = 1; } a = 2; }"));
+        assert!(reassigned("fn main() { let a: i32; for i in 0..3 { a = i; } }"));
+        assert!(reassigned("fn main() { let a: i32; let f = || { a = 1; }; }"));
+    }
+
+    #[test]
+    fn test_traits_and_impls() {
+        let shape = "trait Shape { fn area() -> i32; fn grow(by: i32); }\ntype Sq { side: i32 }\n";
+        let program = |items: &str| with_main(&format!("{}{}\n", shape, items), "");
+        let invalid = |items: &str| matches!(check(&program(items)), Err(TypeError::InvalidTrait(_)));
+        assert!(check(&program(
+            "impl Shape for Sq { fn area() -> i32 { return self.side * self.side; } fn grow(by: i32) { self.side = self.side + by; } }"
+        ))
+        .is_ok());
+        assert!(invalid("impl Shape for Sq { fn area() -> i32 { return 1; } }"));
+        assert!(invalid("impl Shape for Sq { fn area() -> i64 { return 1; } fn grow(by: i32) { } }"));
+        assert!(invalid("impl Shape for Sq { fn area() -> i32 { return 1; } fn grow(by: i32) { } fn grow(by: i32) { } }"));
+        assert!(invalid("impl Shape for Sq { fn area() -> i32 { return 1; } fn grow(by: i32) { } fn shrink() { } }"));
+        assert!(invalid("impl Drawable for Sq { }"));
+        assert!(invalid("impl Shape for Circle { }"));
+        // Method bodies are checked like functions
+        assert!(matches!(
+            check(&program("impl Shape for Sq { fn area() -> i32 { return true; } fn grow(by: i32) { } }")),
+            Err(TypeError::Mismatch { .. })
+        ));
+        assert!(matches!(
+            check("type Sq { side: i32 }\nfn main() { let s = Sq { side: 1 }; for x in s { } }"),
+            Err(TypeError::NotIterable(_, context)) if context.contains("does not implement trait 'Iterator'")
+        ));
+    }
+
+    #[test]
+    fn test_method_dispatch() {
+        let shapes = "trait Shape { fn area() -> i32; fn scale(k: i32) -> i32; }\n\
+            type Sq { side: i32 }\ntype Dot { x: i32 }\n\
+            impl Shape for Sq { fn area() -> i32 { return self.side; } fn scale(k: i32) -> i32 { return self.area() * k; } }\n";
+        let program = |body: &str| with_main(&format!("{}fn total(s: dyn Shape) -> i32 {{ return s.scale(2); }}\n", shapes), body);
+        assert!(check(&program("let s = Sq { side: 2 }; let a: i32 = s.area(); let d: dyn Shape = s; print(total(d));")).is_ok());
+        assert!(matches!(
+            check(&program("let d: dyn Shape = Dot { x: 1 };")),
+            Err(TypeError::Mismatch { expected, .. }) if expected == "dyn Shape"
+        ));
+        assert!(matches!(
+            check(&program("let s = Sq { side: 2 }; let b: bool = s.area();")),
+            Err(TypeError::Mismatch { .. })
+        ));
+        assert!(matches!(
+            check(&program("let s = Sq { side: 2 }; s.perimeter();")),
+            Err(TypeError::UnknownMethod(..))
+        ));
+        // A `dyn` value points to the value it was made from, so cannot
+        // outlive it
+        let unsupported = |source: &str| matches!(check(source), Err(TypeError::Unsupported(message)) if message.contains("dyn"));
+        assert!(unsupported(&format!("{}fn make(n: i32) -> dyn Shape {{ let s = Sq {{ side: n }}; return s; }}\nfn main() {{ }}", shapes)));
+        assert!(unsupported(&format!("{}type Holder {{ s: dyn Shape }}\nfn main() {{ }}", shapes)));
+        assert!(unsupported(&program("let s = Sq { side: 2 }; let ds: [dyn Shape; 1] = [s];")));
+        assert!(unsupported(&program("let a = Sq { side: 1 }; var d: dyn Shape = a; if true { let s = Sq { side: 2 }; d = s; }")));
+        assert!(check(&program("let a = Sq { side: 1 }; var d: dyn Shape = a; let s = Sq { side: 2 }; d = s; print(total(d));")).is_ok());
+        // A method is called on a field or on the result of a call
+        let nested = format!("{}type Holder {{ sq: Sq }}\nfn make() -> Sq {{ return Sq {{ side: 3 }}; }}\n", shapes);
+        assert!(check(&with_main(&nested, "let h = Holder { sq: Sq { side: 2 } }; let a: i32 = h.sq.area() + make().area();")).is_ok());
+        // The same method from two traits, and a method needing `Self` on a `dyn`
+        let twice = "trait Area { fn area() -> i32; }\nimpl Area for Sq { fn area() -> i32 { return 0; } }\n";
+        assert!(matches!(
+            check(&format!("{}{}fn main() {{ let s = Sq {{ side: 2 }}; s.area(); }}", shapes, twice)),
+            Err(TypeError::InvalidTrait(message)) if message.contains("ambiguous")
+        ));
+        assert!(matches!(
+            check("type P { v: i32 }\nfn same(a: dyn Eq, b: P) -> bool { return a.eq(b); }\nfn main() { }"),
+            Err(TypeError::InvalidTrait(message)) if message.contains("cannot be called through 'dyn Eq'")
+        ));
+    }
+
+    #[test]
+    fn test_trait_bounds() {
+        let items = "enum Ordering { Less, Equal, Greater }\n\
+            type Score { value: i32 }\n\
+            impl Ord for Score { fn cmp(other: Score) -> Ordering { return Ordering::Equal; } }\n\
+            type Pair<T: Ord> { left: T, right: T }\n\
+            fn max<T: Ord>(a: T, b: T) -> T { match a.cmp(b) { Ordering::Less => { return b; } _ => { return a; } } }\n\
+            fn first<T>(pair: Pair<T>) -> T where T: Ord { return max(pair.left, pair.right); }\n";
+        let score = "let a = Score { value: 1 }; let b = Score { value: 2 };";
+        assert!(check(&with_main(items, &format!("{} let best = first(Pair {{ left: a, right: b }});", score))).is_ok());
+        assert!(matches!(
+            check(&with_main(items, "let best = max(1, 2);")),
+            Err(TypeError::UnsatisfiedBound { bound, actual, location })
+                if bound == "T: Ord" && actual == "i32" && location.starts_with("call of max")
+        ));
+        assert!(matches!(
+            check(&with_main(items, "let pair = Pair { left: true, right: false };")),
+            Err(TypeError::UnsatisfiedBound { actual, .. }) if actual == "bool"
+        ));
+        assert!(matches!(
+            check(&with_main(items, "let pair: Pair<str> = Pair { left: \"a\", right: \"b\" };")),
+            Err(TypeError::UnsatisfiedBound { actual, .. }) if actual == "str"
+        ));
+
+        // Within a generic item, a type parameter has the traits of its
+        // own bounds only
+        assert!(matches!(
+            check(&format!("{}fn loose<T>(a: T, b: T) -> T {{ return max(a, b); }}\nfn main() {{ }}", items)),
+            Err(TypeError::UnsatisfiedBound { actual, .. }) if actual == "T"
+        ));
+        assert!(matches!(
+            check(&format!("{}fn loose<T>(pair: Pair<T>) {{ }}\nfn main() {{ }}", items)),
+            Err(TypeError::UnsatisfiedBound { location, .. }) if location.starts_with("Pair<T>")
+        ));
+        assert!(matches!(
+            check(&format!("{}fn loose<T>(a: T, b: T) {{ a.cmp(b); }}\nfn main() {{ }}", items)),
+            Err(TypeError::UnknownMethod(..))
+        ));
+        assert!(matches!(
+            check(&format!("{}fn odd<T: Sortable>(a: T) {{ }}\nfn main() {{ }}", items)),
+            Err(TypeError::InvalidTrait(message)) if message.contains("'Sortable' not found")
+        ));
+    }
+
+    #[test]
+    fn test_copy_and_clone() {
+        let items = "type Point { x: i32 }\ntype Named { name: str }\nfn twice<T: Copy>(v: T) -> T { return v; }\n\
+            fn dup<T: Clone>(v: T) -> T { return v.clone(); }\n";
+        assert!(check(&with_main(items, "let n = 1; let m: i32 = n.clone(); let p = Point { x: 1 }; let q: Point = p.clone(); let s = \"a\"; let t: str = s.clone();")).is_ok());
+        assert!(check(&with_main(items, "let p = twice(Point { x: 1 }); let s = dup(\"a\"); let n = dup(1);")).is_ok());
+        assert!(matches!(
+            check(&with_main(items, "let n = Named { name: \"a\" }; let m = n.clone();")),
+            Err(TypeError::UnknownMethod(..))
+        ));
+        assert!(matches!(
+            check(&with_main(items, "let n = twice(Named { name: \"a\" });")),
+            Err(TypeError::UnsatisfiedBound { bound, .. }) if bound == "T: Copy"
+        ));
+        assert!(matches!(
+            check(&format!("{}type P {{ x: i32 }}\nimpl Copy for P {{ }}\nfn main() {{ }}", items)),
+            Err(TypeError::InvalidTrait(message)) if message.contains("cannot be implemented")
+        ));
+    }
+
+    #[test]
+    fn test_explicit_drop() {
+        let items = "type Handle { fd: i32 }\nimpl Drop for Handle { fn drop() { } }\nfn consume<T>(value: T) { }\n";
+        assert!(check(&with_main(items, "let h = Handle { fd: 1 }; consume(h);")).is_ok());
+        assert!(matches!(
+            check(&with_main(items, "let h = Handle { fd: 1 }; h.drop();")),
+            Err(TypeError::InvalidOwnership(message)) if message.contains("mem::drop")
+        ));
+    }
+
+    #[test]
+    fn test_references() {
+        let items = "type Point { x: i32, y: i32 }\ntype Named { name: str }\n\
+            fn sum(p: &Point) -> i32 { return p.x + p.y; }\nfn pick(a: &i32, b: &i32) -> &i32 { return b; }\n";
+        assert!(check(&with_main(items, "let p = Point { x: 1, y: 2 }; let r = &p; let s: i32 = sum(r) + sum(&p) + r.x;")).is_ok());
+        assert!(check(&with_main(items, "let a = 1; let b = 2; let m: i32 = *pick(&a, &b); let xs = [1, 2]; let r = &xs; let x: i32 = r[0];")).is_ok());
+        assert!(matches!(
+            check(&with_main(items, "let p = Point { x: 1, y: 2 }; let s = sum(p);")),
+            Err(TypeError::Mismatch { .. })
+        ));
+        // Only a place can be borrowed, and only a Copy value read out
+        assert!(matches!(check(&with_main(items, "let r = &1;")), Err(TypeError::Unsupported(_))));
+        assert!(matches!(
+            check(&with_main(items, "let n = Named { name: \"a\" }; let r = &n; let m = *r;")),
+            Err(TypeError::InvalidOwnership(_))
+        ));
+        // A reference cannot be stored inside another value
+        assert!(matches!(check(&format!("{}type H {{ r: &i32 }}\nfn main() {{ }}", items)), Err(TypeError::Unsupported(_))));
+        assert!(matches!(check(&with_main(items, "let a = 1; let o = Some(&a);")), Err(TypeError::Unsupported(_))));
+        assert!(matches!(
+            check(&format!("{}fn id<T>(v: T) -> T {{ return v; }}\nfn main() {{ let a = 1; let r = id(&a); }}", items)),
+            Err(TypeError::Unsupported(_))
+        ));
+        // nor can a slice, which borrows the array it views
+        assert!(matches!(
+            check(&format!("{}type H {{ xs: [i32] }}\nfn main() {{ }}", items)),
+            Err(TypeError::Unsupported(msg)) if msg.starts_with("slice type [i32] in field xs")
+        ));
+        assert!(matches!(check(&with_main(items, "let a = [1]; let o: Option<[i32]> = None;")), Err(TypeError::Unsupported(_))));
+        assert!(check(&format!("{}fn f(xs: [i32], r: &[i32]) -> [i32] {{ return xs; }}\nfn main() {{ }}", items)).is_ok());
+    }
+
+    #[test]
+    fn test_actors() {
+        let items = "type Named { name: str }\nactor Worker { fn run(s: str) { } fn peek(n: &Named) { } }\n";
+        assert!(check(&with_main(items, "let w = Worker.spawn(); w.run(\"a\"); let n = Named { name: \"b\" }; w.peek(&n);")).is_ok());
+        // Messages are checked against the method, and spawn takes none
+        assert!(matches!(check(&with_main(items, "let w = Worker.spawn(); w.run(1);")), Err(TypeError::Mismatch { .. })));
+        assert!(matches!(check(&with_main(items, "let w = Worker.spawn(1);")), Err(TypeError::Mismatch { .. })));
+        assert!(matches!(check(&with_main(items, "let w = Worker.spawn(); w.stop();")), Err(TypeError::UnknownMethod(..))));
+        assert!(matches!(check(&with_main(items, "Worker.run(\"a\");")), Err(TypeError::UnknownMethod(..))));
+        // Method bodies are checked like functions
+        assert!(matches!(
+            check(&with_main("actor Log { fn log(line: str) { let n: i32 = line; } }\n", "")),
+            Err(TypeError::Mismatch { .. })
+        ));
+        assert!(matches!(
+            check(&with_main("type Worker { n: i32 }\nactor Worker { fn run() { } }\n", "")),
+            Err(TypeError::DuplicateDefinition(_))
+        ));
+    }
 }